@@ -0,0 +1,73 @@
+#[cfg(feature = "cpal")]
+mod cpal_backend;
+#[cfg(feature = "jack")]
+mod jack_backend;
+#[cfg(feature = "portaudio")]
+mod pa_backend;
+mod sink_backend;
+
+use crate::error::{BaseError, Result};
+
+pub const CHANNELS_NUM: usize = 2;
+// Frames per buffer
+pub const BUF_SIZE: u32 = 600;
+
+// Names for --backend option, the first one is used by default.
+// Sinks without a sound card are always built, for headless runs
+pub const BACKENDS: &[&str] = &[
+    #[cfg(feature = "portaudio")]
+    "portaudio",
+    #[cfg(feature = "cpal")]
+    "cpal",
+    #[cfg(feature = "jack")]
+    "jack",
+    "null",
+    "file",
+    "tcp",
+    "udp",
+];
+
+// Sinks take a target after a colon: file:out.wav, tcp:host:port
+pub fn split_name(name: &str) -> (&str, Option<&str>) {
+    match name.find(':') {
+        Some(idx) => (&name[..idx], Some(&name[idx + 1..])),
+        None => (name, None),
+    }
+}
+
+// Fills interleaved stereo buffer. Returns false when there is nothing
+// to play, then the buffer is left as is and stream may finish
+pub type OutputCallback = dyn FnMut(&mut [i16]) -> bool + Send;
+
+pub trait OutputStream {
+    // Starting a started stream or stopping a stopped one does nothing
+    fn start(&mut self) -> Result<()>;
+    fn stop(&mut self) -> Result<()>;
+    // Output device is gone, stream has to be opened again
+    fn lost(&self) -> bool;
+    // Hz, can differ from the requested one if backend has its own
+    fn sample_rate(&self) -> f32;
+}
+
+pub trait AudioBackend {
+    // Opens stream on default output device, it's stopped until started
+    fn open_stream(&self, sample_rate: f32, callback: Box<OutputCallback>) -> Result<Box<dyn OutputStream>>;
+}
+
+pub fn create(name: Option<&str>) -> Result<Box<dyn AudioBackend>> {
+    let (name, target) = split_name(name.unwrap_or(BACKENDS[0]));
+    match name {
+        #[cfg(feature = "portaudio")]
+        "portaudio" => Ok(Box::new(pa_backend::PaBackend::new()?)),
+        #[cfg(feature = "cpal")]
+        "cpal" => Ok(Box::new(cpal_backend::CpalBackend::new())),
+        #[cfg(feature = "jack")]
+        "jack" => Ok(Box::new(jack_backend::JackBackend)),
+        "null" | "file" | "tcp" | "udp" => Ok(Box::new(sink_backend::SinkBackend::new(
+            sink_backend::SinkKind::parse(name, target)?,
+        ))),
+        name => Err(BaseError::InputError(format!(
+            "Unknown audio backend {}, available: {}", name, BACKENDS.join(", ")
+        ))),
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use ::cpal::{BufferSize, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig};
+
+use super::{AudioBackend, OutputCallback, OutputStream, BUF_SIZE, CHANNELS_NUM};
+use crate::error::{BaseError, Context, Result};
+
+pub struct CpalBackend {
+    host: ::cpal::Host,
+}
+
+impl CpalBackend {
+    pub fn new() -> Self {
+        Self {
+            host: ::cpal::default_host(),
+        }
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn open_stream(&self, sample_rate: f32, mut callback: Box<OutputCallback>) -> Result<Box<dyn OutputStream>> {
+        let device = match self.host.default_output_device() {
+            Some(device) => device,
+            None => return Err(BaseError::StreamError("Can't open default device".into())),
+        };
+        let format = device.default_output_config()?.sample_format();
+        let config = StreamConfig {
+            channels: CHANNELS_NUM as u16,
+            sample_rate: SampleRate(sample_rate as u32),
+            buffer_size: BufferSize::Fixed(BUF_SIZE),
+        };
+
+        // Unlike PortAudio stream, cpal one can't complete by itself and keeps asking
+        // for data until paused. It gets silence once callback has nothing to play
+        let finished = Arc::new(AtomicBool::new(true));
+        let finished_in_callback = Arc::clone(&finished);
+        let mut fill = move |output: &mut [i16]| {
+            if finished_in_callback.load(Ordering::Acquire) || !callback(output) {
+                finished_in_callback.store(true, Ordering::Release);
+                output.iter_mut().for_each(|sample| *sample = 0);
+            }
+        };
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_in_callback = Arc::clone(&lost);
+        let on_error = move |e: ::cpal::StreamError| {
+            eprintln!("Output stream error: {}", e);
+            if let ::cpal::StreamError::DeviceNotAvailable = e {
+                lost_in_callback.store(true, Ordering::Release);
+            }
+        };
+
+        let stream = match format {
+            SampleFormat::I16 => device.build_output_stream(
+                &config,
+                move |output: &mut [i16], _: &OutputCallbackInfo| fill(output),
+                on_error,
+            ),
+            // Most hosts want floats, samples are converted from synth's i16
+            SampleFormat::F32 => {
+                let mut buffer = Vec::new();
+                device.build_output_stream(
+                    &config,
+                    move |output: &mut [f32], _: &OutputCallbackInfo| {
+                        buffer.resize(output.len(), 0);
+                        fill(&mut buffer);
+                        for (out, sample) in output.iter_mut().zip(buffer.iter()) {
+                            *out = *sample as f32 / i16::max_value() as f32;
+                        }
+                    },
+                    on_error,
+                )
+            }
+            format => {
+                return Err(BaseError::StreamError(format!("Unsupported sample format {:?}", format)))
+            }
+        };
+        let stream = stream.context("Can't open stream")?;
+
+        Ok(Box::new(CpalStream { stream, finished, lost, sample_rate }))
+    }
+}
+
+struct CpalStream {
+    stream: ::cpal::Stream,
+    // Callback has nothing to play and writes silence
+    finished: Arc<AtomicBool>,
+    lost: Arc<AtomicBool>,
+    sample_rate: f32,
+}
+
+impl OutputStream for CpalStream {
+    fn start(&mut self) -> Result<()> {
+        self.finished.store(false, Ordering::Release);
+        self.stream.play()?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.stream.pause()?;
+        Ok(())
+    }
+
+    fn lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::{AudioBackend, OutputCallback, OutputStream, CHANNELS_NUM};
+use crate::error::{Context, Result};
+use crate::settings::instance_name;
+
+// Every running instance is its own client, beep-boop, beep-boop-2, ...
+const CLIENT_NAME: &str = "beep-boop";
+const PORT_NAMES: [&str; CHANNELS_NUM] = ["out_left", "out_right"];
+
+// Registers a stereo JACK client, its ports can be patched anywhere in the graph.
+// Device is managed by JACK server, so stream can't lose it
+pub struct JackBackend;
+
+impl AudioBackend for JackBackend {
+    fn open_stream(&self, _sample_rate: f32, mut callback: Box<OutputCallback>) -> Result<Box<dyn OutputStream>> {
+        let (client, _status) = jack::Client::new(&instance_name(CLIENT_NAME), jack::ClientOptions::NO_START_SERVER)
+            .context("Can't connect to JACK server")?;
+        // Server runs at its own rate, synth follows it
+        let sample_rate = client.sample_rate() as f32;
+        let mut left = client.register_port(PORT_NAMES[0], jack::AudioOut::default())?;
+        let mut right = client.register_port(PORT_NAMES[1], jack::AudioOut::default())?;
+
+        // JACK keeps calling back while client is active,
+        // silence is written once callback has nothing to play
+        let finished = Arc::new(AtomicBool::new(true));
+        let finished_in_callback = Arc::clone(&finished);
+        let mut buffer = Vec::new();
+        let process = jack::ClosureProcessHandler::new(
+            move |_: &jack::Client, scope: &jack::ProcessScope| -> jack::Control {
+                let left = left.as_mut_slice(scope);
+                let right = right.as_mut_slice(scope);
+                buffer.resize(left.len() * CHANNELS_NUM, 0);
+                if finished_in_callback.load(Ordering::Acquire) || !callback(&mut buffer) {
+                    finished_in_callback.store(true, Ordering::Release);
+                    left.iter_mut().chain(right.iter_mut()).for_each(|sample| *sample = 0.0);
+                    return jack::Control::Continue
+                }
+                for ((l, r), frame) in left.iter_mut().zip(right.iter_mut()).zip(buffer.chunks(CHANNELS_NUM)) {
+                    *l = frame[0] as f32 / i16::max_value() as f32;
+                    *r = frame[1] as f32 / i16::max_value() as f32;
+                }
+                jack::Control::Continue
+            },
+        );
+        let client = client.activate_async((), process)?;
+
+        // Ports go to speakers like with other backends, they can be repatched later
+        let playback = client.as_client().ports(
+            None,
+            Some(jack::jack_sys::FLOAT_MONO_AUDIO),
+            jack::PortFlags::IS_INPUT | jack::PortFlags::IS_PHYSICAL,
+        );
+        for (port, destination) in PORT_NAMES.iter().zip(playback.iter()) {
+            let source = format!("{}:{}", client.as_client().name(), port);
+            if let Err(e) = client.as_client().connect_ports_by_name(&source, destination) {
+                eprintln!("Can't connect {} to {}: {}", source, destination, e);
+            }
+        }
+
+        Ok(Box::new(JackStream { _client: client, finished, sample_rate }))
+    }
+}
+
+struct JackStream<P: jack::ProcessHandler> {
+    // Client is deactivated when dropped
+    _client: jack::AsyncClient<(), P>,
+    finished: Arc<AtomicBool>,
+    sample_rate: f32,
+}
+
+impl<P: jack::ProcessHandler> OutputStream for JackStream<P> {
+    fn start(&mut self) -> Result<()> {
+        self.finished.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.finished.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn lost(&self) -> bool {
+        false
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
@@ -0,0 +1,108 @@
+use portaudio_rs as pa;
+
+use super::{AudioBackend, OutputCallback, OutputStream, BUF_SIZE, CHANNELS_NUM};
+use crate::error::{BaseError, Context, Result};
+
+// PortAudio is initialized while backend exists, so streams have to be dropped before it
+pub struct PaBackend;
+
+impl PaBackend {
+    pub fn new() -> Result<Self> {
+        pa::initialize()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for PaBackend {
+    fn drop(&mut self) {
+        if let Err(e) = pa::terminate() {
+            eprintln!("Can't terminate PortAudio: {}", e);
+        }
+    }
+}
+
+impl AudioBackend for PaBackend {
+    fn open_stream(&self, sample_rate: f32, mut callback: Box<OutputCallback>) -> Result<Box<dyn OutputStream>> {
+        let default_output = match pa::device::get_default_output_index() {
+            Some(dev) => dev,
+            None => {
+                return Err(BaseError::StreamError(
+                    "Can't open default device".into(),
+                ))
+            }
+        };
+
+        let latency = match pa::device::get_info(default_output) {
+            Some(info) => info.default_low_output_latency,
+            None => return Err(BaseError::StreamError("Can't get latency info".to_owned())),
+        };
+
+        let output_params = pa::stream::StreamParameters::<i16> {
+            device: default_output,
+            channel_count: CHANNELS_NUM as u32,
+            suggested_latency: latency,
+            data: i16::min_value(),
+        };
+
+        let _supported =
+            pa::stream::is_format_supported::<i16, i16>(None, Some(output_params), sample_rate as f64)?;
+
+        let pa_callback = Box::new(
+            move |
+                _input: &[i16],
+                output: &mut [i16],
+                _time: pa::stream::StreamTimeInfo,
+                _flags: pa::stream::StreamCallbackFlags| -> pa::stream::StreamCallbackResult
+                {
+                    if callback(output) {
+                        pa::stream::StreamCallbackResult::Continue
+                    } else {
+                        pa::stream::StreamCallbackResult::Complete
+                    }
+                }
+        );
+
+        let stream = match pa::stream::Stream::<i16, i16>::open(
+            None,
+            Some(output_params),
+            sample_rate as f64,
+            BUF_SIZE as u64,
+            pa::stream::StreamFlags::empty(),
+            Some(pa_callback),
+        ) {
+            Ok(s) => s,
+            Err(e) => return Err(e).context("Can't open stream"),
+        };
+
+        Ok(Box::new(PaStream { stream, sample_rate }))
+    }
+}
+
+struct PaStream {
+    stream: pa::stream::Stream<'static, i16, i16>,
+    sample_rate: f32,
+}
+
+impl OutputStream for PaStream {
+    fn start(&mut self) -> Result<()> {
+        if !self.stream.is_active()? {
+            self.stream.start()?
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        if self.stream.is_active()? {
+            self.stream.stop()?
+        }
+        Ok(())
+    }
+
+    fn lost(&self) -> bool {
+        self.stream.is_active().is_err()
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::{AudioBackend, OutputCallback, OutputStream, BUF_SIZE, CHANNELS_NUM};
+use crate::error::{BaseError, Context, Result};
+use crate::settings::instance_name;
+use crate::wav::WavWriter;
+
+const DEFAULT_WAV_NAME: &str = "beep-boop-output";
+// Samples per UDP datagram, keeps datagrams under a common MTU
+const UDP_SAMPLES: usize = 600;
+
+// Where samples go when there is no sound card
+pub enum SinkKind {
+    // Dropped, for benchmarks and tests
+    Null,
+    Wav(PathBuf),
+    // Raw 16-bit little-endian interleaved stereo
+    Tcp(String),
+    Udp(String),
+}
+
+impl SinkKind {
+    // Backend name with an optional target after a colon, e.g. file:out.wav or tcp:host:port
+    pub fn parse(kind: &str, target: Option<&str>) -> Result<Self> {
+        let address = |name: &str| {
+            target
+                .map(str::to_owned)
+                .ok_or_else(|| BaseError::InputError(format!("{} sink needs an address, e.g. {}:localhost:5000", name, name)))
+        };
+        match kind {
+            "null" => Ok(SinkKind::Null),
+            "file" => Ok(SinkKind::Wav(match target {
+                Some(path) => PathBuf::from(path),
+                None => PathBuf::from(format!("{}.wav", instance_name(DEFAULT_WAV_NAME))),
+            })),
+            "tcp" => Ok(SinkKind::Tcp(address("tcp")?)),
+            "udp" => Ok(SinkKind::Udp(address("udp")?)),
+            kind => Err(BaseError::InputError(format!("Unknown sink {}", kind))),
+        }
+    }
+}
+
+trait Sink: Send {
+    // Interleaved stereo samples
+    fn write(&mut self, samples: &[i16]) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct NullSink;
+
+impl Sink for NullSink {
+    fn write(&mut self, _samples: &[i16]) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink for WavWriter {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        WavWriter::write(self, samples)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        WavWriter::finish(*self)
+    }
+}
+
+fn pcm_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect()
+}
+
+struct TcpSink(TcpStream);
+
+impl Sink for TcpSink {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        self.0.write_all(&pcm_bytes(samples))?;
+        Ok(())
+    }
+}
+
+struct UdpSink(UdpSocket);
+
+impl Sink for UdpSink {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        for chunk in samples.chunks(UDP_SAMPLES) {
+            self.0.send(&pcm_bytes(chunk))?;
+        }
+        Ok(())
+    }
+}
+
+// Backend without an audio device: a thread pulls buffers in real time
+// and hands them to a sink. Only the time something plays is written
+pub struct SinkBackend {
+    kind: SinkKind,
+}
+
+impl SinkBackend {
+    pub fn new(kind: SinkKind) -> Self {
+        Self { kind }
+    }
+
+    fn open_sink(&self, sample_rate: f32) -> Result<Box<dyn Sink>> {
+        Ok(match &self.kind {
+            SinkKind::Null => Box::new(NullSink),
+            // Reopened stream starts the file over, it can't change rate midway
+            SinkKind::Wav(path) => Box::new(
+                WavWriter::create(path, sample_rate as u32).context(format!("Can't create {}", path.display()))?,
+            ),
+            SinkKind::Tcp(address) => Box::new(TcpSink(
+                TcpStream::connect(address).context(format!("Can't connect to {}", address))?,
+            )),
+            SinkKind::Udp(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address).context(format!("Can't connect to {}", address))?;
+                Box::new(UdpSink(socket))
+            }
+        })
+    }
+}
+
+impl AudioBackend for SinkBackend {
+    fn open_stream(&self, sample_rate: f32, callback: Box<OutputCallback>) -> Result<Box<dyn OutputStream>> {
+        Ok(Box::new(SinkStream {
+            parts: Some((callback, self.open_sink(sample_rate)?)),
+            thread: None,
+            running: Arc::new(AtomicBool::new(false)),
+            lost: Arc::new(AtomicBool::new(false)),
+            sample_rate,
+        }))
+    }
+}
+
+type StreamParts = (Box<OutputCallback>, Box<dyn Sink>);
+
+struct SinkStream {
+    // Moved to the thread while the stream runs
+    parts: Option<StreamParts>,
+    thread: Option<JoinHandle<StreamParts>>,
+    running: Arc<AtomicBool>,
+    // Sink stopped taking samples, e.g. the other end closed the connection
+    lost: Arc<AtomicBool>,
+    sample_rate: f32,
+}
+
+impl OutputStream for SinkStream {
+    fn start(&mut self) -> Result<()> {
+        let (mut callback, mut sink) = match self.parts.take() {
+            Some(parts) => parts,
+            None => return Ok(()),
+        };
+        self.running.store(true, Ordering::Release);
+        let running = Arc::clone(&self.running);
+        let lost = Arc::clone(&self.lost);
+        let buffer_time = Duration::from_secs_f32(BUF_SIZE as f32 / self.sample_rate);
+        let thread = std::thread::Builder::new()
+            .name("beep-boop-sink".into())
+            .spawn(move || {
+                let mut buffer = vec![0; BUF_SIZE as usize * CHANNELS_NUM];
+                let mut deadline = Instant::now();
+                while running.load(Ordering::Acquire) && callback(&mut buffer) {
+                    if let Err(e) = sink.write(&buffer) {
+                        eprintln!("Sink is lost: {}", e);
+                        lost.store(true, Ordering::Release);
+                        break;
+                    }
+                    // Paced like a sound card, so notes sound for as long as they're held
+                    deadline += buffer_time;
+                    if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                        std::thread::sleep(wait);
+                    }
+                }
+                (callback, sink)
+            })
+            .map_err(|_| BaseError::ThreadError("Can't start sink thread".into()))?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let parts = thread.join().map_err(|_| BaseError::ThreadError("Sink thread panicked".into()))?;
+            self.parts = Some(parts);
+        }
+        Ok(())
+    }
+
+    fn lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+}
+
+impl Drop for SinkStream {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            eprintln!("Can't stop sink stream: {}", e);
+        }
+        if let Some((_, sink)) = self.parts.take() {
+            if let Err(e) = sink.finish() {
+                eprintln!("Can't finish sink: {}", e);
+            }
+        }
+    }
+}
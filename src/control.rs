@@ -0,0 +1,447 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::backend::{BUF_SIZE, CHANNELS_NUM};
+use crate::crash;
+use crate::duet::DuetSender;
+use crate::error::Result;
+use crate::synth::{
+    ADSRParam, ArpMode, ExpressionCurve, StepDivision, ExpressionDestination, FilterMode, GlideCurve, Lfo, LfoTarget, ModDestination, ModSource, NoteEvent, NoteSource, OscEngine, Oscillator, PluckString,
+    Recorder, SampleFormat, Start, Synth, VoiceStealing, Wave, WaveForm, ADSR, HARMONICS,
+};
+use crate::synth_ui::SynthUIEvent;
+
+// Offline render goes on after the last event until release tails end, but not forever
+const RENDER_TAIL_STEP_SECONDS: f32 = 0.1;
+const MAX_RENDER_TAIL_SECONDS: f32 = 30.0;
+
+// Changes posted to the synth by UI and MIDI threads.
+// Audio callback applies them before rendering the next buffer.
+// Waves and oscillators come built, the callback never allocates them
+pub enum SynthCommand<SampleType: SampleFormat> {
+    NoteOn { freq: f32, velocity: f32, source: NoteSource },
+    NoteOff(NoteSource),
+    ModWheel(f32),
+    PitchBend(f32),
+    BendRange(f32),
+    Aftertouch(NoteSource, f32),
+    AllNotesOff,
+    AllSoundOff,
+    // Cuts everything and mutes the output
+    Panic,
+    Mute(bool),
+    Expression(f32),
+    Sustain(bool),
+    ExpressionDestination(ExpressionDestination),
+    ExpressionCurve(ExpressionCurve),
+    GlideTime(f32),
+    GlideLegato(bool),
+    GlideCurve(GlideCurve),
+    Audition(WaveForm, Box<dyn Wave + Send>),
+    // Rendered by render_env_preview
+    PreviewEnv(Vec<f32>),
+    // Oscillator played alone while it's hovered, None ends the solo
+    Solo(Option<usize>),
+    AddOsc(Oscillator),
+    RemoveOsc(usize),
+    AddEnv(ADSR),
+    RemoveEnv(usize),
+    AddLfo(Lfo),
+    Volume(i32),
+    // Output trim measured by auto_gain_trim, 1.0 while auto gain is off
+    GainTrim(f32),
+    VelocitySensitivity(f32),
+    LowCut(f32),
+    HighQuality(bool),
+    DcBlocker(bool),
+    Binaural(bool),
+    // Hz and dBFS, None stops the tone
+    TestTone(Option<(f32, i32)>),
+    MaxVoices(usize),
+    VoiceStealing(VoiceStealing),
+    FmAmount(f32),
+    OscVolume(usize, f32),
+    OscBend(usize, bool),
+    OscDrone(usize, bool),
+    OscEnvAmount(usize, f32),
+    OscSub(usize, bool),
+    OscSubWaveform(usize, WaveForm, Box<dyn Wave + Send>),
+    OscSubLevel(usize, f32),
+    OscPhaseOffset(usize, f32),
+    OscStart(usize, Start),
+    OscTone(usize, bool),
+    OscTilt(usize, f32),
+    OscPulseWidth(usize, f32),
+    // Levels of every harmonic and the current waveform built from them
+    OscHarmonics(usize, [f32; HARMONICS], Box<dyn Wave + Send>),
+    OscEngine(usize, OscEngine),
+    // Built by pluck_strings, sent whenever pluck engine is picked or sample rate changes
+    OscPluckStrings(usize, Vec<PluckString>),
+    OscPluckDamping(usize, f32),
+    OscVelocityRange(usize, u8, u8),
+    Pan(usize, f32),
+    Waveform(usize, WaveForm, Box<dyn Wave + Send>),
+    Transpose(usize, i8),
+    Tune(usize, i8),
+    Unisons(usize, usize),
+    Supersaw(usize, bool),
+    SupersawDetune(usize, f32),
+    SupersawMix(usize, f32),
+    UnisonWidth(usize, f32),
+    WidthTime(usize, f32),
+    Env(usize, usize),
+    EnvParameter(usize, ADSRParam),
+    LfoWaveform(usize, WaveForm, Box<dyn Wave + Send>),
+    LfoRate(usize, f32),
+    LfoDepth(usize, f32),
+    LfoTarget(usize, LfoTarget),
+    FilterCutoff(f32),
+    FilterResonance(f32),
+    FilterMode(FilterMode),
+    FilterEnv(usize),
+    FilterEnvAmount(f32),
+    ModRoute(usize, ModSource, ModDestination),
+    ChaosRate(f32),
+    ArpEnabled(bool),
+    ArpMode(ArpMode),
+    ArpSynced(bool),
+    ArpRate(f32),
+    ArpDivision(StepDivision),
+    ArpOctaves(usize),
+    ArpGate(f32),
+    SeqRunning(bool),
+    SeqDivision(StepDivision),
+    SeqPitch(usize, i32),
+    SeqGate(usize, bool),
+    SeqAccent(usize, bool),
+    Tempo(f32),
+    StepLevel(usize, f32),
+    StepDivision(StepDivision),
+    ModDepth(usize, f32),
+    ModDepthSource(usize, ModSource),
+    SampleRate(f32),
+    // Output fades out and stops, sent when the app is closing
+    FadeOut,
+    StartRecording(Recorder<SampleType>),
+    StopRecording,
+    // Written out by the writer thread, to be filled again
+    RecordingChunk(Vec<[SampleType; 2]>),
+}
+
+// Parts the audio thread let go of. They are sent back and freed
+// by SynthControl, freeing them in the callback could block it
+pub enum Garbage<SampleType: SampleFormat> {
+    Oscillator(Oscillator),
+    Wave(Box<dyn Wave + Send>),
+    Preview(Vec<f32>),
+    PluckStrings(Vec<PluckString>),
+    RecordingChunk(Vec<[SampleType; 2]>),
+}
+
+// Values come from UI controls and are in allowed range, so this shouldn't happen
+fn check(result: Result<()>) {
+    if let Err(e) = result {
+        eprintln!("Can't apply synth command: {}", e);
+    }
+}
+
+impl<SampleType: SampleFormat> SynthCommand<SampleType> {
+    fn apply(self, synth: &mut Synth<SampleType>, garbage: &mpsc::Sender<Garbage<SampleType>>) {
+        // Receiver is gone only when the app is closing
+        let throw = |item: Garbage<SampleType>| {
+            let _ = garbage.send(item);
+        };
+        match self {
+            SynthCommand::NoteOn { freq, velocity, source } => {
+                synth.set_velocity(velocity);
+                synth.note_on(freq, source)
+            }
+            SynthCommand::NoteOff(source) => synth.note_off(source),
+            SynthCommand::ModWheel(value) => synth.set_mod_wheel(value),
+            SynthCommand::PitchBend(value) => synth.set_pitch_bend(value),
+            SynthCommand::BendRange(semitones) => synth.set_bend_range(semitones),
+            SynthCommand::Aftertouch(source, pressure) => synth.set_aftertouch(source, pressure),
+            SynthCommand::AllNotesOff => synth.all_notes_off(),
+            SynthCommand::AllSoundOff => synth.all_sound_off(),
+            SynthCommand::Panic => synth.panic(),
+            SynthCommand::Mute(muted) => synth.set_muted(muted),
+            SynthCommand::Expression(value) => synth.set_expression(value),
+            SynthCommand::Sustain(down) => synth.set_sustain(down),
+            SynthCommand::ExpressionDestination(destination) => synth.set_expression_destination(destination),
+            SynthCommand::ExpressionCurve(curve) => synth.set_expression_curve(curve),
+            SynthCommand::GlideTime(time) => synth.set_glide_time(time),
+            SynthCommand::GlideLegato(legato) => synth.set_glide_legato(legato),
+            SynthCommand::GlideCurve(curve) => synth.set_glide_curve(curve),
+            SynthCommand::Audition(waveform, wave) => throw(Garbage::Wave(synth.audition(waveform, wave))),
+            SynthCommand::PreviewEnv(preview) => throw(Garbage::Preview(synth.play_preview(preview))),
+            SynthCommand::Solo(osc_idx) => synth.set_solo(osc_idx),
+            SynthCommand::AddOsc(osc) => synth.add_osc(osc),
+            SynthCommand::RemoveOsc(idx) => {
+                if let Some(osc) = synth.remove_osc(idx) {
+                    throw(Garbage::Oscillator(osc));
+                }
+            }
+            SynthCommand::AddEnv(env) => synth.add_env(env),
+            SynthCommand::RemoveEnv(idx) => synth.remove_env(idx),
+            SynthCommand::AddLfo(lfo) => synth.add_lfo(lfo),
+            SynthCommand::Volume(volume) => check(synth.set_volume(volume)),
+            SynthCommand::GainTrim(trim) => synth.set_gain_trim(trim),
+            SynthCommand::VelocitySensitivity(sensitivity) => synth.set_velocity_sensitivity(sensitivity),
+            SynthCommand::LowCut(freq) => synth.set_low_cut(freq),
+            SynthCommand::HighQuality(enabled) => synth.set_high_quality(enabled),
+            SynthCommand::DcBlocker(enabled) => synth.set_dc_blocker(enabled),
+            SynthCommand::Binaural(enabled) => synth.set_binaural(enabled),
+            SynthCommand::TestTone(tone) => synth.set_test_tone(tone),
+            SynthCommand::MaxVoices(voices) => synth.set_max_voices(voices),
+            SynthCommand::VoiceStealing(stealing) => synth.set_voice_stealing(stealing),
+            SynthCommand::FmAmount(amount) => synth.set_fm_amount(amount),
+            SynthCommand::OscVolume(idx, volume) => synth.set_osc_volume(idx, volume),
+            SynthCommand::OscBend(idx, enabled) => synth.set_osc_bend(idx, enabled),
+            SynthCommand::OscDrone(idx, enabled) => synth.set_osc_drone(idx, enabled),
+            SynthCommand::OscEnvAmount(idx, amount) => synth.set_osc_env_amount(idx, amount),
+            SynthCommand::OscSub(idx, enabled) => synth.set_osc_sub(idx, enabled),
+            SynthCommand::OscSubWaveform(idx, waveform, wave) => {
+                throw(Garbage::Wave(synth.set_osc_sub_wave(idx, waveform, wave)))
+            }
+            SynthCommand::OscSubLevel(idx, level) => synth.set_osc_sub_level(idx, level),
+            SynthCommand::OscPhaseOffset(idx, degrees) => synth.set_osc_phase_offset(idx, degrees),
+            SynthCommand::OscStart(idx, start) => synth.set_osc_start(idx, start),
+            SynthCommand::OscTone(idx, enabled) => synth.set_osc_tone(idx, enabled),
+            SynthCommand::OscTilt(idx, tilt) => synth.set_osc_tilt(idx, tilt),
+            SynthCommand::OscPulseWidth(idx, width) => synth.set_osc_pulse_width(idx, width),
+            SynthCommand::OscHarmonics(idx, levels, wave) => throw(Garbage::Wave(synth.set_osc_harmonics(idx, levels, wave))),
+            SynthCommand::OscEngine(idx, engine) => synth.set_osc_engine(idx, engine),
+            SynthCommand::OscPluckStrings(idx, strings) => {
+                throw(Garbage::PluckStrings(synth.set_osc_pluck_strings(idx, strings)))
+            }
+            SynthCommand::OscPluckDamping(idx, damping) => synth.set_osc_pluck_damping(idx, damping),
+            SynthCommand::OscVelocityRange(idx, low, high) => synth.set_osc_velocity_range(idx, low, high),
+            SynthCommand::Pan(idx, pan) => synth.set_pan(idx, pan),
+            SynthCommand::Waveform(idx, waveform, wave) => throw(Garbage::Wave(synth.set_waveform(idx, waveform, wave))),
+            SynthCommand::Transpose(idx, semitones) => check(synth.set_transpose(idx, semitones)),
+            SynthCommand::Tune(idx, cents) => check(synth.set_tune(idx, cents)),
+            SynthCommand::Unisons(idx, num) => synth.set_unisons(idx, num),
+            SynthCommand::Supersaw(idx, enabled) => synth.set_supersaw(idx, enabled),
+            SynthCommand::SupersawDetune(idx, detune) => synth.set_supersaw_detune(idx, detune),
+            SynthCommand::SupersawMix(idx, mix) => synth.set_supersaw_mix(idx, mix),
+            SynthCommand::UnisonWidth(idx, width) => synth.set_unison_width(idx, width),
+            SynthCommand::WidthTime(idx, ms) => synth.set_width_time(idx, ms),
+            SynthCommand::Env(osc_idx, env_idx) => synth.set_env(osc_idx, env_idx),
+            SynthCommand::EnvParameter(idx, param) => synth.set_env_parameter(idx, param),
+            SynthCommand::LfoWaveform(idx, waveform, wave) => {
+                throw(Garbage::Wave(synth.set_lfo_waveform(idx, waveform, wave)))
+            }
+            SynthCommand::LfoRate(idx, rate) => synth.set_lfo_rate(idx, rate),
+            SynthCommand::LfoDepth(idx, depth) => synth.set_lfo_depth(idx, depth),
+            SynthCommand::LfoTarget(idx, target) => synth.set_lfo_target(idx, target),
+            SynthCommand::FilterCutoff(cutoff) => synth.set_filter_cutoff(cutoff),
+            SynthCommand::FilterResonance(resonance) => synth.set_filter_resonance(resonance),
+            SynthCommand::FilterMode(mode) => synth.set_filter_mode(mode),
+            SynthCommand::FilterEnv(idx) => synth.set_filter_env(idx),
+            SynthCommand::FilterEnvAmount(amount) => synth.set_filter_env_amount(amount),
+            SynthCommand::ModRoute(idx, source, destination) => synth.set_mod_route(idx, source, destination),
+            SynthCommand::ChaosRate(rate) => synth.set_chaos_rate(rate),
+            SynthCommand::ArpEnabled(enabled) => synth.set_arp_enabled(enabled),
+            SynthCommand::ArpMode(mode) => synth.set_arp_mode(mode),
+            SynthCommand::ArpSynced(synced) => synth.set_arp_synced(synced),
+            SynthCommand::ArpRate(rate) => synth.set_arp_rate(rate),
+            SynthCommand::ArpDivision(division) => synth.set_arp_division(division),
+            SynthCommand::ArpOctaves(octaves) => synth.set_arp_octaves(octaves),
+            SynthCommand::ArpGate(gate) => synth.set_arp_gate(gate),
+            SynthCommand::SeqRunning(running) => synth.set_seq_running(running),
+            SynthCommand::SeqDivision(division) => synth.set_seq_division(division),
+            SynthCommand::SeqPitch(step, pitch) => synth.set_seq_pitch(step, pitch),
+            SynthCommand::SeqGate(step, on) => synth.set_seq_gate(step, on),
+            SynthCommand::SeqAccent(step, accent) => synth.set_seq_accent(step, accent),
+            SynthCommand::Tempo(tempo) => synth.set_tempo(tempo),
+            SynthCommand::StepLevel(step, level) => synth.set_step_level(step, level),
+            SynthCommand::StepDivision(division) => synth.set_step_division(division),
+            SynthCommand::ModDepth(idx, depth) => synth.set_mod_depth(idx, depth),
+            SynthCommand::ModDepthSource(idx, source) => synth.set_mod_depth_source(idx, source),
+            SynthCommand::SampleRate(sample_rate) => synth.set_sample_rate(sample_rate),
+            SynthCommand::FadeOut => synth.fade_out(),
+            SynthCommand::StartRecording(recorder) => synth.start_recording(recorder),
+            SynthCommand::StopRecording => synth.stop_recording(),
+            SynthCommand::RecordingChunk(chunk) => {
+                if let Some(chunk) = synth.recycle_recording_chunk(chunk) {
+                    throw(Garbage::RecordingChunk(chunk));
+                }
+            }
+        }
+    }
+}
+
+// UI and MIDI side of the synth. Never waits for the audio thread
+#[derive(Clone)]
+pub struct SynthControl<SampleType: SampleFormat> {
+    commands: mpsc::Sender<SynthCommand<SampleType>>,
+    // Cleared by the audio callback once it has nothing to play
+    playing: Arc<AtomicBool>,
+    // Set by the audio callback when the soft clipper works, cleared by UI once it's shown
+    clipped: Arc<AtomicBool>,
+    // Stuck voices the audio callback released, logging them there would block it
+    stuck_released: Arc<AtomicUsize>,
+    // Parts the audio thread let go of, freed by whoever sends the next command
+    garbage: Arc<Mutex<mpsc::Receiver<Garbage<SampleType>>>>,
+    event_sender: mpsc::Sender<SynthUIEvent>,
+    // Notes go to another beep-boop instead of this one
+    duet: Option<Arc<DuetSender>>,
+}
+
+impl<SampleType: SampleFormat> SynthControl<SampleType> {
+    // Applies to clones made after it
+    pub fn forward_notes(&mut self, sender: DuetSender) {
+        self.duet = Some(Arc::new(sender));
+    }
+
+    pub fn send(&self, command: SynthCommand<SampleType>) {
+        // Audio side is gone only when the app is closing
+        let _ = self.commands.send(command);
+        // Another thread is on it already
+        if let Ok(garbage) = self.garbage.try_lock() {
+            while garbage.try_recv().is_ok() {}
+        }
+    }
+
+    // For commands that make synth play, stopped output stream is started
+    pub fn play(&self, command: SynthCommand<SampleType>) {
+        self.send(command);
+        if !self.playing.swap(true, Ordering::SeqCst) {
+            let _ = self.event_sender.send(SynthUIEvent::NewNotes);
+        }
+    }
+
+    // Output stream was started for notes and hasn't run out of them yet
+    pub fn playing(&self) -> bool {
+        self.playing.load(Ordering::SeqCst)
+    }
+
+    pub fn take_clipped(&self) -> bool {
+        self.clipped.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn take_stuck_released(&self) -> usize {
+        self.stuck_released.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn note_on(&self, freq: f32, velocity: f32, source: NoteSource) {
+        match &self.duet {
+            Some(duet) => duet.note_on(freq, velocity, source),
+            None => self.play(SynthCommand::NoteOn { freq, velocity, source }),
+        }
+    }
+
+    pub fn note_off(&self, source: NoteSource) {
+        match &self.duet {
+            Some(duet) => duet.note_off(source),
+            None => self.send(SynthCommand::NoteOff(source)),
+        }
+    }
+}
+
+// Audio side of the synth, owned by the callback of the current output stream
+pub struct SynthEngine<SampleType: SampleFormat> {
+    synth: Synth<SampleType>,
+    commands: mpsc::Receiver<SynthCommand<SampleType>>,
+    playing: Arc<AtomicBool>,
+    clipped: Arc<AtomicBool>,
+    stuck_released: Arc<AtomicUsize>,
+    garbage: mpsc::Sender<Garbage<SampleType>>,
+    // Synth renders here before samples are converted to output format
+    buffer: Vec<f32>,
+}
+
+impl<SampleType: SampleFormat> SynthEngine<SampleType> {
+    pub fn new(
+        synth: Synth<SampleType>,
+        event_sender: mpsc::Sender<SynthUIEvent>,
+    ) -> (Self, SynthControl<SampleType>) {
+        let (commands_sender, commands) = mpsc::channel();
+        let (garbage_sender, garbage) = mpsc::channel();
+        let playing = Arc::new(AtomicBool::new(false));
+        let clipped = Arc::new(AtomicBool::new(false));
+        let stuck_released = Arc::new(AtomicUsize::new(0));
+        let engine = Self {
+            synth,
+            commands,
+            playing: Arc::clone(&playing),
+            clipped: Arc::clone(&clipped),
+            stuck_released: Arc::clone(&stuck_released),
+            garbage: garbage_sender,
+            buffer: Vec::with_capacity(BUF_SIZE as usize * CHANNELS_NUM),
+        };
+        let control = SynthControl {
+            commands: commands_sender,
+            playing,
+            clipped,
+            stuck_released,
+            garbage: Arc::new(Mutex::new(garbage)),
+            event_sender,
+            duet: None,
+        };
+        (engine, control)
+    }
+
+    fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            command.apply(&mut self.synth, &self.garbage);
+        }
+    }
+
+    // Renders events offline with the commands sent so far,
+    // output is interleaved stereo including release tails
+    pub fn render(&mut self, events: &[NoteEvent]) -> Vec<SampleType> {
+        self.apply_commands();
+        let end = events.iter().map(|event| event.time()).fold(0.0, f32::max);
+        let mut output = self.synth.render(end, events);
+        let mut tail = 0.0;
+        while self.synth.playing() && tail < MAX_RENDER_TAIL_SECONDS {
+            output.extend(self.synth.render(RENDER_TAIL_STEP_SECONDS, &[]));
+            tail += RENDER_TAIL_STEP_SECONDS;
+        }
+        output.iter().map(|value| SampleType::from_normalized(*value)).collect()
+    }
+
+    // Fills interleaved stereo buffer, returns false when there is nothing to play
+    pub fn process(&mut self, output: &mut [SampleType]) -> bool {
+        self.apply_commands();
+        if !self.synth.playing() {
+            // Notes posted while the flag was still set haven't woken up the stream
+            self.playing.store(false, Ordering::SeqCst);
+            self.apply_commands();
+            if !self.synth.playing() {
+                self.synth.restart_fade();
+                self.publish_state(output.len(), false);
+                return false
+            }
+            self.playing.store(true, Ordering::SeqCst);
+        }
+        // Grows only if backend asks for more than BUF_SIZE frames
+        self.buffer.resize(output.len(), 0.0);
+        self.synth.process(&mut self.buffer, CHANNELS_NUM);
+        if self.synth.take_clipped() {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+        let released = self.synth.take_stuck_released();
+        if released > 0 {
+            self.stuck_released.fetch_add(released, Ordering::Relaxed);
+        }
+        for (sample, value) in output.iter_mut().zip(self.buffer.iter()) {
+            *sample = SampleType::from_normalized(*value);
+        }
+        self.publish_state(output.len(), true);
+        true
+    }
+
+    // For the crash report, it can't ask the audio thread
+    fn publish_state(&self, samples: usize, playing: bool) {
+        crash::publish_engine(
+            self.synth.sample_rate(),
+            samples / CHANNELS_NUM,
+            self.synth.voice_count(),
+            self.synth.oscillators.len(),
+            self.synth.envelopes.len(),
+            playing,
+        );
+    }
+}
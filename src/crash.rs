@@ -0,0 +1,117 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::settings::{instance_name, Settings};
+
+// Saved next to where beep-boop is started from, like settings and presets
+const REPORT_FILE: &str = "beep-boop-crash";
+
+// Published by the audio thread after every buffer. Atomics never block,
+// so neither the audio thread nor the panic hook wait for each other
+struct EngineState {
+    sample_rate: AtomicU32,
+    buffer_frames: AtomicUsize,
+    voices: AtomicUsize,
+    oscillators: AtomicUsize,
+    envelopes: AtomicUsize,
+    playing: AtomicBool,
+}
+
+static ENGINE: EngineState = EngineState {
+    sample_rate: AtomicU32::new(0),
+    buffer_frames: AtomicUsize::new(0),
+    voices: AtomicUsize::new(0),
+    oscillators: AtomicUsize::new(0),
+    envelopes: AtomicUsize::new(0),
+    playing: AtomicBool::new(false),
+};
+
+// Patch kept by UI whenever it changes, it's turned into preset JSON only for a report
+static PATCH: Mutex<Option<Box<dyn Fn() -> String + Send>>> = Mutex::new(None);
+// Settings in use, the file may differ from them in safe mode
+static SETTINGS: Mutex<String> = Mutex::new(String::new());
+
+pub fn publish_engine(sample_rate: f32, buffer_frames: usize, voices: usize, oscillators: usize, envelopes: usize, playing: bool) {
+    ENGINE.sample_rate.store(sample_rate.to_bits(), Ordering::Relaxed);
+    ENGINE.buffer_frames.store(buffer_frames, Ordering::Relaxed);
+    ENGINE.voices.store(voices, Ordering::Relaxed);
+    ENGINE.oscillators.store(oscillators, Ordering::Relaxed);
+    ENGINE.envelopes.store(envelopes, Ordering::Relaxed);
+    ENGINE.playing.store(playing, Ordering::Relaxed);
+}
+
+pub fn publish_patch<F: Fn() -> String + Send + 'static>(json: F) {
+    if let Ok(mut patch) = PATCH.lock() {
+        *patch = Some(Box::new(json));
+    }
+}
+
+pub fn publish_settings(settings: &Settings) {
+    if let (Ok(json), Ok(mut published)) = (serde_json::to_string_pretty(settings), SETTINGS.lock()) {
+        *published = json;
+    }
+}
+
+// A thread could panic while it replaces the text, it isn't waited for
+fn published(text: &Mutex<String>) -> String {
+    match text.try_lock() {
+        Ok(text) if !text.is_empty() => text.clone(),
+        Ok(_) => "Not published yet".to_owned(),
+        Err(_) => "Unavailable, it was being updated".to_owned(),
+    }
+}
+
+fn published_patch() -> String {
+    match PATCH.try_lock() {
+        Ok(patch) => patch.as_ref().map_or_else(|| "Not published yet".to_owned(), |json| json()),
+        Err(_) => "Unavailable, it was being updated".to_owned(),
+    }
+}
+
+// Panics still print as usual, and the report is written next to it
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let path = format!("{}-{}.txt", instance_name(REPORT_FILE), seconds);
+        match fs::write(&path, report(&info.to_string(), seconds)) {
+            Ok(()) => eprintln!("Crash report saved to {}, please attach it to the bug report", path),
+            Err(e) => eprintln!("Can't save crash report to {}: {}", path, e),
+        }
+    }));
+}
+
+fn report(panic: &str, seconds: u64) -> String {
+    let thread = std::thread::current();
+    let lines = [
+        format!("beep-boop {} crash report", env!("CARGO_PKG_VERSION")),
+        format!("Time: {} s since Unix epoch", seconds),
+        format!("System: {} {}", std::env::consts::OS, std::env::consts::ARCH),
+        format!("Command line: {}", std::env::args().collect::<Vec<_>>().join(" ")),
+        format!("Thread: {}", thread.name().unwrap_or("unnamed")),
+        panic.to_owned(),
+        String::new(),
+        "Engine".to_owned(),
+        format!("Sample rate: {} Hz", f32::from_bits(ENGINE.sample_rate.load(Ordering::Relaxed))),
+        format!("Buffer: {} frames", ENGINE.buffer_frames.load(Ordering::Relaxed)),
+        format!("Playing: {}", ENGINE.playing.load(Ordering::Relaxed)),
+        format!("Active voices: {}", ENGINE.voices.load(Ordering::Relaxed)),
+        format!("Oscillators: {}", ENGINE.oscillators.load(Ordering::Relaxed)),
+        format!("Envelopes: {}", ENGINE.envelopes.load(Ordering::Relaxed)),
+        String::new(),
+        "Settings".to_owned(),
+        published(&SETTINGS),
+        String::new(),
+        "Patch".to_owned(),
+        published_patch(),
+        String::new(),
+        "Backtrace".to_owned(),
+        Backtrace::force_capture().to_string(),
+    ];
+    lines.join("\n")
+}
@@ -0,0 +1,170 @@
+use std::net::UdpSocket;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::control::SynthControl;
+use crate::error::{BaseError, Context, Result};
+use crate::synth::NoteSource;
+
+// Longest message is "on <stamp> <id> <freq> <velocity>"
+const MAX_MESSAGE_LEN: usize = 128;
+// Stamp going back further than this means the sender was restarted,
+// anything less is a datagram that arrived out of order
+const RESTART_GAP_MS: u64 = 10_000;
+// Ids of notes that are on, lost note offs leave theirs behind, the oldest are forgotten
+const MAX_SOUNDING: usize = 256;
+// Hz, notes from the network are kept within hearing
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 20_000.0;
+
+// Note message as it goes over the network, one per datagram:
+// "on <stamp> <id> <freq> <velocity>" or "off <stamp> <id>".
+// Stamp is ms since the sender started, id ties note off to its note on
+#[derive(Debug)]
+enum Message {
+    On { stamp: u64, id: u32, freq: f32, velocity: f32 },
+    Off { stamp: u64, id: u32 },
+}
+
+impl Message {
+    fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let kind = words.next()?;
+        let stamp = words.next()?.parse().ok()?;
+        let id = words.next()?.parse().ok()?;
+        match kind {
+            "on" => {
+                let freq: f32 = words.next()?.parse().ok()?;
+                let velocity: f32 = words.next()?.parse().ok()?;
+                // "NaN" and "inf" parse too, one of them would break the filters for good
+                if !freq.is_finite() || !velocity.is_finite() {
+                    return None;
+                }
+                Some(Message::On {
+                    stamp,
+                    id,
+                    freq: freq.max(MIN_FREQ).min(MAX_FREQ),
+                    velocity: velocity.max(0.0).min(1.0),
+                })
+            }
+            "off" => Some(Message::Off { stamp, id }),
+            _ => None,
+        }
+    }
+
+    fn stamp(&self) -> u64 {
+        match self {
+            Message::On { stamp, .. } | Message::Off { stamp, .. } => *stamp,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Message::On { stamp, id, freq, velocity } => format!("on {} {} {} {}", stamp, id, freq, velocity),
+            Message::Off { stamp, id } => format!("off {} {}", stamp, id),
+        }
+    }
+}
+
+// Controller side: notes are sent to another beep-boop instead of being played
+pub struct DuetSender {
+    socket: UdpSocket,
+    started: Instant,
+    // Ids of sounding notes, so note off goes with the same id as its note on
+    held: Mutex<Vec<(NoteSource, u32)>>,
+    next_id: Mutex<u32>,
+}
+
+impl DuetSender {
+    pub fn connect(address: &str) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address).context(format!("Can't connect to {}", address))?;
+        Ok(Self {
+            socket,
+            started: Instant::now(),
+            held: Mutex::new(Vec::new()),
+            next_id: Mutex::new(0),
+        })
+    }
+
+    fn stamp(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn send(&self, message: Message) {
+        // Lost datagrams are lost notes, the player hears it and plays on
+        if let Err(e) = self.socket.send(message.to_text().as_bytes()) {
+            eprintln!("Can't send note: {}", e);
+        }
+    }
+
+    pub fn note_on(&self, freq: f32, velocity: f32, source: NoteSource) {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id = next_id.wrapping_add(1);
+            *next_id
+        };
+        let mut held = self.held.lock().unwrap();
+        held.retain(|(held_source, _)| *held_source != source);
+        held.push((source, id));
+        self.send(Message::On { stamp: self.stamp(), id, freq, velocity });
+    }
+
+    pub fn note_off(&self, source: NoteSource) {
+        let mut held = self.held.lock().unwrap();
+        if let Some(idx) = held.iter().position(|(held_source, _)| *held_source == source) {
+            let (_, id) = held.remove(idx);
+            self.send(Message::Off { stamp: self.stamp(), id });
+        }
+    }
+}
+
+// Sound side: plays notes coming from a DuetSender on another machine
+pub fn listen(port: u16, control: SynthControl<i16>) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port)).context(format!("Can't listen on port {}", port))?;
+    std::thread::Builder::new()
+        .name("beep-boop-duet".into())
+        .spawn(move || {
+            let mut buf = [0u8; MAX_MESSAGE_LEN];
+            let mut last_stamp = 0;
+            let mut sounding: Vec<u32> = Vec::with_capacity(MAX_SOUNDING);
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        eprintln!("Can't receive notes: {}", e);
+                        continue;
+                    }
+                };
+                let message = match std::str::from_utf8(&buf[..len]).ok().and_then(Message::parse) {
+                    Some(message) => message,
+                    None => continue,
+                };
+                let stamp = message.stamp();
+                let late = stamp < last_stamp && last_stamp - stamp < RESTART_GAP_MS;
+                if !late {
+                    last_stamp = stamp;
+                }
+                match message {
+                    // Late note on would start after its note off and never stop
+                    Message::On { .. } if late => {}
+                    Message::On { id, freq, velocity, .. } => {
+                        if sounding.len() == MAX_SOUNDING {
+                            sounding.remove(0);
+                        }
+                        sounding.push(id);
+                        control.note_on(freq, velocity, NoteSource::Remote(id));
+                    }
+                    // Note off stops its note however late it is
+                    Message::Off { id, .. } => {
+                        if let Some(idx) = sounding.iter().position(|sounding_id| *sounding_id == id) {
+                            sounding.remove(idx);
+                            control.note_off(NoteSource::Remote(id));
+                        }
+                    }
+                }
+            }
+        })
+        .map_err(|_| BaseError::ThreadError("Can't start duet thread".into()))?;
+    Ok(())
+}
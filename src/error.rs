@@ -6,6 +6,7 @@ pub type Result<T> = std::result::Result<T, BaseError>;
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum BaseError {
+    #[cfg(feature = "portaudio")]
     PaError(portaudio_rs::PaError),
     InputError(String),
     WindowCreation(String),
@@ -14,11 +15,17 @@ pub enum BaseError {
     StreamError(String),
     GUIError(String),
     ThreadError(String),
+    MidiError(String),
+    IoError(std::io::Error),
+    PresetError(String),
+    // What was being done when the inner error happened
+    Context(String, Box<BaseError>),
 }
 
 impl std::fmt::Display for BaseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            #[cfg(feature = "portaudio")]
             BaseError::PaError(e) => e.fmt(f),
             BaseError::InputError(msg) => write!(f, "Input error: {}", msg),
             BaseError::WindowCreation(msg) => write!(f, "Window creation error: {}", msg),
@@ -27,6 +34,10 @@ impl std::fmt::Display for BaseError {
             BaseError::StreamError(msg) => write!(f, "Stream error: {}", msg),
             BaseError::GUIError(msg) => write!(f, "GUI error: {}", msg),
             BaseError::ThreadError(msg) => write!(f, "Thread error: {}", msg),
+            BaseError::MidiError(msg) => write!(f, "MIDI error: {}", msg),
+            BaseError::IoError(e) => e.fmt(f),
+            BaseError::PresetError(msg) => write!(f, "Preset error: {}", msg),
+            BaseError::Context(context, e) => write!(f, "{}: {}", context, e),
         }
     }
 }
@@ -34,18 +45,91 @@ impl std::fmt::Display for BaseError {
 impl Error for BaseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
+            #[cfg(feature = "portaudio")]
             BaseError::PaError(ref e) => Some(e),
+            BaseError::IoError(ref e) => Some(e),
+            BaseError::Context(_, ref e) => Some(e.as_ref()),
             _ => None,
         }
     }
 }
 
+pub trait Context<T> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T>;
+}
+
+impl<T, E: Into<BaseError>> Context<T> for std::result::Result<T, E> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T> {
+        self.map_err(|e| BaseError::Context(context.to_string(), Box::new(e.into())))
+    }
+}
+
+#[cfg(feature = "portaudio")]
 impl From<portaudio_rs::PaError> for BaseError {
     fn from(e: portaudio_rs::PaError) -> Self {
         BaseError::PaError(e)
     }
 }
 
+#[cfg(feature = "jack")]
+impl From<jack::Error> for BaseError {
+    fn from(e: jack::Error) -> Self {
+        BaseError::StreamError(e.to_string())
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::BuildStreamError> for BaseError {
+    fn from(e: cpal::BuildStreamError) -> Self {
+        BaseError::StreamError(e.to_string())
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::DefaultStreamConfigError> for BaseError {
+    fn from(e: cpal::DefaultStreamConfigError) -> Self {
+        BaseError::StreamError(e.to_string())
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::PlayStreamError> for BaseError {
+    fn from(e: cpal::PlayStreamError) -> Self {
+        BaseError::StreamError(e.to_string())
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl From<cpal::PauseStreamError> for BaseError {
+    fn from(e: cpal::PauseStreamError) -> Self {
+        BaseError::StreamError(e.to_string())
+    }
+}
+
+impl From<midir::InitError> for BaseError {
+    fn from(e: midir::InitError) -> Self {
+        BaseError::MidiError(e.to_string())
+    }
+}
+
+impl<T> From<midir::ConnectError<T>> for BaseError {
+    fn from(e: midir::ConnectError<T>) -> Self {
+        BaseError::MidiError(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for BaseError {
+    fn from(e: std::io::Error) -> Self {
+        BaseError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for BaseError {
+    fn from(e: serde_json::Error) -> Self {
+        BaseError::PresetError(e.to_string())
+    }
+}
+
 // impl From<alsa::Error> for BaseError {
 //     fn from(e: alsa::Error) -> Self {
 //         BaseError::AlsaError(e)
@@ -0,0 +1,334 @@
+use std::fmt;
+
+use druid::{ExtEventSink, Target};
+
+use crate::control::{SynthCommand, SynthControl};
+use crate::error::{BaseError, Result};
+use crate::notification::{report, NotificationSender};
+use crate::synth_ui::PANIC;
+
+// Key of a hotkey, without modifiers
+#[derive(Clone, Copy, PartialEq)]
+enum Key {
+    // Latin letter or digit, letters are uppercase
+    Char(char),
+    // F1-F12
+    Function(u8),
+    Pause,
+    Escape,
+    Space,
+}
+
+// Key combination the system reports to beep-boop whatever window has focus
+#[derive(Clone, Copy)]
+pub struct Hotkey {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    // Windows key, Super or Command
+    logo: bool,
+    key: Key,
+}
+
+impl Hotkey {
+    // Like "Ctrl+Alt+P" or "Shift+F12", case doesn't matter
+    pub fn parse(text: &str) -> Result<Self> {
+        let bad_hotkey = || BaseError::InputError(format!("Bad hotkey {}", text));
+        let mut hotkey = Hotkey { ctrl: false, alt: false, shift: false, logo: false, key: Key::Space };
+        let mut key = None;
+        for part in text.split('+').map(str::trim) {
+            let part_key = match part.to_lowercase().as_str() {
+                "ctrl" | "control" => {
+                    hotkey.ctrl = true;
+                    continue;
+                }
+                "alt" => {
+                    hotkey.alt = true;
+                    continue;
+                }
+                "shift" => {
+                    hotkey.shift = true;
+                    continue;
+                }
+                "super" | "win" | "cmd" => {
+                    hotkey.logo = true;
+                    continue;
+                }
+                "pause" => Key::Pause,
+                "escape" | "esc" => Key::Escape,
+                "space" => Key::Space,
+                name => {
+                    let mut chars = name.chars();
+                    match (chars.next(), chars.as_str()) {
+                        (Some(c), "") if c.is_ascii_alphanumeric() => Key::Char(c.to_ascii_uppercase()),
+                        (Some('f'), number) => match number.parse() {
+                            Ok(n) if (1..=12).contains(&n) => Key::Function(n),
+                            _ => return Err(bad_hotkey()),
+                        },
+                        _ => return Err(bad_hotkey()),
+                    }
+                }
+            };
+            // Only modifiers can be combined, "Ctrl+A+B" has no single key
+            if key.replace(part_key).is_some() {
+                return Err(bad_hotkey());
+            }
+        }
+        hotkey.key = key.ok_or_else(bad_hotkey)?;
+        // A plain key would be taken from every other application
+        if !(hotkey.ctrl || hotkey.alt || hotkey.logo) {
+            return Err(BaseError::InputError(format!("Hotkey {} needs Ctrl, Alt or Super", text)));
+        }
+        Ok(hotkey)
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl+"), (self.alt, "Alt+"), (self.shift, "Shift+"), (self.logo, "Super+")].iter() {
+            if *held {
+                f.write_str(name)?;
+            }
+        }
+        match self.key {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Function(n) => write!(f, "F{}", n),
+            Key::Pause => f.write_str("Pause"),
+            Key::Escape => f.write_str("Escape"),
+            Key::Space => f.write_str("Space"),
+        }
+    }
+}
+
+// Panic hotkey works while another window has focus, e.g. a DAW during a gig.
+// It cuts every sound and mutes the output, the window only shows it
+pub fn listen_panic(text: &str, control: SynthControl<i16>, ui: ExtEventSink, notifications: NotificationSender) -> Result<()> {
+    let hotkey = Hotkey::parse(text)?;
+    let th = std::thread::Builder::new()
+        .name("beep-boop-hotkey".into())
+        .spawn(move || {
+            let result = os::run(&hotkey, &mut || {
+                control.send(SynthCommand::Panic);
+                // Audio is already silent if the window doesn't respond
+                let _ = ui.submit_command(PANIC, (), Target::Auto);
+            });
+            if let Err(e) = result {
+                report(&notifications, BaseError::Context(format!("Can't use panic hotkey {}", hotkey), Box::new(e)));
+            }
+        });
+    match th {
+        Ok(_) => Ok(()),
+        Err(_) => Err(BaseError::ThreadError("Can't start hotkey thread".into())),
+    }
+}
+
+#[cfg(windows)]
+mod os {
+    use std::os::raw::{c_int, c_uint, c_void};
+    use std::ptr::null_mut;
+
+    use super::{Hotkey, Key};
+    use crate::error::{BaseError, Result};
+
+    const MOD_ALT: c_uint = 0x1;
+    const MOD_CONTROL: c_uint = 0x2;
+    const MOD_SHIFT: c_uint = 0x4;
+    const MOD_WIN: c_uint = 0x8;
+    // Holding the keys down doesn't repeat the hotkey
+    const MOD_NOREPEAT: c_uint = 0x4000;
+    const VK_PAUSE: c_uint = 0x13;
+    const VK_ESCAPE: c_uint = 0x1b;
+    const VK_SPACE: c_uint = 0x20;
+    const VK_F1: c_uint = 0x70;
+    const WM_HOTKEY: c_uint = 0x0312;
+    const HOTKEY_ID: c_int = 1;
+
+    #[repr(C)]
+    struct Msg {
+        hwnd: *mut c_void,
+        message: c_uint,
+        wparam: usize,
+        lparam: isize,
+        time: u32,
+        pt_x: i32,
+        pt_y: i32,
+        private: u32,
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn RegisterHotKey(hwnd: *mut c_void, id: c_int, modifiers: c_uint, vk: c_uint) -> c_int;
+        fn GetMessageW(msg: *mut Msg, hwnd: *mut c_void, filter_min: c_uint, filter_max: c_uint) -> c_int;
+    }
+
+    // Hotkey messages go to the thread that registered it, so it waits for them here
+    pub fn run(hotkey: &Hotkey, on_press: &mut dyn FnMut()) -> Result<()> {
+        let mut modifiers = MOD_NOREPEAT;
+        for (held, modifier) in [(hotkey.ctrl, MOD_CONTROL), (hotkey.alt, MOD_ALT), (hotkey.shift, MOD_SHIFT), (hotkey.logo, MOD_WIN)].iter() {
+            if *held {
+                modifiers |= modifier;
+            }
+        }
+        // Virtual key codes of letters and digits are their ASCII codes
+        let vk = match hotkey.key {
+            Key::Char(c) => c as c_uint,
+            Key::Function(n) => VK_F1 + n as c_uint - 1,
+            Key::Pause => VK_PAUSE,
+            Key::Escape => VK_ESCAPE,
+            Key::Space => VK_SPACE,
+        };
+        if unsafe { RegisterHotKey(null_mut(), HOTKEY_ID, modifiers, vk) } == 0 {
+            return Err(BaseError::GUIError("it's taken by another application".into()));
+        }
+        let mut msg = Msg { hwnd: null_mut(), message: 0, wparam: 0, lparam: 0, time: 0, pt_x: 0, pt_y: 0, private: 0 };
+        while unsafe { GetMessageW(&mut msg, null_mut(), 0, 0) } > 0 {
+            if msg.message == WM_HOTKEY {
+                on_press();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod os {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+    use std::ptr::null;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{Hotkey, Key};
+    use crate::error::{BaseError, Result};
+
+    type Display = c_void;
+    type Window = c_ulong;
+    type KeySym = c_ulong;
+    type ErrorHandler = Option<unsafe extern "C" fn(*mut Display, *mut c_void) -> c_int>;
+
+    const SHIFT_MASK: c_uint = 1 << 0;
+    const LOCK_MASK: c_uint = 1 << 1;
+    const CONTROL_MASK: c_uint = 1 << 2;
+    // Alt, Num Lock and Super on the usual keymaps
+    const MOD1_MASK: c_uint = 1 << 3;
+    const MOD2_MASK: c_uint = 1 << 4;
+    const MOD4_MASK: c_uint = 1 << 6;
+    const GRAB_MODE_ASYNC: c_int = 1;
+    const KEY_PRESS: c_int = 2;
+    const NO_SYMBOL: KeySym = 0;
+
+    // XEvent is a union padded to 24 longs, only its type is read
+    #[repr(C)]
+    struct XEvent {
+        kind: c_int,
+        pad: [c_long; 24],
+    }
+
+    #[link(name = "X11")]
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XStringToKeysym(name: *const c_char) -> KeySym;
+        fn XKeysymToKeycode(display: *mut Display, keysym: KeySym) -> u8;
+        fn XGrabKey(
+            display: *mut Display, keycode: c_int, modifiers: c_uint, grab_window: Window,
+            owner_events: c_int, pointer_mode: c_int, keyboard_mode: c_int,
+        ) -> c_int;
+        fn XSync(display: *mut Display, discard: c_int) -> c_int;
+        fn XNextEvent(display: *mut Display, event: *mut XEvent) -> c_int;
+        fn XSetErrorHandler(handler: ErrorHandler) -> ErrorHandler;
+    }
+
+    // Default X error handler quits the whole app when the key is grabbed by someone else
+    static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" fn grab_error(_display: *mut Display, _event: *mut c_void) -> c_int {
+        GRAB_FAILED.store(true, Ordering::SeqCst);
+        0
+    }
+
+    pub fn run(hotkey: &Hotkey, on_press: &mut dyn FnMut()) -> Result<()> {
+        // The window has its own connection, this one only waits for the hotkey
+        let display = unsafe { XOpenDisplay(null()) };
+        if display.is_null() {
+            return Err(BaseError::GUIError("no X11 display, global hotkeys need X11".into()));
+        }
+        let mut modifiers = 0;
+        for (held, mask) in [(hotkey.ctrl, CONTROL_MASK), (hotkey.alt, MOD1_MASK), (hotkey.shift, SHIFT_MASK), (hotkey.logo, MOD4_MASK)].iter() {
+            if *held {
+                modifiers |= mask;
+            }
+        }
+        let name = match hotkey.key {
+            Key::Char(c) => c.to_ascii_lowercase().to_string(),
+            Key::Function(n) => format!("F{}", n),
+            Key::Pause => "Pause".into(),
+            Key::Escape => "Escape".into(),
+            Key::Space => "space".into(),
+        };
+        let name = CString::new(name).map_err(|_| BaseError::GUIError("bad key name".into()))?;
+        let keysym = unsafe { XStringToKeysym(name.as_ptr()) };
+        let keycode = if keysym == NO_SYMBOL { 0 } else { unsafe { XKeysymToKeycode(display, keysym) } };
+        if keycode == 0 {
+            return Err(BaseError::GUIError("the key isn't on this keyboard".into()));
+        }
+        unsafe {
+            let root = XDefaultRootWindow(display);
+            let previous = XSetErrorHandler(Some(grab_error));
+            // Caps Lock and Num Lock are modifiers too, the hotkey works whatever their state
+            for locks in [0, LOCK_MASK, MOD2_MASK, LOCK_MASK | MOD2_MASK].iter() {
+                XGrabKey(display, keycode as c_int, modifiers | locks, root, 0, GRAB_MODE_ASYNC, GRAB_MODE_ASYNC);
+            }
+            XSync(display, 0);
+            XSetErrorHandler(previous);
+        }
+        if GRAB_FAILED.load(Ordering::SeqCst) {
+            return Err(BaseError::GUIError("it's taken by another application".into()));
+        }
+        let mut event = XEvent { kind: 0, pad: [0; 24] };
+        loop {
+            unsafe { XNextEvent(display, &mut event) };
+            if event.kind == KEY_PRESS {
+                on_press();
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+mod os {
+    use super::Hotkey;
+    use crate::error::{BaseError, Result};
+
+    pub fn run(_hotkey: &Hotkey, _on_press: &mut dyn FnMut()) -> Result<()> {
+        Err(BaseError::GUIError("global hotkeys aren't supported on this system".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkey_is_parsed_whatever_the_case_and_spaces() {
+        assert_eq!(Hotkey::parse("ctrl + alt + p").unwrap().to_string(), "Ctrl+Alt+P");
+        assert_eq!(Hotkey::parse("Shift+Win+f12").unwrap().to_string(), "Shift+Super+F12");
+        assert_eq!(Hotkey::parse("Control+Esc").unwrap().to_string(), "Ctrl+Escape");
+        assert_eq!(Hotkey::parse("Alt+7").unwrap().to_string(), "Alt+7");
+    }
+
+    #[test]
+    fn bad_hotkeys_are_rejected() {
+        let bad = ["P", "Shift+Space", "Ctrl+F0", "Ctrl+F13", "Ctrl+Fx", "Ctrl+A+B", "Ctrl+Pause+Space", "Ctrl", "Ctrl+Tab", "Ctrl+"];
+        for text in bad.iter() {
+            assert!(Hotkey::parse(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn displayed_hotkey_parses_back() {
+        for text in ["Ctrl+Alt+P", "Ctrl+Shift+F1", "Alt+Super+Pause", "Super+Space", "Ctrl+Escape"].iter() {
+            let hotkey = Hotkey::parse(text).unwrap();
+            assert_eq!(Hotkey::parse(&hotkey.to_string()).unwrap().to_string(), *text);
+        }
+    }
+}
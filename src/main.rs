@@ -1,131 +1,250 @@
+mod backend;
+mod control;
+mod crash;
+mod duet;
 mod error;
+mod hotkey;
+mod midi;
+mod notification;
+pub mod params;
+mod render;
+mod settings;
 mod synth;
 mod synth_ui;
-/// TODO: Callback, Github, Panning, Filter
-use error::{BaseError, Result};
-use synth::{SampleFormat, Synth};
+mod wav;
+mod zip;
+/// TODO: Callback, Github
+use backend::{OutputCallback, OutputStream, BACKENDS};
+use control::{SynthCommand, SynthControl, SynthEngine};
+use duet::DuetSender;
+use error::{BaseError, Context, Result};
+use notification::{notify, report, Notification, NotificationSender};
+use midi::MidiListener;
+use settings::Settings;
+use synth::Synth;
 
 use druid::{AppLauncher, WindowDesc};
 use std::sync::{mpsc, Arc, Mutex};
-use synth_ui::{build_ui, SynthUIData, SynthUIEvent};
-
-use portaudio_rs as pa;
-
-const SAMPLE_RATE: f32 = 44100.0;
-const CHANNELS_NUM: usize = 2;
-const BUF_SIZE: u32 = 600;
-
-fn create_output_stream<SF>(
-    sample_rate: f32,
-    buf_size: u32,
-    channels_num: u32,
-    callback: Option<Box<pa::stream::StreamCallback<'static, SF, SF>>>
-) -> Result<pa::stream::Stream<'_, SF, SF>>
-where
-    SF: SampleFormat,
-{
-    let default_output = match pa::device::get_default_output_index() {
-        Some(dev) => dev,
-        None => {
-            return Err(BaseError::StreamError(
-                "Can't open default device".into(),
-            ))
+use params::{set_taper, SAMPLE_RATES, DEFAULT_SAMPLE_RATE};
+use std::time::Duration;
+use synth_ui::{build_ui, show_notifications, tr, trf, SynthUIData, SynthUIEvent};
+
+// How often playing stream is checked for a lost device
+const STREAM_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+fn output_callback(
+    engine: Arc<Mutex<SynthEngine<i16>>>,
+    stream_finished: mpsc::Sender<()>,
+) -> Box<OutputCallback> {
+    Box::new(move |output: &mut [i16]| -> bool {
+        // Only the current stream's callback takes the engine, so it never waits here
+        let mut engine = match engine.try_lock() {
+            Ok(engine) => engine,
+            Err(_) => {
+                output.iter_mut().for_each(|sample| *sample = 0);
+                return true
+            }
+        };
+        if !engine.process(output) {
+            stream_finished.send(()).unwrap();
+            return false
         }
-    };
+        true
+    })
+}
 
-    let latency = match pa::device::get_info(default_output) {
-        Some(info) => info.default_low_output_latency,
-        None => return Err(BaseError::StreamError("Can't get latency info".to_owned())),
-    };
+// Value of an option like --backend <name>
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next()
+        }
+    }
+    None
+}
 
-    let output_params = pa::stream::StreamParameters::<SF> {
-        device: default_output,
-        channel_count: channels_num,
-        suggested_latency: latency,
-        data: SF::min_value(),
-    };
+// Audio callback only counts stuck voices it released, they are reported from here
+fn report_stuck_notes(control: &SynthControl<i16>, notifications: &NotificationSender) {
+    let released = control.take_stuck_released();
+    if released > 0 {
+        eprintln!("Released {} MIDI and remote voices held longer than the stuck note timeout", released);
+        notify(notifications, trf("Released {} stuck notes", &[&released]));
+    }
+}
 
-    let _supported =
-        pa::stream::is_format_supported::<SF, SF>(None, Some(output_params), sample_rate as f64)?;
-
-    let stream = match pa::stream::Stream::<SF, SF>::open(
-        None,
-        Some(output_params),
-        sample_rate as f64,
-        buf_size as u64,
-        pa::stream::StreamFlags::empty(),
-        callback,
-    ) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error opening stream: {}", e);
-            return Err(BaseError::PaError(e));
+// Errors the audio thread can recover from are reported instead of stopping it
+fn play_notes(
+    stream: &mut dyn OutputStream,
+    stream_finished: &mpsc::Receiver<()>,
+    control: &SynthControl<i16>,
+    notifications: &NotificationSender,
+) -> Result<()> {
+    stream.start()?;
+    // Callback isn't called anymore if device is gone, so stream is checked from time to time
+    loop {
+        report_stuck_notes(control, notifications);
+        match stream_finished.recv_timeout(STREAM_CHECK_INTERVAL) {
+            Ok(()) => break,
+            Err(_) if stream.lost() => return Err(BaseError::StreamError("Output device is lost".into())),
+            Err(_) => {}
         }
-    };
+    }
+    report_stuck_notes(control, notifications);
+    stream.stop()
+}
 
-    Ok(stream)
+// Some backends (JACK) run at their own rate, synth has to follow the stream
+fn adopt_sample_rate(stream: &dyn OutputStream, control: &SynthControl<i16>, current: f32) -> f32 {
+    let sample_rate = stream.sample_rate();
+    if sample_rate != current {
+        control.send(SynthCommand::SampleRate(sample_rate));
+    }
+    sample_rate
 }
 
 fn main() -> Result<()> {
-    let mut synth = Synth::<i16>::new(SAMPLE_RATE);
+    // Panics of any thread leave a report with the engine state behind
+    crash::install_hook();
+
+    // Rendering from command line doesn't open any window or audio device
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        return render::run(&args[1..]);
+    }
+
+    let backend_name = arg_value("--backend");
+    if let Some(name) = &backend_name {
+        if !BACKENDS.contains(&backend::split_name(name).0) {
+            return Err(BaseError::InputError(format!(
+                "Unknown audio backend {}, available: {}", name, BACKENDS.join(", ")
+            )));
+        }
+    }
+
+    // Copies started side by side get their own port names and settings file
+    if let Some(instance) = arg_value("--instance") {
+        match instance.parse() {
+            Ok(instance) if instance >= 1 && instance <= settings::MAX_INSTANCE => settings::set_instance(instance),
+            _ => return Err(BaseError::InputError(format!(
+                "Bad instance {}, it's a number from 1 to {}", instance, settings::MAX_INSTANCE
+            ))),
+        }
+    }
+
+    let (notification_sender, notification_receiver): (NotificationSender, mpsc::Receiver<Notification>) = mpsc::channel();
+
+    // Starts with default settings, no MIDI mappings and no demo patch, whatever is saved
+    if std::env::args().any(|arg| arg == "--safe-mode") {
+        settings::enter_safe_mode();
+        notify(&notification_sender, tr("Safe mode: settings aren't loaded or saved"));
+    }
+
+    // Demo patch and tips are shown only once
+    let mut settings = Settings::load();
+    crash::publish_settings(&settings);
+    let first_run = !settings.onboarded && !settings::safe_mode();
+    if first_run {
+        settings.onboarded = true;
+        if let Err(e) = settings.save().context("Can't save settings") {
+            report(&notification_sender, e);
+        }
+    }
+    synth_ui::set_language(&settings.language);
+    synth_ui::set_theme(&settings.theme);
+    for slider_taper in settings.slider_tapers.iter() {
+        set_taper(&slider_taper.parameter, slider_taper.taper);
+    }
+    let sample_rate = if SAMPLE_RATES.contains(&settings.sample_rate) {
+        settings.sample_rate as f32
+    } else {
+        report(&notification_sender, BaseError::StreamError(format!(
+            "Unsupported sample rate {} Hz, using {} Hz", settings.sample_rate, DEFAULT_SAMPLE_RATE
+        )));
+        DEFAULT_SAMPLE_RATE as f32
+    };
+
+    let mut synth = Synth::<i16>::new(sample_rate);
     synth.set_volume(-36)?;
-    let synth_arc = Arc::new(Mutex::new(synth));
+    synth.set_stuck_note_timeout(settings.stuck_note_timeout);
+    synth.set_high_quality(settings.high_quality);
+    synth.set_dc_blocker(settings.dc_blocker);
+    synth.set_binaural(settings.binaural);
+    synth.set_bend_range(settings.bend_range as f32);
 
     let (synth_event, wait_synth_event): (mpsc::Sender<SynthUIEvent>, mpsc::Receiver<SynthUIEvent>) = mpsc::channel();
+    let (engine, mut control) = SynthEngine::new(synth, synth_event.clone());
+    let engine = Arc::new(Mutex::new(engine));
 
-    let synth_in_thread = Arc::clone(&synth_arc);
+    // Duet: one instance plays what another one sends over the network
+    if let Some(port) = arg_value("--duet-listen") {
+        let port = port.parse().map_err(|_| BaseError::InputError(format!("Bad duet port {}", port)))?;
+        duet::listen(port, control.clone())?;
+    }
+    if let Some(address) = arg_value("--duet-send") {
+        control.forward_notes(DuetSender::connect(&address)?);
+    }
+
+    let mut midi = MidiListener::new(control.clone());
+    let midi_ports = midi.port_names().to_vec();
+
+    let control_in_thread = control.clone();
+    let notification_in_thread = notification_sender.clone();
     let th = std::thread::Builder::new()
         .name("beep-boop-synth".into())
         .spawn(move || -> Result<()> {
-            pa::initialize()?;
-            let synth = synth_in_thread;
-            let synth_callback = Arc::clone(&synth);
+            let backend = backend::create(backend_name.as_deref())?;
+            let control = control_in_thread;
             let (stream_finished, wait_stream_finished): (mpsc::Sender<()>, mpsc::Receiver<()>) = mpsc::channel();
-            let callback = Box::new(
-                move |
-                    _input: &[i16],
-                    output: &mut [i16],
-                    _time: pa::stream::StreamTimeInfo,
-                    _flags: pa::stream::StreamCallbackFlags| -> pa::stream::StreamCallbackResult
-                    {
-                        let mut synth = synth_callback.lock().unwrap();
-                        if !synth.playing() {
-                            stream_finished.send(()).unwrap();
-                            return pa::stream::StreamCallbackResult::Complete
-                        }
-                        let mut sample = synth.next().unwrap();
-                        let mut written = 0;
-                        for i in 0..output.len() {
-                            output[i] = sample;
-                            written += 1;
-                            if written == CHANNELS_NUM {
-                                sample = synth.next().unwrap();
-                                written = 0;
-                            }
-                        }
-                        pa::stream::StreamCallbackResult::Continue
-                    }
-            );
-            let stream = create_output_stream::<i16>(SAMPLE_RATE, BUF_SIZE, CHANNELS_NUM as u32, Some(callback))?;
+            let mut sample_rate = sample_rate;
+            let callback = output_callback(Arc::clone(&engine), stream_finished.clone());
+            let mut stream = backend.open_stream(sample_rate, callback)?;
+            sample_rate = adopt_sample_rate(stream.as_ref(), &control, sample_rate);
 
             'synthloop: loop {
                 match wait_synth_event.recv() {
                     Ok(SynthUIEvent::NewNotes) => {
-                        if !stream.is_active()? {
-                            stream.start()?
-                        }
-                        wait_stream_finished.recv().unwrap();
-                        if stream.is_active()? {
-                            stream.stop()?
+                        if let Err(e) = play_notes(stream.as_mut(), &wait_stream_finished, &control, &notification_in_thread).context("Can't play notes") {
+                            report(&notification_in_thread, e);
+                            // Device could be unplugged, new stream is opened on the current default one
+                            drop(stream);
+                            let callback = output_callback(Arc::clone(&engine), stream_finished.clone());
+                            stream = backend.open_stream(sample_rate, callback)?;
+                            sample_rate = adopt_sample_rate(stream.as_ref(), &control, sample_rate);
+                            notify(&notification_in_thread, tr("Audio device lost, switched to default output"));
+                            if control.playing() {
+                                if let Err(e) = play_notes(stream.as_mut(), &wait_stream_finished, &control, &notification_in_thread).context("Can't play notes") {
+                                    report(&notification_in_thread, e);
+                                }
+                            }
                         }
                     },
+                    // Handled once notes have finished, so the stream is stopped here
+                    Ok(SynthUIEvent::SampleRate(new_rate)) => {
+                        // Old stream is closed first, device may not allow two of them
+                        drop(stream);
+                        let callback = output_callback(Arc::clone(&engine), stream_finished.clone());
+                        stream = match backend.open_stream(new_rate, callback) {
+                            Ok(new_stream) => {
+                                sample_rate = adopt_sample_rate(new_stream.as_ref(), &control, sample_rate);
+                                new_stream
+                            }
+                            Err(e) => {
+                                report(&notification_in_thread, BaseError::Context(
+                                    format!("Can't switch output to {} Hz", new_rate), Box::new(e)
+                                ));
+                                let callback = output_callback(Arc::clone(&engine), stream_finished.clone());
+                                backend.open_stream(sample_rate, callback)?
+                            }
+                        };
+                    },
                     Ok(SynthUIEvent::WindowClosed) | Err(_) => {
                         break 'synthloop
                     },
                 }
             }
+            // Backend may need its streams closed before it's dropped
             drop(stream);
-            pa::terminate()?;
             Ok(())
         });
 
@@ -135,15 +254,34 @@ fn main() -> Result<()> {
     };
 
     {
-        let window = WindowDesc::new(build_ui)
-            .title("beep-boop")
-            .with_min_size((860.0, 550.0))
+        let window = WindowDesc::new(move || build_ui(midi_ports))
+            .title(settings::instance_name("beep-boop"))
+            .with_min_size((1320.0, 1090.0))
             .resizable(false);
         let launcher = AppLauncher::with_window(window);
 
+        // Controller moves reach the window too, so MIDI learn can bind them
+        midi.forward_controls(launcher.get_external_handle());
+        if !settings.panic_hotkey.is_empty() {
+            let hotkey = hotkey::listen_panic(
+                &settings.panic_hotkey, control.clone(), launcher.get_external_handle(), notification_sender.clone(),
+            );
+            if let Err(e) = hotkey.context("Can't set panic hotkey") {
+                report(&notification_sender, e);
+            }
+        }
+        let sink = launcher.get_external_handle();
+        let notifications_th = std::thread::Builder::new()
+            .name("beep-boop-notifications".into())
+            .spawn(move || show_notifications(notification_receiver, sink));
+        if notifications_th.is_err() {
+            return Err(BaseError::ThreadError("Can't start notifications thread".into()));
+        }
+
         launcher
+            .configure_env(|env, _| synth_ui::configure_env(env))
             .delegate(synth_ui::Delegate)
-            .launch(SynthUIData::new(synth_arc, synth_event, SAMPLE_RATE))
+            .launch(SynthUIData::new(control, synth_event, notification_sender, midi, sample_rate, &settings, first_run))
             .expect("Starting beep-boop GUI failed :(");
     }
 
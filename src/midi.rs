@@ -0,0 +1,159 @@
+use druid::{ExtEventSink, Target};
+use midir::{MidiInput, MidiInputConnection};
+
+use crate::control::{SynthCommand, SynthControl};
+use crate::error::{BaseError, Result};
+use crate::settings::instance_name;
+use crate::synth::NoteSource;
+use crate::synth_ui::MIDI_CC;
+
+// Suffixed with instance index, so every running copy has its own port
+const CLIENT_NAME: &str = "beep-boop";
+const PORT_NAME: &str = "beep-boop-in";
+
+// Status bytes without channel nibble
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const POLY_AFTERTOUCH: u8 = 0xa0;
+const CONTROL_CHANGE: u8 = 0xb0;
+const PITCH_BEND: u8 = 0xe0;
+// 14-bit pitch bend value of the centered wheel
+const PITCH_BEND_CENTER: f32 = 8192.0;
+const MOD_WHEEL_CC: u8 = 1;
+const EXPRESSION_CC: u8 = 11;
+const SUSTAIN_CC: u8 = 64;
+// Switch controllers are on from this value up
+const SWITCH_ON: u8 = 64;
+const ALL_SOUND_OFF_CC: u8 = 120;
+const ALL_NOTES_OFF_CC: u8 = 123;
+
+pub fn note_frequency(note: u8) -> f32 {
+    // A4 is MIDI note 69
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+fn list_ports() -> Result<Vec<String>> {
+    let input = MidiInput::new(&instance_name(CLIENT_NAME))?;
+    input
+        .ports()
+        .iter()
+        .map(|port| {
+            input
+                .port_name(port)
+                .map_err(|e| BaseError::MidiError(e.to_string()))
+        })
+        .collect()
+}
+
+fn handle_message(message: &[u8], control: &SynthControl<i16>, ui: Option<&ExtEventSink>) {
+    if message.len() < 3 {
+        return;
+    }
+    let (status, note, velocity) = (message[0] & 0xf0, message[1], message[2]);
+    // Every controller goes to the window for MIDI learn, fixed ones below still work as usual
+    if let (CONTROL_CHANGE, Some(ui)) = (status, ui) {
+        if ui.submit_command(MIDI_CC, (note, velocity), Target::Auto).is_err() {
+            eprintln!("Can't pass MIDI controller to the window");
+        }
+    }
+    match status {
+        // Note on with zero velocity is the same as note off
+        NOTE_ON if velocity > 0 => {
+            control.note_on(note_frequency(note), velocity as f32 / 127.0, NoteSource::Midi(note));
+        }
+        NOTE_ON | NOTE_OFF => {
+            control.note_off(NoteSource::Midi(note));
+        }
+        // Pressure of a single held key comes in the velocity byte
+        POLY_AFTERTOUCH => {
+            control.send(SynthCommand::Aftertouch(NoteSource::Midi(note), velocity as f32 / 127.0));
+        }
+        // Bend value is split into 7-bit halves, the least significant one comes first
+        PITCH_BEND => {
+            let value = ((velocity as u16) << 7 | note as u16) as f32;
+            control.send(SynthCommand::PitchBend((value - PITCH_BEND_CENTER) / PITCH_BEND_CENTER));
+        }
+        // For controller messages note byte is controller number and velocity is its value
+        CONTROL_CHANGE if note == MOD_WHEEL_CC => {
+            control.send(SynthCommand::ModWheel(velocity as f32 / 127.0));
+        }
+        CONTROL_CHANGE if note == EXPRESSION_CC => {
+            control.send(SynthCommand::Expression(velocity as f32 / 127.0));
+        }
+        CONTROL_CHANGE if note == SUSTAIN_CC => {
+            control.send(SynthCommand::Sustain(velocity >= SWITCH_ON));
+        }
+        CONTROL_CHANGE if note == ALL_SOUND_OFF_CC => {
+            control.send(SynthCommand::AllSoundOff);
+        }
+        CONTROL_CHANGE if note == ALL_NOTES_OFF_CC => {
+            control.send(SynthCommand::AllNotesOff);
+        }
+        _ => {}
+    }
+}
+
+pub struct MidiListener {
+    port_names: Vec<String>,
+    connection: Option<MidiInputConnection<()>>,
+    control: SynthControl<i16>,
+    ui: Option<ExtEventSink>,
+}
+
+impl MidiListener {
+    pub fn new(control: SynthControl<i16>) -> Self {
+        let port_names = list_ports().unwrap_or_else(|e| {
+            eprintln!("Can't list MIDI ports: {}", e);
+            Vec::new()
+        });
+        Self {
+            port_names,
+            connection: None,
+            control,
+            ui: None,
+        }
+    }
+
+    // CC messages of connections made from now on are passed to the window
+    pub fn forward_controls(&mut self, ui: ExtEventSink) {
+        self.ui = Some(ui);
+    }
+
+    pub fn port_names(&self) -> &[String] {
+        &self.port_names
+    }
+
+    pub fn connect(&mut self, port_idx: usize) -> Result<()> {
+        self.disconnect();
+        let name = match self.port_names.get(port_idx) {
+            Some(name) => name,
+            None => return Err(BaseError::MidiError(format!("No MIDI port with index {}", port_idx))),
+        };
+        let input = MidiInput::new(&instance_name(CLIENT_NAME))?;
+        // Ports could change since listing, so look for the port by name
+        let port = match input
+            .ports()
+            .into_iter()
+            .find(|p| input.port_name(p).map(|n| n == *name).unwrap_or(false))
+        {
+            Some(port) => port,
+            None => return Err(BaseError::MidiError(format!("MIDI port {} is gone", name))),
+        };
+        let control = self.control.clone();
+        let ui = self.ui.clone();
+        let connection = input.connect(
+            &port,
+            &instance_name(PORT_NAME),
+            move |_stamp, message, _| handle_message(message, &control, ui.as_ref()),
+            (),
+        )?;
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::sync::mpsc;
+
+use crate::error::BaseError;
+
+pub enum Notification {
+    Info(String),
+    Error(String),
+}
+
+// Any thread can send notifications, UI shows them as toasts
+pub type NotificationSender = mpsc::Sender<Notification>;
+
+pub fn notify<S: Into<String>>(sender: &NotificationSender, message: S) {
+    let _ = sender.send(Notification::Info(message.into()));
+}
+
+// Recoverable errors are reported instead of stopping a thread.
+// Nobody may be listening anymore while the app is closing, so the error is always logged too
+pub fn report(sender: &NotificationSender, e: BaseError) {
+    eprintln!("{}", e);
+    let _ = sender.send(Notification::Error(e.to_string()));
+}
@@ -0,0 +1,702 @@
+// Parameter registry shared by UI and anything else that has to
+// map parameter values and names: choice tables, defaults and scales
+use std::sync::Mutex;
+
+use crate::synth::{adsr_constraints, arp_constraints, chaos_constraints, clock_constraints, pluck_constraints, seq_constraints, ArpMode, OscEngine, StepDivision, WaveForm, FilterMode, GlideCurve, ExpressionCurve, ExpressionDestination, LfoTarget, ModSource, ModDestination, Start, VoiceStealing, osc_constraints};
+
+pub const LOG_SCALE_BASE: f64 = 2.;
+pub const MAX_UNISONS: f64 = osc_constraints::MAX_UNISONS as f64;
+// Hz, output can run at any of these
+pub const SAMPLE_RATES: [u32; 4] = [44100, 48000, 88200, 96000];
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+// Hz, test tone plays one of these
+pub const TEST_TONE_FREQUENCIES: [f32; 4] = [100.0, 440.0, 1000.0, 10000.0];
+pub const DEFAULT_TEST_TONE_IDX: f64 = 2.0;
+
+// Curve of a log scale slider: at 1.0 the log value follows the slider evenly, above it
+// short times and low rates get more of the travel, below it long and high ones do
+pub const DEFAULT_TAPER: f64 = 1.0;
+pub const MIN_TAPER: f64 = 0.25;
+pub const MAX_TAPER: f64 = 4.0;
+// Set from settings on startup, layout is built once like with the language
+static TAPERS: Mutex<Vec<(DefaultParameter, f64)>> = Mutex::new(Vec::new());
+
+pub fn slider_log(x: f32) -> f64 {
+    f64::log2(x as f64)
+}
+
+// Attack in ms from its log scale slider. Short attacks are kept
+// to a tenth of ms, so sub-millisecond snaps are still reachable
+pub fn attack_ms(slider: f64) -> f32 {
+    let ms = LOG_SCALE_BASE.powf(slider);
+    if ms < 10.0 {
+        ((ms * 10.0).round() / 10.0) as f32
+    } else {
+        ms.round() as f32
+    }
+}
+
+// Part of the range from slider position, both are 0.0-1.0
+pub fn taper_value(position: f64, taper: f64) -> f64 {
+    position.max(0.0).min(1.0).powf(taper)
+}
+
+pub fn taper_position(value: f64, taper: f64) -> f64 {
+    value.max(0.0).min(1.0).powf(1.0 / taper)
+}
+
+pub fn set_taper(name: &str, taper: f64) {
+    match TAPERED.iter().find(|parameter| parameter.taper_name() == Some(name)) {
+        Some(parameter) => {
+            let mut tapers = TAPERS.lock().unwrap();
+            tapers.retain(|(tapered, _)| tapered != parameter);
+            tapers.push((*parameter, taper.max(MIN_TAPER).min(MAX_TAPER)));
+        }
+        None => eprintln!("Unknown slider taper {}, log scale sliders are {}", name,
+            TAPERED.iter().filter_map(|parameter| parameter.taper_name()).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+// Semitones of a sequencer step from its bar level
+pub fn seq_pitch(level: f64) -> i32 {
+    let range = (seq_constraints::MAX_PITCH - seq_constraints::MIN_PITCH) as f64;
+    seq_constraints::MIN_PITCH + (level * range).round() as i32
+}
+
+#[derive(Clone)]
+pub struct WaveFormUI {
+    pub name: &'static str,
+    pub waveform: WaveForm,
+}
+
+#[derive(Clone)]
+pub struct OscEngineUI {
+    pub name: &'static str,
+    pub engine: OscEngine,
+}
+
+#[derive(Clone)]
+pub struct StartUI {
+    pub name: &'static str,
+    pub start: Start,
+}
+
+#[derive(Clone)]
+pub struct FilterModeUI {
+    pub name: &'static str,
+    pub mode: FilterMode,
+}
+
+#[derive(Clone)]
+pub struct GlideCurveUI {
+    pub name: &'static str,
+    pub curve: GlideCurve,
+}
+
+#[derive(Clone)]
+pub struct VoiceStealingUI {
+    pub name: &'static str,
+    pub stealing: VoiceStealing,
+}
+
+#[derive(Clone)]
+pub struct ExpressionDestinationUI {
+    pub name: &'static str,
+    pub destination: ExpressionDestination,
+}
+
+#[derive(Clone)]
+pub struct ExpressionCurveUI {
+    pub name: &'static str,
+    pub curve: ExpressionCurve,
+}
+
+#[derive(Clone)]
+pub struct ArpModeUI {
+    pub name: &'static str,
+    pub mode: ArpMode,
+}
+
+#[derive(Clone)]
+pub struct StepDivisionUI {
+    pub name: &'static str,
+    pub division: StepDivision,
+}
+
+#[derive(Clone)]
+pub struct LfoTargetUI {
+    pub name: &'static str,
+    pub target: LfoTarget,
+}
+
+#[derive(Clone)]
+pub struct ModSourceUI {
+    pub name: &'static str,
+    pub source: ModSource,
+}
+
+#[derive(Clone)]
+pub struct ModDestinationUI {
+    pub name: &'static str,
+    pub destination: ModDestination,
+}
+
+
+pub const WAVEFORMS: [WaveFormUI; 8] = [
+    WaveFormUI {
+        name: "Saw",
+        waveform: WaveForm::Saw,
+    },
+    WaveFormUI {
+        name: "Sine",
+        waveform: WaveForm::Sine,
+    },
+    WaveFormUI {
+        name: "Square",
+        waveform: WaveForm::Square,
+    },
+    WaveFormUI {
+        name: "Pulse",
+        waveform: WaveForm::Pulse,
+    },
+    WaveFormUI {
+        name: "Triangle",
+        waveform: WaveForm::Triangle,
+    },
+    WaveFormUI {
+        name: "White noise",
+        waveform: WaveForm::NoiseWhite,
+    },
+    WaveFormUI {
+        name: "Pink noise",
+        waveform: WaveForm::NoisePink,
+    },
+    WaveFormUI {
+        name: "Additive",
+        waveform: WaveForm::Additive,
+    },
+];
+
+// Sub-oscillator keeps to the waves which sound good an octave down
+pub const SUB_WAVEFORMS: [WaveFormUI; 2] = [
+    WaveFormUI {
+        name: "Sine",
+        waveform: WaveForm::Sine,
+    },
+    WaveFormUI {
+        name: "Square",
+        waveform: WaveForm::Square,
+    },
+];
+
+pub const OSC_ENGINES: [OscEngineUI; 2] = [
+    OscEngineUI {
+        name: "Wave",
+        engine: OscEngine::Wave,
+    },
+    OscEngineUI {
+        name: "Pluck",
+        engine: OscEngine::Pluck,
+    },
+];
+
+pub const PHASE_STARTS: [StartUI; 3] = [
+    StartUI {
+        name: "Soft",
+        start: Start::Soft,
+    },
+    StartUI {
+        name: "Hard",
+        start: Start::Hard,
+    },
+    StartUI {
+        name: "Random",
+        start: Start::Random,
+    },
+];
+
+pub const FILTER_MODES: [FilterModeUI; 4] = [
+    FilterModeUI {
+        name: "Low-pass",
+        mode: FilterMode::LowPass,
+    },
+    FilterModeUI {
+        name: "High-pass",
+        mode: FilterMode::HighPass,
+    },
+    FilterModeUI {
+        name: "Band-pass",
+        mode: FilterMode::BandPass,
+    },
+    FilterModeUI {
+        name: "Notch",
+        mode: FilterMode::Notch,
+    },
+];
+
+pub const GLIDE_CURVES: [GlideCurveUI; 2] = [
+    GlideCurveUI {
+        name: "Linear",
+        curve: GlideCurve::Linear,
+    },
+    GlideCurveUI {
+        name: "Exponential",
+        curve: GlideCurve::Exponential,
+    },
+];
+
+pub const VOICE_STEALING: [VoiceStealingUI; 3] = [
+    VoiceStealingUI {
+        name: "Released first",
+        stealing: VoiceStealing::ReleasedFirst,
+    },
+    VoiceStealingUI {
+        name: "Oldest",
+        stealing: VoiceStealing::Oldest,
+    },
+    VoiceStealingUI {
+        name: "Quietest",
+        stealing: VoiceStealing::Quietest,
+    },
+];
+
+pub const EXPRESSION_DESTINATIONS: [ExpressionDestinationUI; 2] = [
+    ExpressionDestinationUI {
+        name: "Volume",
+        destination: ExpressionDestination::Volume,
+    },
+    ExpressionDestinationUI {
+        name: "Mod matrix",
+        destination: ExpressionDestination::ModMatrix,
+    },
+];
+
+pub const EXPRESSION_CURVES: [ExpressionCurveUI; 3] = [
+    ExpressionCurveUI {
+        name: "Linear",
+        curve: ExpressionCurve::Linear,
+    },
+    ExpressionCurveUI {
+        name: "Exponential",
+        curve: ExpressionCurve::Exponential,
+    },
+    ExpressionCurveUI {
+        name: "Logarithmic",
+        curve: ExpressionCurve::Logarithmic,
+    },
+];
+
+pub const ARP_MODES: [ArpModeUI; 4] = [
+    ArpModeUI {
+        name: "Up",
+        mode: ArpMode::Up,
+    },
+    ArpModeUI {
+        name: "Down",
+        mode: ArpMode::Down,
+    },
+    ArpModeUI {
+        name: "Up-down",
+        mode: ArpMode::UpDown,
+    },
+    ArpModeUI {
+        name: "Random",
+        mode: ArpMode::Random,
+    },
+];
+
+pub const STEP_DIVISIONS: [StepDivisionUI; 4] = [
+    StepDivisionUI {
+        name: "1/4",
+        division: StepDivision::Quarter,
+    },
+    StepDivisionUI {
+        name: "1/8",
+        division: StepDivision::Eighth,
+    },
+    StepDivisionUI {
+        name: "1/16",
+        division: StepDivision::Sixteenth,
+    },
+    StepDivisionUI {
+        name: "1/32",
+        division: StepDivision::ThirtySecond,
+    },
+];
+// Index of 1/16, synth starts with it
+pub const DEFAULT_STEP_DIVISION_IDX: f64 = 2.0;
+
+// Targets of oscillators past the first two come last, so older presets keep their indices.
+// Targets of oscillators a patch doesn't have are skipped
+pub const LFO_TARGETS: [LfoTargetUI; 14] = [
+    LfoTargetUI {
+        name: "None",
+        target: LfoTarget::None,
+    },
+    LfoTargetUI {
+        name: "Osc1 pitch",
+        target: LfoTarget::OscPitch(0),
+    },
+    LfoTargetUI {
+        name: "Osc2 pitch",
+        target: LfoTarget::OscPitch(1),
+    },
+    LfoTargetUI {
+        name: "Osc1 volume",
+        target: LfoTarget::OscVolume(0),
+    },
+    LfoTargetUI {
+        name: "Osc2 volume",
+        target: LfoTarget::OscVolume(1),
+    },
+    LfoTargetUI {
+        name: "Filter cutoff",
+        target: LfoTarget::FilterCutoff,
+    },
+    LfoTargetUI {
+        name: "Osc1 PW",
+        target: LfoTarget::OscPulseWidth(0),
+    },
+    LfoTargetUI {
+        name: "Osc2 PW",
+        target: LfoTarget::OscPulseWidth(1),
+    },
+    LfoTargetUI {
+        name: "Osc3 pitch",
+        target: LfoTarget::OscPitch(2),
+    },
+    LfoTargetUI {
+        name: "Osc3 volume",
+        target: LfoTarget::OscVolume(2),
+    },
+    LfoTargetUI {
+        name: "Osc3 PW",
+        target: LfoTarget::OscPulseWidth(2),
+    },
+    LfoTargetUI {
+        name: "Osc4 pitch",
+        target: LfoTarget::OscPitch(3),
+    },
+    LfoTargetUI {
+        name: "Osc4 volume",
+        target: LfoTarget::OscVolume(3),
+    },
+    LfoTargetUI {
+        name: "Osc4 PW",
+        target: LfoTarget::OscPulseWidth(3),
+    },
+];
+
+pub const MOD_SOURCES: [ModSourceUI; 12] = [
+    ModSourceUI {
+        name: "None",
+        source: ModSource::None,
+    },
+    ModSourceUI {
+        name: "LFO1",
+        source: ModSource::Lfo(0),
+    },
+    ModSourceUI {
+        name: "LFO2",
+        source: ModSource::Lfo(1),
+    },
+    ModSourceUI {
+        name: "Env1",
+        source: ModSource::Env(0),
+    },
+    ModSourceUI {
+        name: "Env2",
+        source: ModSource::Env(1),
+    },
+    ModSourceUI {
+        name: "Velocity",
+        source: ModSource::Velocity,
+    },
+    ModSourceUI {
+        name: "Mod wheel",
+        source: ModSource::ModWheel,
+    },
+    ModSourceUI {
+        name: "Aftertouch",
+        source: ModSource::Aftertouch,
+    },
+    ModSourceUI {
+        name: "Expression",
+        source: ModSource::Expression,
+    },
+    ModSourceUI {
+        name: "Chaos",
+        source: ModSource::Chaos,
+    },
+    ModSourceUI {
+        name: "Steps",
+        source: ModSource::Steps,
+    },
+    ModSourceUI {
+        name: "Random",
+        source: ModSource::Random,
+    },
+];
+
+// Ordered like LFO_TARGETS, oscillators past the first two come last
+pub const MOD_DESTINATIONS: [ModDestinationUI; 22] = [
+    ModDestinationUI {
+        name: "None",
+        destination: ModDestination::None,
+    },
+    ModDestinationUI {
+        name: "Osc1 pitch",
+        destination: ModDestination::OscPitch(0),
+    },
+    ModDestinationUI {
+        name: "Osc2 pitch",
+        destination: ModDestination::OscPitch(1),
+    },
+    ModDestinationUI {
+        name: "Osc1 volume",
+        destination: ModDestination::OscVolume(0),
+    },
+    ModDestinationUI {
+        name: "Osc2 volume",
+        destination: ModDestination::OscVolume(1),
+    },
+    ModDestinationUI {
+        name: "Osc1 pan",
+        destination: ModDestination::OscPan(0),
+    },
+    ModDestinationUI {
+        name: "Osc2 pan",
+        destination: ModDestination::OscPan(1),
+    },
+    ModDestinationUI {
+        name: "Osc1 PW",
+        destination: ModDestination::OscPulseWidth(0),
+    },
+    ModDestinationUI {
+        name: "Osc2 PW",
+        destination: ModDestination::OscPulseWidth(1),
+    },
+    ModDestinationUI {
+        name: "Filter cutoff",
+        destination: ModDestination::FilterCutoff,
+    },
+    ModDestinationUI {
+        name: "LFO1 rate",
+        destination: ModDestination::LfoRate(0),
+    },
+    ModDestinationUI {
+        name: "LFO2 rate",
+        destination: ModDestination::LfoRate(1),
+    },
+    ModDestinationUI {
+        name: "LFO1 depth",
+        destination: ModDestination::LfoDepth(0),
+    },
+    ModDestinationUI {
+        name: "LFO2 depth",
+        destination: ModDestination::LfoDepth(1),
+    },
+    ModDestinationUI {
+        name: "Osc3 pitch",
+        destination: ModDestination::OscPitch(2),
+    },
+    ModDestinationUI {
+        name: "Osc3 volume",
+        destination: ModDestination::OscVolume(2),
+    },
+    ModDestinationUI {
+        name: "Osc3 pan",
+        destination: ModDestination::OscPan(2),
+    },
+    ModDestinationUI {
+        name: "Osc3 PW",
+        destination: ModDestination::OscPulseWidth(2),
+    },
+    ModDestinationUI {
+        name: "Osc4 pitch",
+        destination: ModDestination::OscPitch(3),
+    },
+    ModDestinationUI {
+        name: "Osc4 volume",
+        destination: ModDestination::OscVolume(3),
+    },
+    ModDestinationUI {
+        name: "Osc4 pan",
+        destination: ModDestination::OscPan(3),
+    },
+    ModDestinationUI {
+        name: "Osc4 PW",
+        destination: ModDestination::OscPulseWidth(3),
+    },
+];
+
+const DEFAULT_ATTACK: f64 = 300.;
+const DEFAULT_DECAY: f64 = 300.;
+const DEFAULT_SUSTAIN: f64 = 0.7;
+const DEFAULT_RELEASE: f64 = 300.;
+const DEFAULT_DELAY: f64 = adsr_constraints::MIN_DELAY as f64;
+const DEFAULT_HOLD: f64 = adsr_constraints::MIN_HOLD as f64;
+const DEFAULT_TRANSPOSE: f64 = 0.0;
+const DEFAULT_TUNE: f64 = 0.0;
+const DEFAULT_OSC_VOLUME: f64 = 0.5;
+const DEFAULT_OSC_PAN: f64 = 0.5;
+const DEFAULT_OSC_ENV_AMOUNT: f64 = osc_constraints::MAX_ENV_AMOUNT as f64;
+const DEFAULT_OSC_SUB_LEVEL: f64 = osc_constraints::DEFAULT_SUB_LEVEL as f64;
+const DEFAULT_OSC_TILT: f64 = 0.0;
+const DEFAULT_OSC_PHASE_OFFSET: f64 = osc_constraints::MIN_PHASE_OFFSET as f64;
+const DEFAULT_SUPERSAW_DETUNE: f64 = osc_constraints::DEFAULT_SUPERSAW_DETUNE as f64;
+const DEFAULT_SUPERSAW_MIX: f64 = osc_constraints::DEFAULT_SUPERSAW_MIX as f64;
+const DEFAULT_UNISON_WIDTH: f64 = osc_constraints::MIN_UNISON_WIDTH as f64;
+const DEFAULT_WIDTH_TIME: f64 = osc_constraints::MIN_WIDTH_TIME as f64;
+const DEFAULT_PLUCK_DAMPING: f64 = pluck_constraints::DEFAULT_DAMPING as f64;
+const DEFAULT_OSC_PULSE_WIDTH: f64 = osc_constraints::DEFAULT_PULSE_WIDTH as f64;
+const DEFAULT_FILTER_CUTOFF: f64 = 20000.;
+const DEFAULT_FILTER_RESONANCE: f64 = 0.0;
+const DEFAULT_FILTER_ENV_AMOUNT: f64 = 0.0;
+const DEFAULT_LOW_CUT: f64 = 20.0;
+const DEFAULT_VELOCITY_SENSITIVITY: f64 = osc_constraints::DEFAULT_VELOCITY_SENSITIVITY as f64;
+const DEFAULT_MOD_DEPTH: f64 = 0.0;
+const DEFAULT_LFO_RATE: f64 = 2.0;
+const DEFAULT_LFO_DEPTH: f64 = 0.0;
+const DEFAULT_GLIDE_TIME: f64 = 0.0;
+const DEFAULT_FM_AMOUNT: f64 = 0.0;
+const DEFAULT_CHAOS_RATE: f64 = chaos_constraints::DEFAULT_RATE as f64;
+const DEFAULT_TEMPO: f64 = clock_constraints::DEFAULT_TEMPO as f64;
+const DEFAULT_ARP_RATE: f64 = arp_constraints::DEFAULT_RATE as f64;
+const DEFAULT_ARP_GATE: f64 = arp_constraints::DEFAULT_GATE as f64;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DefaultParameter {
+    EnvDelay,
+    EnvAttack,
+    EnvHold,
+    EnvDecay,
+    EnvSustain,
+    EnvRelease,
+    OscTranspose,
+    OscTune,
+    OscVolume,
+    OscPan,
+    OscEnvAmount,
+    OscSubLevel,
+    OscTilt,
+    OscPhaseOffset,
+    OscPulseWidth,
+    PluckDamping,
+    SupersawDetune,
+    SupersawMix,
+    UnisonWidth,
+    WidthTime,
+    FilterCutoff,
+    FilterResonance,
+    FilterEnvAmount,
+    LfoRate,
+    LfoDepth,
+    LowCut,
+    VelocitySensitivity,
+    ModDepth,
+    GlideTime,
+    FmAmount,
+    ChaosRate,
+    Tempo,
+    ArpRate,
+    ArpGate,
+}
+
+// Parameters with a taper, the log scale ones
+const TAPERED: [DefaultParameter; 7] = [
+    DefaultParameter::EnvAttack,
+    DefaultParameter::EnvDecay,
+    DefaultParameter::EnvRelease,
+    DefaultParameter::FilterCutoff,
+    DefaultParameter::LfoRate,
+    DefaultParameter::LowCut,
+    DefaultParameter::ArpRate,
+];
+
+impl DefaultParameter {
+    // Values of these parameters are kept as log2 in UI
+    pub fn log_scale(&self) -> bool {
+        match self {
+            DefaultParameter::EnvAttack
+            | DefaultParameter::EnvDecay
+            | DefaultParameter::EnvRelease
+            | DefaultParameter::FilterCutoff
+            | DefaultParameter::LfoRate
+            | DefaultParameter::LowCut
+            | DefaultParameter::ArpRate => true,
+            _ => false,
+        }
+    }
+
+    // Name of the parameter in slider_tapers of the settings
+    pub fn taper_name(&self) -> Option<&'static str> {
+        match self {
+            DefaultParameter::EnvAttack => Some("attack"),
+            DefaultParameter::EnvDecay => Some("decay"),
+            DefaultParameter::EnvRelease => Some("release"),
+            DefaultParameter::FilterCutoff => Some("cutoff"),
+            DefaultParameter::LfoRate => Some("lfo_rate"),
+            DefaultParameter::LowCut => Some("low_cut"),
+            DefaultParameter::ArpRate => Some("arp_rate"),
+            _ => None,
+        }
+    }
+
+    pub fn taper(&self) -> f64 {
+        TAPERS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(parameter, _)| parameter == self)
+            .map_or(DEFAULT_TAPER, |(_, taper)| *taper)
+    }
+
+    // Default in the same scale as slider value
+    pub fn slider_default(&self) -> f64 {
+        if self.log_scale() {
+            slider_log(self.default_val() as f32)
+        } else {
+            self.default_val()
+        }
+    }
+
+    pub fn default_val(&self) -> f64 {
+        match self {
+            DefaultParameter::EnvDelay => DEFAULT_DELAY,
+            DefaultParameter::EnvAttack => DEFAULT_ATTACK,
+            DefaultParameter::EnvHold => DEFAULT_HOLD,
+            DefaultParameter::EnvDecay => DEFAULT_DECAY,
+            DefaultParameter::EnvSustain => DEFAULT_SUSTAIN,
+            DefaultParameter::EnvRelease => DEFAULT_RELEASE,
+            DefaultParameter::OscTranspose => DEFAULT_TRANSPOSE,
+            DefaultParameter::OscTune => DEFAULT_TUNE,
+            DefaultParameter::OscVolume => DEFAULT_OSC_VOLUME,
+            DefaultParameter::OscPan => DEFAULT_OSC_PAN,
+            DefaultParameter::OscEnvAmount => DEFAULT_OSC_ENV_AMOUNT,
+            DefaultParameter::OscSubLevel => DEFAULT_OSC_SUB_LEVEL,
+            DefaultParameter::OscTilt => DEFAULT_OSC_TILT,
+            DefaultParameter::OscPhaseOffset => DEFAULT_OSC_PHASE_OFFSET,
+            DefaultParameter::OscPulseWidth => DEFAULT_OSC_PULSE_WIDTH,
+            DefaultParameter::PluckDamping => DEFAULT_PLUCK_DAMPING,
+            DefaultParameter::SupersawDetune => DEFAULT_SUPERSAW_DETUNE,
+            DefaultParameter::SupersawMix => DEFAULT_SUPERSAW_MIX,
+            DefaultParameter::UnisonWidth => DEFAULT_UNISON_WIDTH,
+            DefaultParameter::WidthTime => DEFAULT_WIDTH_TIME,
+            DefaultParameter::FilterCutoff => DEFAULT_FILTER_CUTOFF,
+            DefaultParameter::FilterResonance => DEFAULT_FILTER_RESONANCE,
+            DefaultParameter::FilterEnvAmount => DEFAULT_FILTER_ENV_AMOUNT,
+            DefaultParameter::LfoRate => DEFAULT_LFO_RATE,
+            DefaultParameter::LfoDepth => DEFAULT_LFO_DEPTH,
+            DefaultParameter::LowCut => DEFAULT_LOW_CUT,
+            DefaultParameter::VelocitySensitivity => DEFAULT_VELOCITY_SENSITIVITY,
+            DefaultParameter::ModDepth => DEFAULT_MOD_DEPTH,
+            DefaultParameter::GlideTime => DEFAULT_GLIDE_TIME,
+            DefaultParameter::FmAmount => DEFAULT_FM_AMOUNT,
+            DefaultParameter::ChaosRate => DEFAULT_CHAOS_RATE,
+            DefaultParameter::Tempo => DEFAULT_TEMPO,
+            DefaultParameter::ArpRate => DEFAULT_ARP_RATE,
+            DefaultParameter::ArpGate => DEFAULT_ARP_GATE,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,359 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use crate::control::SynthEngine;
+use crate::crash;
+use crate::error::{BaseError, Context, Result};
+use crate::midi::note_frequency;
+use crate::params::SAMPLE_RATES;
+use crate::settings::Settings;
+use crate::synth::{NoteEvent, NoteSource, Synth};
+use crate::synth_ui::send_preset;
+use crate::wav::write_wav;
+
+const USAGE: &str = "Usage: beep-boop render <preset.json> <notes.mid|notes.txt> -o <out.wav> [--rate <Hz>]";
+// Standard MIDI file tempo until a tempo event says otherwise, 120 BPM
+const DEFAULT_MICROS_PER_QUARTER: f64 = 500_000.0;
+const MIDI_NOTE_OFF: u8 = 0x80;
+const MIDI_NOTE_ON: u8 = 0x90;
+const META_EVENT: u8 = 0xff;
+const META_TEMPO: u8 = 0x51;
+const SYSEX: u8 = 0xf0;
+const SYSEX_ESCAPE: u8 = 0xf7;
+
+struct RenderArgs {
+    preset: PathBuf,
+    notes: PathBuf,
+    output: PathBuf,
+    sample_rate: Option<u32>,
+}
+
+fn parse_args(args: &[String]) -> Result<RenderArgs> {
+    let usage = || BaseError::InputError(USAGE.into());
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut sample_rate = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().ok_or_else(usage)?)),
+            "--rate" => {
+                let rate = args.next().ok_or_else(usage)?;
+                sample_rate = Some(rate.parse().map_err(|_| BaseError::InputError(format!("Bad sample rate {}", rate)))?);
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+    match (positional.as_slice(), output) {
+        ([preset, notes], Some(output)) => Ok(RenderArgs {
+            preset: preset.clone(),
+            notes: notes.clone(),
+            output,
+            sample_rate,
+        }),
+        _ => Err(usage()),
+    }
+}
+
+// One note per line: start in seconds, MIDI note number, length in seconds
+// and optional velocity 1-127. Everything after # is a comment
+fn parse_text_notes(text: &str) -> Result<Vec<NoteEvent>> {
+    let mut events = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || BaseError::InputError(format!("Bad note on line {}: {}", line_idx + 1, line));
+        let fields: Vec<f32> = line
+            .split_whitespace()
+            .map(|field| field.parse().map_err(|_| bad_line()))
+            .collect::<Result<_>>()?;
+        let (start, note, length, velocity) = match fields.as_slice() {
+            [start, note, length] => (*start, *note, *length, 127.0),
+            [start, note, length, velocity] => (*start, *note, *length, *velocity),
+            _ => return Err(bad_line()),
+        };
+        if start < 0.0 || length < 0.0 || note < 0.0 || note > 127.0 {
+            return Err(bad_line());
+        }
+        let note = note.round() as u8;
+        // Every line is its own source, so overlapping notes of the same pitch don't cut each other
+        let source = NoteSource::Render(line_idx as u32);
+        events.push(NoteEvent::On {
+            time: start,
+            freq: note_frequency(note),
+            velocity: velocity.max(0.0).min(127.0) / 127.0,
+            source,
+        });
+        events.push(NoteEvent::Off { time: start + length, source });
+    }
+    Ok(events)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    bytes.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Variable-length quantity: 7 bits per byte, the highest bit says more bytes follow
+fn read_varlen(bytes: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value = (value << 7) | (byte & 0x7f) as u32;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// Note on and off events of every track, in ticks. Tempo events are
+// collected too, they apply to all tracks
+fn read_track(track: &[u8], notes: &mut Vec<(u64, u8, u8)>, tempos: &mut Vec<(u64, f64)>) -> Option<()> {
+    let mut pos = 0;
+    let mut tick = 0u64;
+    let mut running_status = 0u8;
+    while pos < track.len() {
+        tick += read_varlen(track, &mut pos)? as u64;
+        let mut status = *track.get(pos)?;
+        if status & 0x80 != 0 {
+            pos += 1;
+        } else {
+            // Running status: the previous status byte is reused
+            status = running_status;
+        }
+        match status {
+            META_EVENT => {
+                let kind = *track.get(pos)?;
+                pos += 1;
+                let len = read_varlen(track, &mut pos)? as usize;
+                let data = track.get(pos..pos + len)?;
+                if kind == META_TEMPO && len == 3 {
+                    let micros = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+                    tempos.push((tick, micros as f64));
+                }
+                pos += len;
+            }
+            SYSEX | SYSEX_ESCAPE => {
+                let len = read_varlen(track, &mut pos)? as usize;
+                pos += len;
+            }
+            _ => {
+                running_status = status;
+                // Program change and channel pressure have one data byte, the rest two
+                let data_len = match status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    _ => 2,
+                };
+                let data = track.get(pos..pos + data_len)?;
+                pos += data_len;
+                match status & 0xf0 {
+                    MIDI_NOTE_ON if data[1] > 0 => notes.push((tick, data[0], data[1])),
+                    // Note on with zero velocity is the same as note off
+                    MIDI_NOTE_ON | MIDI_NOTE_OFF => notes.push((tick, data[0], 0)),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+// Standard MIDI file of format 0 or 1, channels are merged
+fn parse_midi_file(bytes: &[u8]) -> Result<Vec<NoteEvent>> {
+    let bad_file = || BaseError::InputError("Not a standard MIDI file or it's broken".into());
+    if bytes.get(0..4) != Some(&b"MThd"[..]) {
+        return Err(bad_file());
+    }
+    let header_len = read_u32(bytes, 4).ok_or_else(bad_file)? as usize;
+    let division = bytes.get(12..14).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(bad_file)?;
+    if division == 0 {
+        return Err(bad_file());
+    }
+    if division & 0x8000 != 0 {
+        return Err(BaseError::InputError("MIDI files with SMPTE timing aren't supported".into()));
+    }
+    let ticks_per_quarter = division as f64;
+    let mut notes = Vec::new();
+    let mut tempos = Vec::new();
+    let mut pos = 8 + header_len;
+    while pos + 8 <= bytes.len() {
+        let len = read_u32(bytes, pos + 4).ok_or_else(bad_file)? as usize;
+        let chunk = bytes.get(pos + 8..pos + 8 + len).ok_or_else(bad_file)?;
+        // Unknown chunks are skipped, as the format says
+        if &bytes[pos..pos + 4] == b"MTrk" {
+            read_track(chunk, &mut notes, &mut tempos).ok_or_else(bad_file)?;
+        }
+        pos += 8 + len;
+    }
+    notes.sort_by_key(|(tick, _, _)| *tick);
+    tempos.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Ticks to seconds, walking through tempo changes
+    let seconds = |tick: u64| {
+        let (mut time, mut last_tick, mut micros) = (0.0, 0u64, DEFAULT_MICROS_PER_QUARTER);
+        for (tempo_tick, tempo_micros) in tempos.iter().take_while(|(tempo_tick, _)| *tempo_tick <= tick) {
+            time += (tempo_tick - last_tick) as f64 * micros / ticks_per_quarter / 1_000_000.0;
+            last_tick = *tempo_tick;
+            micros = *tempo_micros;
+        }
+        (time + (tick - last_tick) as f64 * micros / ticks_per_quarter / 1_000_000.0) as f32
+    };
+    Ok(notes
+        .into_iter()
+        .map(|(tick, note, velocity)| match velocity {
+            0 => NoteEvent::Off { time: seconds(tick), source: NoteSource::Midi(note) },
+            velocity => NoteEvent::On {
+                time: seconds(tick),
+                freq: note_frequency(note),
+                velocity: velocity as f32 / 127.0,
+                source: NoteSource::Midi(note),
+            },
+        })
+        .collect())
+}
+
+fn read_notes(path: &Path) -> Result<Vec<NoteEvent>> {
+    let midi = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("midi"))
+        .unwrap_or(false);
+    if midi {
+        parse_midi_file(&fs::read(path)?)
+    } else {
+        parse_text_notes(&fs::read_to_string(path)?)
+    }
+}
+
+// beep-boop render: plays notes with a preset offline and writes WAV file, no window is opened
+pub fn run(args: &[String]) -> Result<()> {
+    let args = parse_args(args)?;
+    let settings = Settings::load();
+    crash::publish_settings(&settings);
+    let sample_rate = args.sample_rate.unwrap_or(settings.sample_rate);
+    if !SAMPLE_RATES.contains(&sample_rate) {
+        return Err(BaseError::InputError(format!(
+            "Unsupported sample rate {} Hz, use one of {:?}", sample_rate, SAMPLE_RATES
+        )));
+    }
+    let sample_rate = sample_rate as f32;
+    let events = read_notes(&args.notes).context(format!("Can't read notes from {}", args.notes.display()))?;
+
+    let mut synth = Synth::<i16>::new(sample_rate);
+    synth.set_high_quality(settings.high_quality);
+    synth.set_dc_blocker(settings.dc_blocker);
+    synth.set_binaural(settings.binaural);
+    synth.set_bend_range(settings.bend_range as f32);
+    let (event_sender, _) = mpsc::channel();
+    let (mut engine, control) = SynthEngine::new(synth, event_sender);
+    send_preset(control, &args.preset, sample_rate)?;
+
+    let samples = engine.render(&events);
+    let frames: Vec<[i16; 2]> = samples.chunks(2).map(|frame| [frame[0], frame[1]]).collect();
+    write_wav(&args.output, sample_rate as u32, &frames).context(format!("Can't write {}", args.output.display()))?;
+    println!("Rendered {:.1} s to {}", frames.len() as f32 / sample_rate, args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{Oscillator, WaveForm, ADSR};
+
+    // Format 0, one track, 96 ticks per quarter
+    const MIDI_HEADER: [u8; 14] = [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96];
+
+    fn midi_file(track: &[u8]) -> Vec<u8> {
+        let mut bytes = MIDI_HEADER.to_vec();
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(track);
+        bytes
+    }
+
+    #[test]
+    fn text_notes_turn_into_on_and_off_events() {
+        let events = parse_text_notes("# intro\n0.5 69 1.0\n\n2 60 0.25 64 # soft\n").unwrap();
+        assert_eq!(events.len(), 4);
+        match events[0] {
+            NoteEvent::On { time, freq, velocity, source } => {
+                assert_eq!((time, freq, velocity), (0.5, 440.0, 1.0));
+                assert_eq!(source, NoteSource::Render(1));
+            }
+            _ => panic!("{:?} isn't note on", events[0]),
+        }
+        match events[1] {
+            NoteEvent::Off { time, source } => assert_eq!((time, source), (1.5, NoteSource::Render(1))),
+            _ => panic!("{:?} isn't note off", events[1]),
+        }
+        match events[2] {
+            NoteEvent::On { time, velocity, .. } => assert_eq!((time, velocity), (2.0, 64.0 / 127.0)),
+            _ => panic!("{:?} isn't note on", events[2]),
+        }
+    }
+
+    #[test]
+    fn last_note_is_released_before_tail_ends() {
+        let sample_rate = 44100.0;
+        let mut synth = Synth::<i16>::new(sample_rate);
+        synth.add_env(ADSR::new(sample_rate, 5, 50, 0.6, 100));
+        synth.add_osc(Oscillator::new(sample_rate, WaveForm::Saw, 0, 0.5));
+        let (event_sender, _) = mpsc::channel();
+        let (mut engine, _control) = SynthEngine::new(synth, event_sender);
+        let samples = engine.render(&parse_text_notes("0 60 1").unwrap());
+        // A second of the note and its release, not the whole tail limit
+        let seconds = samples.len() as f32 / 2.0 / sample_rate;
+        assert!(seconds < 1.5, "rendered {} s", seconds);
+    }
+
+    #[test]
+    fn bad_text_notes_are_rejected() {
+        for text in ["1 60", "1 60 1 100 5", "-1 60 1", "0 128 1", "0 sixty 1"].iter() {
+            assert!(parse_text_notes(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn midi_file_follows_tempo_and_running_status() {
+        let track = [
+            // 240 BPM
+            0x00, META_EVENT, META_TEMPO, 0x03, 0x03, 0xd0, 0x90,
+            0x00, MIDI_NOTE_ON, 69, 100,
+            // A quarter later, running status note on with zero velocity
+            0x60, 69, 0,
+            0x00, META_EVENT, 0x2f, 0x00,
+        ];
+        let events = parse_midi_file(&midi_file(&track)).unwrap();
+        assert_eq!(events.len(), 2);
+        match events[0] {
+            NoteEvent::On { time, freq, velocity, source } => {
+                assert_eq!((time, freq, velocity), (0.0, 440.0, 100.0 / 127.0));
+                assert_eq!(source, NoteSource::Midi(69));
+            }
+            _ => panic!("{:?} isn't note on", events[0]),
+        }
+        match events[1] {
+            NoteEvent::Off { time, source } => {
+                assert!((time - 0.25).abs() < 1e-6, "note off at {}", time);
+                assert_eq!(source, NoteSource::Midi(69));
+            }
+            _ => panic!("{:?} isn't note off", events[1]),
+        }
+    }
+
+    #[test]
+    fn broken_midi_files_are_rejected() {
+        assert!(parse_midi_file(b"RIFF").is_err());
+        // Track is shorter than its length says
+        let mut truncated = midi_file(&[0x00, MIDI_NOTE_ON, 69, 100]);
+        truncated.truncate(truncated.len() - 2);
+        assert!(parse_midi_file(&truncated).is_err());
+        // SMPTE timing
+        let mut smpte = midi_file(&[]);
+        smpte[12] = 0xe7;
+        assert!(parse_midi_file(&smpte).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::crash;
+use crate::error::{Context, Result};
+use crate::notification::{report, NotificationSender};
+use crate::params::DEFAULT_SAMPLE_RATE;
+use crate::synth::{bend_constraints, osc_constraints};
+
+// Kept next to where beep-boop is started from, same as presets
+const SETTINGS_FILE: &str = "beep-boop-settings.json";
+// Several copies can run side by side, e.g. one per part of a live rig
+pub const MAX_INSTANCE: usize = 16;
+// Longer than any sane held note, but a stuck one doesn't drone for long
+const DEFAULT_STUCK_NOTE_TIMEOUT: f32 = 60.0;
+// Hardly used by anything else, and hard to hit by accident
+const DEFAULT_PANIC_HOTKEY: &str = "Ctrl+Alt+P";
+// Set by --safe-mode: defaults are used and the file is left as it is,
+// so broken settings can't keep beep-boop from starting
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn enter_safe_mode() {
+    SAFE_MODE.store(true, Ordering::Relaxed);
+}
+
+pub fn safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+// Set by --instance, 1 unless more copies are started
+static INSTANCE: AtomicUsize = AtomicUsize::new(1);
+
+pub fn set_instance(instance: usize) {
+    INSTANCE.store(instance, Ordering::Relaxed);
+}
+
+// Name of a port or file, the first instance keeps the plain one and
+// the others get their index: "beep-boop", "beep-boop-2", ...
+pub fn instance_name(name: &str) -> String {
+    match INSTANCE.load(Ordering::Relaxed) {
+        1 => name.to_owned(),
+        instance => format!("{}-{}", name, instance),
+    }
+}
+
+// beep-boop-settings-2.json for the second instance
+fn settings_file() -> String {
+    format!("{}.json", instance_name(SETTINGS_FILE.trim_end_matches(".json")))
+}
+
+// Control moved by a MIDI controller, learned by right-clicking the control
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MidiBinding {
+    pub cc: u8,
+    // Panel and control name, e.g. "Osc1 volume"
+    pub key: String,
+}
+
+// Curve of a log scale slider, e.g. {"parameter": "attack", "taper": 2.0}
+// for finer short attacks when designing drums
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SliderTaper {
+    // One of attack, decay, release, cutoff, lfo_rate, low_cut and arp_rate
+    pub parameter: String,
+    // 0.25-4.0, 1.0 is the usual log scale
+    pub taper: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Demo patch and tips were already shown
+    pub onboarded: bool,
+    // Hz, one of SAMPLE_RATES
+    pub sample_rate: u32,
+    // Code of one of LANGUAGES
+    pub language: String,
+    // Code of one of THEMES, high_contrast has larger text and doesn't rely on colors
+    pub theme: String,
+    // Seconds, MIDI notes held longer are released. 0 turns it off
+    pub stuck_note_timeout: f32,
+    // Band-limited oscillator waves
+    pub high_quality: bool,
+    // DC offset is removed from the output
+    pub dc_blocker: bool,
+    // Interaural delay for headphones, experimental
+    pub binaural: bool,
+    // MIDI velocity of computer keyboard and on-screen piano notes
+    pub key_velocity: u8,
+    // Semitones the pitch wheel bends at either end
+    pub bend_range: u8,
+    // Hardware controllers drive the same controls whatever patch is loaded
+    pub midi_map: Vec<MidiBinding>,
+    // Every patch change is kept for the F12 change log, costs a bit of CPU on every change
+    pub audit_log: bool,
+    // Only edited in the file, the same curve applies to every slider of the parameter
+    pub slider_tapers: Vec<SliderTaper>,
+    // Works while other windows have focus, e.g. "Ctrl+Alt+P". Empty turns it off
+    pub panic_hotkey: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            onboarded: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            language: "en".to_owned(),
+            theme: "default".to_owned(),
+            stuck_note_timeout: DEFAULT_STUCK_NOTE_TIMEOUT,
+            high_quality: false,
+            dc_blocker: true,
+            binaural: false,
+            key_velocity: osc_constraints::DEFAULT_KEY_VELOCITY,
+            bend_range: bend_constraints::DEFAULT_RANGE,
+            midi_map: Vec::new(),
+            audit_log: false,
+            slider_tapers: Vec::new(),
+            panic_hotkey: DEFAULT_PANIC_HOTKEY.to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    // Missing or broken file means default settings. A new instance
+    // starts with settings of the first one until it saves its own
+    pub fn load() -> Self {
+        if safe_mode() {
+            return Self::default()
+        }
+        fs::read_to_string(settings_file())
+            .or_else(|_| fs::read_to_string(SETTINGS_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    // Written to a temporary file first, so another instance never reads it half-written
+    pub fn save(&self) -> Result<()> {
+        // Crash reports show the settings in use, even when safe mode doesn't write them
+        crash::publish_settings(self);
+        if safe_mode() {
+            return Ok(())
+        }
+        let path = settings_file();
+        let temp_path = format!("{}.tmp", path);
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+// Changes one setting in the file, the rest is kept as other instances may have saved it
+pub fn save_setting<F: FnOnce(&mut Settings)>(sender: &NotificationSender, change: F) {
+    let mut settings = Settings::load();
+    change(&mut settings);
+    if let Err(e) = settings.save().context("Can't save settings") {
+        report(sender, e);
+    }
+}
@@ -1,54 +1,202 @@
+mod arpeggiator;
+mod binaural;
+mod chaos;
+mod clock;
+mod dc_blocker;
 mod envelope;
+mod expression;
+mod filter;
+mod glide;
+mod lfo;
+mod modulation;
 mod oscillator;
+mod pluck;
+mod recorder;
+mod sequencer;
+mod step_mod;
+mod sustain;
+mod test_tone;
 pub mod waves;
 
-pub use self::envelope::{ADSR, ADSRParam, adsr_constraints};
-pub use self::oscillator::{Oscillator, Start};
-pub use self::waves::WaveForm;
+pub use self::arpeggiator::{Arpeggiator, Trigger, ArpMode, arp_constraints};
+pub use self::binaural::Interaural;
+pub use self::chaos::{Chaos, chaos_constraints};
+pub use self::clock::{Clock, clock_constraints};
+pub use self::dc_blocker::DcBlocker;
+pub use self::envelope::{ADSR, ADSRParam, EnvState, adsr_constraints, env_idx_after_removal};
+pub use self::expression::{ExpressionCurve, ExpressionDestination};
+pub use self::filter::{Filter, FilterMode, filter_constraints};
+pub use self::glide::{Glide, GlideCurve, glide_constraints};
+pub use self::lfo::{Lfo, LfoTarget, lfo_constraints};
+pub use self::modulation::{ModSlot, ModSource, ModDestination, MOD_SLOTS, mod_constraints};
+pub use self::oscillator::{Oscillator, Start, VoiceStealing, highest_frequency, osc_constraints, osc_idx_after_removal, pluck_strings};
+pub use self::pluck::{OscEngine, PluckString, pluck_constraints};
+pub use self::recorder::{Recorder, RecorderMessage};
+pub use self::sequencer::{Sequencer, seq_constraints};
+pub use self::step_mod::{StepDivision, StepMod, STEPS};
+pub use self::sustain::Sustain;
+pub use self::test_tone::{TestTone, test_tone_constraints};
+pub use self::waves::{Wave, WaveForm, HARMONICS};
 use crate::error::{BaseError, Result};
 pub use crate::synth_ui::KeyCode;
 
-use std::time::Instant;
 
 #[allow(non_camel_case_types)]
 type dB = i32;
 
+// Auto gain renders this note offline to measure patch loudness
+const AUTO_GAIN_NOTE: f32 = 261.63; // Middle C
+const AUTO_GAIN_MS: f32 = 100.0;
+const AUTO_GAIN_TARGET_RMS: f32 = 0.25;
+const AUTO_GAIN_MIN_TRIM: dB = -24;
+const AUTO_GAIN_MAX_TRIM: dB = 12;
+// Short note played when browsing waveforms
+const AUDITION_NOTE: f32 = 261.63; // Middle C
+const AUDITION_MS: f32 = 300.0;
+// Envelope preview: a sine through the envelope, longer envelopes are sped up to fit
+const PREVIEW_MS: f32 = 1000.0;
+// Time spent at sustain before the preview note is released
+const PREVIEW_SUSTAIN_MS: f32 = 200.0;
+// Cut tails of the preview fade out this fast
+const PREVIEW_FADE_MS: f32 = 5.0;
+const PREVIEW_LEVEL: f32 = 0.5;
+// How often held notes are checked for stuck ones
+const STUCK_CHECK_SECONDS: f32 = 0.1;
+// Filter envelope with amount 1.0 moves cutoff this much up
+const FILTER_ENV_OCTAVES: f32 = 6.0;
+// LFO or mod matrix slot with full depth modulates this much
+const MOD_PITCH_SEMITONES: f32 = 12.0;
+const MOD_CUTOFF_OCTAVES: f32 = 4.0;
+const MOD_LFO_RATE_OCTAVES: f32 = 3.0;
+// Bend and expression follow their controllers within a few ms, so 7-bit steps aren't heard
+const CONTROLLER_SMOOTHING_MS: f32 = 5.0;
+// Output fades in when the stream starts and out before it's closed, so neither clicks
+const FADE_MS: f32 = 5.0;
+// Fade gain follows an exponential curve, which sounds smoother than a straight line
+const FADE_CURVE: f32 = 4.0;
+// Solo mutes other oscillators within a few ms, so switching it doesn't click
+const SOLO_FADE_MS: f32 = 5.0;
+// Step modulator glides to the next level this fast, so a gate doesn't click
+const STEP_SMOOTHING_MS: f32 = 3.0;
+// Envelopes, LFOs and mod matrix are worked out once per this many frames
+const MOD_BLOCK_FRAMES: usize = 32;
+const DEFAULT_VOLUME: dB = -30;
+// Output above this level is bent towards 1.0 instead of being cut off at it
+const SOFT_CLIP_KNEE: f32 = 0.8;
+// Oscillator modulated by the next one in FM mode
+const FM_CARRIER: usize = 0;
+
+#[allow(dead_code)]
+pub mod bend_constraints {
+    // Semitones the pitch wheel bends at either end
+    pub const MIN_RANGE: u8 = 2;
+    pub const MAX_RANGE: u8 = 24;
+    pub const DEFAULT_RANGE: u8 = 2;
+}
+
+// Doesn't depend on audio backend, so synth builds with any of them
 pub trait SampleFormat:
-    portaudio_rs::stream::SampleType
+    Copy
+    + Send
+    + 'static
     + num_traits::AsPrimitive<f32>
     + num_traits::Bounded
     + num_traits::FromPrimitive
     + std::cmp::PartialOrd
 {
+    // Sample in [-1, 1] range scaled to the format
+    fn from_normalized(value: f32) -> Self {
+        Self::from_f32(value.max(-1.0).min(1.0) * Self::max_value().as_()).unwrap()
+    }
 }
 
 impl SampleFormat for u8 {}
 impl SampleFormat for i8 {}
 impl SampleFormat for i16 {}
 impl SampleFormat for i32 {}
-impl SampleFormat for f32 {}
+impl SampleFormat for f32 {
+    fn from_normalized(value: f32) -> Self {
+        value
+    }
+}
+
+// What triggered a note, so the same source can release it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteSource {
+    Key(KeyCode),
+    Midi(u8),
+    // On-screen piano, one key at a time
+    Mouse,
+    Audition,
+    // Played by the arpeggiator from the held keys
+    Arp,
+    Sequencer,
+    // Note id of a duet partner playing over the network
+    Remote(u32),
+    // Line of a notes file rendered from command line
+    Render(u32),
+}
+
+// Note event for offline rendering, time is in seconds from the render start
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum NoteEvent {
+    On {
+        time: f32,
+        freq: f32,
+        velocity: f32,
+        source: NoteSource,
+    },
+    Off {
+        time: f32,
+        source: NoteSource,
+    },
+}
+
+impl NoteEvent {
+    pub fn time(&self) -> f32 {
+        match self {
+            NoteEvent::On { time, .. } | NoteEvent::Off { time, .. } => *time,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Released {
-    pub time: Instant,
+    // Age of the note when it was released
+    pub age: u32,
     pub value: f32,
 }
 
 #[derive(Debug, Clone)]
 pub struct Note {
     frequency: f32,
-    triggered_by: KeyCode,
-    triggered_time: Instant,
+    triggered_by: NoteSource,
+    // 0.0-1.0, how hard the key was hit
+    velocity: f32,
+    // Voice amplitude the velocity makes after sensitivity
+    level: f32,
+    // Samples since the note was triggered, so timing doesn't depend
+    // on how fast samples are pulled
+    age: u32,
     released: Option<Released>,
+    // Slide from the previous note
+    glide: Glide,
+    // Random mod source value in [-1, 1], every voice of the note shares it
+    random: f32,
 }
 
 impl Note {
-    pub fn new(frequency: f32, key: KeyCode) -> Self {
+    pub fn new(frequency: f32, velocity: f32, source: NoteSource) -> Self {
         Self {
             frequency: frequency,
-            triggered_by: key,
-            triggered_time: Instant::now(),
+            triggered_by: source,
+            velocity: velocity,
+            level: 1.0,
+            age: 0,
             released: None,
+            glide: Glide::none(),
+            random: rand::random::<f32>() * 2.0 - 1.0,
         }
     }
 }
@@ -60,11 +208,108 @@ impl PartialEq for Note {
 }
 
 pub struct Synth<SampleType: SampleFormat> {
-    #[allow(dead_code)]
     sample_rate: f32,
     volume: f32,
+    // Auto gain output trim, measured by UI. 1.0 while auto gain is off
+    gain_trim: f32,
+    low_cut: f32,
+    // Band-limited waves for every oscillator
+    high_quality: bool,
+    // Polyphony of every oscillator and the voice a note takes when it's reached
+    max_voices: usize,
+    voice_stealing: VoiceStealing,
+    // Osc2 modulates phase of Osc1, 0.0 turns FM off
+    fm_amount: f32,
     pub oscillators: Vec<Oscillator>,
+    // Oscillator heard alone while its panel is auditioned, the rest are muted but still run for FM
+    solo: Option<usize>,
+    // 0.0-1.0 gain of every oscillator following the solo
+    solo_gains: Vec<f32>,
+    // Experimental headphone mode, every oscillator gets interaural delay from its pan
+    binaural: bool,
+    interaural: Vec<Interaural>,
     pub envelopes: Vec<ADSR>,
+    pub lfos: Vec<Lfo>,
+    pub filter: Filter,
+    // Last stage before the soft clipper
+    dc_blocker: DcBlocker,
+    // Added to the output after everything else
+    test_tone: TestTone,
+    filter_env_idx: usize,
+    filter_env_amount: f32,
+    pub mod_slots: [ModSlot; MOD_SLOTS],
+    // Depths of mod slots after their depth sources, updated every block
+    slot_depths: [f32; MOD_SLOTS],
+    // Global envelopes for modulation, follow all the held notes
+    env_states: Vec<EnvState>,
+    // Source values of the current sample
+    env_values: Vec<f32>,
+    lfo_values: Vec<f32>,
+    // Lorenz attractor for never repeating movement
+    chaos: Chaos,
+    chaos_value: f32,
+    // Tempo for synced modulation
+    clock: Clock,
+    step_mod: StepMod,
+    // Takes held keys over when enabled
+    arp: Arpeggiator,
+    sequencer: Sequencer,
+    // Holds note offs while the pedal is down
+    sustain: Sustain,
+    // Smoothed level of the current step
+    step_value: f32,
+    // Velocity of the last note
+    velocity: f32,
+    // Part of note level that follows velocity
+    velocity_sensitivity: f32,
+    // ms, 0.0 turns glide off
+    glide_time: f32,
+    // Glide only between overlapping notes
+    glide_legato: bool,
+    glide_curve: GlideCurve,
+    // New notes glide from it
+    last_freq: Option<f32>,
+    // Random mod source value of the latest note
+    last_random: f32,
+    mod_wheel: f32,
+    // -1.0-1.0, the wheel position and smoothed bend following it
+    pitch_bend_target: f32,
+    pitch_bend: f32,
+    // Semitones
+    bend_range: f32,
+    // 0.0-1.0, the pedal position and smoothed value following it
+    expression_target: f32,
+    expression: f32,
+    expression_destination: ExpressionDestination,
+    expression_curve: ExpressionCurve,
+    // Pedal position after the curve
+    expression_value: f32,
+    // Plays audition notes apart from the patch
+    audition: Oscillator,
+    audition_left: usize,
+    // Rendered envelope preview and the next frame of it to play
+    preview: Vec<f32>,
+    preview_pos: usize,
+    // Passes frames sent to output to the writer thread
+    recorder: Option<Recorder<SampleType>>,
+    // Seconds, MIDI notes held longer are released. 0.0 turns it off
+    stuck_note_timeout: f32,
+    // Counts down to the next stuck notes check
+    stuck_check_left: usize,
+    // Voices released as stuck since it was last asked, UI reports them
+    stuck_released: usize,
+    // Frames left until modulation is worked out again
+    mod_left: usize,
+    // Cutoff offset in octaves from the last block, without filter envelope
+    cutoff_mod: f32,
+    // 0.0-1.0 position in the output fade
+    fade: f32,
+    fading_out: bool,
+    // Master mute of the panic hotkey, output ramps to its target over FADE_MS
+    muted: bool,
+    mute_gain: f32,
+    // Soft clipper bent the output since it was last asked
+    clipped: bool,
     _sample_type: std::marker::PhantomData<SampleType>,
 }
 
@@ -72,31 +317,187 @@ impl<SampleType: SampleFormat> Synth<SampleType> {
     pub fn new(sample_rate: f32) -> Self {
         Self {
             sample_rate: sample_rate,
-            volume: 1024.0,
+            volume: 10f32.powf(DEFAULT_VOLUME as f32 / 20.0),
+            gain_trim: 1.0,
+            low_cut: osc_constraints::DEFAULT_LOW_CUT,
+            high_quality: false,
+            max_voices: osc_constraints::MAX_VOICES,
+            voice_stealing: VoiceStealing::ReleasedFirst,
+            fm_amount: 0.0,
             oscillators: Vec::new(),
+            solo: None,
+            solo_gains: Vec::new(),
+            binaural: false,
+            interaural: Vec::new(),
             envelopes: Vec::new(),
+            lfos: Vec::new(),
+            filter: Filter::new(
+                sample_rate,
+                filter_constraints::MAX_CUTOFF,
+                filter_constraints::MIN_RESONANCE,
+            ),
+            dc_blocker: DcBlocker::new(sample_rate),
+            test_tone: TestTone::new(sample_rate),
+            filter_env_idx: 0,
+            filter_env_amount: 0.0,
+            mod_slots: [ModSlot::new(); MOD_SLOTS],
+            slot_depths: [0.0; MOD_SLOTS],
+            env_states: Vec::new(),
+            env_values: Vec::new(),
+            lfo_values: Vec::new(),
+            chaos: Chaos::new(chaos_constraints::DEFAULT_RATE),
+            chaos_value: 0.0,
+            clock: Clock::new(clock_constraints::DEFAULT_TEMPO),
+            step_mod: StepMod::new(),
+            arp: Arpeggiator::new(),
+            sequencer: Sequencer::new(),
+            sustain: Sustain::new(),
+            step_value: 1.0,
+            velocity: 1.0,
+            velocity_sensitivity: osc_constraints::DEFAULT_VELOCITY_SENSITIVITY,
+            glide_time: 0.0,
+            glide_legato: false,
+            glide_curve: GlideCurve::Linear,
+            last_freq: None,
+            last_random: 0.0,
+            mod_wheel: 0.0,
+            pitch_bend_target: 0.0,
+            pitch_bend: 0.0,
+            bend_range: bend_constraints::DEFAULT_RANGE as f32,
+            // Synth sounds as usual until the pedal is touched
+            expression_target: 1.0,
+            expression: 1.0,
+            expression_destination: ExpressionDestination::Volume,
+            expression_curve: ExpressionCurve::Linear,
+            expression_value: 1.0,
+            audition: Oscillator::new(sample_rate, WaveForm::Saw, 0, 0.5),
+            audition_left: 0,
+            preview: Vec::new(),
+            preview_pos: 0,
+            recorder: None,
+            stuck_note_timeout: 0.0,
+            stuck_check_left: 0,
+            stuck_released: 0,
+            mod_left: 0,
+            cutoff_mod: 0.0,
+            fade: 0.0,
+            fading_out: false,
+            muted: false,
+            mute_gain: 1.0,
+            clipped: false,
             _sample_type: std::marker::PhantomData,
         }
     }
 
-    pub fn add_osc(&mut self, osc: Oscillator) {
-        self.oscillators.push(osc)
+    // Parts are built on the UI thread, output rate could change meanwhile
+    pub fn add_osc(&mut self, mut osc: Oscillator) {
+        osc.set_sample_rate(self.sample_rate);
+        osc.set_low_cut(self.low_cut);
+        osc.high_quality = self.high_quality;
+        osc.set_max_voices(self.max_voices);
+        osc.stealing = self.voice_stealing;
+        self.oscillators.push(osc);
+        self.solo_gains.push(1.0);
+        self.interaural.push(Interaural::new(self.sample_rate));
     }
 
-    pub fn add_env(&mut self, env: ADSR) {
-        self.envelopes.push(env)
+    // Solo, LFO targets and mod destinations follow their oscillator or end with it,
+    // FM turns off once its pair is broken up. Removed oscillator is handed back
+    // to be freed off the audio thread
+    pub fn remove_osc(&mut self, osc_idx: usize) -> Option<Oscillator> {
+        if osc_idx >= self.oscillators.len() || self.oscillators.len() <= osc_constraints::MIN_OSCILLATORS {
+            return None
+        }
+        let removed = self.oscillators.remove(osc_idx);
+        self.solo_gains.remove(osc_idx);
+        self.interaural.remove(osc_idx);
+        self.solo = self.solo.and_then(|solo| osc_idx_after_removal(solo, osc_idx));
+        for lfo in self.lfos.iter_mut() {
+            lfo.target = lfo.target.after_osc_removal(osc_idx);
+        }
+        for slot in self.mod_slots.iter_mut() {
+            slot.destination = slot.destination.after_osc_removal(osc_idx);
+        }
+        if in_fm_pair(osc_idx) {
+            self.fm_amount = 0.0;
+        }
+        Some(removed)
+    }
+
+    pub fn add_env(&mut self, mut env: ADSR) {
+        env.set_sample_rate(self.sample_rate);
+        self.envelopes.push(env);
+        self.env_states.push(EnvState::new());
+        self.env_values.push(0.0);
+    }
+
+    // The last envelope stays, oscillators and the filter need one
+    pub fn remove_env(&mut self, env_idx: usize) {
+        if env_idx >= self.envelopes.len() || self.envelopes.len() <= adsr_constraints::MIN_ENVELOPES {
+            return
+        }
+        self.envelopes.remove(env_idx);
+        self.env_states.remove(env_idx);
+        self.env_values.remove(env_idx);
+        for osc in self.oscillators.iter_mut() {
+            osc.env_idx = env_idx_after_removal(osc.env_idx, env_idx);
+        }
+        self.audition.env_idx = env_idx_after_removal(self.audition.env_idx, env_idx);
+        self.filter_env_idx = env_idx_after_removal(self.filter_env_idx, env_idx);
+    }
+
+    pub fn add_lfo(&mut self, mut lfo: Lfo) {
+        lfo.set_sample_rate(self.sample_rate);
+        self.lfos.push(lfo);
+        self.lfo_values.push(0.0);
     }
 
     pub fn set_unisons(&mut self, osc_idx: usize, num: usize) {
         self.oscillators[osc_idx].set_unison_num(num);
     }
 
-    pub fn set_transpose(&mut self, osc_idx: usize, semitones: i8) {
+    pub fn set_supersaw(&mut self, osc_idx: usize, enabled: bool) {
+        self.oscillators[osc_idx].set_supersaw(enabled);
+    }
+
+    pub fn set_supersaw_detune(&mut self, osc_idx: usize, detune: f32) {
+        self.oscillators[osc_idx].set_supersaw_detune(detune);
+    }
+
+    pub fn set_supersaw_mix(&mut self, osc_idx: usize, mix: f32) {
+        self.oscillators[osc_idx].set_supersaw_mix(mix);
+    }
+
+    pub fn set_unison_width(&mut self, osc_idx: usize, width: f32) {
+        self.oscillators[osc_idx].set_unison_width(width);
+    }
+
+    pub fn set_width_time(&mut self, osc_idx: usize, ms: f32) {
+        self.oscillators[osc_idx].set_width_time(ms);
+    }
+
+    pub fn set_transpose(&mut self, osc_idx: usize, semitones: i8) -> Result<()> {
+        if semitones < osc_constraints::MIN_TRANSPOSE || semitones > osc_constraints::MAX_TRANSPOSE {
+            return Err(BaseError::SynthError(format!(
+                "[{}, {}] semitones is the range for transpose",
+                osc_constraints::MIN_TRANSPOSE,
+                osc_constraints::MAX_TRANSPOSE
+            )));
+        }
         self.oscillators[osc_idx].transpose(semitones);
+        Ok(())
     }
 
-    pub fn set_tune(&mut self, osc_idx: usize, cents: i8) {
+    pub fn set_tune(&mut self, osc_idx: usize, cents: i8) -> Result<()> {
+        if cents < osc_constraints::MIN_TUNE || cents > osc_constraints::MAX_TUNE {
+            return Err(BaseError::SynthError(format!(
+                "[{}, {}] cents is the range for tune",
+                osc_constraints::MIN_TUNE,
+                osc_constraints::MAX_TUNE
+            )));
+        }
         self.oscillators[osc_idx].tune(cents);
+        Ok(())
     }
 
     pub fn set_volume(&mut self, volume: dB) -> Result<()> {
@@ -105,33 +506,418 @@ impl<SampleType: SampleFormat> Synth<SampleType> {
                 "[-96, 0] dB is the range for volume".to_owned(),
             ));
         }
-        self.volume = SampleType::max_value().as_() * 10f32.powf(volume as f32 / 20.0);
+        self.volume = 10f32.powf(volume as f32 / 20.0);
         Ok(())
     }
 
+    pub fn set_pan(&mut self, osc_idx: usize, pan: f32) {
+        self.oscillators[osc_idx].set_pan(pan);
+    }
+
     pub fn set_osc_volume(&mut self, osc_idx: usize, volume: f32) {
         self.oscillators[osc_idx].volume = volume;
     }
 
-    pub fn note_on(&mut self, freq: f32, key: KeyCode) {
-        let note = Note::new(freq, key);
+    // Hz, lower it for bass-heavy patches
+    pub fn set_low_cut(&mut self, freq: f32) {
+        for osc in self.oscillators.iter_mut() {
+            osc.set_low_cut(freq);
+        }
+        self.low_cut = freq;
+    }
+
+    pub fn set_high_quality(&mut self, enabled: bool) {
+        for osc in self.oscillators.iter_mut() {
+            osc.high_quality = enabled;
+        }
+        self.audition.high_quality = enabled;
+        self.high_quality = enabled;
+    }
+
+    // Frequency in Hz and level in dBFS, None stops it
+    pub fn set_test_tone(&mut self, tone: Option<(f32, dB)>) {
+        match tone {
+            Some((freq, level)) => self.test_tone.set(freq, level),
+            None => self.test_tone.stop(),
+        }
+    }
+
+    pub fn set_binaural(&mut self, enabled: bool) {
+        self.binaural = enabled;
+    }
+
+    pub fn set_dc_blocker(&mut self, enabled: bool) {
+        self.dc_blocker.set_enabled(enabled);
+    }
+
+    pub fn set_max_voices(&mut self, voices: usize) {
+        for osc in self.oscillators.iter_mut() {
+            osc.set_max_voices(voices);
+        }
+        self.max_voices = voices;
+    }
+
+    pub fn set_voice_stealing(&mut self, stealing: VoiceStealing) {
+        for osc in self.oscillators.iter_mut() {
+            osc.stealing = stealing;
+        }
+        self.voice_stealing = stealing;
+    }
+
+    // Momentary, so it isn't part of the patch. None brings all oscillators back
+    pub fn set_solo(&mut self, osc_idx: Option<usize>) {
+        self.solo = osc_idx.filter(|idx| *idx < self.oscillators.len());
+    }
+
+    pub fn set_fm_amount(&mut self, amount: f32) {
+        self.fm_amount = amount
+            .max(osc_constraints::MIN_FM_AMOUNT)
+            .min(osc_constraints::MAX_FM_AMOUNT);
+    }
+
+    // Comes from auto_gain_trim, 1.0 turns auto gain off
+    pub fn set_gain_trim(&mut self, trim: f32) {
+        self.gain_trim = trim;
+    }
+
+    pub fn note_on(&mut self, freq: f32, source: NoteSource) {
+        self.sustain.forget(source);
+        if self.arp.enabled && source != NoteSource::Audition {
+            if self.arp.press(source, freq, self.velocity) {
+                self.clock.restart();
+            }
+            return;
+        }
+        self.start_note(freq, source);
+    }
+
+    fn start_note(&mut self, freq: f32, source: NoteSource) {
+        let mut note = Note::new(freq, self.velocity, source);
+        // Squared, so level follows velocity closer to how loud it sounds
+        note.level = 1.0 - self.velocity_sensitivity * (1.0 - self.velocity * self.velocity);
+        if let Some(last_freq) = self.last_freq {
+            let legato = self.oscillators.iter().any(|osc| osc.has_held_voices());
+            if self.glide_time > 0.0 && (legato || !self.glide_legato) {
+                note.glide = Glide::new(last_freq, freq, self.glide_time, self.glide_curve, self.sample_rate);
+            }
+        }
+        self.last_freq = Some(freq);
+        self.last_random = note.random;
+        // Synced modulation starts from the first step with every new phrase.
+        // Generated notes don't overlap, their phrase starts with the first key
+        let generated = matches!(source, NoteSource::Arp | NoteSource::Sequencer);
+        if !generated && !self.oscillators.iter().any(|osc| osc.has_held_voices()) {
+            self.clock.restart();
+        }
+        // Velocity zones pick which oscillators play the note
+        let velocity = (note.velocity * osc_constraints::MAX_VELOCITY as f32).round() as u8;
         self.oscillators
             .iter_mut()
-            .for_each(|osc| osc.create_voice(&note))
+            .filter(|osc| osc.plays_velocity(velocity))
+            .for_each(|osc| osc.create_voice(&note));
+        self.env_states.iter_mut().for_each(|env| env.trigger());
+    }
+
+    // ms, applies to the next note
+    pub fn set_glide_time(&mut self, time: f32) {
+        self.glide_time = time
+            .max(glide_constraints::MIN_TIME)
+            .min(glide_constraints::MAX_TIME);
+    }
+
+    pub fn set_glide_legato(&mut self, legato: bool) {
+        self.glide_legato = legato;
+    }
+
+    pub fn set_glide_curve(&mut self, curve: GlideCurve) {
+        self.glide_curve = curve;
+    }
+
+    // 0.0-1.0, applies to the next note
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.velocity = velocity.max(0.0).min(1.0);
+    }
+
+    // 0.0-1.0, applies to the next note
+    pub fn set_velocity_sensitivity(&mut self, sensitivity: f32) {
+        self.velocity_sensitivity = sensitivity
+            .max(osc_constraints::MIN_VELOCITY_SENSITIVITY)
+            .min(osc_constraints::MAX_VELOCITY_SENSITIVITY);
+    }
+
+    // 0.0-1.0, pressure of a single held key
+    pub fn set_aftertouch(&mut self, source: NoteSource, pressure: f32) {
+        let pressure = pressure.max(0.0).min(1.0);
+        for osc in self.oscillators.iter_mut() {
+            osc.set_pressure(source, pressure);
+        }
+    }
+
+    // 0.0-1.0
+    pub fn set_mod_wheel(&mut self, value: f32) {
+        self.mod_wheel = value.max(0.0).min(1.0);
+    }
+
+    // Semitones the pitch moves at either end of the bend wheel
+    pub fn set_bend_range(&mut self, semitones: f32) {
+        self.bend_range = semitones
+            .max(bend_constraints::MIN_RANGE as f32)
+            .min(bend_constraints::MAX_RANGE as f32);
+    }
+
+    // -1.0-1.0, 0.0 is the centered wheel
+    pub fn set_pitch_bend(&mut self, value: f32) {
+        self.pitch_bend_target = value.max(-1.0).min(1.0);
+    }
+
+    // 0.0-1.0, heel down to toe down
+    pub fn set_expression(&mut self, value: f32) {
+        self.expression_target = value.max(0.0).min(1.0);
+    }
+
+    pub fn set_expression_destination(&mut self, destination: ExpressionDestination) {
+        self.expression_destination = destination;
     }
 
-    pub fn note_off(&mut self, key: KeyCode) {
+    pub fn set_expression_curve(&mut self, curve: ExpressionCurve) {
+        self.expression_curve = curve;
+    }
+
+    pub fn set_osc_env_amount(&mut self, osc_idx: usize, amount: f32) {
+        self.oscillators[osc_idx].set_env_amount(amount);
+    }
+
+    pub fn set_osc_sub(&mut self, osc_idx: usize, enabled: bool) {
+        self.oscillators[osc_idx].set_sub(enabled);
+    }
+
+    pub fn set_osc_sub_wave(&mut self, osc_idx: usize, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.oscillators[osc_idx].set_sub_wave(waveform, wave)
+    }
+
+    pub fn set_osc_sub_level(&mut self, osc_idx: usize, level: f32) {
+        self.oscillators[osc_idx].set_sub_level(level);
+    }
+
+    pub fn set_osc_phase_offset(&mut self, osc_idx: usize, degrees: f32) {
+        self.oscillators[osc_idx].set_phase_offset(degrees);
+    }
+
+    pub fn set_osc_start(&mut self, osc_idx: usize, start: Start) {
+        self.oscillators[osc_idx].set_start(start);
+    }
+
+    pub fn set_osc_tone(&mut self, osc_idx: usize, enabled: bool) {
+        self.oscillators[osc_idx].set_tone(enabled);
+    }
+
+    pub fn set_osc_tilt(&mut self, osc_idx: usize, tilt: f32) {
+        self.oscillators[osc_idx].set_tilt(tilt);
+    }
+
+    pub fn set_osc_engine(&mut self, osc_idx: usize, engine: OscEngine) {
+        self.oscillators[osc_idx].set_engine(engine);
+    }
+
+    // Replaced strings are handed back to be freed off the audio thread
+    pub fn set_osc_pluck_strings(&mut self, osc_idx: usize, strings: Vec<PluckString>) -> Vec<PluckString> {
+        self.oscillators[osc_idx].set_pluck_strings(strings)
+    }
+
+    pub fn set_osc_pluck_damping(&mut self, osc_idx: usize, damping: f32) {
+        self.oscillators[osc_idx].set_pluck_damping(damping);
+    }
+
+    pub fn set_osc_harmonics(&mut self, osc_idx: usize, levels: [f32; HARMONICS], wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.oscillators[osc_idx].set_harmonics(levels, wave)
+    }
+
+    pub fn set_osc_pulse_width(&mut self, osc_idx: usize, width: f32) {
+        self.oscillators[osc_idx].set_pulse_width(width);
+    }
+
+    pub fn set_osc_velocity_range(&mut self, osc_idx: usize, low: u8, high: u8) {
+        self.oscillators[osc_idx].set_velocity_range(low, high);
+    }
+
+    pub fn set_osc_bend(&mut self, osc_idx: usize, enabled: bool) {
+        self.oscillators[osc_idx].bend = enabled;
+    }
+
+    pub fn set_osc_drone(&mut self, osc_idx: usize, enabled: bool) {
+        self.oscillators[osc_idx].set_drone(enabled);
+        if !self.oscillators.iter().any(|osc| osc.has_held_voices()) {
+            self.env_states.iter_mut().for_each(|env| env.release());
+        }
+    }
+
+    pub fn note_off(&mut self, source: NoteSource) {
+        if self.sustain.defer(source) {
+            return;
+        }
+        self.release_key(source);
+    }
+
+    fn release_key(&mut self, source: NoteSource) {
+        if self.arp.enabled && self.arp.release(source) {
+            return;
+        }
+        self.stop_note(source);
+    }
+
+    // MIDI CC64, keys released while it's down sound until it goes up
+    pub fn set_sustain(&mut self, down: bool) {
+        self.sustain.set_down(down);
+        while let Some(source) = self.sustain.next_released() {
+            self.release_key(source);
+        }
+    }
+
+    fn stop_note(&mut self, source: NoteSource) {
         self.oscillators
             .iter_mut()
-            .for_each(|osc| osc.voice_off(key))
+            .for_each(|osc| osc.voice_off(source));
+        if !self.oscillators.iter().any(|osc| osc.has_held_voices()) {
+            self.env_states.iter_mut().for_each(|env| env.release());
+        }
+    }
+
+    // MIDI All Notes Off: every held note goes to release
+    pub fn all_notes_off(&mut self) {
+        self.arp.clear();
+        self.sustain.clear();
+        for osc in self.oscillators.iter_mut() {
+            osc.release_voices(|_| true);
+        }
+        self.env_states.iter_mut().for_each(|env| env.release());
+    }
+
+    // MIDI All Sound Off: everything stops right away, release tails too
+    pub fn all_sound_off(&mut self) {
+        self.arp.clear();
+        self.sustain.clear();
+        for osc in self.oscillators.iter_mut() {
+            osc.silence();
+        }
+        self.audition.silence();
+        self.audition_left = 0;
+        self.preview_pos = self.preview.len();
+        self.env_states.iter_mut().for_each(|env| *env = EnvState::new());
+    }
+
+    pub fn set_stuck_note_timeout(&mut self, seconds: f32) {
+        self.stuck_note_timeout = seconds.max(0.0);
+    }
+
+    // Some MIDI devices lose note-off messages, such notes would play forever.
+    // Note off from a duet partner can be lost on the network the same way
+    fn release_stuck_notes(&mut self) {
+        let max_age = (self.stuck_note_timeout * self.sample_rate) as u32;
+        let stuck = |note: &Note| matches!(note.triggered_by, NoteSource::Midi(_) | NoteSource::Remote(_)) && note.age >= max_age;
+        // Droning oscillators hold their notes on purpose
+        let released: usize = self
+            .oscillators
+            .iter_mut()
+            .filter(|osc| !osc.drone)
+            .map(|osc| osc.release_voices(stuck))
+            .sum();
+        if released > 0 {
+            self.stuck_released += released;
+            if !self.oscillators.iter().any(|osc| osc.has_held_voices()) {
+                self.env_states.iter_mut().for_each(|env| env.release());
+            }
+        }
+    }
+
+    pub fn take_clipped(&mut self) -> bool {
+        std::mem::take(&mut self.clipped)
+    }
+
+    pub fn take_stuck_released(&mut self) -> usize {
+        std::mem::take(&mut self.stuck_released)
     }
 
     pub fn playing(&self) -> bool {
         self.oscillators.iter().any(|osc| osc.has_active_voices())
+            || self.audition.has_active_voices()
+            || self.preview_pos < self.preview.len()
+            || self.sequencer.running()
+            || self.recorder.is_some()
+            || self.test_tone.enabled
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.oscillators.iter().map(|osc| osc.voice_count()).sum()
     }
 
-    pub fn set_waveform(&mut self, osc_idx: usize, waveform: &WaveForm) {
-        self.oscillators[osc_idx].set_waveform(waveform);
+    // Output stream has to be reopened with the same rate
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for osc in self.oscillators.iter_mut() {
+            osc.set_sample_rate(sample_rate);
+        }
+        self.audition.set_sample_rate(sample_rate);
+        for env in self.envelopes.iter_mut() {
+            env.set_sample_rate(sample_rate);
+        }
+        for lfo in self.lfos.iter_mut() {
+            lfo.set_sample_rate(sample_rate);
+        }
+        self.filter.set_sample_rate(sample_rate);
+        self.dc_blocker.set_sample_rate(sample_rate);
+        self.test_tone.set_sample_rate(sample_rate);
+        for interaural in self.interaural.iter_mut() {
+            interaural.set_sample_rate(sample_rate);
+        }
+    }
+
+    // Recorder comes with its chunks allocated, a recording that's still going is finished
+    pub fn start_recording(&mut self, recorder: Recorder<SampleType>) {
+        recorder.start(self.sample_rate);
+        if let Some(previous) = self.recorder.replace(recorder) {
+            previous.finish();
+        }
+    }
+
+    // The rest of recorded frames goes to the writer thread
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish();
+        }
+    }
+
+    // Chunk is handed back once recording is over or there's no room for it
+    pub fn recycle_recording_chunk(&mut self, chunk: Vec<[SampleType; 2]>) -> Option<Vec<[SampleType; 2]>> {
+        match self.recorder.as_mut() {
+            Some(recorder) => recorder.recycle(chunk),
+            None => Some(chunk),
+        }
+    }
+
+    // Plays a short note with the given waveform through the first envelope,
+    // the wave of the previous audition is handed back
+    pub fn audition(&mut self, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.audition.voice_off(NoteSource::Audition);
+        let old = self.audition.set_wave(waveform, wave);
+        self.audition
+            .create_voice(&Note::new(AUDITION_NOTE, 1.0, NoteSource::Audition));
+        self.audition_left = (AUDITION_MS / 1000.0 * self.sample_rate) as usize;
+        old
+    }
+
+    // Preview comes from render_env_preview, the previous one is handed back
+    // to be freed off the audio thread
+    pub fn play_preview(&mut self, preview: Vec<f32>) -> Vec<f32> {
+        self.preview_pos = 0;
+        std::mem::replace(&mut self.preview, preview)
+    }
+
+    pub fn set_waveform(&mut self, osc_idx: usize, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.oscillators[osc_idx].set_wave(waveform, wave)
     }
 
     pub fn set_env_parameter(&mut self, env_idx: usize, param: ADSRParam) {
@@ -141,22 +927,777 @@ impl<SampleType: SampleFormat> Synth<SampleType> {
     pub fn set_env(&mut self, osc_idx: usize, env_idx: usize) {
         self.oscillators[osc_idx].env_idx = env_idx;
     }
-}
 
-impl<SampleType: SampleFormat> Iterator for Synth<SampleType> {
-    type Item = SampleType;
+    pub fn set_lfo_waveform(&mut self, lfo_idx: usize, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.lfos[lfo_idx].set_wave(waveform, wave)
+    }
+
+    pub fn set_lfo_rate(&mut self, lfo_idx: usize, rate: f32) {
+        self.lfos[lfo_idx].set_rate(rate);
+    }
+
+    pub fn set_lfo_depth(&mut self, lfo_idx: usize, depth: f32) {
+        self.lfos[lfo_idx].set_depth(depth);
+    }
+
+    pub fn set_lfo_target(&mut self, lfo_idx: usize, target: LfoTarget) {
+        self.lfos[lfo_idx].set_target(target);
+    }
+
+    pub fn set_filter_cutoff(&mut self, cutoff: f32) {
+        self.filter.set_cutoff(cutoff);
+    }
+
+    pub fn set_filter_resonance(&mut self, resonance: f32) {
+        self.filter.set_resonance(resonance);
+    }
+
+    pub fn set_filter_mode(&mut self, mode: FilterMode) {
+        self.filter.set_mode(mode);
+    }
+
+    pub fn set_filter_env(&mut self, env_idx: usize) {
+        self.filter_env_idx = env_idx;
+    }
+
+    pub fn set_filter_env_amount(&mut self, amount: f32) {
+        self.filter_env_amount = amount
+            .max(filter_constraints::MIN_ENV_AMOUNT)
+            .min(filter_constraints::MAX_ENV_AMOUNT);
+    }
+
+    pub fn set_mod_route(&mut self, slot_idx: usize, source: ModSource, destination: ModDestination) {
+        self.mod_slots[slot_idx].source = source;
+        self.mod_slots[slot_idx].destination = destination;
+    }
+
+    // BPM
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.clock.set_tempo(tempo);
+    }
+
+    pub fn set_step_level(&mut self, step: usize, level: f32) {
+        self.step_mod.set_level(step, level);
+    }
+
+    pub fn set_step_division(&mut self, division: StepDivision) {
+        self.step_mod.division = division;
+    }
+
+    // Keys held while it's turned off keep sounding, the ones
+    // arpeggiated are dropped
+    pub fn set_arp_enabled(&mut self, enabled: bool) {
+        if self.arp.enabled && !enabled {
+            self.arp.clear();
+            self.stop_note(NoteSource::Arp);
+        }
+        self.arp.enabled = enabled;
+    }
+
+    pub fn set_arp_mode(&mut self, mode: ArpMode) {
+        self.arp.mode = mode;
+    }
+
+    pub fn set_arp_synced(&mut self, synced: bool) {
+        self.arp.synced = synced;
+    }
+
+    // ms per step
+    pub fn set_arp_rate(&mut self, rate: f32) {
+        self.arp.set_rate(rate);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // let sample = self
-        //     .oscillators
-        //     .iter_mut()
-        //     .map(|osc| osc.get_sample())
-        //     .sum::<f32>()
-        //     * self.volume;
-        let mut sample: f32 = 0.0;
+    pub fn set_arp_division(&mut self, division: StepDivision) {
+        self.arp.division = division;
+    }
+
+    pub fn set_arp_octaves(&mut self, octaves: usize) {
+        self.arp.set_octaves(octaves);
+    }
+
+    // Part of a step
+    pub fn set_arp_gate(&mut self, gate: f32) {
+        self.arp.set_gate(gate);
+    }
+
+    pub fn set_seq_running(&mut self, running: bool) {
+        self.sequencer.set_running(running);
+    }
+
+    pub fn set_seq_division(&mut self, division: StepDivision) {
+        self.sequencer.division = division;
+    }
+
+    // Semitones from C4
+    pub fn set_seq_pitch(&mut self, step: usize, pitch: i32) {
+        self.sequencer.set_pitch(step, pitch);
+    }
+
+    pub fn set_seq_gate(&mut self, step: usize, on: bool) {
+        self.sequencer.steps[step % STEPS].on = on;
+    }
+
+    pub fn set_seq_accent(&mut self, step: usize, accent: bool) {
+        self.sequencer.steps[step % STEPS].accent = accent;
+    }
+
+    pub fn set_chaos_rate(&mut self, rate: f32) {
+        self.chaos.set_rate(rate);
+    }
+
+    pub fn set_mod_depth(&mut self, slot_idx: usize, depth: f32) {
+        self.mod_slots[slot_idx].set_depth(depth);
+    }
+
+    pub fn set_mod_depth_source(&mut self, slot_idx: usize, source: ModSource) {
+        self.mod_slots[slot_idx].depth_source = source;
+    }
+
+    fn source_value(&self, source: ModSource) -> f32 {
+        match source {
+            ModSource::None => 0.0,
+            ModSource::Lfo(idx) => self.lfo_values[idx],
+            // Mod matrix can name an envelope that was removed
+            ModSource::Env(idx) => self.env_values.get(idx).copied().unwrap_or(0.0),
+            ModSource::Velocity => self.velocity,
+            ModSource::ModWheel => self.mod_wheel,
+            ModSource::Expression => self.expression_value,
+            ModSource::Chaos => self.chaos_value,
+            ModSource::Steps => self.step_value,
+            // Shared destinations follow the latest note
+            ModSource::Random => self.last_random,
+            // Filter and panning are shared by voices, the hardest pressed key drives them
+            ModSource::Aftertouch => self
+                .oscillators
+                .iter()
+                .map(|osc| osc.max_pressure())
+                .fold(0.0, f32::max),
+        }
+    }
+
+    // Depth of a slot scaled by its depth source, bipolar sources are brought to [0, 1]
+    fn slot_depth(&self, slot: &ModSlot) -> f32 {
+        if slot.depth_source == ModSource::None {
+            return slot.depth;
+        }
+        let value = self.source_value(slot.depth_source);
+        let scale = if slot.depth_source.bipolar() { (value + 1.0) / 2.0 } else { value };
+        slot.depth * scale
+    }
+
+    // Rate multiplier and depth offset of an LFO from mod matrix slots routed to it
+    fn lfo_modulation(&self, lfo_idx: usize) -> (f32, f32) {
+        let (mut rate_octaves, mut depth) = (0.0, 0.0);
+        for (slot, slot_depth) in self.mod_slots.iter().zip(self.slot_depths.iter()) {
+            if !slot.active() {
+                continue;
+            }
+            match slot.destination {
+                ModDestination::LfoRate(idx) if idx == lfo_idx => {
+                    rate_octaves += self.source_value(slot.source) * slot_depth * MOD_LFO_RATE_OCTAVES
+                }
+                ModDestination::LfoDepth(idx) if idx == lfo_idx => {
+                    depth += self.source_value(slot.source) * slot_depth
+                }
+                _ => {}
+            }
+        }
+        (2f32.powf(rate_octaves), depth)
+    }
+
+    // Collects modulation of the next frames from LFO targets and mod matrix,
+    // returns filter cutoff offset in octaves
+    fn modulate(&mut self, frames: usize) -> f32 {
+        let smoothing = 1.0 - (-(frames as f32) / (CONTROLLER_SMOOTHING_MS / 1000.0 * self.sample_rate)).exp();
+        self.pitch_bend += (self.pitch_bend_target - self.pitch_bend) * smoothing;
+        self.expression += (self.expression_target - self.expression) * smoothing;
+        self.expression_value = self.expression_curve.apply(self.expression);
         for osc in self.oscillators.iter_mut() {
-            sample += osc.get_sample(&self.envelopes[osc.env_idx]);
+            osc.reset_modulation();
+            if osc.bend {
+                osc.modulate_pitch(self.pitch_bend * self.bend_range);
+            }
+        }
+        self.chaos_value = self.chaos.advance(frames, self.sample_rate);
+        let step_smoothing = 1.0 - (-(frames as f32) / (STEP_SMOOTHING_MS / 1000.0 * self.sample_rate)).exp();
+        self.step_value += (self.step_mod.value(&self.clock) - self.step_value) * step_smoothing;
+        self.clock.advance(frames, self.sample_rate);
+        // Filter envelope is added every sample in next_frame
+        let mut cutoff_mod = 0.0;
+
+        // Depths go first, so every route is applied with depths of the same moment
+        // whatever order the slots are in. LFOs scaling a depth are a block behind
+        let mut slot_depths = [0.0; MOD_SLOTS];
+        for (depth, slot) in slot_depths.iter_mut().zip(self.mod_slots.iter()) {
+            *depth = self.slot_depth(slot);
+        }
+        self.slot_depths = slot_depths;
+
+        for idx in 0..self.lfos.len() {
+            let (rate_mod, depth_mod) = self.lfo_modulation(idx);
+            let lfo = &mut self.lfos[idx];
+            let value = lfo.advance(frames, rate_mod);
+            self.lfo_values[idx] = value;
+            let depth = (lfo.depth + depth_mod)
+                .max(lfo_constraints::MIN_DEPTH)
+                .min(lfo_constraints::MAX_DEPTH);
+            match lfo.target {
+                LfoTarget::None => {}
+                LfoTarget::OscPitch(idx) => self.oscillators[idx]
+                    .modulate_pitch(value * depth * MOD_PITCH_SEMITONES),
+                LfoTarget::OscVolume(idx) => self.oscillators[idx]
+                    .modulate_volume(modulation::volume_gain(value, true, depth)),
+                LfoTarget::FilterCutoff => cutoff_mod += value * depth * MOD_CUTOFF_OCTAVES,
+                LfoTarget::OscPulseWidth(idx) => self.oscillators[idx].modulate_pulse_width(value * depth),
+            }
+        }
+
+        for slot_idx in 0..MOD_SLOTS {
+            let slot = self.mod_slots[slot_idx];
+            if !slot.active() {
+                continue;
+            }
+            let depth = self.slot_depths[slot_idx];
+            if slot.source == ModSource::Aftertouch {
+                match slot.destination {
+                    ModDestination::OscPitch(idx) => {
+                        self.oscillators[idx].modulate_pitch_by_pressure(depth * MOD_PITCH_SEMITONES);
+                        continue;
+                    }
+                    ModDestination::OscVolume(idx) => {
+                        self.oscillators[idx].modulate_volume_by_pressure(depth);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            if slot.source == ModSource::Random {
+                match slot.destination {
+                    ModDestination::OscPitch(idx) => {
+                        self.oscillators[idx].modulate_pitch_by_random(depth * MOD_PITCH_SEMITONES);
+                        continue;
+                    }
+                    ModDestination::OscVolume(idx) => {
+                        self.oscillators[idx].modulate_volume_by_random(depth);
+                        continue;
+                    }
+                    ModDestination::OscPan(idx) => {
+                        self.oscillators[idx].modulate_pan_by_random(depth);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            let value = self.source_value(slot.source);
+            let amount = value * depth;
+            match slot.destination {
+                ModDestination::None => {}
+                ModDestination::OscPitch(idx) => {
+                    self.oscillators[idx].modulate_pitch(amount * MOD_PITCH_SEMITONES)
+                }
+                ModDestination::OscVolume(idx) => self.oscillators[idx].modulate_volume(
+                    modulation::volume_gain(value, slot.source.bipolar(), depth),
+                ),
+                ModDestination::OscPan(idx) => self.oscillators[idx].modulate_pan(amount),
+                ModDestination::OscPulseWidth(idx) => {
+                    self.oscillators[idx].modulate_pulse_width(amount)
+                }
+                ModDestination::FilterCutoff => cutoff_mod += amount * MOD_CUTOFF_OCTAVES,
+                // Applied while LFOs are advanced
+                ModDestination::LfoRate(_) | ModDestination::LfoDepth(_) => {}
+            }
+        }
+        cutoff_mod
+    }
+}
+
+impl<SampleType: SampleFormat> Synth<SampleType> {
+    // Next stream starts with a fade-in
+    pub fn restart_fade(&mut self) {
+        self.fade = 0.0;
+        self.fading_out = false;
+    }
+
+    // Everything is silenced once the output has faded out, so the stream stops
+    pub fn fade_out(&mut self) {
+        self.fading_out = true;
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    // Panic: every sound is cut and the output stays muted until unmuted
+    pub fn panic(&mut self) {
+        self.all_sound_off();
+        self.muted = true;
+    }
+
+    fn mute_gain(&mut self) -> f32 {
+        let target = if self.muted { 0.0 } else { 1.0 };
+        if self.mute_gain != target {
+            let step = 1000.0 / (FADE_MS * self.sample_rate);
+            self.mute_gain += (target - self.mute_gain).max(-step).min(step);
+        }
+        self.mute_gain
+    }
+
+    fn fade_gain(&mut self) -> f32 {
+        if self.fade >= 1.0 && !self.fading_out {
+            return 1.0;
+        }
+        let step = 1000.0 / (FADE_MS * self.sample_rate);
+        if self.fading_out {
+            self.fade = (self.fade - step).max(0.0);
+            if self.fade == 0.0 {
+                self.fading_out = false;
+                self.sequencer.set_running(false);
+                self.all_sound_off();
+            }
+        } else {
+            self.fade = (self.fade + step).min(1.0);
+        }
+        ((FADE_CURVE * self.fade).exp() - 1.0) / (FADE_CURVE.exp() - 1.0)
+    }
+
+    // Fills interleaved buffer with samples in [-1, 1] range. Mono output
+    // gets both sides mixed, channels above stereo are left silent
+    pub fn process(&mut self, out: &mut [f32], channels: usize) {
+        for frame in out.chunks_mut(channels) {
+            let (left, right) = self.next_frame();
+            let gain = self.fade_gain() * self.mute_gain();
+            let (left, right) = (left * gain, right * gain);
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.push([SampleType::from_normalized(left), SampleType::from_normalized(right)]);
+            }
+            match frame {
+                [mono] => *mono = (left + right) / 2.0,
+                [l, r, rest @ ..] => {
+                    *l = left;
+                    *r = right;
+                    rest.iter_mut().for_each(|sample| *sample = 0.0);
+                }
+                [] => {}
+            }
+        }
+    }
+
+    // Renders events without an audio stream, as fast as it can.
+    // Output is interleaved left and right samples in [-1, 1] range
+    pub fn render(&mut self, duration: f32, events: &[NoteEvent]) -> Vec<f32> {
+        let frames = (duration * self.sample_rate) as usize;
+        let mut events = events.to_vec();
+        events.sort_by(|a, b| a.time().partial_cmp(&b.time()).unwrap_or(std::cmp::Ordering::Equal));
+        let mut events = events.into_iter().peekable();
+        let mut output = Vec::with_capacity(frames * 2);
+        for frame in 0..frames {
+            // Events are applied at the first sample at or after their time
+            while let Some(event) = events.next_if(|e| (e.time() * self.sample_rate) as usize <= frame) {
+                self.apply_event(event);
+            }
+            let (left, right) = self.next_frame();
+            output.push(left);
+            output.push(right);
+        }
+        // Events at the very end, like the last note off, are applied for the next render
+        for event in events {
+            self.apply_event(event);
+        }
+        output
+    }
+
+    fn apply_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { freq, velocity, source, .. } => {
+                self.set_velocity(velocity);
+                self.note_on(freq, source);
+            }
+            NoteEvent::Off { source, .. } => self.note_off(source),
+        }
+    }
+
+    // Notes of the arpeggiator and sequencer, one at a time for each
+    fn play_trigger(&mut self, trigger: Trigger, source: NoteSource) {
+        self.stop_note(source);
+        if let Trigger::On { freq, velocity } = trigger {
+            self.set_velocity(velocity);
+            self.start_note(freq, source);
+        }
+    }
+
+    // Left and right samples in [-1, 1] range, unless the patch clips
+    fn next_frame(&mut self) -> (f32, f32) {
+        if self.stuck_note_timeout > 0.0 {
+            if self.stuck_check_left == 0 {
+                self.stuck_check_left = (STUCK_CHECK_SECONDS * self.sample_rate) as usize;
+                self.release_stuck_notes();
+            }
+            self.stuck_check_left -= 1;
+        }
+        if self.mod_left == 0 {
+            self.mod_left = MOD_BLOCK_FRAMES;
+            self.cutoff_mod = self.modulate(MOD_BLOCK_FRAMES);
+        }
+        self.mod_left -= 1;
+        if self.arp.enabled {
+            if let Some(trigger) = self.arp.tick(self.clock.tempo(), self.sample_rate) {
+                self.play_trigger(trigger, NoteSource::Arp);
+            }
+        }
+        if let Some(trigger) = self.sequencer.tick(self.clock.tempo(), self.sample_rate) {
+            self.play_trigger(trigger, NoteSource::Sequencer);
+        }
+        // Envelopes are sample accurate, so the filter follows even the shortest attacks
+        for ((value, state), adsr) in self.env_values.iter_mut().zip(self.env_states.iter_mut()).zip(self.envelopes.iter()) {
+            *value = state.next(adsr);
+        }
+        let env_mod = self.filter_env_amount * self.env_values[self.filter_env_idx] * FILTER_ENV_OCTAVES;
+        self.filter.modulate_cutoff(self.cutoff_mod + env_mod);
+
+        let (mut left, mut right) = (0.0, 0.0);
+        let solo_step = 1000.0 / (SOLO_FADE_MS * self.sample_rate);
+        // Modulator goes first, so carrier reads outputs of the current sample
+        for idx in (0..self.oscillators.len()).rev() {
+            let (head, tail) = self.oscillators.split_at_mut(idx + 1);
+            let osc = &mut head[idx];
+            let fm = match tail.first() {
+                Some(modulator) if idx == FM_CARRIER && self.fm_amount > 0.0 => Some((&*modulator, self.fm_amount)),
+                _ => None,
+            };
+            let (l, r) = osc.get_sample(&self.envelopes[osc.env_idx], fm);
+            let (l, r) = if self.binaural {
+                self.interaural[idx].process((l, r), osc.panning)
+            } else {
+                (l, r)
+            };
+            let solo_target = match self.solo {
+                Some(solo) if solo != idx => 0.0,
+                _ => 1.0,
+            };
+            let solo_gain = &mut self.solo_gains[idx];
+            *solo_gain = if *solo_gain < solo_target {
+                (*solo_gain + solo_step).min(solo_target)
+            } else {
+                (*solo_gain - solo_step).max(solo_target)
+            };
+            left += l * *solo_gain;
+            right += r * *solo_gain;
+        }
+        if self.audition.has_active_voices() {
+            if self.audition_left > 0 {
+                self.audition_left -= 1;
+                if self.audition_left == 0 {
+                    self.audition.voice_off(NoteSource::Audition);
+                }
+            }
+            let (l, r) = self.audition.get_sample(&self.envelopes[self.audition.env_idx], None);
+            left += l;
+            right += r;
+        }
+        let (mut left, mut right) = self.filter.process((left, right));
+        // Preview is about the envelope alone, the filter doesn't color it
+        if let Some(sample) = self.preview.get(self.preview_pos) {
+            left += sample;
+            right += sample;
+            self.preview_pos += 1;
+        }
+        let mut gain = self.gain_trim * self.volume;
+        if self.expression_destination == ExpressionDestination::Volume {
+            gain *= self.expression_value;
+        }
+        let (left, right) = self.dc_blocker.process((left * gain, right * gain));
+        if left.abs() > SOFT_CLIP_KNEE || right.abs() > SOFT_CLIP_KNEE {
+            self.clipped = true;
+        }
+        let tone = self.test_tone.next();
+        (soft_clip(left) + tone, soft_clip(right) + tone)
+    }
+}
+
+// Renders a second of the envelope on a sine upfront, so it's heard without holding a key.
+// The note is held through delay, attack, hold, decay and a bit of sustain, then released.
+// UI renders it from a copy of the envelope, the audio thread only plays it
+pub fn render_env_preview(adsr: &ADSR, sample_rate: f32) -> Vec<f32> {
+    let held_ms = adsr.delay + adsr.attack + adsr.hold + adsr.decay + PREVIEW_SUSTAIN_MS;
+    // Envelope samples per preview sample, short envelopes play as they are
+    let stretch = ((held_ms + adsr.release) / PREVIEW_MS).max(1.0);
+    let release_at = (held_ms / 1000.0 * sample_rate) as usize;
+    let len = (PREVIEW_MS / 1000.0 * sample_rate) as usize;
+    let fade_len = PREVIEW_FADE_MS / 1000.0 * sample_rate;
+    let phase_step = AUDITION_NOTE / sample_rate;
+    let mut state = EnvState::new();
+    state.trigger();
+    let (mut env_age, mut env_value, mut phase) = (0, 0.0, 0.0);
+    let mut preview = Vec::with_capacity(len);
+    for idx in 0..len {
+        let target_age = ((idx + 1) as f32 * stretch) as usize;
+        while env_age < target_age {
+            if env_age == release_at {
+                state.release();
+            }
+            env_value = state.next(adsr);
+            env_age += 1;
+        }
+        let fade = ((len - idx) as f32 / fade_len).min(1.0);
+        preview.push(env_value * fade * PREVIEW_LEVEL * (2.0 * std::f32::consts::PI * phase).sin());
+        phase = (phase + phase_step).fract();
+    }
+    preview
+}
+
+// The next oscillators would take its place in the pair
+pub fn in_fm_pair(osc_idx: usize) -> bool {
+    osc_idx == FM_CARRIER || osc_idx == FM_CARRIER + 1
+}
+
+// Loudness of the oscillators measured offline, the trim brings it towards
+// AUTO_GAIN_TARGET_RMS, so switching patches keeps similar level.
+// Rendering takes a while, UI works it out and sends the result
+pub fn auto_gain_trim(oscillators: &[Oscillator], sample_rate: f32) -> f32 {
+    let mut buf = vec![0.0; (AUTO_GAIN_MS / 1000.0 * sample_rate) as usize];
+    for osc in oscillators.iter() {
+        osc.render_reference(AUTO_GAIN_NOTE, &mut buf);
+    }
+    let rms = (buf.iter().map(|s| s * s).sum::<f32>() / buf.len() as f32).sqrt();
+    if rms <= 0.0 {
+        return 1.0;
+    }
+    let min_trim = 10f32.powf(AUTO_GAIN_MIN_TRIM as f32 / 20.0);
+    let max_trim = 10f32.powf(AUTO_GAIN_MAX_TRIM as f32 / 20.0);
+    (AUTO_GAIN_TARGET_RMS / rms).max(min_trim).min(max_trim)
+}
+
+// Transparent up to the knee, above it tanh squeezes everything into [-1, 1],
+// so stacked voices saturate instead of being cut flat
+fn soft_clip(sample: f32) -> f32 {
+    if sample.abs() <= SOFT_CLIP_KNEE {
+        return sample
+    }
+    let headroom = 1.0 - SOFT_CLIP_KNEE;
+    sample.signum() * (SOFT_CLIP_KNEE + headroom * ((sample.abs() - SOFT_CLIP_KNEE) / headroom).tanh())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+    const BUFFER_SIZES: [usize; 3] = [64, 256, 1024];
+    // Events land on boundaries of the largest buffer, so every size gets them at the same frame
+    const EVENT_STRIDE: usize = 1024;
+    const RENDER_STRIDES: usize = 80;
+    // Stride of every event and the note it starts, None releases it
+    const EVENTS: [(usize, Option<f32>); 4] = [(0, Some(220.0)), (20, None), (30, Some(330.0)), (50, None)];
+
+    // Deterministic patch touching envelopes, modulation and the filter
+    fn patch() -> Synth<i16> {
+        let mut synth = Synth::new(SAMPLE_RATE);
+        synth.add_env(ADSR::new(SAMPLE_RATE, 5, 50, 0.6, 100));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Saw, 0, 0.5));
+        synth.add_lfo(Lfo::new(SAMPLE_RATE, WaveForm::Sine, 5.0, 0.5, LfoTarget::OscPitch(0)));
+        synth.set_filter_cutoff(2000.0);
+        synth.set_filter_env_amount(0.5);
+        synth
+    }
+
+    fn render(buffer_size: usize) -> Vec<f32> {
+        let mut synth = patch();
+        let mut output = vec![0.0; RENDER_STRIDES * EVENT_STRIDE * 2];
+        for (idx, buffer) in output.chunks_mut(buffer_size * 2).enumerate() {
+            let frame = idx * buffer_size;
+            for (_, event) in EVENTS.iter().filter(|(stride, _)| stride * EVENT_STRIDE == frame) {
+                match event {
+                    Some(freq) => synth.note_on(*freq, NoteSource::Midi(0)),
+                    None => synth.note_off(NoteSource::Midi(0)),
+                }
+            }
+            synth.process(buffer, 2);
+        }
+        output
+    }
+
+    #[test]
+    fn soft_clip_keeps_output_in_range() {
+        assert_eq!(soft_clip(0.5), 0.5);
+        assert_eq!(soft_clip(-SOFT_CLIP_KNEE), -SOFT_CLIP_KNEE);
+        for sample in [0.9, 1.0, 2.0, 100.0].iter() {
+            let clipped = soft_clip(*sample);
+            assert!(clipped > SOFT_CLIP_KNEE && clipped <= 1.0, "{} clipped to {}", sample, clipped);
+            assert_eq!(soft_clip(-sample), -clipped);
+        }
+    }
+
+    #[test]
+    fn tone_tilts_highs_against_lows() {
+        let peak = |tone: bool, tilt: f32, freq: f32| {
+            let mut synth = Synth::<i16>::new(SAMPLE_RATE);
+            synth.add_env(ADSR::new(SAMPLE_RATE, 1, 10, 1.0, 100));
+            synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 0.2));
+            synth.set_osc_tone(0, tone);
+            synth.set_osc_tilt(0, tilt);
+            synth.note_on(freq, NoteSource::Midi(0));
+            let mut output = vec![0.0; 8192];
+            synth.process(&mut output, 2);
+            output[4096..].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        let bypassed = peak(false, 1.0, 5000.0);
+        assert!((peak(true, 0.0, 5000.0) - bypassed).abs() < bypassed * 0.01);
+        assert!(peak(true, 1.0, 5000.0) > bypassed * 1.4);
+        assert!(peak(true, -1.0, 5000.0) < bypassed * 0.7);
+        assert!(peak(true, 1.0, 100.0) < peak(false, 1.0, 100.0) * 0.7);
+    }
+
+    #[test]
+    fn half_period_phase_offset_cancels_twin_oscillator() {
+        let mut synth = Synth::<i16>::new(SAMPLE_RATE);
+        synth.add_env(ADSR::new(SAMPLE_RATE, 1, 10, 1.0, 100));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 0.3));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 0.3));
+        synth.set_osc_phase_offset(1, 180.0);
+        synth.note_on(220.0, NoteSource::Midi(0));
+        let mut output = vec![0.0; 4096];
+        synth.process(&mut output, 2);
+        assert!(output.iter().all(|sample| sample.abs() < 1e-3));
+    }
+
+    #[test]
+    fn removing_envelope_moves_its_users() {
+        let mut synth = patch();
+        synth.add_env(ADSR::new(SAMPLE_RATE, 5, 50, 0.6, 100));
+        synth.add_env(ADSR::new(SAMPLE_RATE, 5, 50, 0.6, 100));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 2, 0.5));
+        synth.set_env(0, 1);
+        synth.set_filter_env(2);
+        synth.remove_env(1);
+        assert_eq!(synth.envelopes.len(), 2);
+        assert_eq!(synth.oscillators[0].env_idx, 0);
+        assert_eq!(synth.oscillators[1].env_idx, 1);
+        assert_eq!(synth.filter_env_idx, 1);
+        synth.remove_env(0);
+        synth.remove_env(0);
+        assert_eq!(synth.envelopes.len(), 1);
+        assert!(synth.oscillators.iter().all(|osc| osc.env_idx == 0));
+    }
+
+    #[test]
+    fn removing_oscillator_moves_solo() {
+        let mut synth = patch();
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 0.5));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Square, 0, 0.5));
+        synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Triangle, 0, 0.5));
+        synth.set_solo(Some(3));
+        synth.remove_osc(2);
+        assert_eq!(synth.oscillators.len(), 3);
+        assert_eq!(synth.solo, Some(2));
+        synth.remove_osc(2);
+        assert_eq!(synth.solo, None);
+        // Two oscillators always stay
+        synth.remove_osc(0);
+        assert_eq!(synth.oscillators.len(), osc_constraints::MIN_OSCILLATORS);
+        assert_eq!(synth.solo_gains.len(), synth.oscillators.len());
+    }
+
+    #[test]
+    fn removing_oscillator_moves_its_targets() {
+        let mut synth = patch();
+        for _ in 0..3 {
+            synth.add_osc(Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 0.5));
+        }
+        synth.set_lfo_target(0, LfoTarget::OscVolume(3));
+        synth.set_mod_route(0, ModSource::ModWheel, ModDestination::OscPan(2));
+        synth.set_mod_route(1, ModSource::ModWheel, ModDestination::OscPitch(1));
+        synth.set_fm_amount(0.5);
+        synth.remove_osc(1);
+        assert!(synth.lfos[0].target == LfoTarget::OscVolume(2));
+        assert!(synth.mod_slots[0].destination == ModDestination::OscPan(1));
+        assert!(synth.mod_slots[1].destination == ModDestination::None);
+        // Osc3 would have become the modulator
+        assert_eq!(synth.fm_amount, 0.0);
+    }
+
+    #[test]
+    fn auto_gain_trims_loud_oscillators_more() {
+        let quiet = [Oscillator::new(SAMPLE_RATE, WaveForm::Saw, 0, 0.2)];
+        let loud = [Oscillator::new(SAMPLE_RATE, WaveForm::Saw, 0, 0.8)];
+        assert!(auto_gain_trim(&loud, SAMPLE_RATE) < auto_gain_trim(&quiet, SAMPLE_RATE));
+        // Silence is left as it is
+        assert_eq!(auto_gain_trim(&[], SAMPLE_RATE), 1.0);
+    }
+
+    #[test]
+    fn envelope_preview_fits_long_envelopes() {
+        let preview = render_env_preview(&ADSR::new(SAMPLE_RATE, 3000, 100, 0.5, 100), SAMPLE_RATE);
+        assert_eq!(preview.len(), SAMPLE_RATE as usize);
+        // Three second attack is sped up, so the peak is reached within the second
+        let peak = preview.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak > PREVIEW_LEVEL * 0.9);
+        assert!(preview.last().unwrap().abs() < 0.01);
+    }
+
+    #[test]
+    fn voice_start_and_end_do_not_click() {
+        let mut synth = Synth::<i16>::new(SAMPLE_RATE);
+        synth.add_env(ADSR::new(SAMPLE_RATE, 0, 0, 1.0, 0));
+        let mut osc = Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 1.0);
+        osc.set_start(Start::Hard);
+        synth.add_osc(osc);
+        let mut output = vec![0.0; EVENT_STRIDE * 2];
+        synth.note_on(220.0, NoteSource::Midi(0));
+        synth.process(&mut output[..EVENT_STRIDE], 2);
+        synth.note_off(NoteSource::Midi(0));
+        synth.process(&mut output[EVENT_STRIDE..], 2);
+        // Sine of 220 Hz moves by less than 0.04 of its amplitude per sample, a click jumps by all of it
+        let mut previous = 0.0;
+        for (idx, sample) in output.iter().step_by(2).enumerate() {
+            assert!((sample - previous).abs() < 0.05, "jump of {} at frame {}", sample - previous, idx);
+            previous = *sample;
+        }
+    }
+
+    #[test]
+    fn fading_voices_never_take_more_slots_than_allocated() {
+        let mut synth = patch();
+        synth.set_max_voices(osc_constraints::MAX_VOICES - 1);
+        for note in 0..osc_constraints::MAX_VOICES {
+            synth.note_on(110.0 + note as f32 * 10.0, NoteSource::Midi(note as u8));
+        }
+        // Stolen voice is still fading when the limit goes up
+        synth.set_max_voices(osc_constraints::MAX_VOICES);
+        for note in osc_constraints::MAX_VOICES..osc_constraints::MAX_VOICES + 4 {
+            synth.note_on(110.0 + note as f32 * 10.0, NoteSource::Midi(note as u8));
+            assert!(synth.voice_count() <= osc_constraints::MAX_VOICES);
+        }
+    }
+
+    #[test]
+    fn pluck_plays_only_with_strings_sent() {
+        let mut synth = Synth::<i16>::new(SAMPLE_RATE);
+        synth.add_env(ADSR::new(SAMPLE_RATE, 0, 0, 1.0, 0));
+        let mut osc = Oscillator::new(SAMPLE_RATE, WaveForm::Sine, 0, 1.0);
+        osc.set_engine(OscEngine::Pluck);
+        synth.add_osc(osc);
+        let mut output = vec![0.0; EVENT_STRIDE * 2];
+        synth.note_on(220.0, NoteSource::Midi(0));
+        synth.process(&mut output, 2);
+        assert!(output.iter().all(|sample| *sample == 0.0));
+        synth.set_osc_pluck_strings(0, pluck_strings(OscEngine::Pluck, SAMPLE_RATE));
+        // More notes than voice slots, stolen slots pluck strings let go of
+        for note in 0..osc_constraints::MAX_VOICES + 4 {
+            synth.note_on(110.0 + note as f32 * 10.0, NoteSource::Midi(note as u8));
+            synth.process(&mut output, 2);
+            assert!(output.iter().any(|sample| *sample != 0.0));
+        }
+        let strings = synth.set_osc_pluck_strings(0, Vec::new());
+        assert_eq!(strings.len(), osc_constraints::MAX_VOICES);
+    }
+
+    #[test]
+    fn output_does_not_depend_on_buffer_size() {
+        let reference = render(BUFFER_SIZES[0]);
+        assert!(reference.iter().any(|sample| *sample != 0.0));
+        for size in BUFFER_SIZES[1..].iter() {
+            let mismatch = render(*size).iter().zip(reference.iter()).position(|(a, b)| a != b);
+            assert_eq!(mismatch, None, "{} frame buffers differ from {} frame ones", size, BUFFER_SIZES[0]);
         }
-        Some(SampleType::from_f32(sample * self.volume).unwrap())
     }
 }
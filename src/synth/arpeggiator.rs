@@ -0,0 +1,197 @@
+use super::step_mod::StepDivision;
+use super::NoteSource;
+
+// Keys pressed beyond this are ignored, so pressing doesn't allocate
+const MAX_HELD: usize = 32;
+
+#[allow(dead_code)]
+pub mod arp_constraints {
+    // ms per step when not synced to tempo
+    pub const MIN_RATE: f32 = 30.;
+    pub const MAX_RATE: f32 = 2000.;
+    pub const DEFAULT_RATE: f32 = 150.;
+    // Held chord is repeated this many octaves up
+    pub const MIN_OCTAVES: usize = 1;
+    pub const MAX_OCTAVES: usize = 4;
+    // Part of a step the note sounds for, 1.0 is legato
+    pub const MIN_GATE: f32 = 0.05;
+    pub const MAX_GATE: f32 = 1.;
+    pub const DEFAULT_GATE: f32 = 0.5;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArpMode {
+    Up,
+    Down,
+    // Bounces without repeating the top and bottom notes
+    UpDown,
+    Random,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct HeldKey {
+    source: NoteSource,
+    freq: f32,
+    velocity: f32,
+}
+
+// What the synth has to do with a generated note at this sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    // Previous note is released before this one starts
+    On { freq: f32, velocity: f32 },
+    Off,
+}
+
+// Sits between note input and voices: held keys aren't played
+// directly but cycled one at a time
+pub struct Arpeggiator {
+    pub enabled: bool,
+    pub mode: ArpMode,
+    // Steps follow tempo divisions instead of rate
+    pub synced: bool,
+    pub division: StepDivision,
+    // ms
+    rate: f32,
+    octaves: usize,
+    gate: f32,
+    // Sorted from the lowest, so the pattern is just an index
+    held: Vec<HeldKey>,
+    // Pattern index of the last played step
+    step: usize,
+    descending: bool,
+    // Samples since the last step, none until the first one is played
+    elapsed: Option<f32>,
+    sounding: bool,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mode: ArpMode::Up,
+            synced: false,
+            division: StepDivision::Sixteenth,
+            rate: arp_constraints::DEFAULT_RATE,
+            octaves: arp_constraints::MIN_OCTAVES,
+            gate: arp_constraints::DEFAULT_GATE,
+            held: Vec::with_capacity(MAX_HELD),
+            step: 0,
+            descending: false,
+            elapsed: None,
+            sounding: false,
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate
+            .max(arp_constraints::MIN_RATE)
+            .min(arp_constraints::MAX_RATE);
+    }
+
+    pub fn set_octaves(&mut self, octaves: usize) {
+        self.octaves = octaves
+            .max(arp_constraints::MIN_OCTAVES)
+            .min(arp_constraints::MAX_OCTAVES);
+    }
+
+    pub fn set_gate(&mut self, gate: f32) {
+        self.gate = gate
+            .max(arp_constraints::MIN_GATE)
+            .min(arp_constraints::MAX_GATE);
+    }
+
+    // True when nothing was held before, so a new phrase starts
+    pub fn press(&mut self, source: NoteSource, freq: f32, velocity: f32) -> bool {
+        let first = self.held.is_empty();
+        self.held.retain(|key| key.source != source);
+        if self.held.len() < MAX_HELD {
+            let idx = self.held.iter().position(|key| key.freq > freq).unwrap_or(self.held.len());
+            self.held.insert(idx, HeldKey { source, freq, velocity });
+        }
+        first
+    }
+
+    // False when the key wasn't taken by the arpeggiator
+    pub fn release(&mut self, source: NoteSource) -> bool {
+        let len = self.held.len();
+        self.held.retain(|key| key.source != source);
+        self.held.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.elapsed = None;
+        self.sounding = false;
+    }
+
+    // Samples per step
+    fn step_len(&self, tempo: f32, sample_rate: f32) -> f32 {
+        let seconds = if self.synced {
+            60.0 / tempo / self.division.steps_per_beat() as f32
+        } else {
+            self.rate / 1000.0
+        };
+        (seconds * sample_rate).max(1.0)
+    }
+
+    fn next_step(&mut self, first: bool) -> usize {
+        let len = self.held.len() * self.octaves;
+        // Keys could be released since the last step
+        let step = self.step.min(len - 1);
+        match self.mode {
+            ArpMode::Up if first => 0,
+            ArpMode::Up => (self.step + 1) % len,
+            ArpMode::Down if first || step == 0 => len - 1,
+            ArpMode::Down => step - 1,
+            ArpMode::UpDown if first || len == 1 => {
+                self.descending = false;
+                0
+            }
+            ArpMode::UpDown => {
+                if step == len - 1 {
+                    self.descending = true;
+                } else if step == 0 {
+                    self.descending = false;
+                }
+                if self.descending { step - 1 } else { step + 1 }
+            }
+            ArpMode::Random => rand::random::<usize>() % len,
+        }
+    }
+
+    // Called every sample, tempo is in BPM
+    pub fn tick(&mut self, tempo: f32, sample_rate: f32) -> Option<Trigger> {
+        if self.held.is_empty() {
+            self.elapsed = None;
+            if self.sounding {
+                self.sounding = false;
+                return Some(Trigger::Off);
+            }
+            return None;
+        }
+        let step_len = self.step_len(tempo, sample_rate);
+        let (elapsed, first) = match self.elapsed {
+            Some(elapsed) => (elapsed + 1.0, false),
+            // First key plays right away
+            None => (step_len, true),
+        };
+        if elapsed >= step_len {
+            self.elapsed = Some(if first { 0.0 } else { elapsed - step_len });
+            self.step = self.next_step(first);
+            let key = self.held[self.step % self.held.len()];
+            let octave = (self.step / self.held.len()) as i32;
+            self.sounding = true;
+            return Some(Trigger::On {
+                freq: key.freq * 2f32.powi(octave),
+                velocity: key.velocity,
+            });
+        }
+        self.elapsed = Some(elapsed);
+        if self.sounding && elapsed >= step_len * self.gate {
+            self.sounding = false;
+            return Some(Trigger::Off);
+        }
+        None
+    }
+}
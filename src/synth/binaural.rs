@@ -0,0 +1,50 @@
+// Sound from one side reaches the far ear up to this much later, for an average head
+const MAX_ITD_SECONDS: f32 = 0.00066;
+// Holds the largest delay at 192 kHz, so the line is never reallocated
+const DELAY_LEN: usize = 256;
+
+// Interaural time difference of one oscillator for headphones: the ear further
+// from its pan position hears it a fraction of a millisecond later, which the
+// brain takes as direction. Level panning is left to the oscillator
+pub struct Interaural {
+    sample_rate: f32,
+    // Past samples of left and right channels
+    delay: [[f32; DELAY_LEN]; 2],
+    pos: usize,
+}
+
+impl Interaural {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            delay: [[0.0; DELAY_LEN]; 2],
+            pos: 0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    // Pan is 0.0 for full left and 1.0 for full right
+    pub fn process(&mut self, (left, right): (f32, f32), pan: f32) -> (f32, f32) {
+        self.delay[0][self.pos] = left;
+        self.delay[1][self.pos] = right;
+        let delay = ((pan - 0.5).abs() * 2.0 * MAX_ITD_SECONDS * self.sample_rate).min((DELAY_LEN - 2) as f32);
+        let mut out = [left, right];
+        // Source on the right is late in the left ear and the other way round
+        let far = if pan > 0.5 { 0 } else { 1 };
+        out[far] = self.read(far, delay);
+        self.pos = (self.pos + 1) % DELAY_LEN;
+        (out[0], out[1])
+    }
+
+    // Fractional delay, pan moves smoothly without clicks
+    fn read(&self, channel: usize, delay: f32) -> f32 {
+        let whole = delay as usize;
+        let frac = delay - whole as f32;
+        let a = self.delay[channel][(self.pos + DELAY_LEN - whole) % DELAY_LEN];
+        let b = self.delay[channel][(self.pos + DELAY_LEN - whole - 1) % DELAY_LEN];
+        a + (b - a) * frac
+    }
+}
@@ -0,0 +1,59 @@
+// Lorenz system with its classic parameters, it never settles and never repeats
+const SIGMA: f32 = 10.0;
+const RHO: f32 = 28.0;
+const BETA: f32 = 8.0 / 3.0;
+// x stays within about this range
+const X_RANGE: f32 = 20.0;
+// Longer Euler steps make the system blow up
+const MAX_STEP: f32 = 0.005;
+
+#[allow(dead_code)]
+pub mod chaos_constraints {
+    // Attractor time units per second, 1.0 is around a swing per second
+    pub const MIN_RATE: f32 = 0.05;
+    pub const MAX_RATE: f32 = 5.;
+    pub const DEFAULT_RATE: f32 = 0.5;
+}
+
+pub struct Chaos {
+    pub rate: f32,
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+impl Chaos {
+    pub fn new(rate: f32) -> Self {
+        let mut chaos = Self {
+            rate: 0.0,
+            // Anywhere off the origin ends up on the attractor
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+        };
+        chaos.set_rate(rate);
+        chaos
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate
+            .max(chaos_constraints::MIN_RATE)
+            .min(chaos_constraints::MAX_RATE);
+    }
+
+    // Bipolar value in [-1, 1] range for the next frames
+    pub fn advance(&mut self, frames: usize, sample_rate: f32) -> f32 {
+        let time = self.rate * frames as f32 / sample_rate;
+        let steps = (time / MAX_STEP).ceil().max(1.0);
+        let dt = time / steps;
+        for _ in 0..steps as usize {
+            let dx = SIGMA * (self.y - self.x);
+            let dy = self.x * (RHO - self.z) - self.y;
+            let dz = self.x * self.y - BETA * self.z;
+            self.x += dx * dt;
+            self.y += dy * dt;
+            self.z += dz * dt;
+        }
+        (self.x / X_RANGE).max(-1.0).min(1.0)
+    }
+}
@@ -0,0 +1,46 @@
+#[allow(dead_code)]
+pub mod clock_constraints {
+    // BPM
+    pub const MIN_TEMPO: f32 = 40.;
+    pub const MAX_TEMPO: f32 = 240.;
+    pub const DEFAULT_TEMPO: f32 = 120.;
+}
+
+// Musical time for everything synced to tempo. Runs freely and
+// starts over from the first beat when a phrase starts
+pub struct Clock {
+    tempo: f32,
+    // Quarter notes since restart
+    beats: f64,
+}
+
+impl Clock {
+    pub fn new(tempo: f32) -> Self {
+        let mut clock = Self { tempo: 0.0, beats: 0.0 };
+        clock.set_tempo(tempo);
+        clock
+    }
+
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.tempo = tempo
+            .max(clock_constraints::MIN_TEMPO)
+            .min(clock_constraints::MAX_TEMPO);
+    }
+
+    // BPM
+    pub fn tempo(&self) -> f32 {
+        self.tempo
+    }
+
+    pub fn restart(&mut self) {
+        self.beats = 0.0;
+    }
+
+    pub fn beats(&self) -> f64 {
+        self.beats
+    }
+
+    pub fn advance(&mut self, frames: usize, sample_rate: f32) {
+        self.beats += (self.tempo / 60.0 * frames as f32 / sample_rate) as f64;
+    }
+}
@@ -0,0 +1,53 @@
+const PI: f32 = std::f32::consts::PI;
+// Hz, far below anything audible, but the offset is gone within a few hundred ms
+const CUTOFF: f32 = 10.0;
+
+// One-pole high-pass at the end of the chain: y = x - x[-1] + r * y[-1].
+// Removes DC offset of asymmetric waves, which thumps when notes start and stop
+pub struct DcBlocker {
+    pub enabled: bool,
+    r: f32,
+    // Previous input and output of left and right channels
+    x1: [f32; 2],
+    y1: [f32; 2],
+}
+
+impl DcBlocker {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut blocker = Self {
+            enabled: true,
+            r: 0.0,
+            x1: [0.0; 2],
+            y1: [0.0; 2],
+        };
+        blocker.set_sample_rate(sample_rate);
+        blocker
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.r = (-2.0 * PI * CUTOFF / sample_rate).exp();
+    }
+
+    // Starts from silence when turned on again, so the old state doesn't click
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.x1 = [0.0; 2];
+            self.y1 = [0.0; 2];
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn process(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        if !self.enabled {
+            return (left, right)
+        }
+        let mut out = [left, right];
+        for (channel, sample) in out.iter_mut().enumerate() {
+            let y = *sample - self.x1[channel] + self.r * self.y1[channel];
+            self.x1[channel] = *sample;
+            self.y1[channel] = y;
+            *sample = y;
+        }
+        (out[0], out[1])
+    }
+}
@@ -1,35 +1,67 @@
 use super::Released;
 use std::ops::{Add, Sub};
-use std::time::Instant;
 
 type Milliseconds = u32;
 
 #[allow(dead_code)]
 pub mod adsr_constraints {
-    pub const MIN_ATTACK: f32 = 1.;
-    pub const MAX_ATTACK: f32 = 3000.;
+    // Envelopes run every sample, so attacks shorter than a millisecond give clicks and snaps
+    pub const MIN_ATTACK: f32 = 0.1;
+    // Slow swells and tails of ambient pads take tens of seconds
+    pub const MAX_ATTACK: f32 = 20000.;
     pub const MIN_DECAY: f32 = 1.;
-    pub const MAX_DECAY: f32 = 3000.;
+    pub const MAX_DECAY: f32 = 20000.;
     pub const MIN_SUSTAIN: f32 = 0.;
     pub const MAX_SUSTAIN: f32 = 1.;
     pub const MIN_RELEASE: f32 = 1.;
-    pub const MAX_RELEASE: f32 = 3000.;
+    pub const MAX_RELEASE: f32 = 20000.;
+    // Silence before attack and full level before decay, 0 skips the stage
+    pub const MIN_DELAY: f32 = 0.;
+    pub const MAX_DELAY: f32 = 2000.;
+    pub const MIN_HOLD: f32 = 0.;
+    pub const MAX_HOLD: f32 = 2000.;
+    // Times a looping envelope plays attack to decay, 0 loops while the note is held
+    pub const MIN_LOOP_COUNT: u32 = 0;
+    pub const MAX_LOOP_COUNT: u32 = 16;
+    // One envelope is always there for oscillators to use
+    pub const MIN_ENVELOPES: usize = 1;
+    pub const MAX_ENVELOPES: usize = 8;
 }
 
+// Envelope index once the envelope at removed is gone: later ones move down,
+// users of the removed one fall back to the one before it
+pub fn env_idx_after_removal(env_idx: usize, removed: usize) -> usize {
+    if env_idx > removed || env_idx == removed && removed > 0 {
+        env_idx - 1
+    } else {
+        env_idx
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum ADSRParam {
+    Delay(f32),
     Attack(f32),
+    Hold(f32),
     Decay(f32),
     Sustain(f32),
     Release(f32),
+    Loop(bool),
+    LoopCount(u32),
 }
 
 #[derive(Clone)]
 pub struct ADSR {
     sample_rate: f32,
+    pub delay: f32,
     pub attack: f32,
+    pub hold: f32,
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
+    // Attack, hold and decay repeat from the level they reached, like a complex LFO
+    pub looping: bool,
+    pub loop_count: u32,
     attack_incr: f32,
     decay_decr: f32,
     #[allow(dead_code)]
@@ -54,10 +86,14 @@ impl ADSR {
         let release_samples = release / 1000.0 * sample_rate;
         Self {
             sample_rate,
+            delay: adsr_constraints::MIN_DELAY,
             attack,
+            hold: adsr_constraints::MIN_HOLD,
             decay,
             sustain,
             release,
+            looping: false,
+            loop_count: adsr_constraints::MIN_LOOP_COUNT,
             attack_incr,
             decay_decr,
             release_decr,
@@ -65,14 +101,28 @@ impl ADSR {
         }
     }
 
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.attack_incr = 1.0 / (self.attack / 1000.0 * sample_rate);
+        self.decay_decr = -((1.0 - self.sustain) / (self.decay / 1000.0 * sample_rate));
+        self.release_decr = -(self.sustain / (self.release / 1000.0 * sample_rate));
+        self.release_samples = self.release / 1000.0 * sample_rate;
+    }
+
     pub fn set_parameter(&mut self, param: ADSRParam) {
         match param {
+            ADSRParam::Delay(val) => {
+                self.delay = val.max(adsr_constraints::MIN_DELAY).min(adsr_constraints::MAX_DELAY);
+            }
+            ADSRParam::Hold(val) => {
+                self.hold = val.max(adsr_constraints::MIN_HOLD).min(adsr_constraints::MAX_HOLD);
+            }
             ADSRParam::Attack(val) => {
-                self.attack = val.max(1.0);
+                self.attack = val.max(adsr_constraints::MIN_ATTACK).min(adsr_constraints::MAX_ATTACK);
                 self.attack_incr = 1.0 / (self.attack / 1000.0 * self.sample_rate);
             }
             ADSRParam::Decay(val) => {
-                self.decay = val.max(3.0);
+                self.decay = val.max(3.0).min(adsr_constraints::MAX_DECAY);
                 self.decay_decr = -((1.0 - self.sustain) / (self.decay / 1000.0 * self.sample_rate));
             }
             ADSRParam::Sustain(val) => {
@@ -81,28 +131,62 @@ impl ADSR {
                 self.decay_decr = -((1.0 - self.sustain) / (self.decay / 1000.0 * self.sample_rate));
             }
             ADSRParam::Release(val) => {
-                self.release = val;
+                self.release = val.max(adsr_constraints::MIN_RELEASE).min(adsr_constraints::MAX_RELEASE);
                 self.release_samples = self.release / 1000.0 * self.sample_rate;
             }
+            ADSRParam::Loop(enabled) => {
+                self.looping = enabled;
+            }
+            ADSRParam::LoopCount(count) => {
+                self.loop_count = count.min(adsr_constraints::MAX_LOOP_COUNT);
+            }
         }
     }
 
+    // Time into the current pass of a looping envelope, the last pass goes on to sustain
+    fn looped(&self, ms: f32) -> f32 {
+        if !self.looping {
+            return ms
+        }
+        let pass = self.attack + self.hold + self.decay;
+        let passes = (ms / pass).floor();
+        if self.loop_count == 0 || passes < self.loop_count as f32 {
+            ms - passes * pass
+        } else {
+            ms
+        }
+    }
+
+    fn samples_to_ms(&self, samples: u32) -> f32 {
+        samples as f32 / self.sample_rate * 1000.0
+    }
+
     // Incremental version
     pub fn get_volume_incr(
         &self,
         current: &f32,
-        triggered: &Instant,
+        age: u32,
         released: &Option<Released>,
     ) -> f32 {
         if let Some(r) = released {
             // Release stage
             current - (r.value / self.release_samples)
         } else {
-            let alive_for = triggered.elapsed().as_millis() as f32;
+            let alive_for = self.samples_to_ms(age);
+            // Delay stage, a retriggered envelope waits where it is
+            if alive_for < self.delay {
+                return *current;
+            }
+            let alive_for = self.looped(alive_for - self.delay);
             // Attack stage
             if alive_for <= self.attack {
                 return current + self.attack_incr;
             }
+            // Hold stage
+            if alive_for <= self.attack.add(self.hold) {
+                return 1.0;
+            }
+            let alive_for = alive_for - self.hold;
             // Decay stage
             if alive_for <= self.attack.add(self.decay) {
                 let output = current + self.decay_decr;
@@ -116,17 +200,25 @@ impl ADSR {
 
     // Old heavy version
     #[allow(dead_code)]
-    pub fn get_volume(&self, triggered: &Instant, released: &Option<Released>) -> f32 {
+    pub fn get_volume(&self, age: u32, released: &Option<Released>) -> f32 {
         match released {
             Some(ref released) => {
-                let released_for = released.time.elapsed().as_millis() as f32;
+                let released_for = self.samples_to_ms(age.saturating_sub(released.age));
                 return released.value * (1.0 - released_for / self.release);
             }
             None => {
-                let active_for = triggered.elapsed().as_millis() as f32;
+                let active_for = self.samples_to_ms(age);
+                if active_for < self.delay {
+                    return 0.0;
+                }
+                let active_for = active_for - self.delay;
                 if active_for <= self.attack {
                     return active_for / self.attack;
                 }
+                if active_for <= self.attack.add(self.hold) {
+                    return 1.0;
+                }
+                let active_for = active_for - self.hold;
                 if active_for <= self.attack.add(self.decay) {
                     let to_sustain = 1.0 - self.sustain;
                     let cur_fraction = 1.0 - active_for.sub(self.attack) / self.decay;
@@ -137,3 +229,86 @@ impl ADSR {
         }
     }
 }
+
+// Envelope state which isn't bound to a single voice, used for modulation.
+// It is retriggered by every new note and released with the last one
+#[derive(Clone)]
+pub struct EnvState {
+    pub value: f32,
+    // Samples since the last trigger
+    age: u32,
+    released: Option<Released>,
+}
+
+impl EnvState {
+    pub fn new() -> Self {
+        Self {
+            value: 0.0,
+            age: 0,
+            released: Some(Released {
+                age: 0,
+                value: 0.0,
+            }),
+        }
+    }
+
+    pub fn trigger(&mut self) {
+        self.age = 0;
+        self.released = None;
+    }
+
+    pub fn release(&mut self) {
+        if self.released.is_none() {
+            self.released = Some(Released {
+                age: self.age,
+                value: self.value,
+            });
+        }
+    }
+
+    pub fn next(&mut self, adsr: &ADSR) -> f32 {
+        self.value = adsr
+            .get_volume_incr(&self.value, self.age, &self.released)
+            .max(0.0)
+            .min(1.0);
+        self.age = self.age.saturating_add(1);
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    #[test]
+    fn envelope_waits_for_delay_and_holds_peak() {
+        let mut adsr = ADSR::new(SAMPLE_RATE, 1, 10, 0.5, 100);
+        adsr.set_parameter(ADSRParam::Delay(10.0));
+        adsr.set_parameter(ADSRParam::Hold(10.0));
+        let mut env = EnvState::new();
+        env.trigger();
+        let values: Vec<f32> = (0..(SAMPLE_RATE * 0.05) as usize).map(|_| env.next(&adsr)).collect();
+        let at = |ms: f32| values[(ms / 1000.0 * SAMPLE_RATE) as usize];
+        assert_eq!(at(9.0), 0.0);
+        assert_eq!(at(15.0), 1.0);
+        assert!(at(25.0) > 0.5 && at(25.0) < 1.0);
+        assert_eq!(at(40.0), 0.5);
+    }
+
+    #[test]
+    fn looping_envelope_repeats_loop_count_times() {
+        let mut adsr = ADSR::new(SAMPLE_RATE, 10, 10, 0.0, 100);
+        adsr.set_parameter(ADSRParam::Loop(true));
+        adsr.set_parameter(ADSRParam::LoopCount(2));
+        let mut env = EnvState::new();
+        env.trigger();
+        let values: Vec<f32> = (0..(SAMPLE_RATE * 0.06) as usize).map(|_| env.next(&adsr)).collect();
+        let at = |ms: f32| values[(ms / 1000.0 * SAMPLE_RATE) as usize];
+        // Peaks of both passes, then sustain
+        assert!(at(10.0) > 0.95 && at(30.0) > 0.95);
+        assert!(at(19.0) < 0.2);
+        assert_eq!(at(50.0), 0.0);
+    }
+}
@@ -0,0 +1,28 @@
+// What expression pedal (CC11) drives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpressionDestination {
+    // Trims output after master volume, heel down is silence
+    Volume,
+    // Leaves output alone, pedal is only a mod matrix source
+    ModMatrix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpressionCurve {
+    Linear,
+    // Most of the travel is spent on quiet levels, good for swells
+    Exponential,
+    // Pedal opens up fast and the rest of the travel is fine control
+    Logarithmic,
+}
+
+impl ExpressionCurve {
+    // 0.0-1.0 pedal position to 0.0-1.0 value
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ExpressionCurve::Linear => value,
+            ExpressionCurve::Exponential => value * value * value,
+            ExpressionCurve::Logarithmic => value.sqrt(),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+const PI: f32 = std::f32::consts::PI;
+// Keeps resonance from reaching self-oscillation
+const MAX_Q_FACTOR: f32 = 0.98;
+
+#[allow(dead_code)]
+pub mod filter_constraints {
+    pub const MIN_CUTOFF: f32 = 20.;
+    pub const MAX_CUTOFF: f32 = 20000.;
+    pub const MIN_RESONANCE: f32 = 0.;
+    pub const MAX_RESONANCE: f32 = 1.;
+    pub const MIN_ENV_AMOUNT: f32 = -1.;
+    pub const MAX_ENV_AMOUNT: f32 = 1.;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+// State variable filter in topology-preserving transform form,
+// stays stable while cutoff is changing
+pub struct Filter {
+    sample_rate: f32,
+    pub cutoff: f32,
+    pub resonance: f32,
+    pub mode: FilterMode,
+    // Modulation offset in octaves
+    cutoff_mod: f32,
+    // Damping, 2.0 means no resonance
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    // Integrator states of left and right channels
+    ic1eq: [f32; 2],
+    ic2eq: [f32; 2],
+}
+
+impl Filter {
+    pub fn new(sample_rate: f32, cutoff: f32, resonance: f32) -> Self {
+        let mut filter = Self {
+            sample_rate,
+            cutoff: 0.0,
+            resonance: 0.0,
+            mode: FilterMode::LowPass,
+            cutoff_mod: 0.0,
+            k: 2.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            ic1eq: [0.0; 2],
+            ic2eq: [0.0; 2],
+        };
+        filter.set_resonance(resonance);
+        filter.set_cutoff(cutoff);
+        filter
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.cutoff = cutoff
+            .max(filter_constraints::MIN_CUTOFF)
+            .min(filter_constraints::MAX_CUTOFF);
+        self.update_coefficients();
+    }
+
+    pub fn set_resonance(&mut self, resonance: f32) {
+        self.resonance = resonance
+            .max(filter_constraints::MIN_RESONANCE)
+            .min(filter_constraints::MAX_RESONANCE);
+        self.k = 2.0 - 2.0 * self.resonance * MAX_Q_FACTOR;
+        self.update_coefficients();
+    }
+
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        self.mode = mode;
+    }
+
+    // Shifts cutoff by some octaves without changing its base value
+    pub fn modulate_cutoff(&mut self, octaves: f32) {
+        if octaves != self.cutoff_mod {
+            self.cutoff_mod = octaves;
+            self.update_coefficients();
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        // Cutoff can't go above Nyquist frequency
+        let cutoff = (self.cutoff * 2f32.powf(self.cutoff_mod))
+            .max(filter_constraints::MIN_CUTOFF)
+            .min(self.sample_rate * 0.49);
+        let g = (PI * cutoff / self.sample_rate).tan();
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+        (self.process_channel(0, input.0), self.process_channel(1, input.1))
+    }
+
+    fn process_channel(&mut self, ch: usize, input: f32) -> f32 {
+        let v3 = input - self.ic2eq[ch];
+        let v1 = self.a1 * self.ic1eq[ch] + self.a2 * v3;
+        let v2 = self.ic2eq[ch] + self.a2 * self.ic1eq[ch] + self.a3 * v3;
+        self.ic1eq[ch] = 2.0 * v1 - self.ic1eq[ch];
+        self.ic2eq[ch] = 2.0 * v2 - self.ic2eq[ch];
+        match self.mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => input - self.k * v1 - v2,
+            FilterMode::BandPass => v1,
+            FilterMode::Notch => input - self.k * v1,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Exponential glide has covered this part of the interval once glide time is over
+const EXP_GLIDE_REMAINING: f32 = 0.01;
+// Semitones, closer than that the note is considered reached
+const GLIDE_DONE: f32 = 0.001;
+
+#[allow(dead_code)]
+pub mod glide_constraints {
+    // ms
+    pub const MIN_TIME: f32 = 0.;
+    pub const MAX_TIME: f32 = 2000.;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlideCurve {
+    // Pitch moves at constant speed and arrives right at glide time
+    Linear,
+    // Pitch rushes towards the note and slows down near it, like analog portamento
+    Exponential,
+}
+
+// Pitch offset of a voice which slides from the previous note, in semitones
+#[derive(Debug, Clone, Copy)]
+pub struct Glide {
+    offset: f32,
+    // Semitones per sample for linear curve, multiplier for exponential one
+    step: f32,
+    curve: GlideCurve,
+}
+
+impl Glide {
+    pub fn none() -> Self {
+        Self {
+            offset: 0.0,
+            step: 0.0,
+            curve: GlideCurve::Linear,
+        }
+    }
+
+    pub fn new(from_freq: f32, to_freq: f32, time: f32, curve: GlideCurve, sample_rate: f32) -> Self {
+        let samples = (time / 1000.0 * sample_rate).max(1.0);
+        let offset = 12.0 * (from_freq / to_freq).log2();
+        let step = match curve {
+            GlideCurve::Linear => offset.abs() / samples,
+            GlideCurve::Exponential => EXP_GLIDE_REMAINING.powf(1.0 / samples),
+        };
+        Self { offset, step, curve }
+    }
+
+    pub fn active(&self) -> bool {
+        self.offset != 0.0
+    }
+
+    // Pitch multiplier for the current sample, moves one sample closer to the note
+    pub fn next(&mut self) -> f32 {
+        let ratio = 2f32.powf(self.offset / 12.0);
+        self.offset = match self.curve {
+            GlideCurve::Linear => self.offset - self.step.min(self.offset.abs()) * self.offset.signum(),
+            GlideCurve::Exponential => self.offset * self.step,
+        };
+        if self.offset.abs() < GLIDE_DONE {
+            self.offset = 0.0;
+        }
+        ratio
+    }
+}
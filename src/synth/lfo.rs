@@ -0,0 +1,118 @@
+use super::oscillator::osc_idx_after_removal;
+use super::waves::{next_phase, Wave, WaveForm, WaveState};
+
+#[allow(dead_code)]
+pub mod lfo_constraints {
+    pub const MIN_RATE: f32 = 0.05;
+    pub const MAX_RATE: f32 = 20.;
+    pub const MIN_DEPTH: f32 = 0.;
+    pub const MAX_DEPTH: f32 = 1.;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LfoTarget {
+    None,
+    OscPitch(usize),
+    OscVolume(usize),
+    FilterCutoff,
+    OscPulseWidth(usize),
+}
+
+impl LfoTarget {
+    pub fn osc(&self) -> Option<usize> {
+        match self {
+            LfoTarget::OscPitch(idx) | LfoTarget::OscVolume(idx) | LfoTarget::OscPulseWidth(idx) => Some(*idx),
+            _ => None,
+        }
+    }
+
+    // Follows its oscillator when an earlier one is removed, targets of the removed one are dropped
+    pub fn after_osc_removal(self, removed: usize) -> Self {
+        let moved = |idx| osc_idx_after_removal(idx, removed);
+        match self {
+            LfoTarget::OscPitch(idx) => moved(idx).map_or(LfoTarget::None, LfoTarget::OscPitch),
+            LfoTarget::OscVolume(idx) => moved(idx).map_or(LfoTarget::None, LfoTarget::OscVolume),
+            LfoTarget::OscPulseWidth(idx) => moved(idx).map_or(LfoTarget::None, LfoTarget::OscPulseWidth),
+            target => target,
+        }
+    }
+}
+
+pub struct Lfo {
+    sample_rate: f32,
+    wave: Box<dyn Wave + Send>,
+    pub waveform: WaveForm,
+    pub rate: f32,
+    pub depth: f32,
+    pub target: LfoTarget,
+    phase: f32,
+    wave_state: WaveState,
+    // Noise is sampled and held for a period, otherwise it would change every block
+    held: f32,
+}
+
+impl Lfo {
+    pub fn new(sample_rate: f32, waveform: WaveForm, rate: f32, depth: f32, target: LfoTarget) -> Self {
+        let mut lfo = Self {
+            sample_rate,
+            wave: waveform.get_wave(),
+            waveform,
+            rate: 0.0,
+            depth: 0.0,
+            target,
+            phase: 0.0,
+            wave_state: WaveState::new(),
+            held: 0.0,
+        };
+        lfo.set_rate(rate);
+        lfo.set_depth(depth);
+        lfo
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    // Wave is built by the caller, the replaced one is handed back to be freed off the audio thread
+    pub fn set_wave(&mut self, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.waveform = waveform;
+        std::mem::replace(&mut self.wave, wave)
+    }
+
+    // Hz
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate
+            .max(lfo_constraints::MIN_RATE)
+            .min(lfo_constraints::MAX_RATE);
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth
+            .max(lfo_constraints::MIN_DEPTH)
+            .min(lfo_constraints::MAX_DEPTH);
+    }
+
+    pub fn set_target(&mut self, target: LfoTarget) {
+        self.target = target;
+    }
+
+    // Bipolar value in [-1, 1] range for the next frames, depth is applied by its target.
+    // Rate multiplier comes from the mod matrix, 1.0 leaves rate as set
+    pub fn advance(&mut self, frames: usize, rate_mod: f32) -> f32 {
+        let rate = (self.rate * rate_mod)
+            .max(lfo_constraints::MIN_RATE)
+            .min(lfo_constraints::MAX_RATE);
+        let incr = rate / self.sample_rate * frames as f32;
+        let value = if self.waveform.is_noise() {
+            // Phase wraps once a period, or it's the first block
+            if self.phase < incr {
+                self.held = self.wave.next_sample(self.phase, incr, false, &mut self.wave_state);
+            }
+            self.held
+        } else {
+            self.wave.wave_func(self.phase)
+        };
+        self.phase = next_phase(self.phase, incr);
+        value
+    }
+}
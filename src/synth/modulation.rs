@@ -0,0 +1,121 @@
+use super::oscillator::osc_idx_after_removal;
+
+// Number of routes in the modulation matrix
+pub const MOD_SLOTS: usize = 6;
+
+#[allow(dead_code)]
+pub mod mod_constraints {
+    pub const MIN_DEPTH: f32 = -1.;
+    pub const MAX_DEPTH: f32 = 1.;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ModSource {
+    None,
+    Lfo(usize),
+    Env(usize),
+    Velocity,
+    ModWheel,
+    // Expression pedal after its curve
+    Expression,
+    // Lorenz attractor, swings in [-1, 1] and never repeats
+    Chaos,
+    // Step modulator in time with tempo
+    Steps,
+    // Polyphonic aftertouch, oscillator pitch and volume follow it per voice
+    Aftertouch,
+    // Picked on note-on and kept for the whole note, oscillator pitch,
+    // volume and pan follow it per voice
+    Random,
+}
+
+impl ModSource {
+    // LFOs, chaos and random swing in [-1, 1], other sources stay in [0, 1]
+    pub fn bipolar(&self) -> bool {
+        match self {
+            ModSource::Lfo(_) | ModSource::Chaos | ModSource::Random => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ModDestination {
+    None,
+    OscPitch(usize),
+    OscVolume(usize),
+    OscPan(usize),
+    OscPulseWidth(usize),
+    FilterCutoff,
+    // LFOs run in index order, so an LFO sees values of the lower ones
+    // from the current block and of itself and the higher ones from the previous
+    LfoRate(usize),
+    LfoDepth(usize),
+}
+
+impl ModDestination {
+    pub fn osc(&self) -> Option<usize> {
+        match self {
+            ModDestination::OscPitch(idx)
+            | ModDestination::OscVolume(idx)
+            | ModDestination::OscPan(idx)
+            | ModDestination::OscPulseWidth(idx) => Some(*idx),
+            _ => None,
+        }
+    }
+
+    // Follows its oscillator when an earlier one is removed, destinations of the removed one are dropped
+    pub fn after_osc_removal(self, removed: usize) -> Self {
+        let moved = |idx| osc_idx_after_removal(idx, removed);
+        match self {
+            ModDestination::OscPitch(idx) => moved(idx).map_or(ModDestination::None, ModDestination::OscPitch),
+            ModDestination::OscVolume(idx) => moved(idx).map_or(ModDestination::None, ModDestination::OscVolume),
+            ModDestination::OscPan(idx) => moved(idx).map_or(ModDestination::None, ModDestination::OscPan),
+            ModDestination::OscPulseWidth(idx) => moved(idx).map_or(ModDestination::None, ModDestination::OscPulseWidth),
+            destination => destination,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub depth: f32,
+    // Scales depth, None keeps it as set
+    pub depth_source: ModSource,
+}
+
+impl ModSlot {
+    pub fn new() -> Self {
+        Self {
+            source: ModSource::None,
+            destination: ModDestination::None,
+            depth: 0.0,
+            depth_source: ModSource::None,
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth
+            .max(mod_constraints::MIN_DEPTH)
+            .min(mod_constraints::MAX_DEPTH);
+    }
+
+    pub fn active(&self) -> bool {
+        self.source != ModSource::None
+            && self.destination != ModDestination::None
+            && self.depth != 0.0
+    }
+}
+
+// Volume can only go down from full level: with positive depth the source
+// at its maximum leaves volume untouched, negative depth inverts it
+pub fn volume_gain(value: f32, bipolar: bool, depth: f32) -> f32 {
+    let unipolar = if bipolar { (value + 1.0) / 2.0 } else { value };
+    if depth >= 0.0 {
+        1.0 - depth * (1.0 - unipolar)
+    } else {
+        1.0 + depth * unipolar
+    }
+}
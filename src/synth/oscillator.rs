@@ -1,195 +1,967 @@
-use std::time::Instant;
-
 use super::envelope::ADSR;
-use super::waves::{Wave, WaveForm};
-use super::{KeyCode, Note, Released};
+use super::modulation::volume_gain;
+use super::pluck::{pluck_constraints, OscEngine, PluckString};
+use super::waves::{default_harmonics, next_phase, Wave, WaveForm, WaveState, DEFAULT_PULSE_WIDTH, HARMONICS};
+use super::{Note, NoteSource, Released};
 
-#[derive(Debug)]
+// Normalized phase increment of Nyquist frequency
+const MAX_PHASE_INCR: f32 = 0.5;
+const TWO_PI: f32 = std::f32::consts::PI * 2.0;
+const HALF_PI: f32 = std::f32::consts::FRAC_PI_2;
+// Per sample step of polyphony gain towards its target, around 20 ms to settle
+const POLY_GAIN_SMOOTHING: f32 = 0.002;
+// Voices fade in and out at least this long whatever the envelope does, so a hard phase
+// start, zero attack or release and stolen voices don't click
+const VOICE_RAMP_MS: f32 = 2.0;
+const SUPERSAW_VOICES: usize = 7;
+// Frequency offsets of supersaw voices at full detune, center one first.
+// Side voices are spread unevenly, so their beating never lines up
+const SUPERSAW_OFFSETS: [f32; SUPERSAW_VOICES] = [0.0, -0.110_023, 0.107_452, -0.062_884, 0.062_165, -0.019_524, 0.019_912];
+// The widest supersaw offset is panned all the way to the side at full width
+const SUPERSAW_WIDEST: f32 = 0.110_023;
+// Tone shelves split the spectrum here, full tilt raises one side and lowers the other this much
+const TONE_PIVOT: f32 = 800.0;
+const TONE_SHELF_DB: f32 = 6.0;
+
+#[allow(dead_code)]
+pub mod osc_constraints {
+    pub const MIN_TRANSPOSE: i8 = -24;
+    pub const MAX_TRANSPOSE: i8 = 24;
+    pub const MIN_TUNE: i8 = -100;
+    pub const MAX_TUNE: i8 = 100;
+    // Hz
+    pub const MIN_LOW_CUT: f32 = 5.;
+    pub const MAX_LOW_CUT: f32 = 80.;
+    pub const DEFAULT_LOW_CUT: f32 = 20.;
+    // 0.0 - full left, 1.0 - full right
+    pub const MIN_PAN: f32 = 0.;
+    pub const MAX_PAN: f32 = 1.;
+    pub const MAX_UNISONS: usize = 7;
+    // Voices are allocated once per oscillator, notes only claim them
+    pub const MIN_VOICES: usize = 1;
+    pub const MAX_VOICES: usize = 32;
+    pub const MIN_SUB_LEVEL: f32 = 0.;
+    pub const MAX_SUB_LEVEL: f32 = 1.;
+    pub const DEFAULT_SUB_LEVEL: f32 = 0.5;
+    // MIDI velocity
+    pub const MIN_VELOCITY: u8 = 0;
+    pub const MAX_VELOCITY: u8 = 127;
+    // How much velocity changes voice level, 0.0 plays every note at full level
+    pub const MIN_VELOCITY_SENSITIVITY: f32 = 0.;
+    pub const MAX_VELOCITY_SENSITIVITY: f32 = 1.;
+    pub const DEFAULT_VELOCITY_SENSITIVITY: f32 = 1.;
+    // Computer keyboard and on-screen piano can't tell how hard a key is hit
+    pub const DEFAULT_KEY_VELOCITY: u8 = 100;
+    // FM index in radians of carrier phase per unit of modulator output
+    pub const MIN_FM_AMOUNT: f32 = 0.;
+    pub const MAX_FM_AMOUNT: f32 = 10.;
+    // How much envelope shapes amplitude, 0.0 drones at full level until release
+    pub const MIN_ENV_AMOUNT: f32 = 0.;
+    pub const MAX_ENV_AMOUNT: f32 = 1.;
+    // Supersaw detune and center to side voices blend
+    pub const MIN_SUPERSAW_DETUNE: f32 = 0.;
+    pub const MAX_SUPERSAW_DETUNE: f32 = 1.;
+    pub const DEFAULT_SUPERSAW_DETUNE: f32 = 0.5;
+    pub const MIN_SUPERSAW_MIX: f32 = 0.;
+    pub const MAX_SUPERSAW_MIX: f32 = 1.;
+    pub const DEFAULT_SUPERSAW_MIX: f32 = 0.5;
+    // Fraction of period, pulse disappears at the very ends
+    pub const MIN_PULSE_WIDTH: f32 = 0.05;
+    pub const MAX_PULSE_WIDTH: f32 = 0.95;
+    pub const DEFAULT_PULSE_WIDTH: f32 = super::DEFAULT_PULSE_WIDTH;
+    // Stereo spread of unisons, 0.0 keeps them all at oscillator pan
+    pub const MIN_UNISON_WIDTH: f32 = 0.;
+    pub const MAX_UNISON_WIDTH: f32 = 1.;
+    // ms for unisons to spread from the center to full width after note on, 0 starts them wide
+    pub const MIN_WIDTH_TIME: f32 = 0.;
+    pub const MAX_WIDTH_TIME: f32 = 5000.;
+    // Degrees the voices start at on top of phase start
+    pub const MIN_PHASE_OFFSET: f32 = 0.;
+    pub const MAX_PHASE_OFFSET: f32 = 360.;
+    // Tone tilt, -1.0 darkens and 1.0 brightens
+    pub const MIN_TILT: f32 = -1.;
+    pub const MAX_TILT: f32 = 1.;
+    // FM, LFO and mod matrix targets name the first two oscillators
+    pub const MIN_OSCILLATORS: usize = 2;
+    pub const MAX_OSCILLATORS: usize = 4;
+}
+
+use self::osc_constraints::{MAX_UNISONS, MAX_VOICES};
+
+// Most of the detune travel is spent on small amounts, they are the useful ones
+fn supersaw_detune_curve(detune: f32) -> f32 {
+    detune * (0.15 + detune * (0.25 + detune * 0.6))
+}
+
+#[derive(Debug, Clone, Copy)]
 struct Unison {
     freq_mod: f32,
     volume: f32,
+    // -1.0 - left, 1.0 - right at full width
+    pan: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct UnisonVoice {
     phase: f32,
     phase_incr: f32,
+    // Pink noise filter of the unison
+    wave_state: WaveState,
     volume: f32,
+    pan: f32,
 }
 
+const NO_UNISON: Unison = Unison { freq_mod: 1.0, volume: 0.0, pan: 0.0 };
+const NO_UNISON_VOICE: UnisonVoice = UnisonVoice { phase: 0.0, phase_incr: 0.0, wave_state: WaveState::new(), volume: 0.0, pan: 0.0 };
+
 #[derive(Debug)]
 pub struct Voice {
     note: Note,
     volume: f32,
-    unisons: Vec<UnisonVoice>,
+    // First unison_num of oscillator are playing
+    unisons: [UnisonVoice; MAX_UNISONS],
+    // Polyphonic aftertouch of the key that plays the voice, 0.0-1.0
+    pressure: f32,
+    // Sub-oscillator plays one octave below the note
+    sub_phase: f32,
+    // Last enveloped sample, before oscillator volume and pan. FM carrier reads it
+    output: f32,
+    // Index of the plucked string in strings of the oscillator, waves don't need it
+    string: Option<usize>,
+    // Previous input and output of low cut filter
+    hp_in: f32,
+    hp_out: f32,
+    // The same for side signal of unisons spread in stereo
+    hp_side_in: f32,
+    hp_side_out: f32,
+    // Note off came while the oscillator drones, the voice is released once drone is off
+    droned: bool,
+    // Envelope gain of the last sample, micro ramps limit how fast it moves at start and end
+    gain: f32,
+    // Stolen or silenced voice fades out in a micro ramp and is removed
+    cut: bool,
 }
 
-#[allow(dead_code)]
+impl Voice {
+    fn cut(&mut self) {
+        if self.note.released.is_none() {
+            self.note.released = Some(Released {
+                age: self.note.age,
+                value: self.volume,
+            });
+        }
+        self.cut = true;
+    }
+}
+
+// Which voice a new note takes once all voices play
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoiceStealing {
+    // Quietest released voice, or the oldest one if every voice is held
+    ReleasedFirst,
+    Oldest,
+    Quietest,
+}
+
+// Where the wave of a new voice starts
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Start {
+    // Zero crossing of the sine, the same transient every note
     Soft,
+    // Quarter period in, the wave starts at its peak
     Hard,
+    // Every voice somewhere else, notes never sound exactly alike
     Random,
 }
 
-enum PhaseStart {
-    Soft,
-    Hard(f32),
-    Random(f32),
-}
-
-impl PhaseStart {
-    fn value(&self) -> f32 {
+impl Start {
+    // Normalized phase of central unison on voice creation
+    fn phase(&self) -> f32 {
         match self {
-            PhaseStart::Soft => 0.0,
-            PhaseStart::Hard(period) => period / 4.0,
-            PhaseStart::Random(period) => period * rand::random::<f32>(),
+            Start::Soft => 0.0,
+            Start::Hard => 0.25,
+            Start::Random => rand::random::<f32>(),
         }
     }
+}
 
-    fn change_period(&mut self, period: f32) {
-        match self {
-            PhaseStart::Soft => {}
-            PhaseStart::Hard(_) => *self = PhaseStart::Random(period),
-            PhaseStart::Random(_) => *self = PhaseStart::Random(period),
-        }
+// Left and right gains of constant power pan law, pan is clamped to its range
+fn pan_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.max(osc_constraints::MIN_PAN).min(osc_constraints::MAX_PAN);
+    (((1.0 - pan) * HALF_PI).sin(), (pan * HALF_PI).sin())
+}
+
+// Envelope value scaled by env amount. Part of the level it doesn't shape
+// is held until release, then all of it fades out with the release stage
+fn env_gain(volume: f32, released: &Option<Released>, amount: f32) -> f32 {
+    match released {
+        Some(r) => (1.0 - amount + amount * r.value) * volume / r.value.max(f32::EPSILON),
+        None => 1.0 - amount + amount * volume,
     }
 }
 
-// Panning TODO:
+// Constant power panning:
 // pan value == 0.0 - full left; == 1.0 - full right
 // left = value * sin((1- pan) * PI / 2)
 // right = value * sin(pan * PI / 2)
 pub struct Oscillator {
     sample_rate: f32,
     wave: Box<dyn Wave + Send>,
+    // Loudness compensation for current wave
+    wave_gain: f32,
     pub waveform: WaveForm,
+    // Pluck engine ignores waveform and unisons
+    pub engine: OscEngine,
+    pub pluck_damping: f32,
+    // Delay lines of pluck engine, one per voice slot. Built off the audio thread,
+    // empty until pluck engine is picked
+    strings: Vec<PluckString>,
     pub env_idx: usize,
+    pub env_amount: f32,
+    // Duty cycle of pulse wave, modulation is added on top of it
+    pub pulse_width: f32,
+    // Partial levels of additive wave
+    pub harmonics: [f32; HARMONICS],
     pub volume: f32,
+    // Follows pitch wheel
+    pub bend: bool,
+    // Ignores note offs, held voices sound until drone is turned off
+    pub drone: bool,
+    // Band-limited waves, cost a bit more per sample
+    pub high_quality: bool,
+    // Sine or square one octave below every voice
+    pub sub: bool,
+    sub_wave: Box<dyn Wave + Send>,
+    sub_gain: f32,
+    pub sub_waveform: WaveForm,
+    pub sub_level: f32,
+    // Tilt EQ of the mixed voices, bypassed while it's off
+    pub tone: bool,
+    pub tilt: f32,
+    tone_coeff: f32,
+    low_shelf_gain: f32,
+    high_shelf_gain: f32,
+    // One-pole low-pass of left and right, the rest of the signal is the high shelf
+    tone_lp: (f32, f32),
+    // Notes with MIDI velocity in this range start voices, others pass by
+    pub velocity_low: u8,
+    pub velocity_high: u8,
+    // Never grows past MAX_VOICES, so it's never reallocated
     voices: Vec<Voice>,
-    #[allow(dead_code)]
-    panning: f32,
+    // Polyphony limit, up to MAX_VOICES
+    max_voices: usize,
+    pub stealing: VoiceStealing,
+    pub panning: f32,
     pub transpose: f32,
     pub tune: f32,
-    unisons: Vec<Unison>,
-    phase_start: PhaseStart,
+    unisons: [Unison; MAX_UNISONS],
+    unison_num: usize,
+    // Unison number set by user, supersaw plays its own number of voices
+    unison_setting: usize,
+    // Supersaw replaces unisons with its own detune, tune only shifts the whole stack
+    pub supersaw: bool,
+    pub supersaw_detune: f32,
+    pub supersaw_mix: f32,
+    // Keeps sum of unisons of a single voice within [-1, 1]
+    unison_gain: f32,
+    // Unisons of every voice spread from the center to this width over width_time ms
+    unison_width: f32,
+    width_time: f32,
+    width_samples: f32,
+    // Headroom for chords, goes down as 1 / sqrt(voices)
+    poly_gain: f32,
+    // Per sample step of voice micro ramps and their length at voice start
+    ramp_step: f32,
+    ramp_samples: u32,
+    phase_start: Start,
+    // Normalized, added to the phase every new voice starts at
+    phase_offset: f32,
+    // Hz, voices are high-passed and notes below it fade out
+    low_cut: f32,
+    hp_coeff: f32,
+    // Modulation for the current sample, reset by synth every sample
+    pitch_mod: f32,
+    volume_mod: f32,
+    pan_mod: f32,
+    pulse_width_mod: f32,
+    // Aftertouch routes, each voice scales them by its own pressure
+    pressure_pitch: f32,
+    pressure_volume: f32,
+    // Random source routes, each voice scales them by random value of its note
+    random_pitch: f32,
+    random_volume: f32,
+    random_pan: f32,
 }
 
 impl Oscillator {
     pub fn new(sample_rate: f32, waveform: WaveForm, env_idx: usize, volume: f32) -> Self {
         let volume = volume.min(1.0).max(0.0);
-        Self {
+        let wave = waveform.get_wave();
+        let sub_wave = WaveForm::Sine.get_wave();
+        let mut osc = Self {
             sample_rate: sample_rate,
-            wave: waveform.get_wave(),
+            wave_gain: wave.gain(),
+            wave: wave,
             waveform: waveform,
+            engine: OscEngine::Wave,
+            pluck_damping: pluck_constraints::DEFAULT_DAMPING,
+            strings: Vec::new(),
             env_idx: env_idx,
+            env_amount: osc_constraints::MAX_ENV_AMOUNT,
+            pulse_width: osc_constraints::DEFAULT_PULSE_WIDTH,
+            harmonics: default_harmonics(),
             volume: volume,
-            voices: Vec::new(),
-            panning: 0.0,
+            bend: true,
+            drone: false,
+            high_quality: false,
+            sub: false,
+            sub_gain: sub_wave.gain(),
+            sub_wave: sub_wave,
+            sub_waveform: WaveForm::Sine,
+            sub_level: osc_constraints::DEFAULT_SUB_LEVEL,
+            tone: false,
+            tilt: 0.0,
+            tone_coeff: 0.0,
+            low_shelf_gain: 1.0,
+            high_shelf_gain: 1.0,
+            tone_lp: (0.0, 0.0),
+            velocity_low: osc_constraints::MIN_VELOCITY,
+            velocity_high: osc_constraints::MAX_VELOCITY,
+            voices: Vec::with_capacity(MAX_VOICES),
+            max_voices: MAX_VOICES,
+            stealing: VoiceStealing::ReleasedFirst,
+            panning: 0.5,
             transpose: 1.0,
             tune: 1.0,
-            unisons: vec![Unison {
-                freq_mod: 1.0,
-                volume: 1.0,
-            }],
-            phase_start: PhaseStart::Soft,
-        }
+            unisons: [Unison { freq_mod: 1.0, volume: 1.0, pan: 0.0 }; MAX_UNISONS],
+            unison_num: 1,
+            unison_setting: 1,
+            supersaw: false,
+            supersaw_detune: osc_constraints::DEFAULT_SUPERSAW_DETUNE,
+            supersaw_mix: osc_constraints::DEFAULT_SUPERSAW_MIX,
+            unison_gain: 1.0,
+            unison_width: osc_constraints::MIN_UNISON_WIDTH,
+            width_time: osc_constraints::MIN_WIDTH_TIME,
+            width_samples: 0.0,
+            poly_gain: 1.0,
+            ramp_step: 1.0,
+            ramp_samples: 0,
+            phase_start: Start::Soft,
+            phase_offset: 0.0,
+            low_cut: 0.0,
+            hp_coeff: 1.0,
+            pitch_mod: 1.0,
+            volume_mod: 1.0,
+            pan_mod: 0.0,
+            pulse_width_mod: 0.0,
+            pressure_pitch: 0.0,
+            pressure_volume: 0.0,
+            random_pitch: 0.0,
+            random_volume: 0.0,
+            random_pan: 0.0,
+        };
+        osc.set_low_cut(osc_constraints::DEFAULT_LOW_CUT);
+        osc.set_ramp();
+        osc.set_tone_coeff();
+        osc
     }
 
     pub fn create_voice(&mut self, note: &Note) {
+        // Strings come right after the engine, until then there's nothing to pluck
+        if self.engine == OscEngine::Pluck && self.strings.is_empty() {
+            return
+        }
         if let None = self
             .voices
             .iter()
             .find(|v| v.note == *note && v.note.released.is_none())
         {
             let phase_incr = note.frequency / self.sample_rate * self.transpose;
-            let mut unisons = Vec::<UnisonVoice>::with_capacity(7);
-            let period = self.wave.period();
-            let mut uni_iter = self.unisons.iter();
-            if self.unisons.len() % 2 == 1 {
-                // At least one "unison" is always present
-                let central_uni = uni_iter.next().unwrap();
-                unisons.push(UnisonVoice {
-                    phase: self.phase_start.value(),
-                    phase_incr: phase_incr * central_uni.freq_mod,
-                    volume: central_uni.volume,
-                });
-            }
-            for uni in uni_iter {
-                unisons.push(UnisonVoice {
-                    phase: period * rand::random::<f32>(),
+            let start_phase = (self.phase_start.phase() + self.phase_offset).fract();
+            let mut unisons = [NO_UNISON_VOICE; MAX_UNISONS];
+            for (i, (uni_voice, uni)) in unisons
+                .iter_mut()
+                .zip(self.unisons[..self.unison_num].iter())
+                .enumerate()
+            {
+                // Central unison of odd number starts at the set phase
+                let phase = if i == 0 && self.unison_num % 2 == 1 {
+                    start_phase
+                } else {
+                    rand::random::<f32>()
+                };
+                *uni_voice = UnisonVoice {
+                    phase: phase,
                     phase_incr: phase_incr * uni.freq_mod,
+                    wave_state: WaveState::new(),
                     volume: uni.volume,
-                })
+                    pan: uni.pan,
+                };
             }
-            self.voices.push(Voice {
+            let voice = Voice {
                 note: note.clone(),
                 volume: 0.0,
                 unisons: unisons,
-            });
+                pressure: 0.0,
+                sub_phase: start_phase / 2.0,
+                output: 0.0,
+                string: None,
+                hp_in: 0.0,
+                hp_out: 0.0,
+                hp_side_in: 0.0,
+                hp_side_out: 0.0,
+                droned: false,
+                gain: 0.0,
+                cut: false,
+            };
+            // Fading voices don't count against the limit, they are gone in a few ms.
+            // They take slots though, once all of them are taken the quietest fading one gives way
+            let under_limit = self.voices.iter().filter(|v| !v.cut).count() < self.max_voices;
+            let slot = if under_limit && self.voices.len() < MAX_VOICES {
+                self.voices.push(voice);
+                self.voices.len() - 1
+            } else if under_limit {
+                let idx = self.quietest_cut_voice();
+                self.voices[idx] = voice;
+                idx
+            } else {
+                let idx = self.voice_to_steal();
+                if self.voices.len() < MAX_VOICES {
+                    self.voices[idx].cut();
+                    self.voices.push(voice);
+                    self.voices.len() - 1
+                } else {
+                    self.voices[idx] = voice;
+                    idx
+                }
+            };
+            if self.engine == OscEngine::Pluck {
+                self.pluck(slot, note.frequency);
+            }
         }
     }
 
-    pub fn voice_off(&mut self, key: KeyCode) {
-        if let Some(Voice { note, volume, .. }) = self
+    // There are as many strings as voice slots, so a string no voice holds is always there.
+    // It's refilled in place, nothing is allocated
+    fn pluck(&mut self, slot: usize, freq: f32) {
+        let strings = self.strings.len();
+        let free = (0..strings).find(|idx| !self.voices.iter().any(|v| v.string == Some(*idx)));
+        match free {
+            Some(idx) => {
+                let period = self.period(freq);
+                self.strings[idx].pluck(period);
+                self.voices[slot].string = Some(idx);
+            }
+            None => self.voices[slot].cut(),
+        }
+    }
+
+    // Voices over a lowered limit aren't cut, they end as usual and aren't replaced
+    pub fn set_max_voices(&mut self, voices: usize) {
+        self.max_voices = voices.max(osc_constraints::MIN_VOICES).min(MAX_VOICES);
+    }
+
+    // Called with every slot taken and some of them cut, so there is one
+    fn quietest_cut_voice(&self) -> usize {
+        self.voices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.cut)
+            .min_by(|(_, a), (_, b)| a.gain.partial_cmp(&b.gain).unwrap_or(std::cmp::Ordering::Equal))
+            .map_or(0, |(idx, _)| idx)
+    }
+
+    // Every voice plays, one of them gives way to the new note
+    fn voice_to_steal(&self) -> usize {
+        let quietest = |released_only: bool| {
+            self.voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !v.cut && (!released_only || v.note.released.is_some()))
+                .min_by(|(_, a), (_, b)| a.volume.partial_cmp(&b.volume).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(idx, _)| idx)
+        };
+        let oldest = || {
+            self.voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !v.cut)
+                .max_by_key(|(_, v)| v.note.age)
+                .map(|(idx, _)| idx)
+        };
+        match self.stealing {
+            VoiceStealing::ReleasedFirst => quietest(true).or_else(oldest),
+            VoiceStealing::Oldest => oldest(),
+            VoiceStealing::Quietest => quietest(false),
+        }
+        .unwrap_or(0)
+    }
+
+    pub fn voice_off(&mut self, source: NoteSource) {
+        if let Some(Voice { note, volume, droned, .. }) = self
             .voices
             .iter_mut()
-            .find(|v| v.note.triggered_by == key && v.note.released.is_none())
+            .find(|v| v.note.triggered_by == source && v.note.released.is_none())
         {
+            if self.drone {
+                *droned = true;
+                return
+            }
             note.released = Some(Released {
-                time: Instant::now(),
+                age: note.age,
                 value: *volume,
             })
         }
     }
 
-    pub fn get_sample(&mut self, adsr: &ADSR) -> f32 {
+    // Poly aftertouch message goes to the held voice of its key
+    pub fn set_pressure(&mut self, source: NoteSource, pressure: f32) {
+        if let Some(voice) = self
+            .voices
+            .iter_mut()
+            .find(|v| v.note.triggered_by == source && v.note.released.is_none())
+        {
+            voice.pressure = pressure;
+        }
+    }
+
+    // For destinations shared by all voices
+    pub fn max_pressure(&self) -> f32 {
+        self.voices
+            .iter()
+            .filter(|v| v.note.released.is_none())
+            .map(|v| v.pressure)
+            .fold(0.0, f32::max)
+    }
+
+    // Returns number of voices released
+    pub fn release_voices<F: Fn(&Note) -> bool>(&mut self, filter: F) -> usize {
+        let mut released = 0;
+        for Voice { note, volume, .. } in self.voices.iter_mut() {
+            if note.released.is_none() && filter(note) {
+                note.released = Some(Released {
+                    age: note.age,
+                    value: *volume,
+                });
+                released += 1;
+            }
+        }
+        released
+    }
+
+    // Cuts all voices without release, only the micro ramp is left of them
+    pub fn silence(&mut self) {
+        self.voices.iter_mut().for_each(Voice::cut);
+    }
+
+    // Stereo pair of left and right samples. With fm every voice shifts its phase
+    // by output of the modulator voice playing the same note, scaled by FM amount
+    pub fn get_sample(&mut self, adsr: &ADSR, fm: Option<(&Oscillator, f32)>) -> (f32, f32) {
+        if self.waveform == WaveForm::Pulse {
+            self.update_pulse_width();
+        }
+        let poly_target = 1.0 / (self.voices.len().max(1) as f32).sqrt();
+        self.poly_gain += (poly_target - self.poly_gain) * POLY_GAIN_SMOOTHING;
         let mut sample = 0.0;
+        let mut side = 0.0;
+        let mut spread = (0.0, 0.0);
         let mut muted_voices = false;
+        let unison_num = self.unison_num;
+        // Noise has no pitch to alias or to fall below low cut
+        let noise = self.waveform.is_noise();
         for Voice {
             note,
             volume,
             unisons,
+            pressure,
+            sub_phase,
+            output,
+            string,
+            hp_in,
+            hp_out,
+            hp_side_in,
+            hp_side_out,
+            gain,
+            cut,
+            ..
         } in self.voices.iter_mut()
         {
-            *volume = adsr.get_volume_incr(volume, &note.triggered_time, &note.released);
+            *volume = adsr.get_volume_incr(volume, note.age, &note.released);
+            note.age = note.age.saturating_add(1);
             *volume = volume.min(1.0);
-            if *volume <= 0.01 {
+            let target = if *cut || *volume <= 0.01 {
+                0.0
+            } else {
+                env_gain(*volume, &note.released, self.env_amount)
+            };
+            *gain = if note.age <= self.ramp_samples || note.released.is_some() {
+                *gain + (target - *gain).max(-self.ramp_step).min(self.ramp_step)
+            } else {
+                target
+            };
+            if *gain <= 0.0 {
+                *output = 0.0;
                 muted_voices = true;
                 continue;
             }
-            let mut voice_sample = 0.0;
-            for uni in unisons.iter_mut() {
-                voice_sample += self.wave.wave_func(uni.phase) * uni.volume;
-                uni.phase = self.wave.next_phase(uni.phase, uni.phase_incr);
+            // Radians to normalized phase
+            let fm_offset = fm.map_or(0.0, |(modulator, amount)| modulator.voice_output(note) * amount / TWO_PI);
+            let per_voice_semitones = self.pressure_pitch * *pressure + self.random_pitch * note.random;
+            let pitch_mod = if per_voice_semitones != 0.0 {
+                self.pitch_mod * 2f32.powf(per_voice_semitones / 12.0)
+            } else {
+                self.pitch_mod
+            };
+            let pitch_mod = if note.glide.active() {
+                pitch_mod * note.glide.next()
+            } else {
+                pitch_mod
+            };
+            // Mid and side of the voice, unisons panned left go to side with minus
+            let (mut voice_sample, voice_side) = match string {
+                Some(idx) => {
+                    let period = self.sample_rate / (note.frequency * self.transpose * self.tune * pitch_mod);
+                    (self.strings[*idx].next(period, self.pluck_damping), 0.0)
+                }
+                None => {
+                    let mut voice_sample = 0.0;
+                    let mut voice_side = 0.0;
+                    for uni in unisons[..unison_num].iter_mut() {
+                        let phase_incr = uni.phase_incr * pitch_mod;
+                        // Unisons above Nyquist frequency would alias, so they are muted
+                        if noise || phase_incr < MAX_PHASE_INCR {
+                            let phase = if fm_offset != 0.0 { next_phase(uni.phase, fm_offset) } else { uni.phase };
+                            let uni_sample = self.wave.next_sample(phase, phase_incr, self.high_quality, &mut uni.wave_state) * uni.volume;
+                            voice_sample += uni_sample;
+                            voice_side += uni_sample * uni.pan;
+                        }
+                        uni.phase = next_phase(uni.phase, phase_incr);
+                    }
+                    let uni_gain = self.unison_gain * self.wave_gain;
+                    (voice_sample * uni_gain, voice_side * uni_gain)
+                }
+            };
+            // Sub is mixed in before the envelope, so it follows the voice
+            if self.sub {
+                let sub_incr = note.frequency * self.transpose * pitch_mod / self.sample_rate / 2.0;
+                // Sub waves are never noise, there is no state to keep
+                voice_sample += self.sub_wave.next_sample(*sub_phase, sub_incr, self.high_quality, &mut WaveState::new())
+                    * self.sub_gain
+                    * self.sub_level;
+                *sub_phase = next_phase(*sub_phase, sub_incr);
+            }
+            // Notes too low to be heard are faded out instead of pushing speakers
+            let freq = note.frequency * self.transpose * pitch_mod;
+            let fade = if noise { 1.0 } else { (freq / self.low_cut).min(1.0) };
+            let pressure_gain = if self.pressure_volume != 0.0 {
+                volume_gain(*pressure, false, self.pressure_volume)
+            } else {
+                1.0
+            };
+            let random_gain = if self.random_volume != 0.0 {
+                volume_gain(note.random, true, self.random_volume)
+            } else {
+                1.0
+            };
+            let level = *gain * note.level * fade * pressure_gain * random_gain;
+            let voice_sample = voice_sample * level;
+            // Unisons start at the center and open up to full width as the note gets older
+            let width = if self.width_samples > 0.0 {
+                self.unison_width * (note.age as f32 / self.width_samples).min(1.0)
+            } else {
+                self.unison_width
+            };
+            let voice_side = voice_side * level * width;
+            *output = voice_sample;
+            // One-pole high-pass removes DC offset of very low notes
+            *hp_out = voice_sample - *hp_in + self.hp_coeff * *hp_out;
+            *hp_in = voice_sample;
+            *hp_side_out = voice_side - *hp_side_in + self.hp_coeff * *hp_side_out;
+            *hp_side_in = voice_side;
+            // Voices spread by random pan are panned one by one
+            if self.random_pan != 0.0 {
+                let (left, right) = pan_gains(self.panning + (self.pan_mod + self.random_pan * note.random) / 2.0);
+                spread.0 += (*hp_out - *hp_side_out) * left;
+                spread.1 += (*hp_out + *hp_side_out) * right;
+            } else {
+                sample += *hp_out;
+                side += *hp_side_out;
             }
-            sample += voice_sample * *volume;
         }
         if muted_voices {
             self.voices
-                .retain(|v| !(v.note.released.is_some() && v.volume <= 0.01));
+                .retain(|v| !(v.gain <= 0.0 && (v.cut || v.note.released.is_some() && v.volume <= 0.01)));
+        }
+        let gain = self.poly_gain * self.volume * self.volume_mod;
+        // Modulation offset of 1.0 moves pan across the whole range
+        let (left, right) = pan_gains(self.panning + self.pan_mod / 2.0);
+        let output = (((sample - side) * left + spread.0) * gain, ((sample + side) * right + spread.1) * gain);
+        if self.tone {
+            self.apply_tone(output)
+        } else {
+            output
         }
-        sample * self.volume
     }
 
-    pub fn set_waveform(&mut self, waveform: &WaveForm) {
-        self.waveform = waveform.clone();
-        self.wave = waveform.get_wave();
-        self.phase_start.change_period(self.wave.period());
+    // First-order low and high shelves around TONE_PIVOT, their gains mirror each other
+    fn apply_tone(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        let (lp_left, lp_right) = &mut self.tone_lp;
+        *lp_left += (left - *lp_left) * self.tone_coeff;
+        *lp_right += (right - *lp_right) * self.tone_coeff;
+        (
+            *lp_left * self.low_shelf_gain + (left - *lp_left) * self.high_shelf_gain,
+            *lp_right * self.low_shelf_gain + (right - *lp_right) * self.high_shelf_gain,
+        )
     }
 
-    pub fn set_start(&mut self, start: Start) {
-        match start {
-            Start::Soft => self.phase_start = PhaseStart::Soft,
-            Start::Hard => self.phase_start = PhaseStart::Hard(self.wave.period()),
-            Start::Random => self.phase_start = PhaseStart::Random(self.wave.period()),
+    pub fn reset_modulation(&mut self) {
+        self.pitch_mod = 1.0;
+        self.volume_mod = 1.0;
+        self.pan_mod = 0.0;
+        self.pulse_width_mod = 0.0;
+        self.pressure_pitch = 0.0;
+        self.pressure_volume = 0.0;
+        self.random_pitch = 0.0;
+        self.random_volume = 0.0;
+        self.random_pan = 0.0;
+    }
+
+    pub fn modulate_pitch(&mut self, semitones: f32) {
+        self.pitch_mod *= 2f32.powf(semitones / 12.0);
+    }
+
+    pub fn modulate_volume(&mut self, gain: f32) {
+        self.volume_mod *= gain;
+    }
+
+    // Semitones at full pressure of a voice
+    pub fn modulate_pitch_by_pressure(&mut self, semitones: f32) {
+        self.pressure_pitch += semitones;
+    }
+
+    // Depth in [-1, 1] range, like volume modulation by other sources
+    pub fn modulate_volume_by_pressure(&mut self, depth: f32) {
+        self.pressure_volume = (self.pressure_volume + depth).max(-1.0).min(1.0);
+    }
+
+    // Offset in [-1, 1] range
+    pub fn modulate_pan(&mut self, amount: f32) {
+        self.pan_mod += amount;
+    }
+
+    // Semitones at random value of 1.0
+    pub fn modulate_pitch_by_random(&mut self, semitones: f32) {
+        self.random_pitch += semitones;
+    }
+
+    // Depth in [-1, 1] range, like volume modulation by other sources
+    pub fn modulate_volume_by_random(&mut self, depth: f32) {
+        self.random_volume = (self.random_volume + depth).max(-1.0).min(1.0);
+    }
+
+    // Offset in [-1, 1] range at random value of 1.0
+    pub fn modulate_pan_by_random(&mut self, amount: f32) {
+        self.random_pan += amount;
+    }
+
+    // Offset in [-1, 1] range, 1.0 moves width by half of period
+    pub fn modulate_pulse_width(&mut self, amount: f32) {
+        self.pulse_width_mod += amount;
+    }
+
+    // Sounding voices keep their pitch
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let ratio = self.sample_rate / sample_rate;
+        let unison_num = self.unison_num;
+        for Voice { unisons, .. } in self.voices.iter_mut() {
+            for UnisonVoice { phase_incr, .. } in unisons[..unison_num].iter_mut() {
+                *phase_incr *= ratio;
+            }
         }
+        self.sample_rate = sample_rate;
+        self.set_low_cut(self.low_cut);
+        self.set_ramp();
+        self.set_width_time(self.width_time);
+        self.set_tone_coeff();
+    }
+
+    fn set_tone_coeff(&mut self) {
+        self.tone_coeff = 1.0 - (-TWO_PI * TONE_PIVOT / self.sample_rate).exp();
+    }
+
+    fn set_ramp(&mut self) {
+        self.ramp_samples = (VOICE_RAMP_MS * self.sample_rate / 1000.0).ceil().max(1.0) as u32;
+        self.ramp_step = 1.0 / self.ramp_samples as f32;
+    }
+
+    // Wave is built by the caller with build_wave, the replaced one is handed back,
+    // so neither is allocated nor freed on the audio thread
+    pub fn set_wave(&mut self, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.waveform = waveform;
+        self.replace_wave(wave)
+    }
+
+    fn replace_wave(&mut self, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        let old = std::mem::replace(&mut self.wave, wave);
+        self.update_pulse_width();
+        old
+    }
+
+    // Playing voices keep their engine, the next notes get the new one
+    pub fn set_engine(&mut self, engine: OscEngine) {
+        self.engine = engine;
+    }
+
+    // Strings are built by the caller with pluck_strings, the replaced ones are handed back.
+    // Ringing strings carry on in the new ones, at another sample rate they stop
+    pub fn set_pluck_strings(&mut self, mut strings: Vec<PluckString>) -> Vec<PluckString> {
+        for voice in self.voices.iter_mut() {
+            if let Some(idx) = voice.string {
+                match strings.get_mut(idx) {
+                    Some(string) if string.copy_from(&self.strings[idx]) => (),
+                    _ => {
+                        voice.string = None;
+                        voice.cut();
+                        voice.gain = 0.0;
+                    }
+                }
+            }
+        }
+        std::mem::swap(&mut self.strings, &mut strings);
+        strings
+    }
+
+    pub fn set_pluck_damping(&mut self, damping: f32) {
+        self.pluck_damping = damping
+            .max(pluck_constraints::MIN_DAMPING)
+            .min(pluck_constraints::MAX_DAMPING);
+    }
+
+    // Samples in a period of the note without modulation
+    fn period(&self, freq: f32) -> f32 {
+        self.sample_rate / (freq * self.transpose * self.tune)
+    }
+
+    // Levels in 0.0-1.0, wave is the current waveform built from them
+    pub fn set_harmonics(&mut self, levels: [f32; HARMONICS], wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.harmonics = levels;
+        self.replace_wave(wave)
+    }
+
+    pub fn set_pulse_width(&mut self, width: f32) {
+        self.pulse_width = width
+            .max(osc_constraints::MIN_PULSE_WIDTH)
+            .min(osc_constraints::MAX_PULSE_WIDTH);
+        self.update_pulse_width();
+    }
+
+    // Narrow pulses are quieter, so gain follows the width
+    fn update_pulse_width(&mut self) {
+        let width = (self.pulse_width + self.pulse_width_mod / 2.0)
+            .max(osc_constraints::MIN_PULSE_WIDTH)
+            .min(osc_constraints::MAX_PULSE_WIDTH);
+        self.wave.set_pulse_width(width);
+        self.wave_gain = self.wave.gain();
+    }
+
+    pub fn set_env_amount(&mut self, amount: f32) {
+        self.env_amount = amount
+            .max(osc_constraints::MIN_ENV_AMOUNT)
+            .min(osc_constraints::MAX_ENV_AMOUNT);
+    }
+
+    pub fn set_sub(&mut self, enabled: bool) {
+        self.sub = enabled;
+    }
+
+    // Turning drone off releases voices whose keys were let go meanwhile, held keys keep playing
+    pub fn set_drone(&mut self, enabled: bool) {
+        self.drone = enabled;
+        if !enabled {
+            for Voice { note, volume, droned, .. } in self.voices.iter_mut() {
+                if *droned && note.released.is_none() {
+                    note.released = Some(Released {
+                        age: note.age,
+                        value: *volume,
+                    });
+                }
+                *droned = false;
+            }
+        }
+    }
+
+    // Sine or square, the replaced wave is handed back like set_wave does
+    pub fn set_sub_wave(&mut self, waveform: WaveForm, wave: Box<dyn Wave + Send>) -> Box<dyn Wave + Send> {
+        self.sub_waveform = waveform;
+        self.sub_gain = wave.gain();
+        std::mem::replace(&mut self.sub_wave, wave)
+    }
+
+    pub fn set_sub_level(&mut self, level: f32) {
+        self.sub_level = level
+            .max(osc_constraints::MIN_SUB_LEVEL)
+            .min(osc_constraints::MAX_SUB_LEVEL);
+    }
+
+    // Low-pass state left from the last time it was on is dropped
+    pub fn set_tone(&mut self, enabled: bool) {
+        if enabled && !self.tone {
+            self.tone_lp = (0.0, 0.0);
+        }
+        self.tone = enabled;
+    }
+
+    pub fn set_tilt(&mut self, tilt: f32) {
+        self.tilt = tilt
+            .max(osc_constraints::MIN_TILT)
+            .min(osc_constraints::MAX_TILT);
+        self.high_shelf_gain = 10f32.powf(self.tilt * TONE_SHELF_DB / 20.0);
+        self.low_shelf_gain = 1.0 / self.high_shelf_gain;
+    }
+
+    // Bounds are inclusive and can come in any order
+    pub fn set_velocity_range(&mut self, low: u8, high: u8) {
+        let low = low.min(osc_constraints::MAX_VELOCITY);
+        let high = high.min(osc_constraints::MAX_VELOCITY);
+        self.velocity_low = low.min(high);
+        self.velocity_high = low.max(high);
+    }
+
+    pub fn plays_velocity(&self, velocity: u8) -> bool {
+        (self.velocity_low..=self.velocity_high).contains(&velocity)
+    }
+
+    pub fn set_pan(&mut self, pan: f32) {
+        self.panning = pan
+            .max(osc_constraints::MIN_PAN)
+            .min(osc_constraints::MAX_PAN);
+    }
+
+    pub fn set_low_cut(&mut self, freq: f32) {
+        self.low_cut = freq
+            .max(osc_constraints::MIN_LOW_CUT)
+            .min(osc_constraints::MAX_LOW_CUT);
+        self.hp_coeff = (-TWO_PI * self.low_cut / self.sample_rate).exp();
+    }
+
+    pub fn set_start(&mut self, start: Start) {
+        self.phase_start = start;
+    }
+
+    // Degrees, sounding voices keep their phase
+    pub fn set_phase_offset(&mut self, degrees: f32) {
+        let degrees = degrees
+            .max(osc_constraints::MIN_PHASE_OFFSET)
+            .min(osc_constraints::MAX_PHASE_OFFSET);
+        self.phase_offset = degrees / 360.0;
     }
 
     // Semitones
     pub fn transpose(&mut self, semitones: i8) {
         let transpose = 2f32.powf(semitones as f32 / 12.0);
+        let unison_num = self.unison_num;
         for Voice { unisons, .. } in self.voices.iter_mut() {
-            for UnisonVoice { phase_incr, .. } in unisons.iter_mut() {
+            for UnisonVoice { phase_incr, .. } in unisons[..unison_num].iter_mut() {
                 *phase_incr = *phase_incr / self.transpose * transpose;
             }
         }
@@ -203,22 +975,74 @@ impl Oscillator {
     }
 
     fn update_unison(&mut self) {
-        self.set_unison_num(self.unisons.len());
+        self.set_unison_num(self.unison_setting);
+    }
+
+    pub fn set_supersaw(&mut self, enabled: bool) {
+        self.supersaw = enabled;
+        self.update_unison();
+    }
+
+    pub fn set_supersaw_detune(&mut self, detune: f32) {
+        self.supersaw_detune = detune
+            .max(osc_constraints::MIN_SUPERSAW_DETUNE)
+            .min(osc_constraints::MAX_SUPERSAW_DETUNE);
+        self.update_unison();
+    }
+
+    pub fn set_supersaw_mix(&mut self, mix: f32) {
+        self.supersaw_mix = mix
+            .max(osc_constraints::MIN_SUPERSAW_MIX)
+            .min(osc_constraints::MAX_SUPERSAW_MIX);
+        self.update_unison();
+    }
+
+    pub fn set_unison_width(&mut self, width: f32) {
+        self.unison_width = width
+            .max(osc_constraints::MIN_UNISON_WIDTH)
+            .min(osc_constraints::MAX_UNISON_WIDTH);
+    }
+
+    pub fn set_width_time(&mut self, ms: f32) {
+        self.width_time = ms
+            .max(osc_constraints::MIN_WIDTH_TIME)
+            .min(osc_constraints::MAX_WIDTH_TIME);
+        self.width_samples = self.width_time / 1000.0 * self.sample_rate;
     }
 
     pub fn set_unison_num(&mut self, num: usize) {
-        self.unisons.clear();
-        if num <= 1 {
-            self.unisons.push(Unison {
+        let num = num.max(1).min(MAX_UNISONS);
+        self.unison_setting = num;
+        let num = if self.supersaw { SUPERSAW_VOICES } else { num };
+        self.unisons = [NO_UNISON; MAX_UNISONS];
+        if self.supersaw {
+            let detune = supersaw_detune_curve(self.supersaw_detune);
+            // Center fades a bit and sides come up as mix goes up
+            let mix = self.supersaw_mix;
+            let center_volume = 1.0 - 0.55 * mix;
+            let side_volume = 0.05 + mix * (1.28 - 0.74 * mix);
+            for (i, (uni, offset)) in self.unisons.iter_mut().zip(SUPERSAW_OFFSETS.iter()).enumerate() {
+                *uni = Unison {
+                    freq_mod: self.tune * (1.0 + offset * detune),
+                    volume: if i == 0 { center_volume } else { side_volume },
+                    pan: offset / SUPERSAW_WIDEST,
+                };
+            }
+        } else if num == 1 {
+            self.unisons[0] = Unison {
                 freq_mod: self.tune,
                 volume: 1.0,
-            });
+                pan: 0.0,
+            };
         } else {
+            let mut idx = 0;
             if num % 2 == 1 {
-                self.unisons.push(Unison {
+                self.unisons[idx] = Unison {
                     freq_mod: 1.0,
                     volume: 1.0,
-                })
+                    pan: 0.0,
+                };
+                idx += 1;
             }
             let pairs_num = (num - num % 2) / 2;
             // Do better
@@ -228,41 +1052,131 @@ impl Oscillator {
                 let fraction: f32 = (pairs_num - i) as f32 / pairs_num as f32;
                 let volume = volume_step * (pairs_num - i) as f32;
                 let freq_mod = self.tune.powf(fraction);
-                self.unisons.push(Unison {
+                // The most detuned pair is panned the widest, sharp one to the right
+                self.unisons[idx] = Unison {
                     freq_mod: freq_mod,
                     volume: volume,
-                });
+                    pan: fraction,
+                };
                 // Detune in other direction
-                self.unisons.push(Unison {
+                self.unisons[idx + 1] = Unison {
                     freq_mod: 1.0 / freq_mod,
                     volume: volume,
-                });
+                    pan: -fraction,
+                };
+                idx += 2;
             }
         }
-        // Update for existing voices
-        let period = self.wave.period();
+        let old_num = self.unison_num;
+        self.unison_num = num;
+        self.unison_gain = 1.0 / self.unisons[..num].iter().map(|u| u.volume).sum::<f32>();
+        // Update for existing voices, unisons they already had keep their phases
         for Voice { note, unisons, .. } in self.voices.iter_mut() {
             let phase_incr = note.frequency * self.transpose / self.sample_rate;
-            let phases: Vec<f32> = unisons.iter().map(|u| u.phase).collect();
-            unisons.clear();
-            for i in 0..self.unisons.len() {
-                let phase: f32;
-                if i < phases.len() {
-                    phase = phases[i];
+            for (i, (uni_voice, uni)) in unisons.iter_mut().zip(self.unisons[..num].iter()).enumerate() {
+                let (phase, wave_state) = if i < old_num {
+                    (uni_voice.phase, uni_voice.wave_state)
                 } else {
-                    phase = period * rand::random::<f32>();
-                }
-                unisons.push(UnisonVoice {
+                    (rand::random::<f32>(), WaveState::new())
+                };
+                *uni_voice = UnisonVoice {
                     phase: phase,
-                    phase_incr: phase_incr * self.unisons[i].freq_mod,
-                    volume: self.unisons[i].volume,
-                })
+                    phase_incr: phase_incr * uni.freq_mod,
+                    wave_state,
+                    volume: uni.volume,
+                    pan: uni.pan,
+                };
+            }
+        }
+    }
+
+    // Renders a single note with envelope bypassed into buf.
+    // Unison phases are spread evenly so the result is repeatable
+    pub fn render_reference(&self, freq: f32, buf: &mut [f32]) {
+        let phase_incr = freq / self.sample_rate * self.transpose;
+        let uni_num = self.unison_num as f32;
+        if self.engine == OscEngine::Pluck {
+            let period = self.period(freq);
+            let mut string = PluckString::new(self.sample_rate);
+            string.pluck(period);
+            for sample in buf.iter_mut() {
+                *sample += string.next(period, self.pluck_damping) * self.volume;
+            }
+        }
+        let unisons = if self.engine == OscEngine::Wave { self.unison_num } else { 0 };
+        for (i, uni) in self.unisons[..unisons].iter().enumerate() {
+            let mut phase = i as f32 / uni_num;
+            for sample in buf.iter_mut() {
+                *sample += self.wave.wave_func(phase)
+                    * uni.volume
+                    * self.unison_gain
+                    * self.wave_gain
+                    * self.volume;
+                phase = next_phase(phase, phase_incr * uni.freq_mod);
+            }
+        }
+        if self.sub {
+            let mut phase = 0.0;
+            for sample in buf.iter_mut() {
+                *sample += self.sub_wave.wave_func(phase) * self.sub_gain * self.sub_level * self.volume;
+                phase = next_phase(phase, phase_incr / 2.0);
             }
         }
-        //
+    }
+
+    // Voice of the same note in the same state, a retriggered note
+    // shouldn't be modulated by the release tail of the previous one
+    fn voice_output(&self, note: &Note) -> f32 {
+        self.voices
+            .iter()
+            .find(|v| v.note == *note && v.note.released.is_some() == note.released.is_some())
+            .map_or(0.0, |v| v.output)
+    }
+
+    pub fn has_held_voices(&self) -> bool {
+        self.voices.iter().any(|v| v.note.released.is_none())
     }
 
     pub fn has_active_voices(&self) -> bool {
         !self.voices.is_empty()
     }
+
+    // Released voices count until they fade out
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+}
+
+// One string per voice slot for pluck engine, wave engine needs none
+pub fn pluck_strings(engine: OscEngine, sample_rate: f32) -> Vec<PluckString> {
+    match engine {
+        OscEngine::Wave => Vec::new(),
+        OscEngine::Pluck => (0..MAX_VOICES).map(|_| PluckString::new(sample_rate)).collect(),
+    }
+}
+
+// Oscillator index once the oscillator at removed is gone: later ones move down,
+// None for the removed one
+pub fn osc_idx_after_removal(osc_idx: usize, removed: usize) -> Option<usize> {
+    if osc_idx == removed {
+        None
+    } else if osc_idx > removed {
+        Some(osc_idx - 1)
+    } else {
+        Some(osc_idx)
+    }
+}
+
+// Highest note frequency at which every unison is still below Nyquist.
+// Worked out from settings, so UI doesn't have to ask the audio thread
+pub fn highest_frequency(sample_rate: f32, semitones: i8, cents: i8, unisons: usize) -> f32 {
+    let transpose = 2f32.powf(semitones as f32 / 12.0);
+    let tune = 2f32.powf(cents as f32 / (12.0 * 100.0));
+    // Unison pairs are detuned both ways, a single one is just tuned
+    let max_freq_mod = if unisons <= 1 {
+        tune.max(1.0)
+    } else {
+        tune.max(1.0 / tune)
+    };
+    sample_rate * MAX_PHASE_INCR / (transpose * max_freq_mod)
 }
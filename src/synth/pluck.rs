@@ -0,0 +1,84 @@
+// Energy kept by the string on every trip through the delay line
+const LOOP_GAIN: f32 = 0.998;
+// Delay lines are allocated for notes down to this frequency, lower ones
+// and bends below it stay at it
+const LOWEST_FREQUENCY: f32 = 20.0;
+
+#[allow(dead_code)]
+pub mod pluck_constraints {
+    // 0.0 is a bright metallic string, 1.0 is a dull thud
+    pub const MIN_DAMPING: f32 = 0.;
+    pub const MAX_DAMPING: f32 = 1.;
+    pub const DEFAULT_DAMPING: f32 = 0.5;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OscEngine {
+    // Waveforms with unisons
+    Wave,
+    // Karplus-Strong plucked string
+    Pluck,
+}
+
+// Delay line filled with noise on pluck. Every trip through the loop
+// low-passes it, so high harmonics die out first
+#[derive(Debug)]
+pub struct PluckString {
+    buf: Vec<f32>,
+    pos: usize,
+    last: f32,
+}
+
+impl PluckString {
+    // Long enough for the lowest note, built off the audio thread
+    pub fn new(sample_rate: f32) -> Self {
+        let len = (sample_rate / LOWEST_FREQUENCY).ceil() as usize + 2;
+        Self { buf: vec![0.0; len], pos: 0, last: 0.0 }
+    }
+
+    // Refills the string in place, period is the length of a single period in samples
+    pub fn pluck(&mut self, period: f32) {
+        let len = self.buf.len();
+        let excited = (period.ceil() as usize).min(len);
+        self.buf[..len - excited].iter_mut().for_each(|sample| *sample = 0.0);
+        for sample in self.buf[len - excited..].iter_mut() {
+            *sample = rand::random::<f32>() * 2.0 - 1.0;
+        }
+        // Noise burst without DC, otherwise it's left in the string
+        let mean = self.buf.iter().sum::<f32>() / excited.max(1) as f32;
+        for sample in self.buf[len - excited..].iter_mut() {
+            *sample -= mean;
+        }
+        self.pos = 0;
+        self.last = 0.0;
+    }
+
+    // Takes over the state of a string of the same length, a longer or shorter one is left as it is
+    pub fn copy_from(&mut self, other: &PluckString) -> bool {
+        if self.buf.len() != other.buf.len() {
+            return false
+        }
+        self.buf.copy_from_slice(&other.buf);
+        self.pos = other.pos;
+        self.last = other.last;
+        true
+    }
+
+    pub fn next(&mut self, period: f32, damping: f32) -> f32 {
+        let len = self.buf.len();
+        // Averaging filter delays by about its coefficient, delay is shortened to stay in tune
+        let coeff = 0.05 + 0.45 * damping;
+        let delay = (period - coeff).max(1.0).min((len - 2) as f32);
+        let read = self.pos as f32 + len as f32 - delay;
+        let idx = read.floor() as usize;
+        let frac = read - read.floor();
+        let a = self.buf[idx % len];
+        let b = self.buf[(idx + 1) % len];
+        let delayed = a + (b - a) * frac;
+        let out = (delayed * (1.0 - coeff) + self.last * coeff) * LOOP_GAIN;
+        self.last = delayed;
+        self.buf[self.pos] = out;
+        self.pos = (self.pos + 1) % len;
+        out
+    }
+}
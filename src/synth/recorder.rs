@@ -0,0 +1,162 @@
+use std::mem;
+use std::sync::mpsc;
+
+// Frames in one chunk, about a third of a second at 44.1 kHz
+const RECORDING_CHUNK_FRAMES: usize = 16384;
+// Chunks on their way to the writer and back. Writer can fall
+// this many chunks behind before frames are lost
+const RECORDING_SPARE_CHUNKS: usize = 16;
+
+pub enum RecorderMessage<SampleType> {
+    // Sent once before any chunk, the file is made for this rate
+    Started(f32),
+    Chunk(Vec<[SampleType; 2]>),
+    // Spare chunks go to the writer as well, so the audio thread doesn't free them
+    Finished {
+        last: Vec<[SampleType; 2]>,
+        spare: Vec<Vec<[SampleType; 2]>>,
+        // Frames lost while the writer was behind
+        dropped: usize,
+    },
+}
+
+// Audio thread side of recording. Every chunk is allocated upfront, full ones
+// go to the writer thread and come back cleared, so the callback never allocates
+pub struct Recorder<SampleType> {
+    chunk: Vec<[SampleType; 2]>,
+    spare: Vec<Vec<[SampleType; 2]>>,
+    writer: mpsc::Sender<RecorderMessage<SampleType>>,
+    dropped: usize,
+}
+
+impl<SampleType> Recorder<SampleType> {
+    pub fn new(writer: mpsc::Sender<RecorderMessage<SampleType>>) -> Self {
+        let spare = (0..RECORDING_SPARE_CHUNKS)
+            .map(|_| Vec::with_capacity(RECORDING_CHUNK_FRAMES))
+            .collect();
+        Self {
+            chunk: Vec::with_capacity(RECORDING_CHUNK_FRAMES),
+            spare,
+            writer,
+            dropped: 0,
+        }
+    }
+
+    // Writer is gone only when it failed, frames are lost then anyway
+    fn send(&self, message: RecorderMessage<SampleType>) {
+        let _ = self.writer.send(message);
+    }
+
+    pub fn start(&self, sample_rate: f32) {
+        self.send(RecorderMessage::Started(sample_rate));
+    }
+
+    pub fn push(&mut self, frame: [SampleType; 2]) {
+        if self.chunk.len() == self.chunk.capacity() {
+            match self.spare.pop() {
+                Some(next) => {
+                    let full = mem::replace(&mut self.chunk, next);
+                    // Failed writer hands the chunk back, it's kept so the audio thread doesn't free it
+                    if let Err(mpsc::SendError(RecorderMessage::Chunk(mut full))) = self.writer.send(RecorderMessage::Chunk(full)) {
+                        self.dropped += full.len();
+                        full.clear();
+                        self.spare.push(full);
+                    }
+                }
+                None => {
+                    self.dropped += 1;
+                    return
+                }
+            }
+        }
+        self.chunk.push(frame);
+    }
+
+    // Chunk back from the writer, handed back when there's no room for it
+    pub fn recycle(&mut self, mut chunk: Vec<[SampleType; 2]>) -> Option<Vec<[SampleType; 2]>> {
+        if self.spare.len() == RECORDING_SPARE_CHUNKS {
+            return Some(chunk)
+        }
+        chunk.clear();
+        self.spare.push(chunk);
+        None
+    }
+
+    pub fn finish(self) {
+        let Self { chunk, spare, writer, dropped } = self;
+        let _ = writer.send(RecorderMessage::Finished { last: chunk, spare, dropped });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(messages: &mpsc::Receiver<RecorderMessage<i16>>) -> (Vec<[i16; 2]>, usize) {
+        let mut frames = Vec::new();
+        for message in messages.try_iter() {
+            match message {
+                RecorderMessage::Started(_) => (),
+                RecorderMessage::Chunk(chunk) => frames.extend(chunk),
+                RecorderMessage::Finished { last, dropped, .. } => {
+                    frames.extend(last);
+                    return (frames, dropped)
+                }
+            }
+        }
+        panic!("recording isn't finished");
+    }
+
+    #[test]
+    fn recorder_keeps_frames_in_order() {
+        let (writer, messages) = mpsc::channel();
+        let mut recorder = Recorder::new(writer);
+        recorder.start(44100.0);
+        let total = RECORDING_CHUNK_FRAMES * 3 + 5;
+        for i in 0..total {
+            recorder.push([i as i16, -(i as i16)]);
+        }
+        recorder.finish();
+        let (frames, dropped) = frames(&messages);
+        assert_eq!(dropped, 0);
+        assert_eq!(frames.len(), total);
+        assert!(frames.iter().enumerate().all(|(i, frame)| frame[0] == i as i16));
+    }
+
+    #[test]
+    fn recorder_drops_frames_while_writer_is_behind() {
+        let (writer, messages) = mpsc::channel();
+        let mut recorder = Recorder::new(writer);
+        // Nothing comes back, every chunk but the current one is full
+        let total = RECORDING_CHUNK_FRAMES * (RECORDING_SPARE_CHUNKS + 1);
+        for _ in 0..total + 10 {
+            recorder.push([0, 0]);
+        }
+        recorder.finish();
+        let (frames, dropped) = frames(&messages);
+        assert_eq!(frames.len(), total);
+        assert_eq!(dropped, 10);
+    }
+
+    #[test]
+    fn recycled_chunks_come_back_cleared() {
+        let (writer, _messages) = mpsc::channel();
+        let mut recorder = Recorder::<i16>::new(writer);
+        assert!(recorder.recycle(vec![[1, 1]]).is_some());
+        recorder.spare.pop();
+        assert!(recorder.recycle(vec![[1, 1]]).is_none());
+        assert!(recorder.spare.iter().all(|chunk| chunk.is_empty()));
+    }
+
+    #[test]
+    fn recorder_keeps_chunks_when_writer_is_gone() {
+        let (writer, messages) = mpsc::channel();
+        let mut recorder = Recorder::<i16>::new(writer);
+        drop(messages);
+        for _ in 0..RECORDING_CHUNK_FRAMES * 3 {
+            recorder.push([0, 0]);
+        }
+        assert_eq!(recorder.spare.len(), RECORDING_SPARE_CHUNKS);
+        assert_eq!(recorder.dropped, RECORDING_CHUNK_FRAMES * 2);
+    }
+}
@@ -0,0 +1,94 @@
+use super::arpeggiator::Trigger;
+use super::step_mod::{StepDivision, STEPS};
+
+// C4, step pitches are semitones from it
+const ROOT_FREQ: f32 = 261.63;
+// Steps sound for this part of their length
+const GATE: f64 = 0.5;
+const VELOCITY: f32 = 0.6;
+const ACCENT_VELOCITY: f32 = 1.0;
+
+#[allow(dead_code)]
+pub mod seq_constraints {
+    // Semitones from the root
+    pub const MIN_PITCH: i32 = -12;
+    pub const MAX_PITCH: i32 = 12;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SeqStep {
+    pub on: bool,
+    pub pitch: i32,
+    pub accent: bool,
+}
+
+// Plays a pattern of notes in time with tempo, so a patch can be
+// listened to without holding keys
+pub struct Sequencer {
+    pub steps: [SeqStep; STEPS],
+    pub division: StepDivision,
+    running: bool,
+    // Steps since start, none until the first one is played
+    position: Option<f64>,
+    sounding: bool,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self {
+            steps: [SeqStep { on: true, pitch: 0, accent: false }; STEPS],
+            division: StepDivision::Sixteenth,
+            running: false,
+            position: None,
+            sounding: false,
+        }
+    }
+
+    // Pattern starts over from the first step
+    pub fn set_running(&mut self, running: bool) {
+        self.running = running;
+        self.position = None;
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn set_pitch(&mut self, step: usize, pitch: i32) {
+        self.steps[step % STEPS].pitch = pitch
+            .max(seq_constraints::MIN_PITCH)
+            .min(seq_constraints::MAX_PITCH);
+    }
+
+    // Called every sample, tempo is in BPM
+    pub fn tick(&mut self, tempo: f32, sample_rate: f32) -> Option<Trigger> {
+        if !self.running {
+            if self.sounding {
+                self.sounding = false;
+                return Some(Trigger::Off);
+            }
+            return None;
+        }
+        let advance = tempo as f64 / 60.0 * self.division.steps_per_beat() / sample_rate as f64;
+        let (position, new_step) = match self.position {
+            Some(position) => (position + advance, (position + advance).floor() > position.floor()),
+            None => (0.0, true),
+        };
+        self.position = Some(position);
+        if new_step {
+            let step = self.steps[position as usize % STEPS];
+            if step.on {
+                self.sounding = true;
+                return Some(Trigger::On {
+                    freq: ROOT_FREQ * 2f32.powf(step.pitch as f32 / 12.0),
+                    velocity: if step.accent { ACCENT_VELOCITY } else { VELOCITY },
+                });
+            }
+        }
+        if self.sounding && (new_step || position.fract() >= GATE) {
+            self.sounding = false;
+            return Some(Trigger::Off);
+        }
+        None
+    }
+}
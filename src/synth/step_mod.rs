@@ -0,0 +1,49 @@
+use super::clock::Clock;
+
+// Length of step pattern
+pub const STEPS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepDivision {
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl StepDivision {
+    pub(super) fn steps_per_beat(&self) -> f64 {
+        match self {
+            StepDivision::Quarter => 1.0,
+            StepDivision::Eighth => 2.0,
+            StepDivision::Sixteenth => 4.0,
+            StepDivision::ThirtySecond => 8.0,
+        }
+    }
+}
+
+// Rhythmic mod source: steps of levels played in time with the clock
+pub struct StepMod {
+    pub levels: [f32; STEPS],
+    pub division: StepDivision,
+}
+
+impl StepMod {
+    pub fn new() -> Self {
+        Self {
+            levels: [1.0; STEPS],
+            division: StepDivision::Sixteenth,
+        }
+    }
+
+    // 0.0-1.0
+    pub fn set_level(&mut self, step: usize, level: f32) {
+        self.levels[step % STEPS] = level.max(0.0).min(1.0);
+    }
+
+    // Level of the step under the clock, 0.0-1.0
+    pub fn value(&self, clock: &Clock) -> f32 {
+        let step = (clock.beats() * self.division.steps_per_beat()) as usize % STEPS;
+        self.levels[step]
+    }
+}
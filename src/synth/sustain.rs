@@ -0,0 +1,52 @@
+use super::NoteSource;
+
+// Keys released under the pedal beyond this stop right away, so holding doesn't allocate
+const MAX_DEFERRED: usize = 64;
+
+// Sits in front of note off: while the sustain pedal (CC64) is down,
+// released keys keep sounding until the pedal goes up
+pub struct Sustain {
+    down: bool,
+    deferred: Vec<NoteSource>,
+}
+
+impl Sustain {
+    pub fn new() -> Self {
+        Self {
+            down: false,
+            deferred: Vec::with_capacity(MAX_DEFERRED),
+        }
+    }
+
+    // True when the note off is held back until the pedal is released
+    pub fn defer(&mut self, source: NoteSource) -> bool {
+        if !self.down || self.deferred.len() >= MAX_DEFERRED {
+            return false;
+        }
+        if !self.deferred.contains(&source) {
+            self.deferred.push(source);
+        }
+        true
+    }
+
+    // Key struck again under the pedal is held by the key, not the pedal
+    pub fn forget(&mut self, source: NoteSource) {
+        self.deferred.retain(|deferred| *deferred != source);
+    }
+
+    pub fn set_down(&mut self, down: bool) {
+        self.down = down;
+    }
+
+    // Deferred note offs, one at a time once the pedal is up
+    pub fn next_released(&mut self) -> Option<NoteSource> {
+        if self.down {
+            return None;
+        }
+        self.deferred.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.deferred.clear();
+    }
+}
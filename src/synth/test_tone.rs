@@ -0,0 +1,127 @@
+const PI: f32 = std::f32::consts::PI;
+// Level changes, start and stop are ramped over this, so they don't click
+const TEST_TONE_RAMP_MS: f32 = 5.0;
+
+#[allow(dead_code)]
+pub mod test_tone_constraints {
+    // dBFS
+    pub const MIN_LEVEL: i32 = -60;
+    pub const MAX_LEVEL: i32 = 0;
+    // Usual line-up level of digital gear
+    pub const DEFAULT_LEVEL: i32 = -18;
+}
+
+// Steady sine for setting up levels of a mixer or an interface. It goes straight
+// to the output, past volume and clipping, so its level is exactly the set one
+pub struct TestTone {
+    sample_rate: f32,
+    pub enabled: bool,
+    freq: f32,
+    gain: f32,
+    target_gain: f32,
+    gain_step: f32,
+    phase: f32,
+}
+
+impl TestTone {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            enabled: false,
+            freq: 1000.0,
+            gain: 0.0,
+            target_gain: 10f32.powf(test_tone_constraints::DEFAULT_LEVEL as f32 / 20.0),
+            gain_step: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    // Starts from zero phase and silence, then ramps to the level
+    pub fn set(&mut self, freq: f32, level: i32) {
+        if !self.enabled {
+            self.phase = 0.0;
+            self.gain = 0.0;
+        }
+        self.enabled = true;
+        self.freq = freq.max(1.0).min(self.sample_rate / 2.0);
+        let level = level.max(test_tone_constraints::MIN_LEVEL).min(test_tone_constraints::MAX_LEVEL);
+        self.ramp_to(10f32.powf(level as f32 / 20.0));
+    }
+
+    // Fades out, it's disabled once silent
+    pub fn stop(&mut self) {
+        if self.enabled {
+            self.ramp_to(0.0);
+        }
+    }
+
+    fn ramp_to(&mut self, gain: f32) {
+        self.target_gain = gain;
+        self.gain_step = (gain - self.gain).abs() * 1000.0 / (TEST_TONE_RAMP_MS * self.sample_rate);
+    }
+
+    pub fn next(&mut self) -> f32 {
+        if !self.enabled {
+            return 0.0
+        }
+        self.gain = if self.gain < self.target_gain {
+            (self.gain + self.gain_step).min(self.target_gain)
+        } else {
+            (self.gain - self.gain_step).max(self.target_gain)
+        };
+        if self.gain == 0.0 && self.target_gain == 0.0 {
+            self.enabled = false;
+            return 0.0
+        }
+        let sample = (2.0 * PI * self.phase).sin() * self.gain;
+        self.phase = (self.phase + self.freq / self.sample_rate).fract();
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn max_jump(tone: &mut TestTone, frames: usize) -> f32 {
+        let mut last = tone.next();
+        let mut jump: f32 = 0.0;
+        for _ in 0..frames {
+            let sample = tone.next();
+            jump = jump.max((sample - last).abs());
+            last = sample;
+        }
+        jump
+    }
+
+    #[test]
+    fn test_tone_fades_out_before_it_is_disabled() {
+        let mut tone = TestTone::new(SAMPLE_RATE);
+        tone.set(1000.0, 0);
+        // Well past the ramp, a full scale sine
+        max_jump(&mut tone, 1000);
+        tone.stop();
+        assert!(tone.enabled);
+        // Sine at 1 kHz moves by 2 * PI * 1000 / 48000 at most per frame
+        let sine_step = 2.0 * PI * 1000.0 / SAMPLE_RATE;
+        assert!(max_jump(&mut tone, 1000) <= sine_step + 0.01);
+        assert!(!tone.enabled);
+        assert_eq!(tone.next(), 0.0);
+    }
+
+    #[test]
+    fn test_tone_level_changes_are_ramped() {
+        let mut tone = TestTone::new(SAMPLE_RATE);
+        tone.set(1000.0, test_tone_constraints::MIN_LEVEL);
+        max_jump(&mut tone, 1000);
+        tone.set(1000.0, test_tone_constraints::MAX_LEVEL);
+        let sine_step = 2.0 * PI * 1000.0 / SAMPLE_RATE;
+        assert!(max_jump(&mut tone, 1000) <= sine_step + 0.01);
+    }
+}
@@ -1,196 +1,553 @@
 const TWO_PI: f32 = std::f32::consts::PI * 2.0;
-const PI: f32 = std::f32::consts::PI;
+// Saw and triangle RMS. Every wave is scaled to it, so switching
+// waveform doesn't change loudness
+const NORMALIZED_RMS: f32 = 0.577_350_3; // 1 / sqrt(3)
+// Brings pink noise filter output mostly within [-1, 1], the rare peaks are clipped
+const PINK_NOISE_SCALE: f32 = 1.0 / 7.0;
+// Measured over a few million samples of scaled pink noise
+const PINK_NOISE_RMS: f32 = 0.245;
+// Pulse waves start with a quarter of period up
+pub const DEFAULT_PULSE_WIDTH: f32 = 0.25;
+// Partials of additive wave, the fundamental included
+pub const HARMONICS: usize = 16;
+// Samples per period of additive wavetable
+const TABLE_SIZE: usize = 2048;
 
 #[derive(Clone, PartialEq)]
 pub enum WaveForm {
     Sine,
     Square,
-    Pulse25,
+    Pulse,
     Saw,
     Triangle,
+    NoiseWhite,
+    NoisePink,
+    Additive,
 }
 
 impl WaveForm {
     pub fn get_wave(&self) -> Box<dyn Wave + Send> {
         match self {
-            WaveForm::Sine => Box::new(Sine::new()),
-            WaveForm::Square => Box::new(Square::new()),
-            WaveForm::Pulse25 => Box::new(Pulse25::new()),
-            WaveForm::Saw => Box::new(Saw::new()),
-            WaveForm::Triangle => Box::new(Triangle::new()),
+            WaveForm::Sine => Box::new(Sine),
+            WaveForm::Square => Box::new(Square),
+            WaveForm::Pulse => Box::new(Pulse::new(DEFAULT_PULSE_WIDTH)),
+            WaveForm::Saw => Box::new(Saw),
+            WaveForm::Triangle => Box::new(Triangle),
+            WaveForm::NoiseWhite => Box::new(NoiseWhite::new()),
+            WaveForm::NoisePink => Box::new(NoisePink::new()),
+            WaveForm::Additive => Box::new(Additive::new(&default_harmonics())),
+        }
+    }
+
+    // Additive wave is built from the levels, other waves ignore them.
+    // Building can take a while, so it's never done on the audio thread
+    pub fn build_wave(&self, harmonics: &[f32]) -> Box<dyn Wave + Send> {
+        match self {
+            WaveForm::Additive => Box::new(Additive::new(harmonics)),
+            _ => self.get_wave(),
+        }
+    }
+
+    // Noise has no period, pitch doesn't change it
+    pub fn is_noise(&self) -> bool {
+        match self {
+            WaveForm::NoiseWhite | WaveForm::NoisePink => true,
+            _ => false,
         }
     }
 }
 
+// Filter memory of waves that have one, pink noise. Every voice and unison
+// keeps its own next to its phase, so they don't feed each other's filters
+#[derive(Debug, Clone, Copy)]
+pub struct WaveState {
+    pink: [f32; 3],
+}
 
-pub trait Wave {
-    fn wave_func(&self, phase: f32) -> f32;
-    fn next_phase(&self, phase: f32, incr: f32) -> f32;
-    fn period(&self) -> f32;
+impl WaveState {
+    pub const fn new() -> Self {
+        Self { pink: [0.0; 3] }
+    }
 }
 
-pub struct Sine {
-    period: f32,
+// Phase of every wave is normalized to [0, 1) and incr is
+// a fraction of period passed since the previous sample.
+// Increment can be bigger than the whole period for high notes,
+// so phase is wrapped with modulo instead of a single subtraction
+pub fn next_phase(phase: f32, incr: f32) -> f32 {
+    let phase = phase + incr;
+    phase - phase.floor()
 }
 
-impl Sine {
-    pub fn new() -> Self {
-        Self { period: TWO_PI }
+// Correction for a unit step at phase 0 spread over one sample on each side.
+// Added to a naive wave it rounds off the jump, which cuts most of the aliasing
+fn poly_blep(phase: f32, incr: f32) -> f32 {
+    if phase < incr {
+        let t = phase / incr;
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - incr {
+        let t = (phase - 1.0) / incr;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+pub trait Wave {
+    fn wave_func(&self, phase: f32) -> f32;
+    // HQ mode variant, incr is the same as for next_phase.
+    // Waves without jumps alias little and keep the naive one
+    fn band_limited(&self, phase: f32, _incr: f32) -> f32 {
+        self.wave_func(phase)
+    }
+    // Playback goes through here, so noise can update its generator and the state of the voice
+    fn next_sample(&mut self, phase: f32, incr: f32, high_quality: bool, _state: &mut WaveState) -> f32 {
+        if high_quality {
+            self.band_limited(phase, incr)
+        } else {
+            self.wave_func(phase)
+        }
+    }
+    // Only pulse has a width, other waves ignore it
+    fn set_pulse_width(&mut self, _width: f32) {}
+    // Levels of partials in 0.0-1.0, only additive wave is built from them
+    fn set_harmonics(&mut self, _levels: &[f32]) {}
+    // RMS of wave_func over one period
+    fn rms(&self) -> f32;
+
+    fn gain(&self) -> f32 {
+        NORMALIZED_RMS / self.rms()
     }
 }
 
+pub struct Sine;
+
 impl Wave for Sine {
     fn wave_func(&self, phase: f32) -> f32 {
-        phase.sin()
+        (phase * TWO_PI).sin()
+    }
+
+    fn rms(&self) -> f32 {
+        std::f32::consts::FRAC_1_SQRT_2
     }
+}
 
-    fn next_phase(&self, mut phase: f32, incr: f32) -> f32 {
-        phase += incr * self.period;
-        if phase >= self.period {
-            phase -= self.period
+pub struct Square;
+
+impl Wave for Square {
+    fn wave_func(&self, phase: f32) -> f32 {
+        if phase < 0.5 {
+            1.0
+        } else {
+            -1.0
         }
-        phase
     }
 
-    fn period(&self) -> f32 {
-        self.period
+    fn band_limited(&self, phase: f32, incr: f32) -> f32 {
+        // Jumps up at the start and down in the middle of period
+        self.wave_func(phase) + poly_blep(phase, incr) - poly_blep(next_phase(phase, 0.5), incr)
     }
-}
 
-pub struct Square {
-    period: f32,
-    half_period: f32,
+    fn rms(&self) -> f32 {
+        1.0
+    }
 }
 
-impl Square {
-    pub fn new() -> Self {
-        Self {
-            period: TWO_PI,
-            half_period: PI,
+pub struct Saw;
+
+impl Wave for Saw {
+    // Rises from 0 to 1, jumps to -1 in the middle of period and rises back to 0
+    fn wave_func(&self, phase: f32) -> f32 {
+        if phase < 0.5 {
+            phase * 2.0
+        } else {
+            phase * 2.0 - 2.0
         }
     }
+
+    fn band_limited(&self, phase: f32, incr: f32) -> f32 {
+        self.wave_func(phase) - poly_blep(next_phase(phase, 0.5), incr)
+    }
+
+    fn rms(&self) -> f32 {
+        NORMALIZED_RMS
+    }
 }
 
-impl Wave for Square {
+pub struct Pulse {
+    // Fraction of period spent on the upper level
+    width: f32,
+    // Levels keep the wave free of DC offset with the longer side at -1 or 1
+    upper_level: f32,
+    lower_level: f32,
+}
+
+impl Pulse {
+    pub fn new(width: f32) -> Self {
+        let mut pulse = Self {
+            width: 0.0,
+            upper_level: 0.0,
+            lower_level: 0.0,
+        };
+        pulse.set_pulse_width(width);
+        pulse
+    }
+}
+
+impl Wave for Pulse {
     fn wave_func(&self, phase: f32) -> f32 {
-        if phase <= self.half_period {
-            0.7
+        if phase < self.width {
+            self.upper_level
         } else {
-            -0.7
+            self.lower_level
         }
     }
 
-    fn next_phase(&self, mut phase: f32, incr: f32) -> f32 {
-        phase += incr * self.period;
-        if phase >= self.period {
-            phase -= self.period;
+    fn band_limited(&self, phase: f32, incr: f32) -> f32 {
+        // poly_blep is made for a step of 2, pulse jumps are shorter
+        let step = (self.upper_level - self.lower_level) / 2.0;
+        self.wave_func(phase)
+            + step * (poly_blep(phase, incr) - poly_blep(next_phase(phase, 1.0 - self.width), incr))
+    }
+
+    fn set_pulse_width(&mut self, width: f32) {
+        if width == self.width {
+            return;
         }
-        phase
+        let peak = width.max(1.0 - width);
+        self.width = width;
+        self.upper_level = (1.0 - width) / peak;
+        self.lower_level = -width / peak;
     }
 
-    fn period(&self) -> f32 {
-        self.period
+    fn rms(&self) -> f32 {
+        (self.width * (1.0 - self.width)).sqrt() / self.width.max(1.0 - self.width)
     }
 }
 
-pub struct Saw {
-    period: f32,
-    half_period: f32,
+// Saw-like spectrum, every harmonic at 1 / n
+pub fn default_harmonics() -> [f32; HARMONICS] {
+    let mut levels = [0.0; HARMONICS];
+    for (n, level) in levels.iter_mut().enumerate() {
+        *level = 1.0 / (n + 1) as f32;
+    }
+    levels
 }
 
-impl Saw {
-    pub fn new() -> Self {
-        Self {
-            period: 2.0,
-            half_period: 1.0,
-        }
+// Sum of sines at harmonic levels, precomputed for a single period
+pub struct Additive {
+    // One extra sample at the end repeats the first for interpolation
+    table: Vec<f32>,
+    rms: f32,
+}
+
+impl Additive {
+    pub fn new(levels: &[f32]) -> Self {
+        let mut additive = Self {
+            table: vec![0.0; TABLE_SIZE + 1],
+            rms: 1.0,
+        };
+        additive.set_harmonics(levels);
+        additive
     }
 }
 
-impl Wave for Saw {
+impl Wave for Additive {
     fn wave_func(&self, phase: f32) -> f32 {
-        phase
+        let pos = phase * TABLE_SIZE as f32;
+        let idx = (pos as usize).min(TABLE_SIZE - 1);
+        let frac = pos - idx as f32;
+        self.table[idx] + (self.table[idx + 1] - self.table[idx]) * frac
     }
 
-    fn next_phase(&self, mut phase: f32, incr: f32) -> f32 {
-        phase += incr * self.period;
-        if phase >= self.half_period {
-            phase -= self.period;
+    // Table is rebuilt in place, so dragging a harmonic doesn't allocate
+    fn set_harmonics(&mut self, levels: &[f32]) {
+        for (i, sample) in self.table[..TABLE_SIZE].iter_mut().enumerate() {
+            let phase = i as f32 / TABLE_SIZE as f32 * TWO_PI;
+            *sample = levels
+                .iter()
+                .enumerate()
+                .map(|(n, level)| level * (phase * (n + 1) as f32).sin())
+                .sum();
         }
-        phase
+        // Peak is brought to 1.0 like the other waves have
+        let peak = self.table[..TABLE_SIZE].iter().fold(0f32, |peak, s| peak.max(s.abs()));
+        if peak > 0.0 {
+            self.table.iter_mut().for_each(|s| *s /= peak);
+            let squares: f32 = self.table[..TABLE_SIZE].iter().map(|s| s * s).sum();
+            self.rms = (squares / TABLE_SIZE as f32).sqrt();
+        } else {
+            // All harmonics are down, silence needs no gain
+            self.rms = 1.0;
+        }
+        self.table[TABLE_SIZE] = self.table[0];
     }
 
-    fn period(&self) -> f32 {
-        self.period
+    fn rms(&self) -> f32 {
+        self.rms
     }
 }
 
-pub struct Pulse25 {
-    period: f32,
-    upper_part: f32,
+pub struct Triangle;
+
+impl Wave for Triangle {
+    // Starts from 0 like the sine does: rises to 1, falls to -1 and rises back to 0
+    fn wave_func(&self, phase: f32) -> f32 {
+        if phase < 0.25 {
+            phase * 4.0
+        } else if phase < 0.75 {
+            2.0 - phase * 4.0
+        } else {
+            phase * 4.0 - 4.0
+        }
+    }
+
+    fn rms(&self) -> f32 {
+        NORMALIZED_RMS
+    }
 }
 
-impl Pulse25 {
+// Xorshift, cheap enough to run for every unison of every voice
+struct NoiseGen {
+    state: u32,
+}
+
+impl NoiseGen {
+    fn new() -> Self {
+        // Zero state would stay zero forever
+        Self { state: rand::random::<u32>() | 1 }
+    }
+
+    // Uniform in [-1, 1]
+    fn next(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+// Noise has no phase to be a function of. Without state the best it can do
+// is hash the phase, which is enough for offline measurements
+fn phase_noise(phase: f32) -> f32 {
+    let mut gen = NoiseGen { state: phase.to_bits() | 1 };
+    gen.next();
+    gen.next()
+}
+
+pub struct NoiseWhite {
+    gen: NoiseGen,
+}
+
+impl NoiseWhite {
     pub fn new() -> Self {
-        Self {
-            period: 2.0,
-            upper_part: 2.0 * 0.25,
-        }
+        Self { gen: NoiseGen::new() }
     }
 }
 
-impl Wave for Pulse25 {
+impl Wave for NoiseWhite {
     fn wave_func(&self, phase: f32) -> f32 {
-        if phase <= self.upper_part {
-            0.7
-        } else {
-            -0.7
-        }
+        phase_noise(phase)
     }
 
-    fn next_phase(&self, mut phase: f32, incr: f32) -> f32 {
-        phase += incr * self.period;
-        if phase >= self.period {
-            phase -= self.period;
-        }
-        phase
+    fn next_sample(&mut self, _phase: f32, _incr: f32, _high_quality: bool, _state: &mut WaveState) -> f32 {
+        self.gen.next()
     }
 
-    fn period(&self) -> f32 {
-        self.period
+    fn rms(&self) -> f32 {
+        // Uniform noise has the same RMS as saw
+        NORMALIZED_RMS
     }
 }
 
-pub struct Triangle {
-    period: f32,
-    amplitide: f32,
-    half_amplitude: f32,
+// White noise through Paul Kellet's economy filter, -3 dB per octave.
+// Generator is shared, the filter state comes with every sample
+pub struct NoisePink {
+    gen: NoiseGen,
 }
 
-impl Triangle {
+impl NoisePink {
     pub fn new() -> Self {
-        Self {
-            period: 4.0,
-            amplitide: 2.0,
-            half_amplitude: 1.0,
-        }
+        Self { gen: NoiseGen::new() }
     }
 }
 
-impl Wave for Triangle {
+impl Wave for NoisePink {
     fn wave_func(&self, phase: f32) -> f32 {
-        -(phase - self.amplitide).abs() + self.half_amplitude
+        // Spectrum is wrong, but loudness is what offline rendering needs
+        phase_noise(phase) * PINK_NOISE_RMS / NORMALIZED_RMS
+    }
+
+    fn next_sample(&mut self, _phase: f32, _incr: f32, _high_quality: bool, state: &mut WaveState) -> f32 {
+        let white = self.gen.next();
+        let b = &mut state.pink;
+        b[0] = 0.99765 * b[0] + white * 0.099_046;
+        b[1] = 0.96300 * b[1] + white * 0.296_516;
+        b[2] = 0.57000 * b[2] + white * 1.052_691;
+        let pink = b[0] + b[1] + b[2] + white * 0.1848;
+        (pink * PINK_NOISE_SCALE).max(-1.0).min(1.0)
+    }
+
+    fn rms(&self) -> f32 {
+        PINK_NOISE_RMS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 1000;
+    const WAVEFORMS: [WaveForm; 6] = [
+        WaveForm::Sine,
+        WaveForm::Square,
+        WaveForm::Pulse,
+        WaveForm::Saw,
+        WaveForm::Triangle,
+        WaveForm::Additive,
+    ];
+
+    fn render_period(wave: &dyn Wave) -> Vec<f32> {
+        let incr = 1.0 / SAMPLES as f32;
+        let mut phase = 0.0;
+        (0..SAMPLES)
+            .map(|_| {
+                let sample = wave.wave_func(phase);
+                phase = next_phase(phase, incr);
+                sample
+            })
+            .collect()
     }
 
-    fn next_phase(&self, mut phase: f32, incr: f32) -> f32 {
-        phase += incr * self.period;
-        if phase >= self.period {
-            phase -= self.period;
+    #[test]
+    fn waves_span_unit_range() {
+        for waveform in WAVEFORMS.iter() {
+            let samples = render_period(waveform.get_wave().as_ref());
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+            let peak = samples.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+            assert!(peak > 0.99, "peak is {}", peak);
         }
-        phase
     }
 
-    fn period(&self) -> f32 {
-        self.period
+    #[test]
+    fn waves_have_zero_mean() {
+        for waveform in WAVEFORMS.iter() {
+            let samples = render_period(waveform.get_wave().as_ref());
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            assert!(mean.abs() < 0.01, "mean is {}", mean);
+        }
+    }
+
+    #[test]
+    fn waves_are_periodic() {
+        for waveform in WAVEFORMS.iter() {
+            let wave = waveform.get_wave();
+            for i in 0..SAMPLES {
+                // Middle of each step, away from the edges
+                let phase = (i as f32 + 0.5) / SAMPLES as f32;
+                // Whole periods increment wraps back to the same point
+                for periods in 1..4 {
+                    let wrapped = next_phase(phase, periods as f32);
+                    assert!((wave.wave_func(phase) - wave.wave_func(wrapped)).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn phase_stays_in_period_for_large_increments() {
+        let mut phase = 0.0;
+        for incr in [0.3, 0.99, 1.5, 2.75, 7.01].iter() {
+            phase = next_phase(phase, *incr);
+            assert!((0.0..1.0).contains(&phase), "phase is {}", phase);
+        }
+    }
+
+    #[test]
+    fn waves_rms_is_declared_rms() {
+        for waveform in WAVEFORMS.iter() {
+            let wave = waveform.get_wave();
+            let samples = render_period(wave.as_ref());
+            let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+            assert!((rms - wave.rms()).abs() < 0.01, "rms is {}", rms);
+        }
+    }
+
+    #[test]
+    fn pulse_width_keeps_zero_mean_and_declared_rms() {
+        for width in [0.05, 0.25, 0.5, 0.8, 0.95].iter() {
+            let mut wave = Pulse::new(0.5);
+            wave.set_pulse_width(*width);
+            let samples = render_period(&wave);
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            assert!(mean.abs() < 0.01, "mean is {} for width {}", mean, width);
+            let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+            assert!((rms - wave.rms()).abs() < 0.01, "rms is {} for width {}", rms, width);
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range_with_declared_rms() {
+        for waveform in [WaveForm::NoiseWhite, WaveForm::NoisePink].iter() {
+            let mut wave = waveform.get_wave();
+            let mut state = WaveState::new();
+            // Pink noise filter needs time to settle
+            let samples: Vec<f32> = (0..100 * SAMPLES).map(|_| wave.next_sample(0.0, 0.0, false, &mut state)).collect();
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)));
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            assert!(mean.abs() < 0.05, "mean is {}", mean);
+            let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+            assert!((rms - wave.rms()).abs() < 0.03, "rms is {}", rms);
+        }
+    }
+
+    // Correlation of neighbouring samples, pink noise is about 0.8 and white noise 0
+    fn lag_one_correlation(samples: &[f32]) -> f32 {
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let covariance: f32 = samples.windows(2).map(|pair| (pair[0] - mean) * (pair[1] - mean)).sum();
+        let variance: f32 = samples.iter().map(|s| (s - mean) * (s - mean)).sum();
+        covariance / variance
+    }
+
+    #[test]
+    fn pink_noise_stays_pink_with_several_voices() {
+        const VOICES: usize = 4;
+        let mut wave = WaveForm::NoisePink.get_wave();
+        let mut states = [WaveState::new(); VOICES];
+        let mut voices = vec![Vec::new(); VOICES];
+        // Voices take samples in turns, the way an oscillator plays them
+        for _ in 0..100 * SAMPLES {
+            for (state, samples) in states.iter_mut().zip(voices.iter_mut()) {
+                samples.push(wave.next_sample(0.0, 0.0, false, state));
+            }
+        }
+        // A filter shared by the voices correlates each of them about 0.64
+        for samples in voices.iter() {
+            let correlation = lag_one_correlation(samples);
+            assert!(correlation > 0.75, "correlation is {}", correlation);
+        }
+    }
+
+    #[test]
+    fn band_limited_waves_smooth_only_the_jumps() {
+        // Around 2 kHz at 44.1 kHz
+        let incr = 0.05;
+        for waveform in WAVEFORMS.iter() {
+            let wave = waveform.get_wave();
+            for i in 0..SAMPLES {
+                let phase = (i as f32 + 0.5) / SAMPLES as f32;
+                let naive = wave.wave_func(phase);
+                let smooth = wave.band_limited(phase, incr);
+                assert!((-1.0..=1.0).contains(&smooth), "sample is {}", smooth);
+                // Further than one increment from any jump the wave is left as it is
+                let near_jump = [0.0, 0.25, 0.5, 1.0]
+                    .iter()
+                    .any(|edge| (phase - edge).abs() <= incr);
+                if !near_jump {
+                    assert!((naive - smooth).abs() < 1e-6);
+                }
+            }
+        }
     }
 }
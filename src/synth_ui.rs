@@ -1,35 +1,75 @@
 mod model;
 mod layout;
 mod widgets;
-mod constants;
+mod preset;
+mod recording;
+mod session;
+mod midi_learn;
+mod audit;
+mod keymap;
+mod onboarding;
+mod i18n;
+mod themes;
+mod toasts;
 
 pub use druid::Code as KeyCode;
 use druid::widget::prelude::*;
 use druid::widget::{Flex,CrossAxisAlignment};
 use druid::{WidgetExt};
 
-pub use model::{SynthUIData, SynthUIEvent, Delegate};
+pub use model::{SynthUIData, SynthUIEvent, Delegate, PANIC};
+pub use preset::send_preset;
+pub use midi_learn::MIDI_CC;
+pub use toasts::show_notifications;
+pub use i18n::{set_language, tr, trf};
+pub use themes::{configure_env, set_theme};
 use widgets::SynthUI;
-use layout::{BACKGROUND_COLOR, oscillator_layout, synth_volume_layout, env_layout};
+use themes::BACKGROUND_COLOR;
+use layout::{oscs_layout, synth_volume_layout, midi_layout, output_layout, test_tone_layout, language_layout, theme_layout, help_layout, tips_layout, toasts_layout, audit_layout, preset_layout, envs_layout, lfo_layout, mod_matrix_layout, steps_layout, seq_layout, arp_layout, filter_layout, piano_layout};
 
 
-pub fn build_ui() -> impl Widget<SynthUIData> {
-    let mut synth_ui = SynthUI::new();
+pub fn build_ui(midi_ports: Vec<String>) -> impl Widget<SynthUIData> {
+    let mut synth_ui = SynthUI::new(help_layout(), tips_layout(), toasts_layout(), audit_layout());
 
-    synth_ui.root.add_child(Flex::column()
+    let mut columns = Flex::row().cross_axis_alignment(CrossAxisAlignment::Start);
+    columns.add_child(Flex::column()
                         .cross_axis_alignment(CrossAxisAlignment::Center)
-                        .with_child(oscillator_layout("Osc1", SynthUIData::osc1))
+                        .with_child(oscs_layout())
                         .with_spacer(10.0)
-                        .with_child(oscillator_layout("Osc2", SynthUIData::osc2)));
+                        .with_child(filter_layout()));
 
     let control_layout = Flex::<SynthUIData>::column()
                     .cross_axis_alignment(CrossAxisAlignment::Center)
                     .with_child(synth_volume_layout())
                     .with_spacer(10.0)
-                    .with_child(env_layout("Env1", SynthUIData::env1))
+                    .with_child(midi_layout(midi_ports))
                     .with_spacer(10.0)
-                    .with_child(env_layout("Env2", SynthUIData::env2));
-    synth_ui.root.add_child(control_layout.padding((20.0, 0.0, 0.0, 0.0)));
+                    .with_child(output_layout())
+                    .with_spacer(10.0)
+                    .with_child(test_tone_layout())
+                    .with_spacer(10.0)
+                    .with_child(language_layout())
+                    .with_spacer(10.0)
+                    .with_child(theme_layout())
+                    .with_spacer(10.0)
+                    .with_child(preset_layout())
+                    .with_spacer(10.0)
+                    .with_child(envs_layout());
+    columns.add_child(control_layout.padding((20.0, 0.0, 0.0, 0.0)));
+
+    let lfo_layout = Flex::<SynthUIData>::column()
+                    .cross_axis_alignment(CrossAxisAlignment::Center)
+                    .with_child(lfo_layout("LFO1", SynthUIData::lfo1))
+                    .with_spacer(10.0)
+                    .with_child(lfo_layout("LFO2", SynthUIData::lfo2))
+                    .with_spacer(10.0)
+                    .with_child(mod_matrix_layout())
+                    .with_child(steps_layout())
+                    .with_child(seq_layout())
+                    .with_child(arp_layout());
+    columns.add_child(lfo_layout.padding((20.0, 0.0, 0.0, 0.0)));
+    synth_ui.root.add_child(columns);
+    synth_ui.root.add_child(piano_layout());
 
     synth_ui.center().background(BACKGROUND_COLOR)
 }
@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use serde_json::Value;
+
+use super::model::SynthUIData;
+use super::preset::Preset;
+
+// Oldest changes are dropped, the panel shows all that are kept
+pub const MAX_AUDIT_ENTRIES: usize = 40;
+
+// Where the latest change came from
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChangeSource {
+    Ui,
+    Midi,
+    Preset,
+}
+
+impl ChangeSource {
+    fn name(&self) -> &'static str {
+        match self {
+            ChangeSource::Ui => "UI",
+            ChangeSource::Midi => "MIDI",
+            ChangeSource::Preset => "Preset",
+        }
+    }
+}
+
+struct AuditEntry {
+    // Since beep-boop was started
+    seconds: f64,
+    source: ChangeSource,
+    // Path in preset JSON, e.g. "oscs[0].volume" or "mod_slots[2].depth"
+    parameter: String,
+    old: String,
+    new: String,
+}
+
+// Every change of the patch with its source, to find out what changed it during a performance.
+// Changes are worked out by comparing presets, so it's off unless turned on in settings
+pub struct AuditLog {
+    enabled: bool,
+    started: Instant,
+    source: ChangeSource,
+    entries: VecDeque<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            started: Instant::now(),
+            source: ChangeSource::Ui,
+            entries: VecDeque::with_capacity(MAX_AUDIT_ENTRIES),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Set before the data is changed, every change up to the next source is put on it
+    pub fn set_source(&mut self, source: ChangeSource) {
+        self.source = source;
+    }
+
+    pub fn record(&mut self, old: &SynthUIData, new: &SynthUIData) {
+        if !self.enabled {
+            return
+        }
+        let (old, new) = match (serde_json::to_value(Preset::from_data(old)), serde_json::to_value(Preset::from_data(new))) {
+            (Ok(old), Ok(new)) => (old, new),
+            _ => return,
+        };
+        let mut changes = Vec::new();
+        diff_values("", &old, &new, &mut changes);
+        let seconds = self.started.elapsed().as_secs_f64();
+        for (parameter, old, new) in changes {
+            if self.entries.len() == MAX_AUDIT_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(AuditEntry { seconds, source: self.source, parameter, old, new });
+        }
+    }
+
+    // Newest change first
+    pub fn lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .rev()
+            .map(|entry| {
+                let minutes = (entry.seconds / 60.0).floor();
+                format!(
+                    "{:02.0}:{:06.3}  {:<6} {}  {} \u{2192} {}",
+                    minutes, entry.seconds - minutes * 60.0, entry.source.name(), entry.parameter, entry.old, entry.new
+                )
+            })
+            .collect()
+    }
+}
+
+// Leaves of two JSON trees that differ, with their paths
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<(String, String, String)>) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            for (name, new_value) in new_fields {
+                let field_path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                match old_fields.get(name) {
+                    Some(old_value) => diff_values(&field_path, old_value, new_value, changes),
+                    None => changes.push((field_path, "-".into(), format_value(new_value))),
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) if old_items.len() == new_items.len() => {
+            for (idx, (old_value, new_value)) in old_items.iter().zip(new_items.iter()).enumerate() {
+                diff_values(&format!("{}[{}]", path, idx), old_value, new_value, changes);
+            }
+        }
+        (old, new) if old != new => changes.push((path.to_owned(), format_value(old), format_value(new))),
+        _ => {}
+    }
+}
+
+// Slider values are long floats, three decimals tell enough
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let text = format!("{:.3}", number.as_f64().unwrap_or_default());
+            text.trim_end_matches('0').trim_end_matches('.').to_owned()
+        }
+        Value::String(text) => text.clone(),
+        Value::Array(items) => format!("[{} items]", items.len()),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// UI strings are looked up by their English text, so English needs no table
+// and anything missing from a translation falls back to English
+pub struct Language {
+    pub code: &'static str,
+    pub name: &'static str,
+    translations: &'static [(&'static str, &'static str)],
+}
+
+pub const LANGUAGES: [Language; 2] = [
+    Language {
+        code: "en",
+        name: "English",
+        translations: &[],
+    },
+    Language {
+        code: "ru",
+        name: "Русский",
+        translations: RUSSIAN,
+    },
+];
+
+// Index in LANGUAGES. Layout is built once, so it's set only on startup
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_language(code: &str) {
+    match LANGUAGES.iter().position(|lang| lang.code == code) {
+        Some(idx) => CURRENT.store(idx, Ordering::Relaxed),
+        None => eprintln!("Unknown language {}, using English", code),
+    }
+}
+
+pub fn current_language() -> usize {
+    CURRENT.load(Ordering::Relaxed)
+}
+
+pub fn tr(key: &'static str) -> &'static str {
+    LANGUAGES[current_language()]
+        .translations
+        .iter()
+        .find(|(english, _)| *english == key)
+        .map_or(key, |(_, translated)| translated)
+}
+
+// Translates a template and fills its {} placeholders in order
+pub fn trf(key: &'static str, args: &[&dyn Display]) -> String {
+    let mut parts = tr(key).split("{}");
+    let mut args = args.iter();
+    let mut result = parts.next().unwrap_or_default().to_owned();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+const RUSSIAN: &[(&str, &str)] = &[
+    // Panels
+    ("Filter", "Фильтр"),
+    ("Mod matrix", "Матрица модуляции"),
+    ("Arpeggiator", "Арпеджиатор"),
+    ("Sequencer", "Секвенсор"),
+    ("Keyboard", "Клавиатура"),
+    ("Shortcuts", "Горячие клавиши"),
+    // Controls
+    ("Volume", "Громкость"),
+    ("Envelope", "Огибающая"),
+    ("Waveform", "Форма волны"),
+    ("Transpose", "Транспозиция"),
+    ("Tune", "Подстройка"),
+    ("Unisons", "Унисоны"),
+    ("Pan", "Панорама"),
+    ("PW", "Ширина"),
+    ("Delay", "Задержка"),
+    ("Attack", "Атака"),
+    ("Hold", "Удержание"),
+    ("Loop", "Цикл"),
+    ("{} times", "{} раз"),
+    ("Add envelope", "Добавить огибающую"),
+    ("Add oscillator", "Добавить осциллятор"),
+    ("Preview", "Прослушать"),
+    ("Theme", "Тема"),
+    ("Default", "Обычная"),
+    ("High contrast", "Высокий контраст"),
+    ("Decay", "Спад"),
+    ("Sustain", "Сустейн"),
+    ("Release", "Затухание"),
+    ("Rate", "Частота"),
+    ("Depth", "Глубина"),
+    ("Target", "Цель"),
+    ("Mode", "Режим"),
+    ("Cutoff", "Срез"),
+    ("Resonance", "Резонанс"),
+    ("Env amount", "Огибающая"),
+    ("Clip", "Клип"),
+    ("Auto gain", "Автоусиление"),
+    ("Low cut", "Срез низов"),
+    ("FM amount", "Глубина FM"),
+    ("MIDI in", "MIDI вход"),
+    ("Output", "Выход"),
+    ("Test tone", "Тестовый тон"),
+    ("Preset", "Пресет"),
+    ("Language", "Язык"),
+    ("Save", "Сохранить"),
+    ("Load", "Загрузить"),
+    ("Export", "Экспорт"),
+    ("Record", "Запись"),
+    ("Stop", "Стоп"),
+    ("Play", "Играть"),
+    ("Accent", "Акцент"),
+    ("Next", "Далее"),
+    ("Done", "Готово"),
+    ("Skip tips", "Пропустить"),
+    ("None", "Нет"),
+    ("On", "Вкл"),
+    ("Sync", "Синхр."),
+    ("Octaves", "Октавы"),
+    ("Octave", "Октава"),
+    ("Gate", "Длительность"),
+    // Values
+    ("{} semitones", "{} полутонов"),
+    ("{} cents", "{} центов"),
+    ("{} ms", "{} мс"),
+    ("{} s", "{} с"),
+    ("{} Hz", "{} Гц"),
+    ("{} kHz", "{} кГц"),
+    ("{} dB", "{} дБ"),
+    ("Notes above {} Hz are muted to avoid aliasing", "Ноты выше {} Гц заглушены, чтобы не было алиасинга"),
+    ("\u{25cf} Recording", "\u{25cf} Запись"),
+    ("Restart to apply", "Применится после перезапуска"),
+    ("{}  Octave down / up, now C{}", "{}  Октава вниз / вверх, сейчас C{}"),
+    ("Tip {} of {}", "Подсказка {} из {}"),
+    // Notifications
+    ("Preset saved", "Пресет сохранён"),
+    ("Patch copied", "Патч скопирован"),
+    ("Patch pasted", "Патч вставлен"),
+    ("Recording saved to {}", "Запись сохранена в {}"),
+    ("{} frames were lost, disk was too slow", "Потеряно кадров: {}, диск не успевал"),
+    ("Session exported to {}", "Сессия экспортирована в {}"),
+    ("Move a MIDI control to bind {}", "Подвигайте MIDI-контроллер, чтобы привязать {}"),
+    ("{} bound to CC {}", "{} привязан к CC {}"),
+    ("{} unbound", "{} отвязан"),
+    ("Show or hide change log", "Показать или скрыть журнал изменений"),
+    ("Change log", "Журнал изменений"),
+    ("Voices", "Голоса"),
+    ("Released first", "Сначала отпущенные"),
+    ("Oldest", "Самый старый"),
+    ("Quietest", "Самый тихий"),
+    ("Safe mode: settings aren't loaded or saved", "Безопасный режим: настройки не загружаются и не сохраняются"),
+    ("No changes yet", "Изменений пока нет"),
+    ("Set audit_log to true in beep-boop-settings.json to record changes", "Чтобы записывать изменения, установите audit_log в true в beep-boop-settings.json"),
+    ("MIDI input {} connected", "MIDI вход {} подключён"),
+    ("Released {} stuck notes", "Отпущено зависших нот: {}"),
+    ("Audio device lost, switched to default output", "Аудиоустройство потеряно, используется устройство по умолчанию"),
+    // Choice tables
+    ("Saw", "Пила"),
+    ("Sine", "Синус"),
+    ("Square", "Меандр"),
+    ("Pulse", "Импульс"),
+    ("Triangle", "Треугольник"),
+    ("White noise", "Белый шум"),
+    ("Pink noise", "Розовый шум"),
+    ("Additive", "Аддитивная"),
+    ("Wave", "Волна"),
+    ("Pluck", "Струна"),
+    ("Damping", "Демпфирование"),
+    ("Low-pass", "ФНЧ"),
+    ("High-pass", "ФВЧ"),
+    ("Band-pass", "Полосовой"),
+    ("Notch", "Режекторный"),
+    ("Velocity", "Сила нажатия"),
+    ("Keys", "Клавиши"),
+    ("Bend", "Бенд"),
+    ("Mod wheel", "Колесо модуляции"),
+    ("Aftertouch", "Послекасание"),
+    ("Chaos", "Хаос"),
+    ("Chaos rate", "Скорость хаоса"),
+    ("Steps", "Шаги"),
+    ("Random", "Случайный"),
+    ("Up", "Вверх"),
+    ("Down", "Вниз"),
+    ("Up-down", "Вверх-вниз"),
+    ("Tempo", "Темп"),
+    ("Glide", "Глайд"),
+    ("Legato only", "Только легато"),
+    ("Drone", "Бурдон"),
+    ("Pitch bend", "Питч-бенд"),
+    ("Sub", "Суб"),
+    ("Phase", "Фаза"),
+    ("Soft", "Мягкий"),
+    ("Hard", "Жёсткий"),
+    ("Tone", "Тон"),
+    ("Dark", "Темнее"),
+    ("Bright", "Ярче"),
+    ("Supersaw", "Суперпила"),
+    ("Detune", "Расстройка"),
+    ("Mix", "Смесь"),
+    ("Mute", "Заглушить"),
+    ("Panic: all sound off, output muted", "Паника: звук остановлен, выход заглушён"),
+    ("Width", "Ширина"),
+    ("Bloom", "Раскрытие"),
+    ("Linear", "Линейный"),
+    ("Exponential", "Экспоненциальный"),
+    ("Logarithmic", "Логарифмический"),
+    ("Expression", "Экспрессия"),
+    ("Osc1 pitch", "Osc1 высота"),
+    ("Osc2 pitch", "Osc2 высота"),
+    ("Osc1 volume", "Osc1 громкость"),
+    ("Osc2 volume", "Osc2 громкость"),
+    ("Osc1 pan", "Osc1 панорама"),
+    ("Osc2 pan", "Osc2 панорама"),
+    ("Osc1 PW", "Osc1 ширина"),
+    ("Osc2 PW", "Osc2 ширина"),
+    ("Osc3 pitch", "Osc3 высота"),
+    ("Osc3 volume", "Osc3 громкость"),
+    ("Osc3 pan", "Osc3 панорама"),
+    ("Osc3 PW", "Osc3 ширина"),
+    ("Osc4 pitch", "Osc4 высота"),
+    ("Osc4 volume", "Osc4 громкость"),
+    ("Osc4 pan", "Osc4 панорама"),
+    ("Osc4 PW", "Osc4 ширина"),
+    ("Filter cutoff", "Срез фильтра"),
+    ("LFO1 rate", "LFO1 частота"),
+    ("LFO2 rate", "LFO2 частота"),
+    ("LFO1 depth", "LFO1 глубина"),
+    ("LFO2 depth", "LFO2 глубина"),
+    // Shortcuts
+    ("Save preset", "Сохранить пресет"),
+    ("Load preset", "Загрузить пресет"),
+    ("Copy patch to clipboard", "Копировать патч в буфер обмена"),
+    ("Paste patch from clipboard", "Вставить патч из буфера обмена"),
+    ("Start or stop recording", "Начать или остановить запись"),
+    ("Show or hide this help", "Показать или скрыть справку"),
+    ("Close help or quit", "Закрыть справку или выйти"),
+    // Tips
+    (
+        "Play with the bottom letter row: Z is C, S is C#, X is D and so on up to M. Q row plays the octave above. Press F1 any time to see the whole layout.",
+        "Играйте нижним рядом букв: Z это C, S это C#, X это D и так далее до M. Ряд Q играет октавой выше. F1 в любой момент покажет всю раскладку.",
+    ),
+    (
+        "Left and right arrow keys move the keyboard an octave down or up.",
+        "Стрелки влево и вправо сдвигают клавиатуру на октаву вниз или вверх.",
+    ),
+    (
+        "Ctrl+click on a slider resets it to default. Value labels next to sliders can be dragged for finer steps.",
+        "Ctrl+клик по слайдеру возвращает значение по умолчанию. Значения рядом со слайдерами можно перетаскивать для точной настройки.",
+    ),
+    (
+        "This demo patch is just a starting point. Tweak it and save your own sounds with Ctrl+S.",
+        "Этот демо-патч только отправная точка. Меняйте его и сохраняйте свои звуки через Ctrl+S.",
+    ),
+];
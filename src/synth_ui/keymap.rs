@@ -0,0 +1,105 @@
+use druid::Code as KeyCode;
+
+// Computer keyboard layout. Help overlay is built from these tables,
+// so changing a key here changes it everywhere
+
+pub struct NoteKey {
+    pub key: KeyCode,
+    pub label: &'static str,
+    pub note: &'static str,
+    // Frequency in the lowest octave
+    pub freq: f32,
+}
+
+impl NoteKey {
+    pub fn sharp(&self) -> bool {
+        self.note.ends_with('#')
+    }
+}
+
+// Z row plays an octave from C, Q row the octave above it and a bit more.
+// Keys go up by a semitone, so an index is also a semitone from the lowest C
+pub const NOTE_KEYS: [NoteKey; 29] = [
+    NoteKey { key: KeyCode::KeyZ, label: "Z", note: "C", freq: 130.81 },
+    NoteKey { key: KeyCode::KeyS, label: "S", note: "C#", freq: 138.59 },
+    NoteKey { key: KeyCode::KeyX, label: "X", note: "D", freq: 146.83 },
+    NoteKey { key: KeyCode::KeyD, label: "D", note: "D#", freq: 155.56 },
+    NoteKey { key: KeyCode::KeyC, label: "C", note: "E", freq: 164.81 },
+    NoteKey { key: KeyCode::KeyV, label: "V", note: "F", freq: 174.61 },
+    NoteKey { key: KeyCode::KeyG, label: "G", note: "F#", freq: 185.00 },
+    NoteKey { key: KeyCode::KeyB, label: "B", note: "G", freq: 196.00 },
+    NoteKey { key: KeyCode::KeyH, label: "H", note: "G#", freq: 207.65 },
+    NoteKey { key: KeyCode::KeyN, label: "N", note: "A", freq: 220.00 },
+    NoteKey { key: KeyCode::KeyJ, label: "J", note: "A#", freq: 233.08 },
+    NoteKey { key: KeyCode::KeyM, label: "M", note: "B", freq: 246.94 },
+    NoteKey { key: KeyCode::KeyQ, label: "Q", note: "C", freq: 261.63 },
+    NoteKey { key: KeyCode::Digit2, label: "2", note: "C#", freq: 277.18 },
+    NoteKey { key: KeyCode::KeyW, label: "W", note: "D", freq: 293.66 },
+    NoteKey { key: KeyCode::Digit3, label: "3", note: "D#", freq: 311.13 },
+    NoteKey { key: KeyCode::KeyE, label: "E", note: "E", freq: 329.63 },
+    NoteKey { key: KeyCode::KeyR, label: "R", note: "F", freq: 349.23 },
+    NoteKey { key: KeyCode::Digit5, label: "5", note: "F#", freq: 369.99 },
+    NoteKey { key: KeyCode::KeyT, label: "T", note: "G", freq: 392.00 },
+    NoteKey { key: KeyCode::Digit6, label: "6", note: "G#", freq: 415.30 },
+    NoteKey { key: KeyCode::KeyY, label: "Y", note: "A", freq: 440.00 },
+    NoteKey { key: KeyCode::Digit7, label: "7", note: "A#", freq: 466.16 },
+    NoteKey { key: KeyCode::KeyU, label: "U", note: "B", freq: 493.88 },
+    NoteKey { key: KeyCode::KeyI, label: "I", note: "C", freq: 523.25 },
+    NoteKey { key: KeyCode::Digit9, label: "9", note: "C#", freq: 554.37 },
+    NoteKey { key: KeyCode::KeyO, label: "O", note: "D", freq: 587.33 },
+    NoteKey { key: KeyCode::Digit0, label: "0", note: "D#", freq: 622.25 },
+    NoteKey { key: KeyCode::KeyP, label: "P", note: "E", freq: 659.26 },
+];
+// Index of the first Q row key
+pub const UPPER_ROW_START: usize = 12;
+
+pub const OCTAVE_DOWN_KEY: KeyCode = KeyCode::ArrowLeft;
+pub const OCTAVE_UP_KEY: KeyCode = KeyCode::ArrowRight;
+pub const OCTAVE_KEYS_LABEL: &str = "\u{2190} / \u{2192}";
+// Octave of the lowest C of Z row with octave modifier 1.0
+pub const BASE_OCTAVE: i32 = 3;
+// From C0 at 16 Hz up to C7, where the top of Q row is still below 11 kHz
+pub const MIN_OCTAVE: i32 = 0;
+pub const MAX_OCTAVE: i32 = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    SavePreset,
+    LoadPreset,
+    CopyPatch,
+    PastePatch,
+    Record,
+    Help,
+    ChangeLog,
+    Close,
+}
+
+pub struct Shortcut {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub label: &'static str,
+    pub action: Action,
+    pub description: &'static str,
+}
+
+pub const SHORTCUTS: [Shortcut; 8] = [
+    Shortcut { key: KeyCode::KeyS, ctrl: true, label: "Ctrl+S", action: Action::SavePreset, description: "Save preset" },
+    Shortcut { key: KeyCode::KeyO, ctrl: true, label: "Ctrl+O", action: Action::LoadPreset, description: "Load preset" },
+    Shortcut { key: KeyCode::KeyC, ctrl: true, label: "Ctrl+C", action: Action::CopyPatch, description: "Copy patch to clipboard" },
+    Shortcut { key: KeyCode::KeyV, ctrl: true, label: "Ctrl+V", action: Action::PastePatch, description: "Paste patch from clipboard" },
+    Shortcut { key: KeyCode::KeyR, ctrl: true, label: "Ctrl+R", action: Action::Record, description: "Start or stop recording" },
+    Shortcut { key: KeyCode::F1, ctrl: false, label: "F1", action: Action::Help, description: "Show or hide this help" },
+    Shortcut { key: KeyCode::F12, ctrl: false, label: "F12", action: Action::ChangeLog, description: "Show or hide change log" },
+    Shortcut { key: KeyCode::Escape, ctrl: false, label: "Esc", action: Action::Close, description: "Close help or quit" },
+];
+
+pub fn get_note(key: &KeyCode) -> Option<f32> {
+    NOTE_KEYS.iter().find(|note| note.key == *key).map(|note| note.freq)
+}
+
+pub fn get_action(key: &KeyCode, ctrl: bool) -> Option<Action> {
+    SHORTCUTS
+        .iter()
+        .find(|shortcut| shortcut.key == *key && shortcut.ctrl == ctrl)
+        .map(|shortcut| shortcut.action)
+}
@@ -1,102 +1,274 @@
-use druid::{Lens, LensExt, WidgetExt};
+use druid::{lens, theme, Key, Lens, LensExt, WidgetExt};
 use druid::widget::prelude::*;
-use druid::widget::{Flex, Stepper, Slider, Label, CrossAxisAlignment};
+use druid::widget::{Flex, Either, Stepper, Slider, Label, LineBreaking, Button, Checkbox, List, Painter, ViewSwitcher, Scroll, SizedBox, TextBox, CrossAxisAlignment};
 
-use super::model::{SynthUIData, OscSettings, EnvSettings};
-use super::constants::{WAVEFORMS, DefaultParameter};
-use super::widgets::DefaultSlider;
-use crate::synth::adsr_constraints;
-
-
-pub const LOG_SCALE_BASE: f64 = 2.;
+use super::model::{AUDITION_WAVEFORM, PREVIEW_ENV, SynthUIData, OscSettings, EnvSettings, FilterSettings, GlideSettings, VoiceSettings, ExpressionSettings, ArpSettings, SeqSettings, LfoSettings, ModSlotSettings};
+use crate::params::{
+    attack_ms, slider_log, LOG_SCALE_BASE, MAX_UNISONS, SAMPLE_RATES, TEST_TONE_FREQUENCIES,
+    WAVEFORMS, SUB_WAVEFORMS, OSC_ENGINES, PHASE_STARTS, ARP_MODES, STEP_DIVISIONS, FILTER_MODES, GLIDE_CURVES, VOICE_STEALING, EXPRESSION_DESTINATIONS, EXPRESSION_CURVES, LFO_TARGETS, MOD_SOURCES, MOD_DESTINATIONS, DefaultParameter,
+};
+use super::widgets::{ClipIndicator, DefaultSlider, DragValue, Dropdown, PresetBrowser, BarGraph, Piano, SoloOnHover};
+use super::preset::{save_preset_dialog, load_preset_dialog, step_preset};
+use super::recording::toggle_recording;
+use super::session::export_session_dialog;
+use super::midi_learn::MidiLearnExt;
+use super::keymap::{NoteKey, NOTE_KEYS, OCTAVE_KEYS_LABEL, SHORTCUTS, UPPER_ROW_START};
+use super::onboarding::{next_tip, TIPS};
+use super::i18n::{tr, trf, current_language, LANGUAGES};
+use super::toasts::{Toast, HIDE_TOAST};
+use super::themes::{
+    current_theme, high_contrast, THEMES, TEXT_LARGE, TEXT_MEDIUM, TEXT_SMALL,
+    LABEL_COLOR_MAIN, LABEL_COLOR_SECONDARY, LABEL_COLOR_WARNING, BORDER_COLOR, BACKGROUND_COLOR,
+};
+use crate::synth::{adsr_constraints, arp_constraints, bend_constraints, chaos_constraints, clock_constraints, filter_constraints, glide_constraints, lfo_constraints, mod_constraints, osc_constraints, pluck_constraints, test_tone_constraints, highest_frequency, OscEngine, WaveForm, MOD_SLOTS, STEPS};
 
 const BASIC_LABEL_WITDH: f64 = 80.0;
-const LABEL_COLOR_MAIN: druid::Color = druid::Color::rgba8(0xe9, 0x1e, 0x63, 0xff);
-const LABEL_COLOR_SECONDARY: druid::Color = druid::Color::rgba8(0x35, 0xaa, 0xee, 0xff);
-const BORDER_COLOR: druid::Color = druid::Color::rgba8(0x03, 0x12, 0x14, 0xff);
-pub const BACKGROUND_COLOR: druid::Color = druid::Color::rgba8(0x29, 0x29, 0x29, 0xff);
-const TEXT_LARGE: f64 = 22.0;
-const TEXT_MEDIUM: f64 = 18.0;
-const TEXT_SMALL: f64 = 14.0;
-const MAX_UNISONS: f64 = 7.0;
-const ENV_NUM: f64 = 2.0;
+// Colors oscillator panels can be marked with
+pub const ACCENT_COLORS: [druid::Color; 6] = [
+    druid::Color::rgba8(0xe9, 0x1e, 0x63, 0xff),
+    druid::Color::rgba8(0x35, 0xaa, 0xee, 0xff),
+    druid::Color::rgba8(0xff, 0xc1, 0x07, 0xff),
+    druid::Color::rgba8(0x4c, 0xaf, 0x50, 0xff),
+    druid::Color::rgba8(0xab, 0x47, 0xbc, 0xff),
+    druid::Color::rgba8(0x26, 0xa6, 0x9a, 0xff),
+];
+// Accent color of the oscillator widgets belong to
+const ACCENT_COLOR: Key<druid::Color> = Key::new("beep-boop.accent-color");
+// Step bars and sequencer toggles are lined up at this width
+const STEPS_WIDTH: f64 = 330.0;
+const A4_FREQUENCY: f64 = 440.0;
+// Cents the tune slider snaps to: a quarter-tone and a usual unison detune either way
+const TUNE_SNAPS: [f64; 5] = [-50.0, -7.0, 0.0, 7.0, 50.0];
+// MIDI note 127
+const HIGHEST_MIDI_FREQUENCY: f32 = 12543.85;
 const SLIDER_WIDTH_SMALL: f64 = 110.0;
 const SLIDER_WIDTH_MEDIUM: f64 = 170.0;
+const MIN_VOLUME_DB: f64 = -96.0;
+// Volume slider position is (1 - (dB / MIN_VOLUME_DB) ^ (1 / taper)),
+// so -36..0 dB takes about 3/4 of slider travel
+const VOLUME_TAPER: f64 = 3.0;
+const PRESET_BROWSER_HEIGHT: f64 = 100.0;
+const KEYCAP_SIZE: f64 = 44.0;
+const TIPS_WIDTH: f64 = 420.0;
+const TOAST_WIDTH: f64 = 480.0;
+const CHANGE_LOG_WIDTH: f64 = 420.0;
+const KEYCAP_SPACING: f64 = 6.0;
+
+
+fn slider_db(db: f64) -> f64 {
+    1.0 - (db / MIN_VOLUME_DB).max(0.0).powf(1.0 / VOLUME_TAPER)
+}
 
+fn db_from_slider(position: f64) -> f64 {
+    MIN_VOLUME_DB * (1.0 - position).powf(VOLUME_TAPER)
+}
+
+// Envelope times go up to tens of seconds, long ones read better in seconds
+fn env_time(ms: f32) -> String {
+    if ms >= 1000.0 {
+        trf("{} s", &[&format!("{:.1}", ms / 1000.0)])
+    } else {
+        trf("{} ms", &[&ms])
+    }
+}
+
+// High contrast theme has no accent colors, oscillators are told apart by their names
+fn accent_color(color_idx: f64) -> druid::Color {
+    if high_contrast() {
+        return druid::Color::WHITE
+    }
+    ACCENT_COLORS[color_idx.round() as usize % ACCENT_COLORS.len()].clone()
+}
 
-pub fn slider_log(x: f32) -> f64 {
-    f64::log2(x as f64)
+// Stepper lens over a table of targets that skips targets of oscillators the patch
+// doesn't have. Skipping goes on in the direction of the step, around the ends as well
+struct OscTargets<L> {
+    inner: L,
+    len: usize,
+    osc: fn(usize) -> Option<usize>,
+}
+
+impl<L: Lens<SynthUIData, f64>> Lens<SynthUIData, f64> for OscTargets<L> {
+    fn with<V, F: FnOnce(&f64) -> V>(&self, data: &SynthUIData, f: F) -> V {
+        self.inner.with(data, f)
+    }
+
+    fn with_mut<V, F: FnOnce(&mut f64) -> V>(&self, data: &mut SynthUIData, f: F) -> V {
+        let oscs = data.oscs.len();
+        let (len, osc) = (self.len, self.osc);
+        self.inner.with_mut(data, |idx| {
+            let old = idx.round() as usize;
+            let result = f(idx);
+            let mut new = (idx.round().max(0.0) as usize).min(len - 1);
+            let up = match (old, new) {
+                (old, 0) if old == len - 1 => true,
+                (0, new) if new == len - 1 => false,
+                (old, new) => new > old,
+            };
+            // First entry is None, so there is always something to stop at
+            while osc(new).map_or(false, |osc| osc >= oscs) {
+                new = if up { (new + 1) % len } else { (new + len - 1) % len };
+            }
+            *idx = new as f64;
+            result
+        })
+    }
+}
+
+// Table names start with default oscillator names in every language
+fn osc_target_name(data: &SynthUIData, name: &str, osc: Option<usize>) -> String {
+    match osc {
+        Some(osc) => tr(name).replacen(&format!("Osc{}", osc + 1), &data.osc_name(osc), 1),
+        None => tr(name).into(),
+    }
 }
 
 // unison(label + label + stepper);
-pub fn oscillator_layout<L>(title: &str, osc_lens: L) -> impl Widget<SynthUIData>
-where
-    L: Lens<SynthUIData, OscSettings>
-    + Clone
-    + 'static
-{
+fn oscillator_layout(osc_idx: usize) -> impl Widget<SynthUIData> {
+    let title = format!("Osc{}", osc_idx + 1);
+    let osc_lens = SynthUIData::oscs.then(lens::Identity.index(osc_idx).in_arc());
     let left_padding = (10.0, 0.0, 0.0, 0.0);
     let row_padding = (10.0, 0.0, 0.0, 10.0);
+    // Editable name and color swatch, click on swatch picks the next color
+    let name = TextBox::new()
+                .with_placeholder(title.clone())
+                .with_text_size(TEXT_MEDIUM)
+                .with_text_color(ACCENT_COLOR)
+                .lens(osc_lens.clone().then(OscSettings::name))
+                .fix_width(150.0);
+    let swatch = Painter::new(|ctx, color_idx: &f64, _| {
+                    let rect = ctx.size().to_rect();
+                    ctx.fill(rect, &accent_color(*color_idx));
+                })
+                .on_click(|_, color_idx: &mut f64, _| {
+                    *color_idx = (color_idx.round() + 1.0) % ACCENT_COLORS.len() as f64;
+                })
+                .fix_size(16.0, 16.0)
+                .lens(osc_lens.clone().then(OscSettings::color_idx));
+    let remove = Either::new(
+        |data: &SynthUIData, _| data.oscs.len() > osc_constraints::MIN_OSCILLATORS,
+        Button::new("\u{2212}").on_click(move |_ctx, data: &mut SynthUIData, _env| data.remove_osc(osc_idx)),
+        SizedBox::empty(),
+    );
     let mut osc_flex = Flex::column()
                 .cross_axis_alignment(CrossAxisAlignment::Start)
                 .with_child(
-        Label::new(title).with_text_size(TEXT_MEDIUM).with_text_color(LABEL_COLOR_SECONDARY).padding(10.0)
+        Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(swatch)
+            .with_spacer(5.0)
+            .with_child(name)
+            .with_spacer(5.0)
+            .with_child(remove)
+            .padding(10.0)
     );
     // Volume and envelope
-    osc_flex.add_child(Label::new("Volume").with_text_size(TEXT_SMALL).padding(left_padding));
+    osc_flex.add_child(Label::new(tr("Volume")).with_text_size(TEXT_SMALL).padding(left_padding));
     // Volume slider
-    let volume_slider = DefaultSlider::new(Slider::new()
-                    .with_range(0.0, 1.0), DefaultParameter::OscVolume)
+    let volume_slider = DefaultSlider::new(0.0, 1.0, DefaultParameter::OscVolume)
+                    .midi_learn(format!("{} volume", title))
                     .lens(osc_lens.clone().then(OscSettings::volume)).fix_width(SLIDER_WIDTH_SMALL);
     // Envelope
+    let env_select = Dropdown::dynamic(adsr_constraints::MAX_ENVELOPES, |data: &SynthUIData, idx| data.env_name(idx),
+                    osc_lens.clone().then(OscSettings::env_idx), TEXT_SMALL)
+                    .with_shown(|data: &SynthUIData| data.envs.len());
+    let volume_env_flex = Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Start)
+                    .with_child(volume_slider)
+                    .with_child(Label::new(tr("Envelope")).with_text_size(TEXT_SMALL))
+                    .with_spacer(5.0)
+                    .with_child(env_select);
+    osc_flex.add_child(volume_env_flex.padding((0.0, 0.0, 0.0, 10.0)));
+    // Envelope amount, at 0% the oscillator drones until release
     let lens_clone = osc_lens.clone();
-    let env_idx = Label::dynamic(
+    let env_amount_value = Label::dynamic(
         move |data: &SynthUIData, _| {
-            ((lens_clone.with(data, |osc| osc.env_idx) + 1.0).round()).to_string()
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.0}%", osc.env_amount * 100.0))
         }
     ).with_text_size(TEXT_SMALL);
-    let env_stepper = Stepper::new()
-                    .with_range(0.0, ENV_NUM - 1.0)
-                    .with_wraparound(true)
-                    .lens(osc_lens.clone().then(OscSettings::env_idx));
-    let volume_env_flex = Flex::row()
-                    .with_child(volume_slider)
-                    .with_child(Label::new("Envelope").with_text_size(TEXT_SMALL))
-                    .with_child(env_idx)
-                    .with_child(env_stepper);
-    osc_flex.add_child(volume_env_flex.padding((0.0, 0.0, 0.0, 10.0)));
+    let env_amount_slider = DefaultSlider::new(osc_constraints::MIN_ENV_AMOUNT as f64, osc_constraints::MAX_ENV_AMOUNT as f64,
+                        DefaultParameter::OscEnvAmount)
+                        .midi_learn(format!("{} env amount", title))
+                        .lens(osc_lens.clone().then(OscSettings::env_amount));
+    let env_amount_flex = Flex::row()
+                    .with_child(Label::new(tr("Env amount")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(env_amount_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+                    .with_child(DragValue::new(env_amount_value, osc_lens.clone().then(OscSettings::env_amount),
+                        osc_constraints::MIN_ENV_AMOUNT as f64, osc_constraints::MAX_ENV_AMOUNT as f64).fix_width(35.0));
+    osc_flex.add_child(env_amount_flex.padding(row_padding));
 
-    // Waveform
+    // Waveform, hovering an option auditions it
+    let wave_names = WAVEFORMS.iter().map(|w| tr(w.name).to_owned()).collect();
+    let wave_select = Dropdown::new(wave_names, TEXT_SMALL)
+        .with_hover_command(AUDITION_WAVEFORM)
+        .lens(osc_lens.clone().then(OscSettings::wave_idx));
+    // Engine, pluck plays a string instead of the waveform
+    let engine_names = OSC_ENGINES.iter().map(|e| tr(e.name).to_owned()).collect();
+    let engine_select = Dropdown::new(engine_names, TEXT_SMALL)
+        .lens(osc_lens.clone().then(OscSettings::engine_idx));
+    osc_flex.add_child(
+        Flex::row()
+            .with_child(engine_select)
+            .with_spacer(5.0)
+            .with_child(wave_select)
+            .padding(row_padding)
+    );
+
+    // String damping, only pluck engine has it
     let lens_clone = osc_lens.clone();
-    let wave_label = Label::dynamic(
+    let damping_value = Label::dynamic(
         move |data: &SynthUIData, _| {
-            let idx = lens_clone.with(data, |osc: &OscSettings| { osc.wave_idx });
-            WAVEFORMS[idx.round() as usize].name.into()
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.2}", osc.pluck_damping))
         }
+    ).with_text_size(TEXT_SMALL);
+    let damping_slider = DefaultSlider::new(pluck_constraints::MIN_DAMPING as f64, pluck_constraints::MAX_DAMPING as f64,
+                        DefaultParameter::PluckDamping)
+                        .midi_learn(format!("{} damping", title))
+                        .lens(osc_lens.clone().then(OscSettings::pluck_damping));
+    let damping_flex = Flex::row()
+                    .with_child(Label::new(tr("Damping")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(damping_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+                    .with_child(DragValue::new(damping_value, osc_lens.clone().then(OscSettings::pluck_damping),
+                        pluck_constraints::MIN_DAMPING as f64, pluck_constraints::MAX_DAMPING as f64).fix_width(25.0));
+    let lens_clone = osc_lens.clone();
+    let damping = Either::new(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc| OSC_ENGINES[osc.engine_idx.round() as usize].engine == OscEngine::Pluck)
+        },
+        damping_flex,
+        SizedBox::empty(),
     );
-    let wave_step = Stepper::new()
-        .with_range(0.0, (WAVEFORMS.len() - 1) as f64)
-        .with_wraparound(true)
-        .lens(osc_lens.clone().then(OscSettings::wave_idx));
-    let wave_flex = Flex::row().with_child(wave_label.fix_width(100.0)).with_child(wave_step);
-    osc_flex.add_child(wave_flex.padding(row_padding));
+    osc_flex.add_child(damping.padding(row_padding));
+
+    // Harmonics editor, only additive wave has them
+    let lens_clone = osc_lens.clone();
+    let harmonics = Either::new(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc| WAVEFORMS[osc.wave_idx.round() as usize].waveform == WaveForm::Additive)
+        },
+        BarGraph::new(30.0).lens(osc_lens.clone().then(OscSettings::harmonics)).fix_width(370.0),
+        SizedBox::empty(),
+    );
+    osc_flex.add_child(harmonics.padding(row_padding));
 
     // Transpose
     let lens_clone = osc_lens.clone();
     let transpose_value = Label::dynamic(
         move |data: &SynthUIData, _| {
             lens_clone.with(data, |osc: &OscSettings| {
-                format!("{} semitones",(osc.transpose as i8))
+                trf("{} semitones", &[&(osc.transpose as i8)])
             })
         }
     ).with_text_size(TEXT_SMALL);
-    let transpose_slider = DefaultSlider::new(Slider::new()
-                        .with_range(-24.0, 24.0), DefaultParameter::OscTranspose)
+    let transpose_slider = DefaultSlider::new(osc_constraints::MIN_TRANSPOSE as f64, osc_constraints::MAX_TRANSPOSE as f64,
+                        DefaultParameter::OscTranspose)
+                        .midi_learn(format!("{} transpose", title))
                         .lens(osc_lens.clone().then(OscSettings::transpose));
     let transpose_flex = Flex::row()
-                    .with_child(Label::new("Transpose").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(Label::new(tr("Transpose")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
                     .with_child(transpose_slider.fix_width(SLIDER_WIDTH_MEDIUM))
-                    .with_child(transpose_value.fix_width(25.0));
+                    .with_child(DragValue::new(transpose_value, osc_lens.clone().then(OscSettings::transpose),
+                        osc_constraints::MIN_TRANSPOSE as f64, osc_constraints::MAX_TRANSPOSE as f64).fix_width(25.0));
     osc_flex.add_child(transpose_flex.padding(row_padding));
 
     // Tune
@@ -104,24 +276,109 @@ where
     let tune_value = Label::dynamic(
         move |data: &SynthUIData, _| {
             lens_clone.with(data, |osc: &OscSettings| { 
-                format!("{} cents",(osc.tune as i8))
+                trf("{} cents", &[&(osc.tune as i8)])
             })
         }
     ).with_text_size(TEXT_SMALL);
-    let tune_slider = DefaultSlider::new(Slider::new()
-                        .with_range(-100.0, 100.0), DefaultParameter::OscTune)
+    // Offset of A4 in Hz, cents alone don't tell how fast two oscillators beat
+    let lens_clone = osc_lens.clone();
+    let tune_hz = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| {
+                let offset = A4_FREQUENCY * (2f64.powf(osc.tune.round() / 1200.0) - 1.0);
+                trf("{} Hz", &[&format!("{:+.1}", offset)])
+            })
+        }
+    ).with_text_size(TEXT_SMALL);
+    let tune_slider = DefaultSlider::new(osc_constraints::MIN_TUNE as f64, osc_constraints::MAX_TUNE as f64,
+                        DefaultParameter::OscTune)
+                        .with_snaps(&TUNE_SNAPS)
+                        .midi_learn(format!("{} tune", title))
                         .lens(osc_lens.clone().then(OscSettings::tune));
     let tune_flex = Flex::row()
-                    .with_child(Label::new("Tune").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(Label::new(tr("Tune")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
                     .with_child(tune_slider.fix_width(SLIDER_WIDTH_MEDIUM))
-                    .with_child(tune_value.fix_width(25.0));
+                    .with_child(DragValue::new(tune_value, osc_lens.clone().then(OscSettings::tune),
+                        osc_constraints::MIN_TUNE as f64, osc_constraints::MAX_TUNE as f64).fix_width(25.0))
+                    .with_spacer(5.0)
+                    .with_child(tune_hz.fix_width(50.0));
     osc_flex.add_child(tune_flex.padding(row_padding));
 
+    // Pan
+    let lens_clone = osc_lens.clone();
+    let pan_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| {
+                let percent = ((osc.pan - 0.5) * 200.0).round();
+                if percent < 0.0 {
+                    format!("L {}", -percent)
+                } else if percent > 0.0 {
+                    format!("R {}", percent)
+                } else {
+                    "C".to_owned()
+                }
+            })
+        }
+    ).with_text_size(TEXT_SMALL);
+    let pan_slider = DefaultSlider::new(osc_constraints::MIN_PAN as f64, osc_constraints::MAX_PAN as f64,
+                        DefaultParameter::OscPan)
+                        .midi_learn(format!("{} pan", title))
+                        .lens(osc_lens.clone().then(OscSettings::pan));
+    let pan_flex = Flex::row()
+                    .with_child(Label::new(tr("Pan")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(pan_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+                    .with_child(DragValue::new(pan_value, osc_lens.clone().then(OscSettings::pan),
+                        osc_constraints::MIN_PAN as f64, osc_constraints::MAX_PAN as f64).fix_width(25.0));
+    osc_flex.add_child(pan_flex.padding(row_padding));
+
+    // Phase every new voice starts at, shifted by the offset
+    let start_names = PHASE_STARTS.iter().map(|s| tr(s.name).to_owned()).collect();
+    let start_select = Dropdown::new(start_names, TEXT_SMALL)
+        .lens(osc_lens.clone().then(OscSettings::start_idx));
+    let lens_clone = osc_lens.clone();
+    let phase_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.0}\u{b0}", osc.phase_offset))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let phase_slider = DefaultSlider::new(osc_constraints::MIN_PHASE_OFFSET as f64, osc_constraints::MAX_PHASE_OFFSET as f64,
+                        DefaultParameter::OscPhaseOffset)
+                        .midi_learn(format!("{} phase", title))
+                        .lens(osc_lens.clone().then(OscSettings::phase_offset));
+    let phase_flex = Flex::row()
+                    .with_child(Label::new(tr("Phase")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(phase_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+                    .with_child(DragValue::new(phase_value, osc_lens.clone().then(OscSettings::phase_offset),
+                        osc_constraints::MIN_PHASE_OFFSET as f64, osc_constraints::MAX_PHASE_OFFSET as f64).fix_width(35.0))
+                    .with_spacer(5.0)
+                    .with_child(start_select);
+    osc_flex.add_child(phase_flex.padding(row_padding));
+
+    // Pulse width, only pulse waveform follows it
+    let lens_clone = osc_lens.clone();
+    let pw_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.0}%", osc.pulse_width * 100.0))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let pw_slider = DefaultSlider::new(osc_constraints::MIN_PULSE_WIDTH as f64, osc_constraints::MAX_PULSE_WIDTH as f64,
+                        DefaultParameter::OscPulseWidth)
+                        .midi_learn(format!("{} pulse width", title))
+                        .lens(osc_lens.clone().then(OscSettings::pulse_width));
+    let pw_flex = Flex::row()
+                    .with_child(Label::new(tr("PW")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(pw_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+                    .with_child(DragValue::new(pw_value, osc_lens.clone().then(OscSettings::pulse_width),
+                        osc_constraints::MIN_PULSE_WIDTH as f64, osc_constraints::MAX_PULSE_WIDTH as f64).fix_width(25.0));
+    osc_flex.add_child(pw_flex.padding(row_padding));
+
     // Unisons
     let uni_stepper = Stepper::new()
                     .with_range(1.0, MAX_UNISONS)
                     .with_wraparound(false)
                     .with_step(1.0)
+                    .midi_learn(format!("{} unisons", title), 1.0, MAX_UNISONS)
+                    .whole_steps()
                     .lens(osc_lens.clone().then(OscSettings::unisons));
     let lens_clone = osc_lens.clone();
     let uni_label = Label::dynamic(
@@ -132,12 +389,170 @@ where
         }
     );
     let uni_flex = Flex::row()
-                    .with_child(Label::new("Unisons").with_text_size(TEXT_SMALL))
+                    .with_child(Label::new(tr("Unisons")).with_text_size(TEXT_SMALL))
                     .with_child(uni_label)
-                    .with_child(uni_stepper);
+                    .with_child(uni_stepper)
+                    .with_spacer(20.0)
+                    .with_child(Checkbox::new(tr("Pitch bend")).lens(osc_lens.clone().then(OscSettings::bend)))
+                    .with_spacer(10.0)
+                    .with_child(Checkbox::new(tr("Drone")).lens(osc_lens.clone().then(OscSettings::drone)));
     osc_flex.add_child(uni_flex.padding(row_padding));
 
-    osc_flex.padding(5.0).border(BORDER_COLOR, 1.0).fix_width(390.0)
+    // Supersaw takes over unisons
+    let detune_slider = DefaultSlider::new(osc_constraints::MIN_SUPERSAW_DETUNE as f64, osc_constraints::MAX_SUPERSAW_DETUNE as f64,
+                        DefaultParameter::SupersawDetune)
+                        .midi_learn(format!("{} detune", title))
+                        .lens(osc_lens.clone().then(OscSettings::supersaw_detune));
+    let mix_slider = DefaultSlider::new(osc_constraints::MIN_SUPERSAW_MIX as f64, osc_constraints::MAX_SUPERSAW_MIX as f64,
+                        DefaultParameter::SupersawMix)
+                        .midi_learn(format!("{} supersaw mix", title))
+                        .lens(osc_lens.clone().then(OscSettings::supersaw_mix));
+    let supersaw_flex = Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Center)
+                    .with_child(Checkbox::new(tr("Supersaw")).lens(osc_lens.clone().then(OscSettings::supersaw)).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(Label::new(tr("Detune")).with_text_size(TEXT_SMALL))
+                    .with_child(detune_slider.fix_width(SLIDER_WIDTH_SMALL / 2.0))
+                    .with_child(Label::new(tr("Mix")).with_text_size(TEXT_SMALL))
+                    .with_child(mix_slider.fix_width(SLIDER_WIDTH_SMALL / 2.0));
+    osc_flex.add_child(supersaw_flex.padding(row_padding));
+
+    // Unisons spread in stereo, opening up from the center after note on
+    let width_slider = DefaultSlider::new(osc_constraints::MIN_UNISON_WIDTH as f64, osc_constraints::MAX_UNISON_WIDTH as f64,
+                        DefaultParameter::UnisonWidth)
+                        .midi_learn(format!("{} unison width", title))
+                        .lens(osc_lens.clone().then(OscSettings::unison_width));
+    let width_time_slider = DefaultSlider::new(osc_constraints::MIN_WIDTH_TIME as f64, osc_constraints::MAX_WIDTH_TIME as f64,
+                        DefaultParameter::WidthTime)
+                        .midi_learn(format!("{} width time", title))
+                        .lens(osc_lens.clone().then(OscSettings::width_time));
+    let lens_clone = osc_lens.clone();
+    let width_time_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| trf("{} ms", &[&osc.width_time.round()]))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let width_flex = Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Center)
+                    .with_child(Label::new(tr("Width")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(width_slider.fix_width(SLIDER_WIDTH_SMALL / 2.0))
+                    .with_child(Label::new(tr("Bloom")).with_text_size(TEXT_SMALL))
+                    .with_child(width_time_slider.fix_width(SLIDER_WIDTH_SMALL / 2.0))
+                    .with_child(width_time_value);
+    osc_flex.add_child(width_flex.padding(row_padding));
+
+    // Sub-oscillator
+    let sub_names = SUB_WAVEFORMS.iter().map(|w| tr(w.name).to_owned()).collect();
+    let lens_clone = osc_lens.clone();
+    let sub_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.2}", osc.sub_level))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let sub_slider = DefaultSlider::new(osc_constraints::MIN_SUB_LEVEL as f64, osc_constraints::MAX_SUB_LEVEL as f64,
+                        DefaultParameter::OscSubLevel)
+                        .midi_learn(format!("{} sub level", title))
+                        .lens(osc_lens.clone().then(OscSettings::sub_level));
+    let sub_flex = Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Center)
+                    .with_child(Checkbox::new(tr("Sub")).lens(osc_lens.clone().then(OscSettings::sub)).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(Dropdown::new(sub_names, TEXT_SMALL).lens(osc_lens.clone().then(OscSettings::sub_wave_idx)))
+                    .with_child(sub_slider.fix_width(SLIDER_WIDTH_SMALL))
+                    .with_child(DragValue::new(sub_value, osc_lens.clone().then(OscSettings::sub_level),
+                        osc_constraints::MIN_SUB_LEVEL as f64, osc_constraints::MAX_SUB_LEVEL as f64).fix_width(25.0));
+    osc_flex.add_child(sub_flex.padding(row_padding));
+
+    // Tone tilts the oscillator darker or brighter, unchecked it's bypassed
+    let lens_clone = osc_lens.clone();
+    let tilt_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:+.2}", osc.tilt))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let tilt_slider = DefaultSlider::new(osc_constraints::MIN_TILT as f64, osc_constraints::MAX_TILT as f64,
+                        DefaultParameter::OscTilt)
+                        .midi_learn(format!("{} tone", title))
+                        .lens(osc_lens.clone().then(OscSettings::tilt));
+    let tone_flex = Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Center)
+                    .with_child(Checkbox::new(tr("Tone")).lens(osc_lens.clone().then(OscSettings::tone)).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(Label::new(tr("Dark")).with_text_size(TEXT_SMALL))
+                    .with_child(tilt_slider.fix_width(SLIDER_WIDTH_SMALL))
+                    .with_child(Label::new(tr("Bright")).with_text_size(TEXT_SMALL))
+                    .with_spacer(5.0)
+                    .with_child(DragValue::new(tilt_value, osc_lens.clone().then(OscSettings::tilt),
+                        osc_constraints::MIN_TILT as f64, osc_constraints::MAX_TILT as f64).fix_width(35.0));
+    osc_flex.add_child(tone_flex.padding(row_padding));
+
+    // Velocity zone, drag the bounds up and down
+    let lens_clone = osc_lens.clone();
+    let velocity_low = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.0}", osc.velocity_low))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let lens_clone = osc_lens.clone();
+    let velocity_high = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |osc: &OscSettings| format!("{:.0}", osc.velocity_high))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let velocity_flex = Flex::row()
+                    .with_child(Label::new(tr("Velocity")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+                    .with_child(DragValue::new(velocity_low, osc_lens.clone().then(OscSettings::velocity_low),
+                        osc_constraints::MIN_VELOCITY as f64, osc_constraints::MAX_VELOCITY as f64).fix_width(25.0))
+                    .with_child(Label::new("-").with_text_size(TEXT_SMALL))
+                    .with_spacer(5.0)
+                    .with_child(DragValue::new(velocity_high, osc_lens.clone().then(OscSettings::velocity_high),
+                        osc_constraints::MIN_VELOCITY as f64, osc_constraints::MAX_VELOCITY as f64).fix_width(25.0));
+    osc_flex.add_child(velocity_flex.padding(row_padding));
+
+    // Aliasing hint
+    let lens_clone = osc_lens.clone();
+    let alias_hint = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let sample_rate = SAMPLE_RATES[data.sample_rate_idx.round() as usize] as f32;
+            let highest = lens_clone.with(data, |osc| {
+                highest_frequency(sample_rate, osc.transpose as i8, osc.tune as i8, osc.unisons.round() as usize)
+            });
+            if highest < HIGHEST_MIDI_FREQUENCY {
+                trf("Notes above {} Hz are muted to avoid aliasing", &[&highest.round()])
+            } else {
+                String::new()
+            }
+        }
+    ).with_text_size(TEXT_SMALL).with_text_color(LABEL_COLOR_WARNING);
+    osc_flex.add_child(alias_hint.padding(row_padding));
+
+    let solo_lens = osc_lens.clone();
+    let panel = osc_flex.padding(5.0).border(ACCENT_COLOR, 1.0).fix_width(390.0)
+        .env_scope(move |env, data: &SynthUIData| {
+            env.set(ACCENT_COLOR, accent_color(osc_lens.with(data, |osc| osc.color_idx)))
+        });
+    SoloOnHover::new(panel, solo_lens)
+}
+
+// Panels of removed oscillators are dropped, like the envelope ones
+pub fn oscs_layout() -> impl Widget<SynthUIData> {
+    let mut oscs_flex = Flex::column().cross_axis_alignment(CrossAxisAlignment::Center);
+    for osc_idx in 0..osc_constraints::MAX_OSCILLATORS {
+        oscs_flex.add_child(ViewSwitcher::new(
+            move |data: &SynthUIData, _| data.oscs.len() > osc_idx,
+            move |shown, _, _| -> Box<dyn Widget<SynthUIData>> {
+                if *shown {
+                    Box::new(oscillator_layout(osc_idx).padding((0.0, 0.0, 0.0, 10.0)))
+                } else {
+                    Box::new(SizedBox::empty())
+                }
+            },
+        ));
+    }
+    let add = Button::new(tr("Add oscillator")).on_click(|_ctx, data: &mut SynthUIData, _env| data.add_osc());
+    oscs_flex.add_child(Either::new(
+        |data: &SynthUIData, _| data.oscs.len() < osc_constraints::MAX_OSCILLATORS,
+        add,
+        SizedBox::empty(),
+    ));
+    oscs_flex
 }
 
 pub fn synth_volume_layout() -> impl Widget<SynthUIData> {
@@ -149,74 +564,411 @@ pub fn synth_volume_layout() -> impl Widget<SynthUIData> {
                     .with_spacer(10.0);
     let volume_control = Flex::row()
                 .cross_axis_alignment(CrossAxisAlignment::Center)
-                .with_child(Label::new("Volume").with_text_size(TEXT_MEDIUM).fix_width(BASIC_LABEL_WITDH))
+                .with_child(Label::new(tr("Volume")).with_text_size(TEXT_MEDIUM).fix_width(BASIC_LABEL_WITDH))
                 .with_child(
                     Slider::new()
-                    .with_range(-96.0, 0.0)
-                    .lens(SynthUIData::volume_db)
+                    .with_range(0.0, 1.0)
+                    .midi_learn("Volume", 0.0, 1.0)
+                    .lens(SynthUIData::volume_db.map(
+                        |db: &f64| slider_db(*db),
+                        |db: &mut f64, position: f64| *db = db_from_slider(position)))
                     .padding((5.0, 0.0, 5.0, 0.0))
                     .fix_width(SLIDER_WIDTH_SMALL))
                 .with_child(
                     Label::dynamic(
                         |data: &SynthUIData, _| {
-                            format!("{} dB", data.volume_db.round())
+                            trf("{} dB", &[&data.volume_db.round()])
                         }
                     ).fix_width(25.0)
                 );
 
     volume_flex.add_child(volume_control);
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Checkbox::new(tr("Auto gain")).lens(SynthUIData::auto_gain))
+        .with_spacer(20.0)
+        .with_child(ClipIndicator::new())
+        .with_spacer(5.0)
+        .with_child(Label::new(tr("Clip")).with_text_size(TEXT_SMALL))
+        .with_spacer(20.0)
+        .with_child(Checkbox::new(tr("Mute")).lens(SynthUIData::muted))
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // How much softer notes get with lower velocity
+    let velocity_slider = DefaultSlider::new(osc_constraints::MIN_VELOCITY_SENSITIVITY as f64, osc_constraints::MAX_VELOCITY_SENSITIVITY as f64,
+                    DefaultParameter::VelocitySensitivity)
+                    .midi_learn("Velocity sensitivity")
+                    .lens(SynthUIData::velocity_sensitivity);
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Velocity")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(velocity_slider.padding((5.0, 0.0, 5.0, 0.0)).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(
+            Label::dynamic(
+                |data: &SynthUIData, _| format!("{:.0}%", data.velocity_sensitivity * 100.0)
+            ).with_text_size(TEXT_SMALL).fix_width(50.0)
+        )
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // Log scale slider
+    let low_cut_slider = DefaultSlider::new(slider_log(osc_constraints::MIN_LOW_CUT), slider_log(osc_constraints::MAX_LOW_CUT),
+                    DefaultParameter::LowCut)
+                    .midi_learn("Low cut")
+                    .lens(SynthUIData::low_cut);
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Low cut")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(low_cut_slider.padding((5.0, 0.0, 5.0, 0.0)).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(
+            Label::dynamic(
+                |data: &SynthUIData, _| {
+                    trf("{} Hz", &[&format!("{:.0}", LOG_SCALE_BASE.powf(data.low_cut))])
+                }
+            ).with_text_size(TEXT_SMALL).fix_width(50.0)
+        )
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // Glide from the previous note, 0 ms turns it off
+    let glide_slider = DefaultSlider::new(glide_constraints::MIN_TIME as f64, glide_constraints::MAX_TIME as f64,
+                    DefaultParameter::GlideTime)
+                    .midi_learn("Glide time")
+                    .lens(SynthUIData::glide.then(GlideSettings::time));
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Glide")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(glide_slider.padding((5.0, 0.0, 5.0, 0.0)).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(
+            Label::dynamic(
+                |data: &SynthUIData, _| {
+                    trf("{} ms", &[&data.glide.time.round()])
+                }
+            ).with_text_size(TEXT_SMALL).fix_width(50.0)
+        )
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+    let curves = GLIDE_CURVES.iter().map(|curve| tr(curve.name).to_owned()).collect();
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Checkbox::new(tr("Legato only")).lens(SynthUIData::glide.then(GlideSettings::legato)))
+        .with_spacer(10.0)
+        .with_child(Dropdown::new(curves, TEXT_SMALL).lens(SynthUIData::glide.then(GlideSettings::curve_idx)))
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // Polyphony limit keeps big chords with many unisons from eating the CPU
+    let stealing = VOICE_STEALING.iter().map(|stealing| tr(stealing.name).to_owned()).collect();
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Voices")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(DragValue::new(
+            Label::dynamic(|data: &SynthUIData, _| format!("{:.0}", data.voices.max_voices)).with_text_size(TEXT_SMALL),
+            SynthUIData::voices.then(VoiceSettings::max_voices),
+            osc_constraints::MIN_VOICES as f64,
+            osc_constraints::MAX_VOICES as f64,
+        ).fix_width(25.0))
+        .with_spacer(10.0)
+        .with_child(Dropdown::new(stealing, TEXT_SMALL).lens(SynthUIData::voices.then(VoiceSettings::stealing_idx)))
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // Osc2 modulates phase of Osc1
+    let fm_slider = DefaultSlider::new(osc_constraints::MIN_FM_AMOUNT as f64, osc_constraints::MAX_FM_AMOUNT as f64,
+                    DefaultParameter::FmAmount)
+                    .midi_learn("FM amount")
+                    .lens(SynthUIData::fm_amount);
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("FM amount")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(fm_slider.padding((5.0, 0.0, 5.0, 0.0)).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(
+            Label::dynamic(
+                |data: &SynthUIData, _| format!("{:.1}", data.fm_amount)
+            ).with_text_size(TEXT_SMALL).fix_width(50.0)
+        )
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+
+    // Expression pedal (CC11), apart from master volume above
+    let destinations = EXPRESSION_DESTINATIONS.iter().map(|dest| tr(dest.name).to_owned()).collect();
+    let curves = EXPRESSION_CURVES.iter().map(|curve| tr(curve.name).to_owned()).collect();
+    volume_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Expression")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Dropdown::new(destinations, TEXT_SMALL)
+            .lens(SynthUIData::expression.then(ExpressionSettings::destination_idx)))
+        .with_spacer(10.0)
+        .with_child(Dropdown::new(curves, TEXT_SMALL).lens(SynthUIData::expression.then(ExpressionSettings::curve_idx)))
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
 
     volume_flex
 }
 
-pub fn env_layout<L>(title: &str, env_lens: L) -> impl Widget<SynthUIData>
-where
-    L: Lens<SynthUIData, EnvSettings>
-    + Clone
-    + 'static
-{
+pub fn midi_layout(ports: Vec<String>) -> impl Widget<SynthUIData> {
+    let mut options = vec![tr("None").to_owned()];
+    options.extend(ports);
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("MIDI in")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Dropdown::new(options, TEXT_SMALL).lens(SynthUIData::midi_port))
+        .with_spacer(10.0)
+        // Velocity of computer keyboard and on-screen piano
+        .with_child(Label::new(tr("Keys")).with_text_size(TEXT_SMALL))
+        .with_spacer(5.0)
+        .with_child(DragValue::new(
+            Label::dynamic(|data: &SynthUIData, _| format!("{:.0}", data.key_velocity)).with_text_size(TEXT_SMALL),
+            SynthUIData::key_velocity,
+            1.0,
+            osc_constraints::MAX_VELOCITY as f64,
+        ).fix_width(25.0))
+        .with_spacer(10.0)
+        // Pitch wheel range in semitones
+        .with_child(Label::new(tr("Bend")).with_text_size(TEXT_SMALL))
+        .with_spacer(5.0)
+        .with_child(DragValue::new(
+            Label::dynamic(|data: &SynthUIData, _| format!("\u{b1}{:.0}", data.bend_range)).with_text_size(TEXT_SMALL),
+            SynthUIData::bend_range,
+            bend_constraints::MIN_RANGE as f64,
+            bend_constraints::MAX_RANGE as f64,
+        ).fix_width(30.0))
+}
+
+pub fn output_layout() -> impl Widget<SynthUIData> {
+    let button = Button::dynamic(|data: &SynthUIData, _| {
+            tr(if data.recording { "Stop" } else { "Record" }).into()
+        })
+        .on_click(|ctx, data: &mut SynthUIData, _env| toggle_recording(data, ctx.get_external_handle()));
+    let rates = SAMPLE_RATES.iter().map(|rate| trf("{} kHz", &[&(*rate as f64 / 1000.0)])).collect();
+    let status = Label::dynamic(|data: &SynthUIData, _| {
+            if data.recording { tr("\u{25cf} Recording").into() } else { String::new() }
+        })
+        .with_text_size(TEXT_SMALL)
+        .with_text_color(LABEL_COLOR_MAIN);
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Output")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Dropdown::new(rates, TEXT_SMALL).lens(SynthUIData::sample_rate_idx))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("HQ").lens(SynthUIData::high_quality))
+        .with_spacer(5.0)
+        .with_child(Checkbox::new("DC").lens(SynthUIData::dc_blocker))
+        .with_spacer(5.0)
+        // Binaural, for headphones
+        .with_child(Checkbox::new("3D").lens(SynthUIData::binaural))
+        .with_spacer(5.0)
+        .with_child(button)
+        .with_spacer(10.0)
+        .with_child(status)
+        .fix_width(410.0)
+}
+
+// Sine for setting up levels of external gear, plays until unchecked
+pub fn test_tone_layout() -> impl Widget<SynthUIData> {
+    let frequencies = TEST_TONE_FREQUENCIES.iter().map(|freq| trf("{} Hz", &[freq])).collect();
+    let level = Label::dynamic(|data: &SynthUIData, _| trf("{} dB", &[&data.test_tone_level.round()]))
+        .with_text_size(TEXT_SMALL);
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Test tone")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Checkbox::new("").lens(SynthUIData::test_tone))
+        .with_spacer(5.0)
+        .with_child(Dropdown::new(frequencies, TEXT_SMALL).lens(SynthUIData::test_tone_idx))
+        .with_spacer(10.0)
+        .with_child(DragValue::new(level, SynthUIData::test_tone_level,
+            test_tone_constraints::MIN_LEVEL as f64, test_tone_constraints::MAX_LEVEL as f64).fix_width(50.0))
+        .fix_width(370.0)
+}
+
+// Layout is built once, so a new language is shown after restart
+pub fn language_layout() -> impl Widget<SynthUIData> {
+    let names = LANGUAGES.iter().map(|lang| lang.name.to_owned()).collect();
+    let hint = Label::dynamic(|data: &SynthUIData, _| {
+            if data.language_idx.round() as usize != current_language() {
+                tr("Restart to apply").into()
+            } else {
+                String::new()
+            }
+        })
+        .with_text_size(TEXT_SMALL)
+        .with_text_color(LABEL_COLOR_WARNING);
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Language")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Dropdown::new(names, TEXT_SMALL).lens(SynthUIData::language_idx))
+        .with_spacer(10.0)
+        .with_child(hint)
+        .fix_width(330.0)
+}
+
+// Larger text and no meaning carried by color alone, for low vision
+pub fn theme_layout() -> impl Widget<SynthUIData> {
+    let names = THEMES.iter().map(|theme| tr(theme.name).to_owned()).collect();
+    let hint = Label::dynamic(|data: &SynthUIData, _| {
+            if data.theme_idx.round() as usize != current_theme() {
+                tr("Restart to apply").into()
+            } else {
+                String::new()
+            }
+        })
+        .with_text_size(TEXT_SMALL)
+        .with_text_color(LABEL_COLOR_WARNING);
+    Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Theme")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Dropdown::new(names, TEXT_SMALL).lens(SynthUIData::theme_idx))
+        .with_spacer(10.0)
+        .with_child(hint)
+        .fix_width(330.0)
+}
+
+pub fn preset_layout() -> impl Widget<SynthUIData> {
+    let buttons = Flex::row()
+        .with_child(Label::new(tr("Preset")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Button::new(tr("Save")).on_click(|ctx, _data: &mut SynthUIData, _env| {
+            ctx.submit_command(save_preset_dialog())
+        }))
+        .with_spacer(5.0)
+        .with_child(Button::new(tr("Load")).on_click(|ctx, _data: &mut SynthUIData, _env| {
+            ctx.submit_command(load_preset_dialog())
+        }))
+        .with_spacer(5.0)
+        .with_child(Button::new("<").on_click(|_ctx, data: &mut SynthUIData, _env| {
+            step_preset(data, false)
+        }))
+        .with_child(Button::new(">").on_click(|_ctx, data: &mut SynthUIData, _env| {
+            step_preset(data, true)
+        }))
+        .with_spacer(5.0)
+        // Patch and recordings in one zip
+        .with_child(Button::new(tr("Export")).on_click(|ctx, _data: &mut SynthUIData, _env| {
+            ctx.submit_command(export_session_dialog())
+        }));
+    let browser = Scroll::new(PresetBrowser::new(TEXT_SMALL))
+        .vertical()
+        .fix_height(PRESET_BROWSER_HEIGHT)
+        .border(BORDER_COLOR, 1.0);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(buttons)
+        .with_spacer(5.0)
+        .with_child(browser.fix_width(330.0))
+}
+
+fn env_layout(env_idx: usize) -> impl Widget<SynthUIData> {
+    let title = format!("Env{}", env_idx + 1);
+    let env_lens = SynthUIData::envs.then(lens::Identity.index(env_idx).in_arc());
+    // Header is editable, the name shows up in envelope selectors
+    let name = TextBox::new()
+                    .with_placeholder(title.clone())
+                    .with_text_size(TEXT_MEDIUM)
+                    .lens(env_lens.clone().then(EnvSettings::name))
+                    .fix_width(150.0);
+    // Oscillators and the filter using it move to the envelope before it
+    let remove = Either::new(
+        |data: &SynthUIData, _| data.envs.len() > adsr_constraints::MIN_ENVELOPES,
+        Button::new("\u{2212}").on_click(move |_ctx, data: &mut SynthUIData, _env| data.remove_env(env_idx)),
+        SizedBox::empty(),
+    );
+    // Hear the envelope without holding a key
+    let preview = Button::new(tr("Preview"))
+                    .on_click(move |ctx, _data: &mut SynthUIData, _env| ctx.submit_command(PREVIEW_ENV.with(env_idx)));
     let mut env_flex = Flex::column()
                     .cross_axis_alignment(CrossAxisAlignment::Start)
-                    .with_child(Label::new(title).with_text_size(TEXT_MEDIUM).padding(5.0));
+                    .with_child(
+                        Flex::row()
+                        .cross_axis_alignment(CrossAxisAlignment::Center)
+                        .with_child(name)
+                        .with_spacer(5.0)
+                        .with_child(preview)
+                        .with_spacer(5.0)
+                        .with_child(remove)
+                        .padding(5.0)
+                    );
+
+    // Delay before attack and hold at the peak, both off at 0
+    let lens_clone = env_lens.clone();
+    let delay_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            env_time(lens_clone.with(data, |env| { env.delay.round() as f32 }))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let delay_slider = DefaultSlider::new(adsr_constraints::MIN_DELAY as f64, adsr_constraints::MAX_DELAY as f64,
+                    DefaultParameter::EnvDelay)
+                    .midi_learn(format!("{} delay", title))
+                    .lens(env_lens.clone().then(EnvSettings::delay));
+    let lens_clone = env_lens.clone();
+    let hold_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            env_time(lens_clone.with(data, |env| { env.hold.round() as f32 }))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let hold_slider = DefaultSlider::new(adsr_constraints::MIN_HOLD as f64, adsr_constraints::MAX_HOLD as f64,
+                    DefaultParameter::EnvHold)
+                    .midi_learn(format!("{} hold", title))
+                    .lens(env_lens.clone().then(EnvSettings::hold));
+    env_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Delay")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(delay_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL / 2.0))
+        .with_child(DragValue::new(delay_value, env_lens.clone().then(EnvSettings::delay),
+            adsr_constraints::MIN_DELAY as f64, adsr_constraints::MAX_DELAY as f64).fix_width(50.0))
+        .with_child(Label::new(tr("Hold")).with_text_size(TEXT_SMALL))
+        .with_child(hold_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL / 2.0))
+        .with_child(DragValue::new(hold_value, env_lens.clone().then(EnvSettings::hold),
+            adsr_constraints::MIN_HOLD as f64, adsr_constraints::MAX_HOLD as f64).fix_width(50.0)).padding(5.0)
+    );
 
     // Attack
     let lens_clone = env_lens.clone();
     let attack_value = Label::dynamic(
         move |data: &SynthUIData, _| {
-            format!("{} ms", lens_clone.with(data, |env| { LOG_SCALE_BASE.powf(env.attack).round() }))
+            env_time(lens_clone.with(data, |env| { attack_ms(env.attack) }))
         }
     ).with_text_size(TEXT_SMALL);
     // Log scale slider
     let attack_min = slider_log(adsr_constraints::MIN_ATTACK);
     let attack_max = slider_log(adsr_constraints::MAX_ATTACK);
-    let attack_slider = DefaultSlider::new(Slider::new()
-                    .with_range(attack_min, attack_max), DefaultParameter::EnvAttack)
+    let attack_slider = DefaultSlider::new(attack_min, attack_max, DefaultParameter::EnvAttack)
+                    .midi_learn(format!("{} attack", title))
                     .lens(env_lens.clone().then(EnvSettings::attack));
     env_flex.add_child(
         Flex::row()
-        .with_child(Label::new("Attack").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Label::new(tr("Attack")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
         .with_child(attack_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
-        .with_child(attack_value.fix_width(45.0)).padding(5.0)
+        .with_child(DragValue::new(attack_value, env_lens.clone().then(EnvSettings::attack),
+            attack_min, attack_max).fix_width(50.0)).padding(5.0)
     );
 
     // Decay
     let lens_clone = env_lens.clone();
     let decay_value = Label::dynamic(
         move |data: &SynthUIData, _| {
-            format!("{} ms", lens_clone.with(data, |env| { LOG_SCALE_BASE.powf(env.decay).round() }))
+            env_time(lens_clone.with(data, |env| { LOG_SCALE_BASE.powf(env.decay).round() as f32 }))
         }
     ).with_text_size(TEXT_SMALL);
     // Log scale slider
     let decay_min = slider_log(adsr_constraints::MIN_DECAY);
     let decay_max = slider_log(adsr_constraints::MAX_DECAY);
-    let decay_slider = DefaultSlider::new(Slider::new()
-                    .with_range(decay_min, decay_max), DefaultParameter::EnvDecay)
+    let decay_slider = DefaultSlider::new(decay_min, decay_max, DefaultParameter::EnvDecay)
+                    .midi_learn(format!("{} decay", title))
                     .lens(env_lens.clone().then(EnvSettings::decay));
     env_flex.add_child(
         Flex::row()
-        .with_child(Label::new("Decay").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Label::new(tr("Decay")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
         .with_child(decay_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
-        .with_child(decay_value.fix_width(45.0)).padding(5.0)
+        .with_child(DragValue::new(decay_value, env_lens.clone().then(EnvSettings::decay),
+            decay_min, decay_max).fix_width(50.0)).padding(5.0)
     );
 
     // Sustain
@@ -226,35 +978,716 @@ where
             format!("{:.2}", lens_clone.with(data, |env| { env.sustain }))
         }
     ).with_text_size(TEXT_SMALL);
-    let sustain_slider = DefaultSlider::new(Slider::new()
-                    .with_range(0.0, 1.0), DefaultParameter::EnvSustain)
+    let sustain_slider = DefaultSlider::new(0.0, 1.0, DefaultParameter::EnvSustain)
+                    .midi_learn(format!("{} sustain", title))
                     .lens(env_lens.clone().then(EnvSettings::sustain));
     env_flex.add_child(
         Flex::row()
-        .with_child(Label::new("Sustain").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Label::new(tr("Sustain")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
         .with_child(sustain_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
-        .with_child(sustain_value.fix_width(45.0)).padding(5.0)
+        .with_child(DragValue::new(sustain_value, env_lens.clone().then(EnvSettings::sustain),
+            0.0, 1.0).fix_width(45.0)).padding(5.0)
     );
 
     // Release
     let lens_clone = env_lens.clone();
     let release_value = Label::dynamic(
         move |data: &SynthUIData, _| {
-            format!("{} ms", lens_clone.with(data, |env| { LOG_SCALE_BASE.powf(env.release).round() }))
+            env_time(lens_clone.with(data, |env| { LOG_SCALE_BASE.powf(env.release).round() as f32 }))
         }
     ).with_text_size(TEXT_SMALL);
     // Log scale slider
     let release_min = slider_log(adsr_constraints::MIN_RELEASE);
     let release_max = slider_log(adsr_constraints::MAX_RELEASE);
-    let release_slider = DefaultSlider::new(Slider::new()
-                    .with_range(release_min, release_max), DefaultParameter::EnvRelease)
+    let release_slider = DefaultSlider::new(release_min, release_max, DefaultParameter::EnvRelease)
+                    .midi_learn(format!("{} release", title))
                     .lens(env_lens.clone().then(EnvSettings::release));
     env_flex.add_child(
         Flex::row()
-        .with_child(Label::new("Release").with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(Label::new(tr("Release")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
         .with_child(release_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
-        .with_child(release_value.fix_width(45.0)).padding(5.0)
+        .with_child(DragValue::new(release_value, env_lens.clone().then(EnvSettings::release),
+            release_min, release_max).fix_width(50.0)).padding(5.0)
+    );
+
+    // Looping turns the envelope into a complex LFO while the note is held
+    let loop_count_max = adsr_constraints::MAX_LOOP_COUNT as f64;
+    let loop_stepper = Stepper::new()
+                    .with_range(adsr_constraints::MIN_LOOP_COUNT as f64, loop_count_max)
+                    .with_wraparound(false)
+                    .with_step(1.0)
+                    .midi_learn(format!("{} loop count", title), adsr_constraints::MIN_LOOP_COUNT as f64, loop_count_max)
+                    .whole_steps()
+                    .lens(env_lens.clone().then(EnvSettings::loop_count));
+    let lens_clone = env_lens.clone();
+    let loop_count_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            lens_clone.with(data, |env| match env.loop_count.round() as u32 {
+                0 => "\u{221e}".to_owned(),
+                count => trf("{} times", &[&count]),
+            })
+        }
+    ).with_text_size(TEXT_SMALL);
+    env_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Checkbox::new(tr("Loop")).lens(env_lens.clone().then(EnvSettings::looping)).fix_width(BASIC_LABEL_WITDH))
+        .with_child(loop_count_label)
+        .with_child(loop_stepper)
+        .padding(5.0)
     );
 
     env_flex.padding(15.0).fix_width(360.0)
-}
\ No newline at end of file
+}
+
+// Panels of removed envelopes are dropped, so they never see an index past the list
+pub fn envs_layout() -> impl Widget<SynthUIData> {
+    let mut envs_flex = Flex::column().cross_axis_alignment(CrossAxisAlignment::Center);
+    for env_idx in 0..adsr_constraints::MAX_ENVELOPES {
+        envs_flex.add_child(ViewSwitcher::new(
+            move |data: &SynthUIData, _| data.envs.len() > env_idx,
+            move |shown, _, _| -> Box<dyn Widget<SynthUIData>> {
+                if *shown {
+                    Box::new(env_layout(env_idx).padding((0.0, 0.0, 0.0, 10.0)))
+                } else {
+                    Box::new(SizedBox::empty())
+                }
+            },
+        ));
+    }
+    let add = Button::new(tr("Add envelope")).on_click(|_ctx, data: &mut SynthUIData, _env| data.add_env());
+    envs_flex.add_child(Either::new(
+        |data: &SynthUIData, _| data.envs.len() < adsr_constraints::MAX_ENVELOPES,
+        add,
+        SizedBox::empty(),
+    ));
+    envs_flex
+}
+
+pub fn lfo_layout<L>(title: &str, lfo_lens: L) -> impl Widget<SynthUIData>
+where
+    L: Lens<SynthUIData, LfoSettings>
+    + Clone
+    + 'static
+{
+    let mut lfo_flex = Flex::column()
+                    .cross_axis_alignment(CrossAxisAlignment::Start)
+                    .with_child(Label::new(title).with_text_size(TEXT_MEDIUM).padding(5.0));
+
+    // Waveform
+    let lens_clone = lfo_lens.clone();
+    let wave_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let idx = lens_clone.with(data, |lfo: &LfoSettings| { lfo.wave_idx });
+            tr(WAVEFORMS[idx.round() as usize].name).into()
+        }
+    ).with_text_size(TEXT_SMALL);
+    let wave_step = Stepper::new()
+        .with_range(0.0, (WAVEFORMS.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn(format!("{} wave", title), 0.0, (WAVEFORMS.len() - 1) as f64)
+        .whole_steps()
+        .lens(lfo_lens.clone().then(LfoSettings::wave_idx));
+    lfo_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Waveform")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(wave_label.fix_width(100.0))
+        .with_child(wave_step).padding(5.0)
+    );
+
+    // Rate
+    let lens_clone = lfo_lens.clone();
+    let rate_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            trf("{} Hz", &[&format!("{:.2}", lens_clone.with(data, |lfo| { LOG_SCALE_BASE.powf(lfo.rate) }))])
+        }
+    ).with_text_size(TEXT_SMALL);
+    // Log scale slider
+    let rate_min = slider_log(lfo_constraints::MIN_RATE);
+    let rate_max = slider_log(lfo_constraints::MAX_RATE);
+    let rate_slider = DefaultSlider::new(rate_min, rate_max, DefaultParameter::LfoRate)
+                    .midi_learn(format!("{} rate", title))
+                    .lens(lfo_lens.clone().then(LfoSettings::rate));
+    lfo_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Rate")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(rate_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
+        .with_child(DragValue::new(rate_value, lfo_lens.clone().then(LfoSettings::rate),
+            rate_min, rate_max).fix_width(65.0)).padding(5.0)
+    );
+
+    // Depth
+    let lens_clone = lfo_lens.clone();
+    let depth_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            format!("{:.2}", lens_clone.with(data, |lfo| { lfo.depth }))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let depth_slider = DefaultSlider::new(lfo_constraints::MIN_DEPTH as f64, lfo_constraints::MAX_DEPTH as f64,
+                    DefaultParameter::LfoDepth)
+                    .midi_learn(format!("{} depth", title))
+                    .lens(lfo_lens.clone().then(LfoSettings::depth));
+    lfo_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Depth")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(depth_slider.padding(2.0).fix_width(SLIDER_WIDTH_MEDIUM))
+        .with_child(DragValue::new(depth_value, lfo_lens.clone().then(LfoSettings::depth),
+            lfo_constraints::MIN_DEPTH as f64, lfo_constraints::MAX_DEPTH as f64).fix_width(65.0)).padding(5.0)
+    );
+
+    // Target
+    let lens_clone = lfo_lens.clone();
+    let target_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let idx = lens_clone.with(data, |lfo: &LfoSettings| { lfo.target_idx });
+            let target = &LFO_TARGETS[idx.round() as usize];
+            osc_target_name(data, target.name, target.target.osc())
+        }
+    ).with_text_size(TEXT_SMALL);
+    let target_step = Stepper::new()
+        .with_range(0.0, (LFO_TARGETS.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn(format!("{} target", title), 0.0, (LFO_TARGETS.len() - 1) as f64)
+        .whole_steps()
+        .lens(OscTargets {
+            inner: lfo_lens.clone().then(LfoSettings::target_idx),
+            len: LFO_TARGETS.len(),
+            osc: |idx| LFO_TARGETS[idx].target.osc(),
+        });
+    lfo_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Target")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(target_label.fix_width(100.0))
+        .with_child(target_step).padding(5.0)
+    );
+
+    lfo_flex.padding(15.0).fix_width(360.0)
+}
+
+fn mod_slot_layout(slot_idx: usize) -> impl Widget<SynthUIData> {
+    let slot_lens = SynthUIData::mod_slots.then(lens::Identity.index(slot_idx).in_arc());
+
+    let lens_clone = slot_lens.clone();
+    let source_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let idx = lens_clone.with(data, |slot: &ModSlotSettings| { slot.source_idx });
+            tr(MOD_SOURCES[idx.round() as usize].name).into()
+        }
+    ).with_text_size(TEXT_SMALL);
+    let source_step = Stepper::new()
+        .with_range(0.0, (MOD_SOURCES.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn(format!("Mod {} source", slot_idx + 1), 0.0, (MOD_SOURCES.len() - 1) as f64)
+        .whole_steps()
+        .lens(slot_lens.clone().then(ModSlotSettings::source_idx));
+
+    // Oscillator destinations are shown with its name and color
+    let lens_clone = slot_lens.clone();
+    let destination_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let idx = lens_clone.with(data, |slot: &ModSlotSettings| { slot.destination_idx });
+            let destination = &MOD_DESTINATIONS[idx.round() as usize];
+            osc_target_name(data, destination.name, destination.destination.osc())
+        }
+    ).with_text_size(TEXT_SMALL).with_text_color(ACCENT_COLOR);
+    let lens_clone = slot_lens.clone();
+    let destination_label = destination_label.env_scope(move |env, data: &SynthUIData| {
+        let idx = lens_clone.with(data, |slot: &ModSlotSettings| { slot.destination_idx });
+        let color = match MOD_DESTINATIONS[idx.round() as usize].destination.osc() {
+            Some(osc) => accent_color(data.osc(osc).color_idx),
+            None => env.get(theme::LABEL_COLOR),
+        };
+        env.set(ACCENT_COLOR, color);
+    });
+    let destination_step = Stepper::new()
+        .with_range(0.0, (MOD_DESTINATIONS.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn(format!("Mod {} destination", slot_idx + 1), 0.0, (MOD_DESTINATIONS.len() - 1) as f64)
+        .whole_steps()
+        .lens(OscTargets {
+            inner: slot_lens.clone().then(ModSlotSettings::destination_idx),
+            len: MOD_DESTINATIONS.len(),
+            osc: |idx| MOD_DESTINATIONS[idx].destination.osc(),
+        });
+
+    let lens_clone = slot_lens.clone();
+    let depth_value = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            format!("{:.2}", lens_clone.with(data, |slot: &ModSlotSettings| { slot.depth }))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let depth_slider = DefaultSlider::new(mod_constraints::MIN_DEPTH as f64, mod_constraints::MAX_DEPTH as f64,
+                    DefaultParameter::ModDepth)
+                    .midi_learn(format!("Mod {} depth", slot_idx + 1))
+                    .lens(slot_lens.clone().then(ModSlotSettings::depth));
+
+    // Source scaling the depth, e.g. mod wheel bringing in vibrato
+    let lens_clone = slot_lens.clone();
+    let depth_source_label = Label::dynamic(
+        move |data: &SynthUIData, _| {
+            let idx = lens_clone.with(data, |slot: &ModSlotSettings| { slot.depth_source_idx });
+            format!("\u{d7} {}", tr(MOD_SOURCES[idx.round() as usize].name))
+        }
+    ).with_text_size(TEXT_SMALL);
+    let depth_source_step = Stepper::new()
+        .with_range(0.0, (MOD_SOURCES.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn(format!("Mod {} depth source", slot_idx + 1), 0.0, (MOD_SOURCES.len() - 1) as f64)
+        .whole_steps()
+        .lens(slot_lens.clone().then(ModSlotSettings::depth_source_idx));
+
+    Flex::row()
+        .with_child(source_label.fix_width(75.0))
+        .with_child(source_step)
+        .with_spacer(10.0)
+        .with_child(destination_label.fix_width(90.0))
+        .with_child(destination_step)
+        .with_child(depth_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(DragValue::new(depth_value, slot_lens.then(ModSlotSettings::depth),
+            mod_constraints::MIN_DEPTH as f64, mod_constraints::MAX_DEPTH as f64).fix_width(40.0))
+        .with_child(depth_source_label.fix_width(85.0))
+        .with_child(depth_source_step)
+        .padding(2.0)
+}
+
+pub fn mod_matrix_layout() -> impl Widget<SynthUIData> {
+    let mut matrix_flex = Flex::column()
+                    .cross_axis_alignment(CrossAxisAlignment::Start)
+                    .with_child(Label::new(tr("Mod matrix")).with_text_size(TEXT_MEDIUM).padding(5.0));
+    for slot_idx in 0..MOD_SLOTS {
+        matrix_flex.add_child(mod_slot_layout(slot_idx));
+    }
+
+    // Speed of chaos source
+    let chaos_slider = DefaultSlider::new(chaos_constraints::MIN_RATE as f64, chaos_constraints::MAX_RATE as f64,
+                    DefaultParameter::ChaosRate)
+                    .midi_learn("Chaos rate")
+                    .lens(SynthUIData::chaos_rate);
+    matrix_flex.add_child(
+        Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Center)
+        .with_child(Label::new(tr("Chaos rate")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(chaos_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(
+            Label::dynamic(|data: &SynthUIData, _| format!("{:.2}", data.chaos_rate))
+                .with_text_size(TEXT_SMALL)
+        )
+        .padding((0.0, 5.0, 0.0, 0.0))
+    );
+    matrix_flex.padding(10.0)
+}
+
+// Step modulator, a mod source in time with tempo
+pub fn steps_layout() -> impl Widget<SynthUIData> {
+    let tempo_slider = DefaultSlider::new(clock_constraints::MIN_TEMPO as f64, clock_constraints::MAX_TEMPO as f64,
+                    DefaultParameter::Tempo)
+                    .midi_learn("Tempo")
+                    .lens(SynthUIData::tempo);
+    let tempo_value = Label::dynamic(|data: &SynthUIData, _| format!("{:.0} BPM", data.tempo))
+                    .with_text_size(TEXT_SMALL);
+    let divisions = STEP_DIVISIONS.iter().map(|division| division.name.to_owned()).collect();
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Steps")).with_text_size(TEXT_MEDIUM).padding(5.0))
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Tempo")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(tempo_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL))
+            .with_child(DragValue::new(tempo_value, SynthUIData::tempo,
+                clock_constraints::MIN_TEMPO as f64, clock_constraints::MAX_TEMPO as f64).fix_width(60.0))
+            .with_child(Dropdown::new(divisions, TEXT_SMALL).lens(SynthUIData::step_division_idx))
+        )
+        .with_spacer(5.0)
+        .with_child(BarGraph::new(50.0).lens(SynthUIData::step_levels).fix_width(STEPS_WIDTH))
+        .padding(10.0)
+}
+
+// Step sequencer playing a pattern around C4 in time with tempo of the step modulator
+pub fn seq_layout() -> impl Widget<SynthUIData> {
+    let transport = Button::dynamic(|data: &SynthUIData, _| {
+            tr(if data.seq_running { "Stop" } else { "Play" }).into()
+        })
+        .on_click(|_ctx, data: &mut SynthUIData, _env| data.seq_running = !data.seq_running);
+    let divisions = STEP_DIVISIONS.iter().map(|division| division.name.to_owned()).collect();
+
+    let step_width = STEPS_WIDTH / STEPS as f64;
+    let mut gates = Flex::row();
+    let mut accents = Flex::row();
+    for step in 0..STEPS {
+        gates.add_child(Checkbox::new("")
+            .lens(SynthUIData::seq.then(SeqSettings::gates).then(lens::Identity.index(step).in_arc()))
+            .fix_width(step_width));
+        accents.add_child(Checkbox::new("")
+            .lens(SynthUIData::seq.then(SeqSettings::accents).then(lens::Identity.index(step).in_arc()))
+            .fix_width(step_width));
+    }
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Sequencer")).with_text_size(TEXT_MEDIUM).padding(5.0))
+            .with_spacer(10.0)
+            .with_child(transport)
+            .with_spacer(10.0)
+            .with_child(Dropdown::new(divisions, TEXT_SMALL).lens(SynthUIData::seq.then(SeqSettings::division_idx)))
+        )
+        .with_spacer(5.0)
+        .with_child(BarGraph::new(50.0).lens(SynthUIData::seq.then(SeqSettings::pitches)).fix_width(STEPS_WIDTH))
+        .with_child(
+            Flex::row()
+            .with_child(gates)
+            .with_child(Label::new(tr("On")).with_text_size(TEXT_SMALL))
+        )
+        .with_child(
+            Flex::row()
+            .with_child(accents)
+            .with_child(Label::new(tr("Accent")).with_text_size(TEXT_SMALL))
+        )
+        .padding(10.0)
+}
+
+// Arpeggiator cycling held keys, synced steps share tempo with the step modulator
+pub fn arp_layout() -> impl Widget<SynthUIData> {
+    let modes = ARP_MODES.iter().map(|mode| tr(mode.name).to_owned()).collect();
+    let divisions = STEP_DIVISIONS.iter().map(|division| division.name.to_owned()).collect();
+
+    // Log scale slider
+    let rate_min = slider_log(arp_constraints::MIN_RATE);
+    let rate_max = slider_log(arp_constraints::MAX_RATE);
+    let rate_slider = DefaultSlider::new(rate_min, rate_max, DefaultParameter::ArpRate)
+                    .midi_learn("Arp rate")
+                    .lens(SynthUIData::arp.then(ArpSettings::rate));
+    let rate_value = Label::dynamic(
+        |data: &SynthUIData, _| trf("{} ms", &[&LOG_SCALE_BASE.powf(data.arp.rate).round()])
+    ).with_text_size(TEXT_SMALL);
+    let rate_control = Either::new(
+        |data: &SynthUIData, _| data.arp.synced,
+        Dropdown::new(divisions, TEXT_SMALL).lens(SynthUIData::arp.then(ArpSettings::division_idx)),
+        Flex::row()
+            .with_child(rate_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL))
+            .with_child(DragValue::new(rate_value, SynthUIData::arp.then(ArpSettings::rate),
+                rate_min, rate_max).fix_width(60.0)),
+    );
+
+    let octaves_value = Label::dynamic(|data: &SynthUIData, _| format!("{:.0}", data.arp.octaves))
+                    .with_text_size(TEXT_SMALL);
+    let octaves_step = Stepper::new()
+        .with_range(arp_constraints::MIN_OCTAVES as f64, arp_constraints::MAX_OCTAVES as f64)
+        .midi_learn("Arp octaves", arp_constraints::MIN_OCTAVES as f64, arp_constraints::MAX_OCTAVES as f64)
+        .whole_steps()
+        .lens(SynthUIData::arp.then(ArpSettings::octaves));
+
+    let gate_slider = DefaultSlider::new(arp_constraints::MIN_GATE as f64, arp_constraints::MAX_GATE as f64,
+                    DefaultParameter::ArpGate)
+                    .midi_learn("Arp gate")
+                    .lens(SynthUIData::arp.then(ArpSettings::gate));
+    let gate_value = Label::dynamic(|data: &SynthUIData, _| format!("{:.0}%", data.arp.gate * 100.0))
+                    .with_text_size(TEXT_SMALL);
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Arpeggiator")).with_text_size(TEXT_MEDIUM).padding(5.0))
+            .with_spacer(10.0)
+            .with_child(Checkbox::new(tr("On")).lens(SynthUIData::arp.then(ArpSettings::enabled)))
+        )
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Mode")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(Dropdown::new(modes, TEXT_SMALL).lens(SynthUIData::arp.then(ArpSettings::mode_idx)))
+            .with_spacer(10.0)
+            .with_child(Checkbox::new(tr("Sync")).lens(SynthUIData::arp.then(ArpSettings::synced)))
+            .padding((0.0, 5.0, 0.0, 0.0))
+        )
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Rate")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(rate_control)
+            .padding((0.0, 5.0, 0.0, 0.0))
+        )
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Octaves")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(octaves_value.fix_width(20.0))
+            .with_child(octaves_step)
+            .padding((0.0, 5.0, 0.0, 0.0))
+        )
+        .with_child(
+            Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Center)
+            .with_child(Label::new(tr("Gate")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(gate_slider.padding(2.0).fix_width(SLIDER_WIDTH_SMALL))
+            .with_child(DragValue::new(gate_value, SynthUIData::arp.then(ArpSettings::gate),
+                arp_constraints::MIN_GATE as f64, arp_constraints::MAX_GATE as f64).fix_width(45.0))
+            .padding((0.0, 5.0, 0.0, 0.0))
+        )
+        .padding(10.0)
+}
+
+pub fn filter_layout() -> impl Widget<SynthUIData> {
+    let mut filter_flex = Flex::column()
+                    .cross_axis_alignment(CrossAxisAlignment::Start)
+                    .with_child(
+        Label::new(tr("Filter")).with_text_size(TEXT_MEDIUM).with_text_color(LABEL_COLOR_SECONDARY).padding(10.0)
+    );
+
+    // Mode
+    let mode_label = Label::dynamic(
+        |data: &SynthUIData, _| {
+            tr(FILTER_MODES[data.filter.mode_idx.round() as usize].name).into()
+        }
+    );
+    let mode_step = Stepper::new()
+        .with_range(0.0, (FILTER_MODES.len() - 1) as f64)
+        .with_wraparound(true)
+        .midi_learn("Filter mode", 0.0, (FILTER_MODES.len() - 1) as f64)
+        .whole_steps()
+        .lens(SynthUIData::filter.then(FilterSettings::mode_idx));
+    filter_flex.add_child(
+        Flex::row().with_child(mode_label.fix_width(100.0)).with_child(mode_step)
+        .padding((10.0, 0.0, 0.0, 10.0))
+    );
+
+    // Cutoff
+    let cutoff_value = Label::dynamic(
+        |data: &SynthUIData, _| {
+            trf("{} Hz", &[&LOG_SCALE_BASE.powf(data.filter.cutoff).round()])
+        }
+    ).with_text_size(TEXT_SMALL);
+    // Log scale slider
+    let cutoff_min = slider_log(filter_constraints::MIN_CUTOFF);
+    let cutoff_max = slider_log(filter_constraints::MAX_CUTOFF);
+    let cutoff_slider = DefaultSlider::new(cutoff_min, cutoff_max, DefaultParameter::FilterCutoff)
+                    .midi_learn("Filter cutoff")
+                    .lens(SynthUIData::filter.then(FilterSettings::cutoff));
+    filter_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Cutoff")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(cutoff_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+        .with_child(DragValue::new(cutoff_value, SynthUIData::filter.then(FilterSettings::cutoff),
+            cutoff_min, cutoff_max).fix_width(70.0)).padding((10.0, 0.0, 0.0, 10.0))
+    );
+
+    // Resonance
+    let resonance_value = Label::dynamic(
+        |data: &SynthUIData, _| {
+            format!("{:.2}", data.filter.resonance)
+        }
+    ).with_text_size(TEXT_SMALL);
+    let resonance_slider = DefaultSlider::new(filter_constraints::MIN_RESONANCE as f64, filter_constraints::MAX_RESONANCE as f64,
+                    DefaultParameter::FilterResonance)
+                    .midi_learn("Filter resonance")
+                    .lens(SynthUIData::filter.then(FilterSettings::resonance));
+    filter_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Resonance")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(resonance_slider.fix_width(SLIDER_WIDTH_MEDIUM))
+        .with_child(DragValue::new(resonance_value, SynthUIData::filter.then(FilterSettings::resonance),
+            filter_constraints::MIN_RESONANCE as f64, filter_constraints::MAX_RESONANCE as f64).fix_width(70.0)).padding((10.0, 0.0, 0.0, 10.0))
+    );
+
+    // Envelope and its amount
+    let env_select = Dropdown::dynamic(adsr_constraints::MAX_ENVELOPES, |data: &SynthUIData, idx| data.env_name(idx),
+                    SynthUIData::filter.then(FilterSettings::env_idx), TEXT_SMALL)
+                    .with_shown(|data: &SynthUIData| data.envs.len());
+    let env_amount_value = Label::dynamic(
+        |data: &SynthUIData, _| {
+            format!("{:+.2}", data.filter.env_amount)
+        }
+    ).with_text_size(TEXT_SMALL);
+    let env_amount_slider = DefaultSlider::new(filter_constraints::MIN_ENV_AMOUNT as f64, filter_constraints::MAX_ENV_AMOUNT as f64,
+                    DefaultParameter::FilterEnvAmount)
+                    .midi_learn("Filter env amount")
+                    .lens(SynthUIData::filter.then(FilterSettings::env_amount));
+    filter_flex.add_child(
+        Flex::row()
+        .with_child(Label::new(tr("Env amount")).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+        .with_child(env_amount_slider.fix_width(SLIDER_WIDTH_SMALL))
+        .with_child(DragValue::new(env_amount_value, SynthUIData::filter.then(FilterSettings::env_amount),
+            filter_constraints::MIN_ENV_AMOUNT as f64, filter_constraints::MAX_ENV_AMOUNT as f64).fix_width(45.0))
+        .with_child(Label::new(tr("Envelope")).with_text_size(TEXT_SMALL))
+        .with_spacer(5.0)
+        .with_child(env_select)
+        .padding((10.0, 0.0, 0.0, 10.0))
+    );
+
+    filter_flex.padding(5.0).border(BORDER_COLOR, 1.0).fix_width(390.0)
+}
+
+// Three octaves from the one computer keyboard plays, so both rows light up on it.
+// Octave buttons do the same as arrow keys
+pub fn piano_layout() -> impl Widget<SynthUIData> {
+    let octave = Label::dynamic(|data: &SynthUIData, _| format!("C{}", data.octave()))
+        .with_text_size(TEXT_MEDIUM)
+        .center()
+        .fix_width(40.0);
+    let octave_flex = Flex::column()
+        .with_child(Label::new(tr("Octave")).with_text_size(TEXT_SMALL))
+        .with_spacer(5.0)
+        .with_child(
+            Flex::row()
+            .with_child(Button::new("-").on_click(|_ctx, data: &mut SynthUIData, _env| data.shift_octave(-1)))
+            .with_child(octave)
+            .with_child(Button::new("+").on_click(|_ctx, data: &mut SynthUIData, _env| data.shift_octave(1)))
+        );
+    Flex::row()
+        .with_child(octave_flex.padding((0.0, 0.0, 10.0, 0.0)))
+        .with_child(Piano::new(3, 70.0).fix_width(700.0))
+        .padding((0.0, 10.0, 0.0, 0.0))
+}
+
+fn keycap(label: &str, note: &str) -> impl Widget<SynthUIData> {
+    Flex::column()
+        .with_child(Label::new(label).with_text_size(TEXT_MEDIUM))
+        .with_child(Label::new(note).with_text_size(TEXT_SMALL).with_text_color(LABEL_COLOR_SECONDARY))
+        .center()
+        .fix_size(KEYCAP_SIZE, KEYCAP_SIZE)
+        .border(LABEL_COLOR_SECONDARY, 1.0)
+        .padding(KEYCAP_SPACING / 2.0)
+}
+
+// Keys are laid out like piano keys: sharps in the upper row between naturals
+fn keyboard_row(note_keys: &[NoteKey]) -> Flex<SynthUIData> {
+    let key_width = KEYCAP_SIZE + KEYCAP_SPACING;
+    let mut upper_row = Flex::row().with_spacer(key_width / 2.0);
+    let mut lower_row = Flex::row();
+    for (i, note_key) in note_keys.iter().enumerate() {
+        if note_key.sharp() {
+            upper_row.add_child(keycap(note_key.label, note_key.note));
+        } else {
+            lower_row.add_child(keycap(note_key.label, note_key.note));
+            // No sharp between E and F or after B
+            if !note_keys.get(i + 1).map_or(false, |next| next.sharp()) {
+                upper_row.add_spacer(key_width);
+            }
+        }
+    }
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(upper_row)
+        .with_child(lower_row)
+}
+
+pub fn help_layout() -> impl Widget<SynthUIData> {
+    let octave = Label::dynamic(|data: &SynthUIData, _| {
+            trf("{}  Octave down / up, now C{}", &[&OCTAVE_KEYS_LABEL, &data.octave()])
+        })
+        .with_text_size(TEXT_SMALL);
+
+    let mut help_flex = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Keyboard")).with_text_size(TEXT_LARGE).with_text_color(LABEL_COLOR_MAIN))
+        .with_spacer(10.0)
+        // Q row plays the octave above Z row, so it's shown above it like on the keyboard
+        .with_child(keyboard_row(&NOTE_KEYS[UPPER_ROW_START..]))
+        .with_spacer(10.0)
+        .with_child(keyboard_row(&NOTE_KEYS[..UPPER_ROW_START]))
+        .with_spacer(10.0)
+        .with_child(octave)
+        .with_spacer(20.0)
+        .with_child(Label::new(tr("Shortcuts")).with_text_size(TEXT_LARGE).with_text_color(LABEL_COLOR_MAIN))
+        .with_spacer(10.0);
+    for shortcut in SHORTCUTS.iter() {
+        help_flex.add_child(
+            Flex::row()
+            .with_child(Label::new(shortcut.label).with_text_size(TEXT_SMALL).fix_width(BASIC_LABEL_WITDH))
+            .with_child(Label::new(tr(shortcut.description)).with_text_size(TEXT_SMALL))
+            .padding((0.0, 0.0, 0.0, 5.0))
+        );
+    }
+    help_flex
+        .padding(20.0)
+        .background(BACKGROUND_COLOR)
+        .border(BORDER_COLOR, 1.0)
+}
+
+pub fn tips_layout() -> impl Widget<SynthUIData> {
+    let counter = Label::dynamic(|data: &SynthUIData, _| {
+            trf("Tip {} of {}", &[&(data.tip_idx.unwrap_or(0) + 1), &TIPS.len()])
+        })
+        .with_text_size(TEXT_SMALL)
+        .with_text_color(LABEL_COLOR_MAIN);
+    let tip = Label::dynamic(|data: &SynthUIData, _| {
+            data.tip_idx.map_or(String::new(), |idx| tr(TIPS[idx]).into())
+        })
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .with_text_size(TEXT_SMALL)
+        .fix_width(TIPS_WIDTH);
+    let next = Button::dynamic(|data: &SynthUIData, _| {
+            tr(if data.tip_idx.map_or(true, |idx| idx + 1 >= TIPS.len()) { "Done" } else { "Next" }).into()
+        })
+        .on_click(|_ctx, data: &mut SynthUIData, _env| next_tip(data));
+    let skip = Button::new(tr("Skip tips")).on_click(|_ctx, data: &mut SynthUIData, _env| {
+            data.tip_idx = None
+        });
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(counter)
+        .with_spacer(5.0)
+        .with_child(tip)
+        .with_spacer(10.0)
+        .with_child(Flex::row().with_child(skip).with_spacer(5.0).with_child(next))
+        .padding(15.0)
+        .background(BACKGROUND_COLOR)
+        .border(LABEL_COLOR_SECONDARY, 1.0)
+}
+
+// Click on a toast hides it before time
+pub fn toasts_layout() -> impl Widget<SynthUIData> {
+    List::new(|| {
+        // Errors are marked with a sign, not only with the border color
+        Label::dynamic(|toast: &Toast, _| {
+                if toast.error && high_contrast() {
+                    format!("\u{26a0} {}", toast.message)
+                } else {
+                    toast.message.clone()
+                }
+            })
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .with_text_size(TEXT_SMALL)
+            .fix_width(TOAST_WIDTH)
+            .padding(10.0)
+            .background(BACKGROUND_COLOR)
+            .border(ACCENT_COLOR, 1.0)
+            .env_scope(|env, toast: &Toast| {
+                env.set(ACCENT_COLOR, env.get(if toast.error { LABEL_COLOR_WARNING } else { LABEL_COLOR_SECONDARY }))
+            })
+            .on_click(|ctx, toast: &mut Toast, _env| ctx.submit_command(HIDE_TOAST.with(toast.id)))
+            .padding((0.0, 0.0, 0.0, 5.0))
+    })
+    .lens(SynthUIData::toasts)
+}
+
+// Debug panel for patches changing by themselves: what changed, from what to what and who did it
+pub fn audit_layout() -> impl Widget<SynthUIData> {
+    let entries = Label::dynamic(|data: &SynthUIData, _| {
+            let log = data.audit.lock().unwrap();
+            let lines = log.lines();
+            if !log.enabled() {
+                tr("Set audit_log to true in beep-boop-settings.json to record changes").into()
+            } else if lines.is_empty() {
+                tr("No changes yet").into()
+            } else {
+                lines.join("\n")
+            }
+        })
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .with_text_size(TEXT_SMALL)
+        .fix_width(CHANGE_LOG_WIDTH);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(tr("Change log")).with_text_size(TEXT_MEDIUM).with_text_color(LABEL_COLOR_MAIN))
+        .with_spacer(5.0)
+        .with_child(entries)
+        .padding(15.0)
+        .background(BACKGROUND_COLOR)
+        .border(LABEL_COLOR_SECONDARY, 1.0)
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use druid::widget::prelude::*;
+use druid::{DelegateCtx, Selector, Target};
+
+use crate::notification::notify;
+use crate::params::{taper_value, DEFAULT_TAPER};
+use crate::settings::{save_setting, MidiBinding};
+use super::model::SynthUIData;
+use super::i18n::trf;
+
+// Controller number and value 0-127, sent by MIDI thread for every CC message
+pub const MIDI_CC: Selector<(u8, u8)> = Selector::new("beep-boop.midi-cc");
+// Key of the control right-clicked to learn
+pub const START_MIDI_LEARN: Selector<String> = Selector::new("beep-boop.start-midi-learn");
+// Key of the control waiting for a CC, None once learning is over
+const MIDI_LEARN_TARGET: Selector<Option<String>> = Selector::new("beep-boop.midi-learn-target");
+// Key of the bound control and CC value scaled to 0.0-1.0
+pub(super) const SET_MIDI_VALUE: Selector<(String, f64)> = Selector::new("beep-boop.set-midi-value");
+const LEARN_OUTLINE_COLOR: druid::Color = druid::Color::rgba8(0xff, 0xc1, 0x07, 0xff);
+const LEARN_OUTLINE_WIDTH: f64 = 2.0;
+const MAX_CC_VALUE: f64 = 127.0;
+
+// Right-clicking the same control again while it waits for a CC unbinds it
+pub fn start_learn(ctx: &mut DelegateCtx, data: &mut SynthUIData, key: &str) {
+    if data.midi_learn.as_deref() == Some(key) {
+        data.midi_learn = None;
+        let bindings = data.midi_map.iter().filter(|binding| binding.key != key).cloned().collect();
+        save_bindings(data, bindings);
+        notify(&data.notification_sender, trf("{} unbound", &[&key]));
+    } else {
+        data.midi_learn = Some(key.to_owned());
+        notify(&data.notification_sender, trf("Move a MIDI control to bind {}", &[&key]));
+    }
+    ctx.submit_command(MIDI_LEARN_TARGET.with(data.midi_learn.clone()).to(Target::Global));
+}
+
+// Binds the control being learned, then moves every control bound to the CC
+pub fn handle_cc(ctx: &mut DelegateCtx, data: &mut SynthUIData, cc: u8, value: u8) {
+    if let Some(key) = data.midi_learn.take() {
+        // Control follows one CC, but one CC can move many controls
+        let mut bindings: Vec<MidiBinding> = data.midi_map.iter().filter(|binding| binding.key != key).cloned().collect();
+        bindings.push(MidiBinding { cc, key: key.clone() });
+        save_bindings(data, bindings);
+        notify(&data.notification_sender, trf("{} bound to CC {}", &[&key, &cc]));
+        ctx.submit_command(MIDI_LEARN_TARGET.with(None).to(Target::Global));
+    }
+    for binding in data.midi_map.iter().filter(|binding| binding.cc == cc) {
+        ctx.submit_command(SET_MIDI_VALUE.with((binding.key.clone(), value as f64 / MAX_CC_VALUE)).to(Target::Global));
+    }
+}
+
+fn save_bindings(data: &mut SynthUIData, bindings: Vec<MidiBinding>) {
+    save_setting(&data.notification_sender, |settings| settings.midi_map = bindings.clone());
+    data.midi_map = Arc::new(bindings);
+}
+
+// Makes a control learnable: right click waits for a CC, bound CC moves the control
+// through its whole range
+pub struct MidiLearn<W> {
+    inner: W,
+    key: String,
+    min: f64,
+    max: f64,
+    // Log scale sliders with a taper follow the CC the way they follow the mouse
+    taper: f64,
+    // Steppers go through indices, values between them mean nothing
+    whole_steps: bool,
+    learning: bool,
+}
+
+impl<W> MidiLearn<W> {
+    pub fn new(inner: W, key: impl Into<String>, min: f64, max: f64) -> Self {
+        Self {
+            inner,
+            key: key.into(),
+            min,
+            max,
+            taper: DEFAULT_TAPER,
+            whole_steps: false,
+            learning: false,
+        }
+    }
+
+    pub fn with_taper(mut self, taper: f64) -> Self {
+        self.taper = taper;
+        self
+    }
+
+    pub fn whole_steps(mut self) -> Self {
+        self.whole_steps = true;
+        self
+    }
+}
+
+pub trait MidiLearnExt: Widget<f64> + Sized {
+    fn midi_learn(self, key: impl Into<String>, min: f64, max: f64) -> MidiLearn<Self> {
+        MidiLearn::new(self, key, min, max)
+    }
+}
+
+impl<W: Widget<f64>> MidiLearnExt for W {}
+
+impl<W: Widget<f64>> Widget<f64> for MidiLearn<W> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut f64, env: &Env) {
+        match event {
+            Event::MouseDown(e) if e.button.is_right() => {
+                ctx.submit_command(START_MIDI_LEARN.with(self.key.clone()));
+                ctx.set_handled();
+                return
+            }
+            Event::Command(cmd) => {
+                if let Some(target) = cmd.get(MIDI_LEARN_TARGET) {
+                    self.learning = target.as_deref() == Some(self.key.as_str());
+                    ctx.request_paint();
+                }
+                if let Some((key, value)) = cmd.get(SET_MIDI_VALUE) {
+                    if *key == self.key {
+                        let value = self.min + taper_value(*value, self.taper) * (self.max - self.min);
+                        *data = if self.whole_steps { value.round() } else { value };
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.inner.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &f64, env: &Env) {
+        self.inner.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &f64, new: &f64, env: &Env) {
+        self.inner.update(ctx, old, new, env)
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &f64, env: &Env) -> Size {
+        self.inner.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &f64, env: &Env) {
+        self.inner.paint(ctx, data, env);
+        if self.learning {
+            let outline = ctx.size().to_rect().inset(-LEARN_OUTLINE_WIDTH / 2.0);
+            ctx.stroke(outline, &LEARN_OUTLINE_COLOR, LEARN_OUTLINE_WIDTH);
+        }
+    }
+}
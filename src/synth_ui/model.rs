@@ -2,16 +2,42 @@ use std::sync::{Arc, mpsc, Mutex};
 
 use druid::widget::prelude::*;
 use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
 
-use crate::synth::{Synth, Oscillator, ADSR, Start};
-use super::layout::{slider_log};
-use super::constants::{WAVEFORMS, DefaultParameter};
+use crate::control::{SynthCommand, SynthControl};
+use crate::synth::{auto_gain_trim, render_env_preview, Oscillator, Wave, ADSR, ADSRParam, Lfo, MOD_SLOTS, STEPS, HARMONICS, adsr_constraints, env_idx_after_removal, in_fm_pair, arp_constraints, osc_constraints, test_tone_constraints};
+use crate::error::Context;
+use crate::notification::{notify, report, NotificationSender};
+use crate::midi::MidiListener;
+use crate::settings::{MidiBinding, Settings};
+use crate::params::{attack_ms, LOG_SCALE_BASE, WAVEFORMS, SUB_WAVEFORMS, OSC_ENGINES, PHASE_STARTS, FILTER_MODES, LFO_TARGETS, MOD_DESTINATIONS, SAMPLE_RATES, DEFAULT_STEP_DIVISION_IDX, DEFAULT_TEST_TONE_IDX, DefaultParameter};
+use super::preset::{Preset, preset_names};
+use super::recording::{finish_recording, recording_saved, RECORDING_SAVED};
+use super::session::{export_session, EXPORT_SESSION};
+use super::audit::AuditLog;
+use super::midi_learn::{handle_cc, start_learn, MIDI_CC, START_MIDI_LEARN};
+use super::i18n::{current_language, tr};
+use super::themes::current_theme;
+use super::layout::ACCENT_COLORS;
+use super::keymap::{NOTE_KEYS, BASE_OCTAVE, MIN_OCTAVE, MAX_OCTAVE};
+use super::toasts::{Toast, SHOW_TOAST, HIDE_TOAST, MAX_TOASTS};
 
 
-use druid::{DelegateCtx, WindowId};
+use druid::{commands, Command, DelegateCtx, Handled, Selector, SingleUse, Target, WindowId};
+
+// Plays a short note with waveform of given index
+pub const AUDITION_WAVEFORM: Selector<usize> = Selector::new("beep-boop.audition-waveform");
+// Plays a second of the envelope with given index on a test tone
+pub const PREVIEW_ENV: Selector<usize> = Selector::new("beep-boop.preview-env");
+// Sent by hotkey thread, the synth is already muted by then
+pub const PANIC: Selector = Selector::new("beep-boop.panic");
+// New patches start with this many envelopes, more can be added
+const DEFAULT_ENVELOPES: usize = 2;
 
 pub enum SynthUIEvent {
     NewNotes,
+    // Hz, output stream is reopened with it
+    SampleRate(f32),
     WindowClosed,
 }
 
@@ -25,134 +51,847 @@ impl druid::AppDelegate<SynthUIData> for Delegate {
         _env: &Env,
         _ctx: &mut DelegateCtx
     ) {
+        // Unfinished recording is saved, not lost
+        finish_recording(data);
+        // Held keys and running sequencer would keep the stream going,
+        // it fades out instead of being cut in the middle of a note
+        data.control.send(SynthCommand::FadeOut);
         data.event_sender.send(SynthUIEvent::WindowClosed).unwrap();
     }
+
+    fn command(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        data: &mut SynthUIData,
+        _env: &Env
+    ) -> Handled {
+        if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            match Preset::from_data(data).save(file_info.path()).context("Can't save preset") {
+                Ok(()) => notify(&data.notification_sender, tr("Preset saved")),
+                Err(e) => report(&data.notification_sender, e),
+            }
+            data.presets = Arc::new(preset_names());
+            data.preset_idx = None;
+            return Handled::Yes
+        }
+        if let Some(file_info) = cmd.get(EXPORT_SESSION) {
+            export_session(data, file_info.path());
+            return Handled::Yes
+        }
+        if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
+            match Preset::load(file_info.path()).context("Can't load preset") {
+                Ok(preset) => {
+                    preset.apply(data);
+                    data.preset_idx = None;
+                }
+                Err(e) => report(&data.notification_sender, e),
+            }
+            return Handled::Yes
+        }
+        if let Some(toast) = cmd.get(SHOW_TOAST) {
+            let mut toasts = data.toasts.to_vec();
+            toasts.push(toast.clone());
+            if toasts.len() > MAX_TOASTS {
+                toasts.remove(0);
+            }
+            data.toasts = Arc::new(toasts);
+            return Handled::Yes
+        }
+        if let Some(id) = cmd.get(HIDE_TOAST) {
+            data.toasts = Arc::new(data.toasts.iter().filter(|toast| toast.id != *id).cloned().collect());
+            return Handled::Yes
+        }
+        if let Some(key) = cmd.get(START_MIDI_LEARN) {
+            start_learn(ctx, data, key);
+            return Handled::Yes
+        }
+        if let Some((cc, value)) = cmd.get(MIDI_CC) {
+            handle_cc(ctx, data, *cc, *value);
+            return Handled::Yes
+        }
+        if cmd.is(PANIC) {
+            data.muted = true;
+            notify(&data.notification_sender, tr("Panic: all sound off, output muted"));
+            return Handled::Yes
+        }
+        if let Some(idx) = cmd.get(AUDITION_WAVEFORM) {
+            let waveform = &WAVEFORMS[*idx].waveform;
+            data.control.play(SynthCommand::Audition(waveform.clone(), waveform.get_wave()));
+            return Handled::Yes
+        }
+        if let Some(saved) = cmd.get(RECORDING_SAVED).and_then(SingleUse::take) {
+            recording_saved(data, saved);
+            return Handled::Yes
+        }
+        if let Some(idx) = cmd.get(PREVIEW_ENV) {
+            data.preview_env(*idx);
+            return Handled::Yes
+        }
+        Handled::No
+    }
 }
 
-#[derive(Clone, Data, Lens)]
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
 pub struct OscSettings {
     pub id: usize,
-    // title: String,
+    #[serde(default)]
+    pub(super) name: String,
+    // Index of accent color of oscillator panel
+    #[serde(default)]
+    pub(super) color_idx: f64,
     pub(super) wave_idx: f64,
     pub(super) volume: f64,
+    pub(super) pan: f64,
     pub(super) transpose: f64,
     pub(super) tune: f64,
+    // Degrees, older presets start at 0
+    #[serde(default)]
+    pub(super) phase_offset: f64,
+    // Index in PHASE_STARTS, older presets start soft
+    #[serde(default)]
+    pub(super) start_idx: f64,
     pub(super) unisons: f64,
     pub(super) env_idx: f64,
+    // Older presets are fully enveloped
+    #[serde(default = "env_amount_default")]
+    pub(super) env_amount: f64,
+    // Presets saved before it was added bend all oscillators
+    #[serde(default = "bend_default")]
+    pub(super) bend: bool,
+    // Notes hold until it's turned off, older presets don't drone
+    #[serde(default)]
+    pub(super) drone: bool,
+    // Older presets have no sub-oscillator
+    #[serde(default)]
+    pub(super) sub: bool,
+    // Index in SUB_WAVEFORMS
+    #[serde(default)]
+    pub(super) sub_wave_idx: f64,
+    #[serde(default = "sub_level_default")]
+    pub(super) sub_level: f64,
+    // Tilt EQ, older presets have it bypassed
+    #[serde(default)]
+    pub(super) tone: bool,
+    #[serde(default)]
+    pub(super) tilt: f64,
+    // Older presets use plain unisons
+    #[serde(default)]
+    pub(super) supersaw: bool,
+    #[serde(default = "supersaw_detune_default")]
+    pub(super) supersaw_detune: f64,
+    #[serde(default = "supersaw_mix_default")]
+    pub(super) supersaw_mix: f64,
+    // Stereo spread of unisons and ms it takes to open up, older presets play unisons in mono
+    #[serde(default)]
+    pub(super) unison_width: f64,
+    #[serde(default)]
+    pub(super) width_time: f64,
+    // Index in OSC_ENGINES, older presets play waves
+    #[serde(default)]
+    pub(super) engine_idx: f64,
+    #[serde(default = "pluck_damping_default")]
+    pub(super) pluck_damping: f64,
+    // Levels of additive wave partials, older presets get the default saw-like spectrum
+    #[serde(with = "shared_list", default = "harmonics_default")]
+    pub(super) harmonics: Arc<Vec<f64>>,
+    // Older presets had a fixed 25% pulse, which is the default
+    #[serde(default = "pulse_width_default")]
+    pub(super) pulse_width: f64,
+    // MIDI velocity zone, older presets play every velocity
+    #[serde(default)]
+    pub(super) velocity_low: f64,
+    #[serde(default = "velocity_high_default")]
+    pub(super) velocity_high: f64,
 }
 
-#[derive(Clone, Data, Lens)]
+fn bend_default() -> bool {
+    true
+}
+
+fn env_amount_default() -> f64 {
+    DefaultParameter::OscEnvAmount.default_val()
+}
+
+fn sub_level_default() -> f64 {
+    DefaultParameter::OscSubLevel.default_val()
+}
+
+fn pluck_damping_default() -> f64 {
+    DefaultParameter::PluckDamping.default_val()
+}
+
+fn harmonics_default() -> Arc<Vec<f64>> {
+    Arc::new((1..=HARMONICS).map(|n| 1.0 / n as f64).collect())
+}
+
+// Serde can't handle Arc without its rc feature, values are saved as a plain list
+mod shared_list {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer, T: Serialize>(values: &Arc<Vec<T>>, serializer: S) -> Result<S::Ok, S::Error> {
+        values.as_slice().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Arc<Vec<T>>, D::Error> {
+        Vec::deserialize(deserializer).map(Arc::new)
+    }
+}
+
+fn supersaw_detune_default() -> f64 {
+    DefaultParameter::SupersawDetune.default_val()
+}
+
+fn supersaw_mix_default() -> f64 {
+    DefaultParameter::SupersawMix.default_val()
+}
+
+fn pulse_width_default() -> f64 {
+    DefaultParameter::OscPulseWidth.default_val()
+}
+
+fn velocity_high_default() -> f64 {
+    osc_constraints::MAX_VELOCITY as f64
+}
+
+impl OscSettings {
+    // Empty name shows the position, like Osc3. Neighbours get different colors
+    pub(super) fn new(id: usize) -> Self {
+        Self {
+            id,
+            name: String::new(),
+            color_idx: ((2 * id + 1) % ACCENT_COLORS.len()) as f64,
+            wave_idx: 0.0,
+            volume: 0.3,
+            pan: DefaultParameter::OscPan.default_val(),
+            transpose: 0.0,
+            tune: 0.0,
+            phase_offset: DefaultParameter::OscPhaseOffset.default_val(),
+            start_idx: 0.0,
+            unisons: 1.0,
+            env_idx: 0.0,
+            env_amount: DefaultParameter::OscEnvAmount.default_val(),
+            bend: true,
+            drone: false,
+            sub: false,
+            sub_wave_idx: 0.0,
+            sub_level: DefaultParameter::OscSubLevel.default_val(),
+            tone: false,
+            tilt: DefaultParameter::OscTilt.default_val(),
+            engine_idx: 0.0,
+            pluck_damping: DefaultParameter::PluckDamping.default_val(),
+            harmonics: harmonics_default(),
+            supersaw: false,
+            supersaw_detune: DefaultParameter::SupersawDetune.default_val(),
+            supersaw_mix: DefaultParameter::SupersawMix.default_val(),
+            unison_width: DefaultParameter::UnisonWidth.default_val(),
+            width_time: DefaultParameter::WidthTime.default_val(),
+            pulse_width: DefaultParameter::OscPulseWidth.default_val(),
+            velocity_low: osc_constraints::MIN_VELOCITY as f64,
+            velocity_high: osc_constraints::MAX_VELOCITY as f64,
+        }
+    }
+
+    // Everything that changes loudness is applied, auto gain measures oscillators built here.
+    // The rest of the settings are oscillator defaults
+    fn oscillator(&self, sample_rate: f32) -> Oscillator {
+        let mut oscillator = Oscillator::new(
+            sample_rate,
+            WAVEFORMS[self.wave_idx as usize].waveform.clone(),
+            self.env_idx as usize,
+            self.volume as f32);
+        oscillator.set_harmonics(self.harmonic_levels(), self.wave());
+        oscillator.set_pulse_width(self.pulse_width as f32);
+        oscillator.set_engine(OSC_ENGINES[self.engine_idx.round() as usize].engine);
+        oscillator.set_pluck_damping(self.pluck_damping as f32);
+        oscillator.set_start(PHASE_STARTS[self.start_idx as usize].start);
+        oscillator.set_pan(self.pan as f32);
+        oscillator.tune(self.tune as i8);
+        oscillator.transpose(self.transpose as i8);
+        oscillator.set_supersaw(self.supersaw);
+        oscillator.set_supersaw_detune(self.supersaw_detune as f32);
+        oscillator.set_supersaw_mix(self.supersaw_mix as f32);
+        oscillator.set_unison_num(self.unisons as usize);
+        let sub_waveform = &SUB_WAVEFORMS[self.sub_wave_idx.round() as usize].waveform;
+        oscillator.set_sub_wave(sub_waveform.clone(), sub_waveform.get_wave());
+        oscillator.set_sub(self.sub);
+        oscillator.set_sub_level(self.sub_level as f32);
+        oscillator
+    }
+
+    pub(super) fn harmonic_levels(&self) -> [f32; HARMONICS] {
+        let mut levels = [0.0; HARMONICS];
+        for (level, value) in levels.iter_mut().zip(self.harmonics.iter()) {
+            *level = value.max(0.0).min(1.0) as f32;
+        }
+        levels
+    }
+
+    // Current waveform, additive one is built from the harmonics
+    pub(super) fn wave(&self) -> Box<dyn Wave + Send> {
+        WAVEFORMS[self.wave_idx as usize].waveform.build_wave(&self.harmonic_levels())
+    }
+}
+
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
 pub struct EnvSettings {
     pub(super) id: usize,
+    #[serde(default)]
+    pub(super) name: String,
+    // ms, linear scale. Older presets have neither stage
+    #[serde(default)]
+    pub(super) delay: f64,
     pub(super) attack: f64,
+    #[serde(default)]
+    pub(super) hold: f64,
     pub(super) decay: f64,
     pub(super) sustain: f64,
     pub(super) release: f64,
+    // Attack to decay repeats loop_count times, 0 is while held. Older presets don't loop
+    #[serde(default)]
+    pub(super) looping: bool,
+    #[serde(default)]
+    pub(super) loop_count: f64,
+}
+
+impl EnvSettings {
+    // Empty name shows the position, like Env3
+    pub(super) fn new(id: usize) -> Self {
+        Self {
+            id,
+            name: String::new(),
+            delay: DefaultParameter::EnvDelay.default_val(),
+            // attack, decay and release are log scaler representation now
+            attack: DefaultParameter::EnvAttack.slider_default(),
+            hold: DefaultParameter::EnvHold.default_val(),
+            decay: DefaultParameter::EnvDecay.slider_default(),
+            sustain: DefaultParameter::EnvSustain.default_val(),
+            release: DefaultParameter::EnvRelease.slider_default(),
+            looping: false,
+            loop_count: adsr_constraints::MIN_LOOP_COUNT as f64,
+        }
+    }
+
+    // Everything the synth envelope is set from, sliders turned into its units
+    pub(super) fn params(&self) -> [ADSRParam; 8] {
+        [
+            ADSRParam::Delay(self.delay.round() as f32),
+            ADSRParam::Hold(self.hold.round() as f32),
+            ADSRParam::Attack(attack_ms(self.attack)),
+            ADSRParam::Decay(LOG_SCALE_BASE.powf(self.decay).round() as f32),
+            ADSRParam::Sustain(self.sustain as f32),
+            ADSRParam::Release(LOG_SCALE_BASE.powf(self.release).round() as f32),
+            ADSRParam::Loop(self.looping),
+            ADSRParam::LoopCount(self.loop_count.round() as u32),
+        ]
+    }
+
+    // Same envelope as the synth has, for rendering it on the UI thread
+    fn adsr(&self, sample_rate: f32) -> ADSR {
+        let mut adsr = default_adsr(sample_rate);
+        for param in self.params().iter() {
+            adsr.set_parameter(*param);
+        }
+        adsr
+    }
+}
+
+fn default_adsr(sample_rate: f32) -> ADSR {
+    ADSR::new(
+        sample_rate,
+        DefaultParameter::EnvAttack.default_val() as u32,
+        DefaultParameter::EnvDecay.default_val() as u32,
+        DefaultParameter::EnvSustain.default_val() as f32,
+        DefaultParameter::EnvRelease.default_val() as u32)
+}
+
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub(super) id: usize,
+    pub(super) wave_idx: f64,
+    // Log scale representation
+    pub(super) rate: f64,
+    pub(super) depth: f64,
+    pub(super) target_idx: f64,
+}
+
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct ModSlotSettings {
+    pub(super) id: usize,
+    pub(super) source_idx: f64,
+    pub(super) destination_idx: f64,
+    pub(super) depth: f64,
+    // Index in MOD_SOURCES of the source scaling depth, older presets have none
+    #[serde(default)]
+    pub(super) depth_source_idx: f64,
+}
+
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct FilterSettings {
+    // Log scale representation
+    pub(super) cutoff: f64,
+    pub(super) resonance: f64,
+    pub(super) mode_idx: f64,
+    pub(super) env_idx: f64,
+    pub(super) env_amount: f64,
+}
+
+// Presets saved before glide was added load with it turned off
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct GlideSettings {
+    // ms
+    pub(super) time: f64,
+    pub(super) legato: bool,
+    pub(super) curve_idx: f64,
+}
+
+impl Default for GlideSettings {
+    fn default() -> Self {
+        Self {
+            time: DefaultParameter::GlideTime.default_val(),
+            legato: false,
+            curve_idx: 0.0,
+        }
+    }
+}
+
+// Presets saved before polyphony limit was added play all voices
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct VoiceSettings {
+    pub(super) max_voices: f64,
+    // Index in VOICE_STEALING
+    pub(super) stealing_idx: f64,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            max_voices: osc_constraints::MAX_VOICES as f64,
+            stealing_idx: 0.0,
+        }
+    }
+}
+
+// Presets saved before expression pedal was added load with it trimming volume
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct ExpressionSettings {
+    pub(super) destination_idx: f64,
+    pub(super) curve_idx: f64,
+}
+
+impl Default for ExpressionSettings {
+    fn default() -> Self {
+        Self {
+            destination_idx: 0.0,
+            curve_idx: 0.0,
+        }
+    }
+}
+
+// Presets saved before arpeggiator was added load with it turned off
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct ArpSettings {
+    pub(super) enabled: bool,
+    // Index in ARP_MODES
+    pub(super) mode_idx: f64,
+    // Steps follow tempo instead of rate
+    pub(super) synced: bool,
+    // Log scale representation of ms per step
+    pub(super) rate: f64,
+    // Index in STEP_DIVISIONS
+    pub(super) division_idx: f64,
+    pub(super) octaves: f64,
+    pub(super) gate: f64,
+}
+
+impl Default for ArpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode_idx: 0.0,
+            synced: false,
+            rate: DefaultParameter::ArpRate.slider_default(),
+            division_idx: DEFAULT_STEP_DIVISION_IDX,
+            octaves: arp_constraints::MIN_OCTAVES as f64,
+            gate: DefaultParameter::ArpGate.default_val(),
+        }
+    }
+}
+
+// Presets saved before sequencer was added load with an even pattern of roots
+#[derive(Clone, Data, Lens, Serialize, Deserialize)]
+pub struct SeqSettings {
+    // Index in STEP_DIVISIONS
+    pub(super) division_idx: f64,
+    // 0.0-1.0 each, the middle is the root
+    #[serde(with = "shared_list")]
+    pub(super) pitches: Arc<Vec<f64>>,
+    #[serde(with = "shared_list")]
+    pub(super) gates: Arc<Vec<bool>>,
+    #[serde(with = "shared_list")]
+    pub(super) accents: Arc<Vec<bool>>,
+}
+
+impl Default for SeqSettings {
+    fn default() -> Self {
+        Self {
+            division_idx: DEFAULT_STEP_DIVISION_IDX,
+            pitches: Arc::new(vec![0.5; STEPS]),
+            gates: Arc::new(vec![true; STEPS]),
+            accents: Arc::new(vec![false; STEPS]),
+        }
+    }
 }
 
 #[derive(Clone, Data, Lens)]
 pub struct SynthUIData {
     #[data(ignore)]
-    pub(super) synth: Arc<Mutex<Synth<i16>>>,
+    pub(super) control: SynthControl<i16>,
     #[data(ignore)]
     pub(super) event_sender: mpsc::Sender<SynthUIEvent>,
+    #[data(ignore)]
+    pub(super) notification_sender: NotificationSender,
+    // Shown notifications, the newest one is the last
+    pub(super) toasts: Arc<Vec<Toast>>,
+    #[data(ignore)]
+    pub(super) midi: Arc<Mutex<MidiListener>>,
+    pub(super) midi_port: f64,
+    // Names of presets in presets directory
+    pub(super) presets: Arc<Vec<String>>,
+    pub(super) preset_idx: Option<usize>,
+    pub(super) recording: bool,
+    // Disconnects once the writer thread has finished the file
+    #[data(ignore)]
+    pub(super) recording_writer: Option<Arc<Mutex<mpsc::Receiver<()>>>>,
+    // Recordings saved since start, they go to exported session
+    pub(super) session_recordings: Arc<Vec<String>>,
+    // Index in SAMPLE_RATES
+    pub(super) sample_rate_idx: f64,
+    // Band-limited waves, kept in settings rather than presets
+    pub(super) high_quality: bool,
+    pub(super) dc_blocker: bool,
+    pub(super) binaural: bool,
+    // Calibration tone, neither in presets nor in settings. Index in TEST_TONE_FREQUENCIES and dBFS
+    pub(super) test_tone: bool,
+    pub(super) test_tone_idx: f64,
+    pub(super) test_tone_level: f64,
+    // MIDI velocity of notes played without a MIDI keyboard, kept in settings too
+    pub(super) key_velocity: f64,
+    // Semitones of the pitch wheel, a setting of the MIDI keyboard rather than of a patch
+    pub(super) bend_range: f64,
+    // Controls driven by MIDI CCs, saved in settings as soon as learned
+    #[data(ignore)]
+    pub(super) midi_map: Arc<Vec<MidiBinding>>,
+    // Key of the control waiting for a CC to be bound to
+    pub(super) midi_learn: Option<String>,
+    #[data(ignore)]
+    pub(super) audit: Arc<Mutex<AuditLog>>,
+    // Index in LANGUAGES, applied on the next launch
+    pub(super) language_idx: f64,
+    // Index in THEMES, applied on the next launch
+    pub(super) theme_idx: f64,
+    // Demo patch is loaded once the window shows up
+    pub(super) first_run: bool,
+    // Onboarding tip being shown
+    pub(super) tip_idx: Option<usize>,
     pub(super) octave_modifier: f32,
+    // Notes of computer keyboard being held, in NOTE_KEYS order
+    pub(super) held_keys: Arc<Vec<bool>>,
     pub(super) volume_db: f64,
-    pub(super) osc1: OscSettings,
-    pub(super) osc2: OscSettings,
-    pub(super) env1: EnvSettings,
-    pub(super) env2: EnvSettings,
+    pub(super) auto_gain: bool,
+    // Set by the panic hotkey, neither in presets nor in settings
+    pub(super) muted: bool,
+    // 0.0 plays every note at full level
+    pub(super) velocity_sensitivity: f64,
+    // Log scale representation
+    pub(super) low_cut: f64,
+    pub(super) glide: GlideSettings,
+    pub(super) voices: VoiceSettings,
+    // Osc2 modulates Osc1, 0 turns FM off
+    pub(super) fm_amount: f64,
+    pub(super) expression: ExpressionSettings,
+    // Ids are positions, like envelopes
+    pub(super) oscs: Arc<Vec<OscSettings>>,
+    // Ids are positions, they follow removal of envelopes
+    pub(super) envs: Arc<Vec<EnvSettings>>,
+    pub(super) lfo1: LfoSettings,
+    pub(super) lfo2: LfoSettings,
+    pub(super) mod_slots: Arc<Vec<ModSlotSettings>>,
+    // Speed of chaos mod source
+    pub(super) chaos_rate: f64,
+    // BPM, shared by everything synced to tempo
+    pub(super) tempo: f64,
+    // Step modulator levels, 0.0-1.0 each
+    pub(super) step_levels: Arc<Vec<f64>>,
+    // Index in STEP_DIVISIONS
+    pub(super) step_division_idx: f64,
+    pub(super) arp: ArpSettings,
+    // Transport isn't a part of a patch, so it isn't saved in presets
+    pub(super) seq_running: bool,
+    pub(super) seq: SeqSettings,
+    pub(super) filter: FilterSettings,
 }
 
 impl SynthUIData {
-    pub fn new(synth: Arc<Mutex<Synth<i16>>>, event_sender: mpsc::Sender<SynthUIEvent>, sample_rate: f32) -> Self {
-        let mut synth_lock = synth.lock().unwrap();
-
-        // attack, decay and release are log scaler representation now
-        let default_attack_log = slider_log(DefaultParameter::EnvAttack.default_val() as f32);
-        let default_decay_log = slider_log(DefaultParameter::EnvDecay.default_val() as f32);
-        let default_release_log = slider_log(DefaultParameter::EnvRelease.default_val() as f32);
-        let env1 = EnvSettings {
-            id: 0,
-            attack: default_attack_log,
-            decay: default_decay_log,
-            sustain: DefaultParameter::EnvSustain.default_val(),
-            release: default_release_log,
-        };
-        let envelope1 = ADSR::new(
-            sample_rate,
-            DefaultParameter::EnvAttack.default_val() as u32,
-            DefaultParameter::EnvDecay.default_val() as u32,
-            DefaultParameter::EnvSustain.default_val() as f32,
-            DefaultParameter::EnvRelease.default_val() as u32);
-        synth_lock.add_env(envelope1);
+    pub fn new(
+        control: SynthControl<i16>,
+        event_sender: mpsc::Sender<SynthUIEvent>,
+        notification_sender: NotificationSender,
+        midi: MidiListener,
+        sample_rate: f32,
+        settings: &Settings,
+        first_run: bool,
+    ) -> Self {
+        // Synth is built up by commands, they are applied with the first buffer
+        let envs: Vec<EnvSettings> = (0..DEFAULT_ENVELOPES)
+            .map(|id| {
+                control.send(SynthCommand::AddEnv(default_adsr(sample_rate)));
+                EnvSettings::new(id)
+            })
+            .collect();
 
-        let env2 = EnvSettings {
-            id: 1,
-            attack: default_attack_log,
-            decay: default_decay_log,
-            sustain: DefaultParameter::EnvSustain.default_val(),
-            release: default_release_log,
-        };
-        let envelope2 = ADSR::new(
-            sample_rate,
-            DefaultParameter::EnvAttack.default_val() as u32,
-            DefaultParameter::EnvDecay.default_val() as u32,
-            DefaultParameter::EnvSustain.default_val() as f32,
-            DefaultParameter::EnvRelease.default_val() as u32);
-        synth_lock.add_env(envelope2);
+        let oscs: Vec<OscSettings> = (0..osc_constraints::MIN_OSCILLATORS)
+            .map(|id| match id {
+                0 => OscSettings { name: "Osc1".into(), tune: 15.0, unisons: 3.0, ..OscSettings::new(id) },
+                _ => OscSettings { name: "Osc2".into(), wave_idx: 1.0, volume: 0.5, transpose: -12.0, ..OscSettings::new(id) },
+            })
+            .collect();
+        for osc in oscs.iter() {
+            control.send(SynthCommand::AddOsc(osc.oscillator(sample_rate)));
+        }
+        let low_cut = DefaultParameter::LowCut.slider_default();
+        control.send(SynthCommand::LowCut(DefaultParameter::LowCut.default_val() as f32));
 
-        let osc1 = OscSettings {
+        let lfo1 = LfoSettings {
             id: 0,
-            wave_idx: 0.0,
-            volume: 0.3,
-            transpose: 0.0,
-            tune: 15.0,
-            unisons: 3.0,
-            env_idx: 0.0,
+            wave_idx: 1.0,
+            rate: DefaultParameter::LfoRate.slider_default(),
+            depth: DefaultParameter::LfoDepth.default_val(),
+            target_idx: 0.0,
         };
-        let mut oscillator1 = Oscillator::new(
+        control.send(SynthCommand::AddLfo(Lfo::new(
             sample_rate,
-            WAVEFORMS[osc1.wave_idx as usize].waveform.clone(),
-            osc1.env_idx as usize,
-            osc1.volume as f32);
-        oscillator1.set_start(Start::Soft);
-        oscillator1.tune(osc1.tune as i8);
-        oscillator1.transpose(osc1.transpose as i8);
-        oscillator1.set_unison_num(osc1.unisons as usize);
-        synth_lock.add_osc(oscillator1);
-        let osc2 = OscSettings {
+            WAVEFORMS[lfo1.wave_idx as usize].waveform.clone(),
+            DefaultParameter::LfoRate.default_val() as f32,
+            lfo1.depth as f32,
+            LFO_TARGETS[lfo1.target_idx as usize].target)));
+        let lfo2 = LfoSettings {
             id: 1,
             wave_idx: 1.0,
-            volume: 0.5,
-            transpose: -12.0,
-            tune: 0.0,
-            unisons: 1.0,
-            env_idx: 0.0,
+            rate: DefaultParameter::LfoRate.slider_default(),
+            depth: DefaultParameter::LfoDepth.default_val(),
+            target_idx: 0.0,
         };
-        let mut oscillator2 = Oscillator::new(
+        control.send(SynthCommand::AddLfo(Lfo::new(
             sample_rate,
-            WAVEFORMS[osc2.wave_idx as usize].waveform.clone(),
-            osc2.env_idx as usize,
-            osc2.volume as f32);
-        oscillator2.set_start(Start::Soft);
-        oscillator2.tune(osc2.tune as i8);
-        oscillator2.transpose(osc2.transpose as i8);
-        oscillator2.set_unison_num(osc2.unisons as usize);
-        synth_lock.add_osc(oscillator2);
+            WAVEFORMS[lfo2.wave_idx as usize].waveform.clone(),
+            DefaultParameter::LfoRate.default_val() as f32,
+            lfo2.depth as f32,
+            LFO_TARGETS[lfo2.target_idx as usize].target)));
+
+        // Synth starts with empty routes
+        let mod_slots = (0..MOD_SLOTS)
+            .map(|id| ModSlotSettings {
+                id,
+                source_idx: 0.0,
+                destination_idx: 0.0,
+                depth: DefaultParameter::ModDepth.default_val(),
+                depth_source_idx: 0.0,
+            })
+            .collect();
+
+        let filter = FilterSettings {
+            cutoff: DefaultParameter::FilterCutoff.slider_default(),
+            resonance: DefaultParameter::FilterResonance.default_val(),
+            mode_idx: 0.0,
+            env_idx: 1.0,
+            env_amount: DefaultParameter::FilterEnvAmount.default_val(),
+        };
+        control.send(SynthCommand::FilterCutoff(DefaultParameter::FilterCutoff.default_val() as f32));
+        control.send(SynthCommand::FilterResonance(DefaultParameter::FilterResonance.default_val() as f32));
+        control.send(SynthCommand::FilterMode(FILTER_MODES[filter.mode_idx as usize].mode));
+        control.send(SynthCommand::FilterEnv(filter.env_idx as usize));
+        control.send(SynthCommand::FilterEnvAmount(filter.env_amount as f32));
 
         let volume_db = -25.0;
-        synth_lock.set_volume(volume_db as i32).unwrap();
-        drop(synth_lock);
+        control.send(SynthCommand::Volume(volume_db as i32));
         Self {
-            synth,
+            control,
             event_sender,
+            notification_sender,
+            toasts: Arc::new(Vec::new()),
+            midi: Arc::new(Mutex::new(midi)),
+            midi_port: 0.0,
+            presets: Arc::new(preset_names()),
+            preset_idx: None,
+            recording: false,
+            recording_writer: None,
+            session_recordings: Arc::new(Vec::new()),
+            sample_rate_idx: SAMPLE_RATES
+                .iter()
+                .position(|rate| *rate as f32 == sample_rate)
+                .unwrap_or(0) as f64,
+            high_quality: settings.high_quality,
+            dc_blocker: settings.dc_blocker,
+            binaural: settings.binaural,
+            test_tone: false,
+            test_tone_idx: DEFAULT_TEST_TONE_IDX,
+            test_tone_level: test_tone_constraints::DEFAULT_LEVEL as f64,
+            key_velocity: settings.key_velocity as f64,
+            bend_range: settings.bend_range as f64,
+            midi_map: Arc::new(settings.midi_map.clone()),
+            midi_learn: None,
+            audit: Arc::new(Mutex::new(AuditLog::new(settings.audit_log))),
+            language_idx: current_language() as f64,
+            theme_idx: current_theme() as f64,
+            first_run,
+            tip_idx: None,
             octave_modifier: 2.0,
+            held_keys: Arc::new(vec![false; NOTE_KEYS.len()]),
             volume_db,
-            osc1,
-            osc2,
-            env1,
-            env2,
+            auto_gain: false,
+            muted: false,
+            velocity_sensitivity: DefaultParameter::VelocitySensitivity.default_val(),
+            low_cut,
+            glide: GlideSettings::default(),
+            voices: VoiceSettings::default(),
+            fm_amount: DefaultParameter::FmAmount.default_val(),
+            expression: ExpressionSettings::default(),
+            oscs: Arc::new(oscs),
+            envs: Arc::new(envs),
+            lfo1,
+            lfo2,
+            mod_slots: Arc::new(mod_slots),
+            chaos_rate: DefaultParameter::ChaosRate.default_val(),
+            tempo: DefaultParameter::Tempo.default_val(),
+            step_levels: Arc::new(vec![1.0; STEPS]),
+            step_division_idx: DEFAULT_STEP_DIVISION_IDX,
+            arp: ArpSettings::default(),
+            seq_running: false,
+            seq: SeqSettings::default(),
+            filter,
+        }
+    }
+
+    // 0.0-1.0, for computer keyboard and on-screen piano notes
+    pub(super) fn fixed_velocity(&self) -> f32 {
+        self.key_velocity.round() as f32 / osc_constraints::MAX_VELOCITY as f32
+    }
+
+    // Octave of the lowest computer keyboard C, 3 for C3
+    pub(super) fn octave(&self) -> i32 {
+        BASE_OCTAVE + self.octave_modifier.log2().round() as i32
+    }
+
+    pub(super) fn shift_octave(&mut self, shift: i32) {
+        let octave = (self.octave() + shift).max(MIN_OCTAVE).min(MAX_OCTAVE);
+        self.octave_modifier = 2f32.powi(octave - BASE_OCTAVE);
+    }
+
+    pub(super) fn osc(&self, idx: usize) -> &OscSettings {
+        &self.oscs[idx]
+    }
+
+    // Auto gain trim of the current oscillators, 1.0 while it's off
+    pub(super) fn gain_trim(&self) -> f32 {
+        if !self.auto_gain {
+            return 1.0
+        }
+        let sample_rate = SAMPLE_RATES[self.sample_rate_idx.round() as usize] as f32;
+        let oscillators: Vec<Oscillator> = self.oscs.iter().map(|osc| osc.oscillator(sample_rate)).collect();
+        auto_gain_trim(&oscillators, sample_rate)
+    }
+
+    // Presets saved before renaming have no names
+    pub(super) fn osc_name(&self, idx: usize) -> String {
+        let name = &self.osc(idx).name;
+        if name.is_empty() {
+            format!("Osc{}", idx + 1)
+        } else {
+            name.clone()
+        }
+    }
+
+    pub(super) fn env_name(&self, idx: usize) -> String {
+        match self.envs.get(idx) {
+            Some(env) if !env.name.is_empty() => env.name.clone(),
+            _ => format!("Env{}", idx + 1),
+        }
+    }
+
+    pub(super) fn add_osc(&mut self) {
+        if self.oscs.len() >= osc_constraints::MAX_OSCILLATORS {
+            return
+        }
+        let sample_rate = SAMPLE_RATES[self.sample_rate_idx as usize] as f32;
+        let osc = OscSettings::new(self.oscs.len());
+        self.control.send(SynthCommand::AddOsc(osc.oscillator(sample_rate)));
+        Arc::make_mut(&mut self.oscs).push(osc);
+    }
+
+    // Later oscillators move up, their ids follow
+    pub(super) fn remove_osc(&mut self, osc_idx: usize) {
+        if osc_idx >= self.oscs.len() || self.oscs.len() <= osc_constraints::MIN_OSCILLATORS {
+            return
+        }
+        self.control.send(SynthCommand::RemoveOsc(osc_idx));
+        let oscs = Arc::make_mut(&mut self.oscs);
+        oscs.remove(osc_idx);
+        for (id, osc) in oscs.iter_mut().enumerate() {
+            osc.id = id;
+        }
+        // Targets and FM are moved the same way synth moves them
+        let moved_target = |idx: f64| {
+            let target = LFO_TARGETS[idx.round() as usize].target.after_osc_removal(osc_idx);
+            LFO_TARGETS.iter().position(|entry| entry.target == target).unwrap_or(0) as f64
+        };
+        self.lfo1.target_idx = moved_target(self.lfo1.target_idx);
+        self.lfo2.target_idx = moved_target(self.lfo2.target_idx);
+        for slot in Arc::make_mut(&mut self.mod_slots).iter_mut() {
+            let destination = MOD_DESTINATIONS[slot.destination_idx.round() as usize].destination.after_osc_removal(osc_idx);
+            slot.destination_idx = MOD_DESTINATIONS.iter().position(|entry| entry.destination == destination).unwrap_or(0) as f64;
+        }
+        if in_fm_pair(osc_idx) {
+            self.fm_amount = 0.0;
+        }
+    }
+
+    // Synth gets the envelope right away, its settings are the defaults of both
+    pub(super) fn add_env(&mut self) {
+        if self.envs.len() >= adsr_constraints::MAX_ENVELOPES {
+            return
+        }
+        let sample_rate = SAMPLE_RATES[self.sample_rate_idx as usize] as f32;
+        self.control.send(SynthCommand::AddEnv(default_adsr(sample_rate)));
+        Arc::make_mut(&mut self.envs).push(EnvSettings::new(self.envs.len()));
+    }
+
+    // Preview is rendered here, the audio thread only plays it
+    pub(super) fn preview_env(&self, env_idx: usize) {
+        if let Some(env) = self.envs.get(env_idx) {
+            let sample_rate = SAMPLE_RATES[self.sample_rate_idx.round() as usize] as f32;
+            let preview = render_env_preview(&env.adsr(sample_rate), sample_rate);
+            self.control.play(SynthCommand::PreviewEnv(preview));
+        }
+    }
+
+    // Oscillators and the filter are moved the same way synth moves them
+    pub(super) fn remove_env(&mut self, env_idx: usize) {
+        if env_idx >= self.envs.len() || self.envs.len() <= adsr_constraints::MIN_ENVELOPES {
+            return
+        }
+        self.control.send(SynthCommand::RemoveEnv(env_idx));
+        let envs = Arc::make_mut(&mut self.envs);
+        envs.remove(env_idx);
+        for (id, env) in envs.iter_mut().enumerate() {
+            env.id = id;
+        }
+        let moved = |idx: f64| env_idx_after_removal(idx.round() as usize, env_idx) as f64;
+        for osc in Arc::make_mut(&mut self.oscs).iter_mut() {
+            osc.env_idx = moved(osc.env_idx);
         }
+        self.filter.env_idx = moved(self.filter.env_idx);
     }
 }
\ No newline at end of file
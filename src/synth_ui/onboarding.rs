@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use crate::params::slider_log;
+use super::model::SynthUIData;
+
+pub const TIPS: [&str; 4] = [
+    "Play with the bottom letter row: Z is C, S is C#, X is D and so on up to M. Q row plays the octave above. Press F1 any time to see the whole layout.",
+    "Left and right arrow keys move the keyboard an octave down or up.",
+    "Ctrl+click on a slider resets it to default. Value labels next to sliders can be dragged for finer steps.",
+    "This demo patch is just a starting point. Tweak it and save your own sounds with Ctrl+S.",
+];
+
+// Soft detuned pad, so the first key press already sounds nice
+pub fn load_demo_patch(data: &mut SynthUIData) {
+    let oscs = Arc::make_mut(&mut data.oscs);
+    oscs[0].wave_idx = 0.0; // Saw
+    oscs[0].volume = 0.35;
+    oscs[0].unisons = 5.0;
+    oscs[0].tune = 20.0;
+    oscs[0].transpose = 0.0;
+    oscs[1].wave_idx = 1.0; // Sine
+    oscs[1].volume = 0.4;
+    oscs[1].transpose = -12.0;
+    let env1 = &mut Arc::make_mut(&mut data.envs)[0];
+    env1.attack = slider_log(30.0);
+    env1.decay = slider_log(600.0);
+    env1.sustain = 0.6;
+    env1.release = slider_log(800.0);
+    data.filter.cutoff = slider_log(1800.0);
+    data.filter.resonance = 0.25;
+    data.filter.env_idx = 0.0;
+    data.filter.env_amount = 0.35;
+    data.lfo1.wave_idx = 1.0; // Sine
+    data.lfo1.rate = slider_log(0.3);
+    data.lfo1.depth = 0.15;
+    data.lfo1.target_idx = 5.0; // Filter cutoff
+}
+
+pub fn next_tip(data: &mut SynthUIData) {
+    data.tip_idx = match data.tip_idx {
+        Some(idx) if idx + 1 < TIPS.len() => Some(idx + 1),
+        _ => None,
+    };
+}
@@ -0,0 +1,414 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+
+use druid::{commands, Application, Command, FileDialogOptions, FileSpec};
+use serde::{Deserialize, Serialize};
+
+use crate::control::SynthControl;
+use crate::crash;
+use crate::error::{BaseError, Context, Result};
+use crate::midi::MidiListener;
+use crate::settings::Settings;
+use crate::notification::{notify, report};
+use crate::params::{WAVEFORMS, SUB_WAVEFORMS, OSC_ENGINES, PHASE_STARTS, ARP_MODES, STEP_DIVISIONS, DEFAULT_STEP_DIVISION_IDX, FILTER_MODES, GLIDE_CURVES, VOICE_STEALING, EXPRESSION_DESTINATIONS, EXPRESSION_CURVES, LFO_TARGETS, MOD_SOURCES, MOD_DESTINATIONS, DefaultParameter};
+use crate::synth::{adsr_constraints, osc_constraints, MOD_SLOTS, STEPS};
+use super::layout::ACCENT_COLORS;
+use super::i18n::tr;
+use super::widgets::SynthUI;
+use super::audit::ChangeSource;
+use super::model::{SynthUIData, OscSettings, EnvSettings, GlideSettings, VoiceSettings, ExpressionSettings, ArpSettings, SeqSettings, LfoSettings, ModSlotSettings, FilterSettings};
+
+const PRESET_FILE: FileSpec = FileSpec::new("beep-boop preset", &["json"]);
+// Presets from this directory are listed in preset browser
+const PRESETS_DIR: &str = "presets";
+const PRESET_EXTENSION: &str = "json";
+
+// Delegate gets SAVE_FILE_AS or OPEN_FILE command with chosen path
+pub fn save_preset_dialog() -> Command {
+    // Dialog starts in presets directory, so saved presets show up in browser
+    if let Err(e) = fs::create_dir_all(PRESETS_DIR) {
+        eprintln!("Can't create presets directory: {}", e);
+    }
+    let options = FileDialogOptions::new()
+        .allowed_types(vec![PRESET_FILE])
+        .default_type(PRESET_FILE)
+        .default_name("patch.json")
+        .force_starting_directory(PRESETS_DIR);
+    commands::SHOW_SAVE_PANEL.with(options)
+}
+
+pub fn load_preset_dialog() -> Command {
+    let options = FileDialogOptions::new()
+        .allowed_types(vec![PRESET_FILE])
+        .default_type(PRESET_FILE);
+    commands::SHOW_OPEN_PANEL.with(options)
+}
+
+// Everything that makes a patch. Values are stored the same way UI keeps them,
+// so applying a preset to SynthUIData is enough to update the synth
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Preset {
+    volume_db: f64,
+    auto_gain: bool,
+    // Older presets play every note at full level
+    #[serde(default)]
+    velocity_sensitivity: f64,
+    low_cut: f64,
+    #[serde(default)]
+    glide: GlideSettings,
+    #[serde(default)]
+    voices: VoiceSettings,
+    #[serde(default)]
+    fm_amount: f64,
+    #[serde(default)]
+    expression: ExpressionSettings,
+    // Older presets have two oscillators of their own, they are moved to oscs on load
+    #[serde(default, skip_serializing)]
+    osc1: Option<OscSettings>,
+    #[serde(default, skip_serializing)]
+    osc2: Option<OscSettings>,
+    #[serde(default)]
+    oscs: Vec<OscSettings>,
+    // Older presets have two envelopes of their own, they are moved to envs on load
+    #[serde(default, skip_serializing)]
+    env1: Option<EnvSettings>,
+    #[serde(default, skip_serializing)]
+    env2: Option<EnvSettings>,
+    #[serde(default)]
+    envs: Vec<EnvSettings>,
+    lfo1: LfoSettings,
+    lfo2: LfoSettings,
+    mod_slots: Vec<ModSlotSettings>,
+    #[serde(default = "chaos_rate_default")]
+    chaos_rate: f64,
+    #[serde(default = "tempo_default")]
+    tempo: f64,
+    #[serde(default = "step_levels_default")]
+    step_levels: Vec<f64>,
+    #[serde(default = "step_division_default")]
+    step_division_idx: f64,
+    #[serde(default)]
+    arp: ArpSettings,
+    #[serde(default)]
+    seq: SeqSettings,
+    filter: FilterSettings,
+}
+
+fn chaos_rate_default() -> f64 {
+    DefaultParameter::ChaosRate.default_val()
+}
+
+fn tempo_default() -> f64 {
+    DefaultParameter::Tempo.default_val()
+}
+
+fn step_levels_default() -> Vec<f64> {
+    vec![1.0; STEPS]
+}
+
+fn step_division_default() -> f64 {
+    DEFAULT_STEP_DIVISION_IDX
+}
+
+fn preset_path(name: &str) -> PathBuf {
+    Path::new(PRESETS_DIR).join(name).with_extension(PRESET_EXTENSION)
+}
+
+// Sorted names of presets in presets directory
+pub fn preset_names() -> Vec<String> {
+    let entries = match fs::read_dir(PRESETS_DIR) {
+        Ok(entries) => entries,
+        // No presets saved yet
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map(|ext| ext == PRESET_EXTENSION).unwrap_or(false))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+// Loads preset from browser list, previous patch stays if it can't be loaded
+pub fn select_preset(data: &mut SynthUIData, idx: usize) {
+    let name = match data.presets.get(idx) {
+        Some(name) => name.clone(),
+        None => return,
+    };
+    match Preset::load(&preset_path(&name)).context(format!("Can't load preset {}", name)) {
+        Ok(preset) => {
+            preset.apply(data);
+            data.preset_idx = Some(idx);
+        }
+        Err(e) => report(&data.notification_sender, e),
+    }
+}
+
+// Steps through browser list with wraparound
+pub fn step_preset(data: &mut SynthUIData, forward: bool) {
+    let len = data.presets.len();
+    if len == 0 {
+        return;
+    }
+    let idx = match (data.preset_idx, forward) {
+        (None, true) => 0,
+        (None, false) => len - 1,
+        (Some(idx), true) => (idx + 1) % len,
+        (Some(idx), false) => (idx + len - 1) % len,
+    };
+    select_preset(data, idx);
+}
+
+// Builds the default patch and changes it to a preset file without any window,
+// commands are sent the same way UI sends them
+pub fn send_preset(control: SynthControl<i16>, path: &Path, sample_rate: f32) -> Result<()> {
+    let preset = Preset::load(path).context(format!("Can't load preset {}", path.display()))?;
+    let settings = Settings::load();
+    let (event_sender, _) = mpsc::channel();
+    let (notification_sender, _) = mpsc::channel();
+    let midi = MidiListener::new(control.clone());
+    let default = SynthUIData::new(
+        control, event_sender, notification_sender, midi, sample_rate, &settings, false,
+    );
+    let mut data = default.clone();
+    preset.apply(&mut data);
+    SynthUI::update_patch(&data, &default);
+    Ok(())
+}
+
+// Crash report can't reach UI data, so the patch is handed over as it changes.
+// It's formatted only when a report is written
+pub fn publish_patch(data: &SynthUIData) {
+    let preset = Preset::from_data(data);
+    crash::publish_patch(move || {
+        serde_json::to_string_pretty(&preset).unwrap_or_else(|e| format!("Can't format patch: {}", e))
+    });
+}
+
+// Compact JSON, so a patch fits into a chat message
+pub fn copy_patch(data: &SynthUIData) {
+    match serde_json::to_string(&Preset::from_data(data)).context("Can't copy patch") {
+        Ok(json) => {
+            Application::global().clipboard().put_string(json);
+            notify(&data.notification_sender, tr("Patch copied"));
+        }
+        Err(e) => report(&data.notification_sender, e),
+    }
+}
+
+// Pasted patch is checked like a loaded preset, current one stays if it's broken
+pub fn paste_patch(data: &mut SynthUIData) {
+    let json = Application::global().clipboard().get_string().unwrap_or_default();
+    match Preset::from_json(json.trim()).context("Can't paste patch") {
+        Ok(preset) => {
+            preset.apply(data);
+            data.preset_idx = None;
+            notify(&data.notification_sender, tr("Patch pasted"));
+        }
+        Err(e) => report(&data.notification_sender, e),
+    }
+}
+
+fn check_idx(idx: f64, len: usize, what: &str) -> Result<()> {
+    if idx < 0.0 || idx.round() as usize >= len {
+        return Err(BaseError::PresetError(format!("{} index {} is out of range", what, idx)));
+    }
+    Ok(())
+}
+
+// Targets of oscillators past the end would modulate nothing
+fn check_osc(osc: Option<usize>, oscs: usize, what: &str) -> Result<()> {
+    match osc {
+        Some(osc) if osc >= oscs => Err(BaseError::PresetError(format!("{} is Osc{}, preset has {} oscillators", what, osc + 1, oscs))),
+        _ => Ok(()),
+    }
+}
+
+impl Preset {
+    pub fn from_data(data: &SynthUIData) -> Self {
+        Self {
+            volume_db: data.volume_db,
+            auto_gain: data.auto_gain,
+            velocity_sensitivity: data.velocity_sensitivity,
+            low_cut: data.low_cut,
+            glide: data.glide.clone(),
+            voices: data.voices.clone(),
+            fm_amount: data.fm_amount,
+            expression: data.expression.clone(),
+            osc1: None,
+            osc2: None,
+            oscs: data.oscs.to_vec(),
+            env1: None,
+            env2: None,
+            envs: data.envs.to_vec(),
+            lfo1: data.lfo1.clone(),
+            lfo2: data.lfo2.clone(),
+            mod_slots: data.mod_slots.to_vec(),
+            chaos_rate: data.chaos_rate,
+            tempo: data.tempo,
+            step_levels: data.step_levels.to_vec(),
+            step_division_idx: data.step_division_idx,
+            arp: data.arp.clone(),
+            seq: data.seq.clone(),
+            filter: data.filter.clone(),
+        }
+    }
+
+    // SynthUI::update picks up the changes and passes them to the synth.
+    // Ids are kept from data, they point to synth internals
+    pub fn apply(self, data: &mut SynthUIData) {
+        data.audit.lock().unwrap().set_source(ChangeSource::Preset);
+        data.volume_db = self.volume_db;
+        data.auto_gain = self.auto_gain;
+        data.velocity_sensitivity = self.velocity_sensitivity;
+        data.low_cut = self.low_cut;
+        data.glide = self.glide;
+        data.voices = self.voices;
+        data.fm_amount = self.fm_amount;
+        data.expression = self.expression;
+        // Synth gets added and removed envelopes right away, before oscillators point to them
+        while data.envs.len() < self.envs.len() {
+            data.add_env();
+        }
+        while data.envs.len() > self.envs.len() {
+            data.remove_env(data.envs.len() - 1);
+        }
+        data.envs = Arc::new(
+            self.envs
+                .into_iter()
+                .enumerate()
+                .map(|(id, env)| EnvSettings { id, ..env })
+                .collect(),
+        );
+        while data.oscs.len() < self.oscs.len() {
+            data.add_osc();
+        }
+        while data.oscs.len() > self.oscs.len() {
+            data.remove_osc(data.oscs.len() - 1);
+        }
+        data.oscs = Arc::new(
+            self.oscs
+                .into_iter()
+                .enumerate()
+                .map(|(id, osc)| OscSettings { id, ..osc })
+                .collect(),
+        );
+        data.lfo1 = LfoSettings { id: data.lfo1.id, ..self.lfo1 };
+        data.lfo2 = LfoSettings { id: data.lfo2.id, ..self.lfo2 };
+        data.mod_slots = Arc::new(
+            self.mod_slots
+                .into_iter()
+                .enumerate()
+                .map(|(id, slot)| ModSlotSettings { id, ..slot })
+                .collect(),
+        );
+        data.chaos_rate = self.chaos_rate;
+        data.tempo = self.tempo;
+        data.step_levels = Arc::new(self.step_levels);
+        data.step_division_idx = self.step_division_idx;
+        data.arp = self.arp;
+        data.seq = self.seq;
+        data.filter = self.filter;
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn from_json(json: &str) -> Result<Self> {
+        let mut preset: Preset = serde_json::from_str(json)?;
+        if preset.oscs.is_empty() {
+            preset.oscs = preset.osc1.take().into_iter().chain(preset.osc2.take()).collect();
+        }
+        if preset.envs.is_empty() {
+            preset.envs = preset.env1.take().into_iter().chain(preset.env2.take()).collect();
+        }
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    // Indices have to point into UI tables, otherwise UI would panic
+    fn validate(&self) -> Result<()> {
+        let envs = self.envs.len();
+        if envs < adsr_constraints::MIN_ENVELOPES || envs > adsr_constraints::MAX_ENVELOPES {
+            return Err(BaseError::PresetError(format!(
+                "{} envelopes, has to be {}-{}",
+                envs,
+                adsr_constraints::MIN_ENVELOPES,
+                adsr_constraints::MAX_ENVELOPES
+            )));
+        }
+        let oscs = self.oscs.len();
+        if oscs < osc_constraints::MIN_OSCILLATORS || oscs > osc_constraints::MAX_OSCILLATORS {
+            return Err(BaseError::PresetError(format!(
+                "{} oscillators, has to be {}-{}",
+                oscs,
+                osc_constraints::MIN_OSCILLATORS,
+                osc_constraints::MAX_OSCILLATORS
+            )));
+        }
+        for osc in self.oscs.iter() {
+            check_idx(osc.wave_idx, WAVEFORMS.len(), "Waveform")?;
+            check_idx(osc.sub_wave_idx, SUB_WAVEFORMS.len(), "Sub waveform")?;
+            check_idx(osc.engine_idx, OSC_ENGINES.len(), "Oscillator engine")?;
+            check_idx(osc.start_idx, PHASE_STARTS.len(), "Phase start")?;
+            check_idx(osc.env_idx, envs, "Envelope")?;
+            check_idx(osc.color_idx, ACCENT_COLORS.len(), "Oscillator color")?;
+            if osc.harmonics.len() != HARMONICS {
+                return Err(BaseError::PresetError(format!(
+                    "{} harmonics instead of {}",
+                    osc.harmonics.len(),
+                    HARMONICS
+                )));
+            }
+        }
+        for lfo in [&self.lfo1, &self.lfo2].iter() {
+            check_idx(lfo.wave_idx, WAVEFORMS.len(), "LFO waveform")?;
+            check_idx(lfo.target_idx, LFO_TARGETS.len(), "LFO target")?;
+            check_osc(LFO_TARGETS[lfo.target_idx.round() as usize].target.osc(), oscs, "LFO target")?;
+        }
+        if self.mod_slots.len() != MOD_SLOTS {
+            return Err(BaseError::PresetError(format!(
+                "{} mod slots instead of {}",
+                self.mod_slots.len(),
+                MOD_SLOTS
+            )));
+        }
+        for slot in self.mod_slots.iter() {
+            check_idx(slot.source_idx, MOD_SOURCES.len(), "Mod source")?;
+            check_idx(slot.destination_idx, MOD_DESTINATIONS.len(), "Mod destination")?;
+            check_osc(MOD_DESTINATIONS[slot.destination_idx.round() as usize].destination.osc(), oscs, "Mod destination")?;
+            check_idx(slot.depth_source_idx, MOD_SOURCES.len(), "Mod depth source")?;
+        }
+        if self.step_levels.len() != STEPS {
+            return Err(BaseError::PresetError(format!(
+                "{} steps instead of {}",
+                self.step_levels.len(),
+                STEPS
+            )));
+        }
+        check_idx(self.step_division_idx, STEP_DIVISIONS.len(), "Step division")?;
+        check_idx(self.arp.mode_idx, ARP_MODES.len(), "Arpeggiator mode")?;
+        check_idx(self.arp.division_idx, STEP_DIVISIONS.len(), "Arpeggiator division")?;
+        check_idx(self.seq.division_idx, STEP_DIVISIONS.len(), "Sequencer division")?;
+        let seq = &self.seq;
+        if seq.pitches.len() != STEPS || seq.gates.len() != STEPS || seq.accents.len() != STEPS {
+            return Err(BaseError::PresetError(format!("Sequencer has to have {} steps", STEPS)));
+        }
+        check_idx(self.filter.mode_idx, FILTER_MODES.len(), "Filter mode")?;
+        check_idx(self.filter.env_idx, envs, "Filter envelope")?;
+        check_idx(self.glide.curve_idx, GLIDE_CURVES.len(), "Glide curve")?;
+        check_idx(self.voices.stealing_idx, VOICE_STEALING.len(), "Voice stealing")?;
+        check_idx(self.expression.destination_idx, EXPRESSION_DESTINATIONS.len(), "Expression destination")?;
+        check_idx(self.expression.curve_idx, EXPRESSION_CURVES.len(), "Expression curve")?;
+        Ok(())
+    }
+}
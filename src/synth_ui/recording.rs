@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use druid::{ExtEventSink, Selector, SingleUse, Target};
+
+use crate::control::{SynthCommand, SynthControl};
+use crate::error::{BaseError, Context, Result};
+use crate::notification::{notify, report};
+use crate::settings::instance_name;
+use crate::synth::{Recorder, RecorderMessage};
+use crate::wav::WavWriter;
+use super::model::SynthUIData;
+use super::i18n::trf;
+
+// Recordings are saved here, named by the time recording started
+const RECORDINGS_DIR: &str = "recordings";
+// Writer thread is a few chunks behind at most when recording stops
+const RECORDING_FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Path of the saved file and the number of frames lost on the way
+pub type SavedRecording = Result<(PathBuf, usize)>;
+
+pub const RECORDING_SAVED: Selector<SingleUse<SavedRecording>> = Selector::new("beep-boop.recording-saved");
+
+// Starts capturing output to WAV file or stops it. Writer thread posts
+// the result to the window once the file is finished
+pub fn toggle_recording(data: &mut SynthUIData, sink: ExtEventSink) {
+    if data.recording {
+        data.recording = false;
+        data.control.send(SynthCommand::StopRecording);
+    } else {
+        let (writer, messages) = mpsc::channel();
+        let (running, finished) = mpsc::channel::<()>();
+        let control = data.control.clone();
+        let spawned = std::thread::Builder::new()
+            .name("beep-boop-recording".into())
+            .spawn(move || {
+                let _running = running;
+                let saved = write_recording(messages, &control);
+                let _ = sink.submit_command(RECORDING_SAVED, SingleUse::new(saved), Target::Auto);
+            });
+        if let Err(e) = spawned.context("Can't start recording") {
+            report(&data.notification_sender, e);
+            return
+        }
+        data.recording_writer = Some(Arc::new(Mutex::new(finished)));
+        // Stream keeps running while recording, so pauses are captured too
+        data.control.play(SynthCommand::StartRecording(Recorder::new(writer)));
+        data.recording = true;
+    }
+}
+
+// Window is closing, recording is stopped and the file is finished before the app exits
+pub fn finish_recording(data: &mut SynthUIData) {
+    if data.recording {
+        data.recording = false;
+        data.control.send(SynthCommand::StopRecording);
+    }
+    if let Some(writer) = data.recording_writer.take() {
+        // Writer drops its end when done, nothing is ever sent
+        let finished = writer.lock().map(|finished| finished.recv_timeout(RECORDING_FINISH_TIMEOUT));
+        if let Ok(Err(mpsc::RecvTimeoutError::Timeout)) = finished {
+            report(&data.notification_sender, BaseError::ThreadError("Recording writer didn't finish".into()));
+        }
+    }
+}
+
+pub fn recording_saved(data: &mut SynthUIData, saved: SavedRecording) {
+    match saved.context("Can't save recording") {
+        Ok((path, dropped)) => {
+            notify(&data.notification_sender, trf("Recording saved to {}", &[&path.display()]));
+            if dropped > 0 {
+                notify(&data.notification_sender, trf("{} frames were lost, disk was too slow", &[&dropped]));
+            }
+            let mut recordings = data.session_recordings.to_vec();
+            recordings.push(path.to_string_lossy().into_owned());
+            data.session_recordings = Arc::new(recordings);
+        }
+        Err(e) => report(&data.notification_sender, e),
+    }
+}
+
+// Runs on the writer thread. Written chunks go back to the audio thread to be filled again
+fn write_recording(messages: mpsc::Receiver<RecorderMessage<i16>>, control: &SynthControl<i16>) -> SavedRecording {
+    let sample_rate = match messages.recv() {
+        Ok(RecorderMessage::Started(sample_rate)) => sample_rate,
+        _ => return Err(BaseError::StreamError("Recording didn't start".into())),
+    };
+    let path = recording_path()?;
+    let mut wav = WavWriter::create(&path, sample_rate as u32)?;
+    for message in messages {
+        match message {
+            RecorderMessage::Started(_) => (),
+            RecorderMessage::Chunk(chunk) => {
+                for frame in chunk.iter() {
+                    wav.write(frame)?;
+                }
+                control.send(SynthCommand::RecordingChunk(chunk));
+            }
+            RecorderMessage::Finished { last, dropped, .. } => {
+                for frame in last.iter() {
+                    wav.write(frame)?;
+                }
+                wav.finish()?;
+                return Ok((path, dropped))
+            }
+        }
+    }
+    // Recorder is dropped without finishing only with the synth
+    Err(BaseError::StreamError("Recording stopped with the output stream".into()))
+}
+
+fn recording_path() -> Result<PathBuf> {
+    fs::create_dir_all(RECORDINGS_DIR)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or(0);
+    // Instances recording at the same second don't overwrite each other
+    Ok(Path::new(RECORDINGS_DIR).join(format!("{}-{}.wav", instance_name("beep-boop"), timestamp)))
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::Path;
+
+use druid::{commands, Command, FileDialogOptions, FileInfo, FileSpec, Selector};
+
+use crate::error::{Context, Result};
+use crate::notification::{notify, report};
+use crate::zip::ZipWriter;
+use super::model::SynthUIData;
+use super::preset::Preset;
+use super::i18n::trf;
+
+const SESSION_FILE: FileSpec = FileSpec::new("beep-boop session", &["zip"]);
+
+// Save dialog answers with it instead of SAVE_FILE_AS, which saves presets
+pub const EXPORT_SESSION: Selector<FileInfo> = Selector::new("beep-boop.export-session");
+
+pub fn export_session_dialog() -> Command {
+    let options = FileDialogOptions::new()
+        .allowed_types(vec![SESSION_FILE])
+        .default_type(SESSION_FILE)
+        .default_name("session.zip")
+        .accept_command(EXPORT_SESSION);
+    commands::SHOW_SAVE_PANEL.with(options)
+}
+
+pub fn export_session(data: &SynthUIData, path: &Path) {
+    match write_session(data, path).context("Can't export session") {
+        Ok(()) => notify(&data.notification_sender, trf("Session exported to {}", &[&path.display()])),
+        Err(e) => report(&data.notification_sender, e),
+    }
+}
+
+// Current patch and everything recorded since beep-boop was started
+fn write_session(data: &SynthUIData, path: &Path) -> Result<()> {
+    let mut zip = ZipWriter::create(path)?;
+    zip.add("patch.json", serde_json::to_string_pretty(&Preset::from_data(data))?.as_bytes())?;
+    for recording in data.session_recordings.iter() {
+        let recording = Path::new(recording);
+        let wav = fs::read(recording).context(format!("Can't read recording {}", recording.display()))?;
+        let name = recording.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        zip.add(&format!("recordings/{}", name), &wav)?;
+    }
+    zip.finish()
+}
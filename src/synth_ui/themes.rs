@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use druid::{theme, Color, Env, Key};
+
+// Text sizes and colors are looked up in Env, so every widget follows the theme
+pub struct Theme {
+    pub code: &'static str,
+    pub name: &'static str,
+    // Text sizes are multiplied by it
+    text_scale: f64,
+    // Black and white with one bright accent, states are shown with marks as well as colors
+    high_contrast: bool,
+}
+
+pub const THEMES: [Theme; 2] = [
+    Theme {
+        code: "default",
+        name: "Default",
+        text_scale: 1.0,
+        high_contrast: false,
+    },
+    Theme {
+        code: "high_contrast",
+        name: "High contrast",
+        text_scale: 1.25,
+        high_contrast: true,
+    },
+];
+
+pub const TEXT_LARGE: Key<f64> = Key::new("beep-boop.text-large");
+pub const TEXT_MEDIUM: Key<f64> = Key::new("beep-boop.text-medium");
+pub const TEXT_SMALL: Key<f64> = Key::new("beep-boop.text-small");
+pub const LABEL_COLOR_MAIN: Key<Color> = Key::new("beep-boop.label-color-main");
+pub const LABEL_COLOR_SECONDARY: Key<Color> = Key::new("beep-boop.label-color-secondary");
+pub const LABEL_COLOR_WARNING: Key<Color> = Key::new("beep-boop.label-color-warning");
+pub const BORDER_COLOR: Key<Color> = Key::new("beep-boop.border-color");
+pub const BACKGROUND_COLOR: Key<Color> = Key::new("beep-boop.background-color");
+
+const HIGH_CONTRAST_ACCENT: Color = Color::rgb8(0xff, 0xeb, 0x3b);
+
+// Index in THEMES. Layout is built once, so it's set only on startup
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_theme(code: &str) {
+    match THEMES.iter().position(|theme| theme.code == code) {
+        Some(idx) => CURRENT.store(idx, Ordering::Relaxed),
+        None => eprintln!("Unknown theme {}, using default", code),
+    }
+}
+
+pub fn current_theme() -> usize {
+    CURRENT.load(Ordering::Relaxed)
+}
+
+// Widgets add shapes and labels to states that are otherwise told by color
+pub fn high_contrast() -> bool {
+    THEMES[current_theme()].high_contrast
+}
+
+pub fn configure_env(env: &mut Env) {
+    let current = &THEMES[current_theme()];
+    env.set(TEXT_LARGE, 22.0 * current.text_scale);
+    env.set(TEXT_MEDIUM, 18.0 * current.text_scale);
+    env.set(TEXT_SMALL, 14.0 * current.text_scale);
+    if !current.high_contrast {
+        env.set(LABEL_COLOR_MAIN, Color::rgba8(0xe9, 0x1e, 0x63, 0xff));
+        env.set(LABEL_COLOR_SECONDARY, Color::rgba8(0x35, 0xaa, 0xee, 0xff));
+        env.set(LABEL_COLOR_WARNING, Color::rgba8(0xff, 0xc1, 0x07, 0xff));
+        env.set(BORDER_COLOR, Color::rgba8(0x03, 0x12, 0x14, 0xff));
+        env.set(BACKGROUND_COLOR, Color::rgba8(0x29, 0x29, 0x29, 0xff));
+        return
+    }
+    env.set(LABEL_COLOR_MAIN, Color::WHITE);
+    env.set(LABEL_COLOR_SECONDARY, Color::WHITE);
+    env.set(LABEL_COLOR_WARNING, HIGH_CONTRAST_ACCENT);
+    env.set(BORDER_COLOR, Color::WHITE);
+    env.set(BACKGROUND_COLOR, Color::BLACK);
+    // Stock druid widgets: buttons, sliders, checkboxes and text boxes
+    env.set(theme::LABEL_COLOR, Color::WHITE);
+    env.set(theme::WINDOW_BACKGROUND_COLOR, Color::BLACK);
+    env.set(theme::BACKGROUND_LIGHT, Color::BLACK);
+    env.set(theme::BACKGROUND_DARK, Color::BLACK);
+    env.set(theme::BUTTON_LIGHT, Color::BLACK);
+    env.set(theme::BUTTON_DARK, Color::BLACK);
+    env.set(theme::BORDER_LIGHT, Color::WHITE);
+    env.set(theme::BORDER_DARK, Color::WHITE);
+    env.set(theme::FOREGROUND_LIGHT, Color::WHITE);
+    env.set(theme::FOREGROUND_DARK, Color::WHITE);
+    env.set(theme::PRIMARY_LIGHT, HIGH_CONTRAST_ACCENT);
+    env.set(theme::PRIMARY_DARK, HIGH_CONTRAST_ACCENT);
+    env.set(theme::SELECTION_COLOR, HIGH_CONTRAST_ACCENT);
+    env.set(theme::CURSOR_COLOR, Color::WHITE);
+    env.set(theme::TEXT_SIZE_NORMAL, 15.0 * current.text_scale);
+    env.set(theme::TEXT_SIZE_LARGE, 18.0 * current.text_scale);
+}
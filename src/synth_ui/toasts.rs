@@ -0,0 +1,59 @@
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use druid::{Data, ExtEventSink, Selector, Target};
+
+use crate::notification::Notification;
+
+pub const SHOW_TOAST: Selector<Toast> = Selector::new("beep-boop.show-toast");
+// Toast id, sent when toast times out or is clicked
+pub const HIDE_TOAST: Selector<u64> = Selector::new("beep-boop.hide-toast");
+// Oldest toasts are hidden when there are more
+pub const MAX_TOASTS: usize = 4;
+const INFO_DURATION: Duration = Duration::from_secs(3);
+// Errors stay longer, so there is time to read them
+const ERROR_DURATION: Duration = Duration::from_secs(8);
+
+#[derive(Clone, Data)]
+pub struct Toast {
+    pub(super) id: u64,
+    pub(super) error: bool,
+    pub(super) message: String,
+}
+
+// Runs on its own thread: shows every notification and hides it after a while.
+// Notifications sent before the window shows up wait in the channel
+pub fn show_notifications(receiver: mpsc::Receiver<Notification>, sink: ExtEventSink) {
+    let mut next_id = 0;
+    // Shown toasts with time to hide them at
+    let mut shown: Vec<(u64, Instant)> = Vec::new();
+    loop {
+        let received = match shown.iter().map(|(_, hide_at)| *hide_at).min() {
+            Some(hide_at) => receiver.recv_timeout(hide_at.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        let submitted = match received {
+            Ok(notification) => {
+                let (error, message) = match notification {
+                    Notification::Info(message) => (false, message),
+                    Notification::Error(message) => (true, message),
+                };
+                let duration = if error { ERROR_DURATION } else { INFO_DURATION };
+                let toast = Toast { id: next_id, error, message };
+                shown.push((next_id, Instant::now() + duration));
+                next_id += 1;
+                sink.submit_command(SHOW_TOAST, toast, Target::Auto)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let idx = (0..shown.len()).min_by_key(|idx| shown[*idx].1).unwrap();
+                let (id, _) = shown.remove(idx);
+                sink.submit_command(HIDE_TOAST, id, Target::Auto)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        // Window is closed
+        if submitted.is_err() {
+            break
+        }
+    }
+}
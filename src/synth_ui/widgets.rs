@@ -1,84 +1,110 @@
-use std::sync::MutexGuard;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use druid::widget::prelude::*;
-use druid::widget::{Flex, Slider, CrossAxisAlignment};
+use druid::widget::{Flex, Slider, Label, CrossAxisAlignment};
+use druid::{lens, Cursor, KeyOrValue, Lens, LensExt, Point, Rect, Selector, Target, WidgetPod};
+use druid::kurbo::{Circle, Line};
 use druid::Code as KeyCode;
 use druid::KeyEvent;
 use super::{
-    model::{SynthUIData, SynthUIEvent, OscSettings, EnvSettings},
-    layout::{slider_log, LOG_SCALE_BASE},
-    constants::{WAVEFORMS, DefaultParameter},
+    model::{SynthUIData, SynthUIEvent, OscSettings, EnvSettings, FilterSettings, GlideSettings, VoiceSettings, ExpressionSettings, ArpSettings, SeqSettings, LfoSettings, ModSlotSettings},
+    themes::{high_contrast, THEMES, BORDER_COLOR, LABEL_COLOR_WARNING},
+    preset::{save_preset_dialog, load_preset_dialog, select_preset, copy_patch, paste_patch, publish_patch},
+    recording::toggle_recording,
+    midi_learn::{MidiLearn, SET_MIDI_VALUE},
+    audit::ChangeSource,
+    onboarding::load_demo_patch,
+    i18n::{trf, LANGUAGES},
+    keymap::{get_note, get_action, Action, NOTE_KEYS, OCTAVE_DOWN_KEY, OCTAVE_UP_KEY},
 };
-use crate::synth::{Synth, WaveForm, ADSRParam};
+use crate::settings::save_setting;
+use crate::error::{BaseError, Context};
+use crate::notification::{notify, report};
+use crate::params::{seq_pitch, taper_position, taper_value, SAMPLE_RATES, TEST_TONE_FREQUENCIES, LOG_SCALE_BASE, WAVEFORMS, SUB_WAVEFORMS, OSC_ENGINES, PHASE_STARTS, ARP_MODES, STEP_DIVISIONS, FILTER_MODES, GLIDE_CURVES, VOICE_STEALING, EXPRESSION_DESTINATIONS, EXPRESSION_CURVES, LFO_TARGETS, MOD_SOURCES, MOD_DESTINATIONS, DefaultParameter};
+use crate::control::{SynthCommand, SynthControl};
+use crate::synth::{pluck_strings, NoteSource, OscEngine};
 
 
-fn round_float(f: f32, accuracy: i32) -> f32 {
-    let base = 10f32.powi(accuracy);
-    (f * base).round() / base
-}
-
-fn get_note(key: &KeyCode) -> Option<f32> {
-    let freq = match key {
-        KeyCode::KeyZ => 130.81, // C
-        KeyCode::KeyS => 138.59, // C#
-        KeyCode::KeyX => 146.83, // D
-        KeyCode::KeyD => 155.56, // D#
-        KeyCode::KeyC => 164.81, // E
-        KeyCode::KeyV => 174.61, // F
-        KeyCode::KeyG => 185.00, // F#
-        KeyCode::KeyB => 196.00, // G
-        KeyCode::KeyH => 207.65, // G#
-        KeyCode::KeyN => 220.00, // AeE
-        KeyCode::KeyJ => 233.08, // A#
-        KeyCode::KeyM => 246.94, // B
-        _ => return None,
-    };
-    Some(freq)
-}
+const HELP_SHADE_COLOR: druid::Color = druid::Color::rgba8(0x00, 0x00, 0x00, 0xb0);
+// Space between overlay panels and edges of the window
+const OVERLAY_MARGIN: f64 = 20.0;
 
-#[derive(Clone)]
-pub struct WaveFormUI {
-    pub name: &'static str,
-    pub waveform: WaveForm,
+// On-screen piano shows keys held on computer keyboard
+fn set_key_held(data: &mut SynthUIData, key: &KeyCode, held: bool) {
+    if let Some(idx) = NOTE_KEYS.iter().position(|note_key| note_key.key == *key) {
+        if data.held_keys[idx] != held {
+            Arc::make_mut(&mut data.held_keys)[idx] = held;
+        }
+    }
 }
 
 pub struct SynthUI {
     pub root: Flex<SynthUIData>,
+    // Drawn over the root while F1 help is shown
+    help: WidgetPod<SynthUIData, Box<dyn Widget<SynthUIData>>>,
+    show_help: bool,
+    // Onboarding tips at the bottom, shown while there is a tip
+    tips: WidgetPod<SynthUIData, Box<dyn Widget<SynthUIData>>>,
+    // Notifications at the top, each one hides by itself
+    toasts: WidgetPod<SynthUIData, Box<dyn Widget<SynthUIData>>>,
+    // Recent patch changes in the top right corner, toggled with F12.
+    // Unlike help it doesn't cover the controls, so it can be watched while playing
+    log: WidgetPod<SynthUIData, Box<dyn Widget<SynthUIData>>>,
+    show_log: bool,
 }
 
 impl SynthUI {
-    pub fn new() -> Self {
-        Self { root: Flex::row().cross_axis_alignment(CrossAxisAlignment::Start) }
+    pub fn new(
+        help: impl Widget<SynthUIData> + 'static,
+        tips: impl Widget<SynthUIData> + 'static,
+        toasts: impl Widget<SynthUIData> + 'static,
+        log: impl Widget<SynthUIData> + 'static,
+    ) -> Self {
+        Self {
+            root: Flex::column().cross_axis_alignment(CrossAxisAlignment::Center),
+            help: WidgetPod::new(Box::new(help)),
+            show_help: false,
+            tips: WidgetPod::new(Box::new(tips)),
+            toasts: WidgetPod::new(Box::new(toasts)),
+            log: WidgetPod::new(Box::new(log)),
+            show_log: false,
+        }
     }
 
     fn handle_key_press(&self, key: &KeyCode, data: &mut SynthUIData) {
-        match key {
-            KeyCode::ArrowLeft => {
-                let modified = round_float(data.octave_modifier / 2.0, 3);
-                if modified <= 1.0 / 4.0 {
-                    // println!("Lowest octave is active")
-                } else {
-                    data.octave_modifier = modified
-                }
+        if *key == OCTAVE_DOWN_KEY {
+            data.shift_octave(-1)
+        } else if *key == OCTAVE_UP_KEY {
+            data.shift_octave(1)
+        } else if let Some(freq) = get_note(key) {
+            // Computer keyboard has no velocity, notes play at the set one
+            data.control.note_on(freq * data.octave_modifier, data.fixed_velocity(), NoteSource::Key(*key));
+            set_key_held(data, key, true);
+        }
+    }
+
+    fn handle_action(&mut self, ctx: &mut EventCtx, action: Action, data: &mut SynthUIData) {
+        match action {
+            Action::SavePreset => ctx.submit_command(save_preset_dialog()),
+            Action::LoadPreset => ctx.submit_command(load_preset_dialog()),
+            Action::CopyPatch => copy_patch(data),
+            Action::PastePatch => paste_patch(data),
+            Action::Record => toggle_recording(data, ctx.get_external_handle()),
+            Action::Help => {
+                self.show_help = !self.show_help;
+                ctx.request_paint();
             }
-            KeyCode::ArrowRight => {
-                let modified = round_float(data.octave_modifier * 2.0, 3);
-                if modified >= 16.0 {
-                    // println!("Highest octave is active")
-                } else {
-                    data.octave_modifier = modified
-                }
+            Action::ChangeLog => {
+                self.show_log = !self.show_log;
+                ctx.request_paint();
             }
-            KeyCode::ArrowUp => {}
-            KeyCode::ArrowDown => {}
-            KeyCode::KeyU => {}
-            _ => match get_note(key) {
-                None => {} // println!("Key {:?} is not supported", key),
-                Some(freq) => {
-                    let mut synth = data.synth.lock().unwrap();
-                    if !synth.playing() {
-                        data.event_sender.send(SynthUIEvent::NewNotes).unwrap();
-                    }
-                    synth.note_on(freq * data.octave_modifier, *key)
+            Action::Close => {
+                if self.show_help {
+                    self.show_help = false;
+                    ctx.request_paint();
+                } else {
+                    ctx.window().close()
                 }
             }
         }
@@ -86,62 +112,443 @@ impl SynthUI {
 
     fn handle_key_release(&self, key: &KeyCode, data: &mut SynthUIData) {
         if let Some(_) = get_note(key) {
-            data.synth.lock().unwrap().note_off(*key);
+            data.control.note_off(NoteSource::Key(*key));
+            set_key_held(data, key, false);
+        }
+    }
+
+    // Everything presets keep, also used to send a preset without a window.
+    // Returns true when any of it has changed
+    pub(super) fn update_patch(new: &SynthUIData, old: &SynthUIData) -> bool {
+        let mut changed = false;
+        // Added oscillators and envelopes are sent by SynthUIData with the defaults,
+        // presets change them right after
+        if !new.oscs.same(&old.oscs) {
+            changed = true;
+            for new_osc in new.oscs.iter() {
+                let old_osc = old.oscs.get(new_osc.id).cloned().unwrap_or_else(|| OscSettings::new(new_osc.id));
+                if !new_osc.same(&old_osc) {
+                    Self::update_osc(&new.control, new_osc, &old_osc);
+                }
+            }
+        }
+        // Pluck engine plays delay lines sized for the sample rate, they are built here
+        if !new.oscs.same(&old.oscs) || new.sample_rate_idx != old.sample_rate_idx {
+            let sample_rate = SAMPLE_RATES[new.sample_rate_idx.round() as usize] as f32;
+            for new_osc in new.oscs.iter() {
+                let engine = OSC_ENGINES[new_osc.engine_idx.round() as usize].engine;
+                let picked = old.oscs.get(new_osc.id).map_or(true, |old_osc| old_osc.engine_idx != new_osc.engine_idx);
+                if engine == OscEngine::Pluck && (picked || new.sample_rate_idx != old.sample_rate_idx) {
+                    new.control.send(SynthCommand::OscPluckStrings(new_osc.id, pluck_strings(engine, sample_rate)));
+                }
+            }
+        }
+        if new.volume_db != old.volume_db {
+            changed = true;
+            new.control.send(SynthCommand::Volume(new.volume_db.round() as i32));
+        }
+        // Loudness is measured here, rendering it would hold up the audio thread
+        if new.auto_gain != old.auto_gain
+            || (new.auto_gain && (!new.oscs.same(&old.oscs) || new.sample_rate_idx != old.sample_rate_idx))
+        {
+            changed |= new.auto_gain != old.auto_gain;
+            new.control.send(SynthCommand::GainTrim(new.gain_trim()));
         }
+        if new.velocity_sensitivity != old.velocity_sensitivity {
+            changed = true;
+            new.control.send(SynthCommand::VelocitySensitivity(new.velocity_sensitivity as f32));
+        }
+        if new.low_cut != old.low_cut {
+            changed = true;
+            new.control.send(SynthCommand::LowCut(LOG_SCALE_BASE.powf(new.low_cut) as f32));
+        }
+        if new.fm_amount != old.fm_amount {
+            changed = true;
+            new.control.send(SynthCommand::FmAmount(new.fm_amount as f32));
+        }
+        if !new.glide.same(&old.glide) {
+            changed = true;
+            Self::update_glide(&new.control, &new.glide, &old.glide);
+        }
+        if !new.voices.same(&old.voices) {
+            changed = true;
+            Self::update_voices(&new.control, &new.voices, &old.voices);
+        }
+        if !new.expression.same(&old.expression) {
+            changed = true;
+            Self::update_expression(&new.control, &new.expression, &old.expression);
+        }
+        if !new.envs.same(&old.envs) {
+            changed = true;
+            for new_env in new.envs.iter() {
+                let old_env = old.envs.get(new_env.id).cloned().unwrap_or_else(|| EnvSettings::new(new_env.id));
+                if !new_env.same(&old_env) {
+                    Self::update_env(&new.control, new_env, &old_env);
+                }
+            }
+        }
+        if !new.lfo1.same(&old.lfo1) {
+            changed = true;
+            Self::update_lfo(&new.control, &new.lfo1, &old.lfo1);
+        }
+        if !new.lfo2.same(&old.lfo2) {
+            changed = true;
+            Self::update_lfo(&new.control, &new.lfo2, &old.lfo2);
+        }
+        if !new.mod_slots.same(&old.mod_slots) {
+            changed = true;
+            for (new_slot, old_slot) in new.mod_slots.iter().zip(old.mod_slots.iter()) {
+                if !new_slot.same(old_slot) {
+                    Self::update_mod_slot(&new.control, new_slot, old_slot);
+                }
+            }
+        }
+        if new.chaos_rate != old.chaos_rate {
+            changed = true;
+            new.control.send(SynthCommand::ChaosRate(new.chaos_rate as f32));
+        }
+        if new.tempo != old.tempo {
+            changed = true;
+            new.control.send(SynthCommand::Tempo(new.tempo.round() as f32));
+        }
+        if !new.step_levels.same(&old.step_levels) {
+            changed = true;
+            for (step, (new_level, old_level)) in new.step_levels.iter().zip(old.step_levels.iter()).enumerate() {
+                if new_level != old_level {
+                    new.control.send(SynthCommand::StepLevel(step, *new_level as f32));
+                }
+            }
+        }
+        if new.step_division_idx != old.step_division_idx {
+            changed = true;
+            new.control.send(SynthCommand::StepDivision(
+                STEP_DIVISIONS[new.step_division_idx.round() as usize].division,
+            ));
+        }
+        if !new.arp.same(&old.arp) {
+            changed = true;
+            Self::update_arp(&new.control, &new.arp, &old.arp);
+        }
+        if !new.seq.same(&old.seq) {
+            changed = true;
+            Self::update_seq(&new.control, &new.seq, &old.seq);
+        }
+        if !new.filter.same(&old.filter) {
+            changed = true;
+            Self::update_filter(&new.control, &new.filter, &old.filter);
+        }
+        changed
     }
 
-    fn update_osc(&self, synth: &mut MutexGuard<Synth<i16>>, new: &OscSettings, old: &OscSettings) {
+    fn update_osc(control: &SynthControl<i16>, new: &OscSettings, old: &OscSettings) {
         if new.volume != old.volume {
-            synth.set_osc_volume(new.id, new.volume as f32);
+            control.send(SynthCommand::OscVolume(new.id, new.volume as f32));
+        }
+        if new.pan != old.pan {
+            control.send(SynthCommand::Pan(new.id, new.pan as f32));
         }
         if new.wave_idx != old.wave_idx {
-            synth.set_waveform(new.id, &WAVEFORMS[new.wave_idx as usize].waveform);
+            control.send(SynthCommand::Waveform(new.id, WAVEFORMS[new.wave_idx as usize].waveform.clone(), new.wave()));
         }
+        // Slider values are in allowed range
         if new.transpose != old.transpose {
-            synth.set_transpose(new.id, new.transpose as i8);
+            control.send(SynthCommand::Transpose(new.id, new.transpose as i8));
         }
         if new.tune != old.tune {
-            synth.set_tune(new.id, new.tune as i8);
+            control.send(SynthCommand::Tune(new.id, new.tune as i8));
+        }
+        if new.phase_offset != old.phase_offset {
+            control.send(SynthCommand::OscPhaseOffset(new.id, new.phase_offset as f32));
+        }
+        if new.start_idx != old.start_idx {
+            control.send(SynthCommand::OscStart(new.id, PHASE_STARTS[new.start_idx.round() as usize].start));
         }
         if new.unisons != old.unisons {
-            synth.set_unisons(new.id, new.unisons.round() as usize);
+            control.send(SynthCommand::Unisons(new.id, new.unisons.round() as usize));
+        }
+        if new.supersaw != old.supersaw {
+            control.send(SynthCommand::Supersaw(new.id, new.supersaw));
+        }
+        if new.supersaw_detune != old.supersaw_detune {
+            control.send(SynthCommand::SupersawDetune(new.id, new.supersaw_detune as f32));
+        }
+        if new.supersaw_mix != old.supersaw_mix {
+            control.send(SynthCommand::SupersawMix(new.id, new.supersaw_mix as f32));
+        }
+        if new.unison_width != old.unison_width {
+            control.send(SynthCommand::UnisonWidth(new.id, new.unison_width as f32));
+        }
+        if new.width_time != old.width_time {
+            control.send(SynthCommand::WidthTime(new.id, new.width_time.round() as f32));
         }
         if new.env_idx != old.env_idx {
-            synth.set_env(new.id, new.env_idx.round() as usize);
+            control.send(SynthCommand::Env(new.id, new.env_idx.round() as usize));
+        }
+        if new.env_amount != old.env_amount {
+            control.send(SynthCommand::OscEnvAmount(new.id, new.env_amount as f32));
+        }
+        if new.bend != old.bend {
+            control.send(SynthCommand::OscBend(new.id, new.bend));
+        }
+        if new.drone != old.drone {
+            control.send(SynthCommand::OscDrone(new.id, new.drone));
+        }
+        if new.sub != old.sub {
+            control.send(SynthCommand::OscSub(new.id, new.sub));
+        }
+        if new.sub_wave_idx != old.sub_wave_idx {
+            let sub_waveform = &SUB_WAVEFORMS[new.sub_wave_idx.round() as usize].waveform;
+            control.send(SynthCommand::OscSubWaveform(new.id, sub_waveform.clone(), sub_waveform.get_wave()));
+        }
+        if new.sub_level != old.sub_level {
+            control.send(SynthCommand::OscSubLevel(new.id, new.sub_level as f32));
+        }
+        if new.tone != old.tone {
+            control.send(SynthCommand::OscTone(new.id, new.tone));
+        }
+        if new.tilt != old.tilt {
+            control.send(SynthCommand::OscTilt(new.id, new.tilt as f32));
+        }
+        if new.engine_idx != old.engine_idx {
+            control.send(SynthCommand::OscEngine(new.id, OSC_ENGINES[new.engine_idx.round() as usize].engine));
+        }
+        if new.pluck_damping != old.pluck_damping {
+            control.send(SynthCommand::OscPluckDamping(new.id, new.pluck_damping as f32));
+        }
+        // Additive table is rebuilt here, not on the audio thread
+        if !new.harmonics.same(&old.harmonics) {
+            control.send(SynthCommand::OscHarmonics(new.id, new.harmonic_levels(), new.wave()));
+        }
+        if new.pulse_width != old.pulse_width {
+            control.send(SynthCommand::OscPulseWidth(new.id, new.pulse_width as f32));
+        }
+        // Zone bounds are dragged, only whole velocities are worth sending
+        if new.velocity_low.round() != old.velocity_low.round()
+            || new.velocity_high.round() != old.velocity_high.round()
+        {
+            control.send(SynthCommand::OscVelocityRange(
+                new.id,
+                new.velocity_low.round() as u8,
+                new.velocity_high.round() as u8,
+            ));
+        }
+    }
+
+    fn update_env(control: &SynthControl<i16>, new: &EnvSettings, old: &EnvSettings) {
+        for (new_param, old_param) in new.params().iter().zip(old.params().iter()) {
+            if new_param != old_param {
+                control.send(SynthCommand::EnvParameter(new.id, *new_param));
+            }
+        }
+    }
+
+    fn update_lfo(control: &SynthControl<i16>, new: &LfoSettings, old: &LfoSettings) {
+        if new.wave_idx != old.wave_idx {
+            let waveform = &WAVEFORMS[new.wave_idx as usize].waveform;
+            control.send(SynthCommand::LfoWaveform(new.id, waveform.clone(), waveform.get_wave()));
+        }
+        if new.rate != old.rate {
+            control.send(SynthCommand::LfoRate(new.id, LOG_SCALE_BASE.powf(new.rate) as f32));
+        }
+        if new.depth != old.depth {
+            control.send(SynthCommand::LfoDepth(new.id, new.depth as f32));
+        }
+        if new.target_idx != old.target_idx {
+            control.send(SynthCommand::LfoTarget(new.id, LFO_TARGETS[new.target_idx as usize].target));
+        }
+    }
+
+    fn update_mod_slot(control: &SynthControl<i16>, new: &ModSlotSettings, old: &ModSlotSettings) {
+        if new.source_idx != old.source_idx || new.destination_idx != old.destination_idx {
+            control.send(SynthCommand::ModRoute(
+                new.id,
+                MOD_SOURCES[new.source_idx as usize].source,
+                MOD_DESTINATIONS[new.destination_idx as usize].destination));
+        }
+        if new.depth != old.depth {
+            control.send(SynthCommand::ModDepth(new.id, new.depth as f32));
+        }
+        if new.depth_source_idx != old.depth_source_idx {
+            control.send(SynthCommand::ModDepthSource(new.id, MOD_SOURCES[new.depth_source_idx as usize].source));
+        }
+    }
+
+    fn update_glide(control: &SynthControl<i16>, new: &GlideSettings, old: &GlideSettings) {
+        if new.time != old.time {
+            control.send(SynthCommand::GlideTime(new.time.round() as f32))
+        }
+        if new.legato != old.legato {
+            control.send(SynthCommand::GlideLegato(new.legato))
+        }
+        if new.curve_idx != old.curve_idx {
+            control.send(SynthCommand::GlideCurve(GLIDE_CURVES[new.curve_idx.round() as usize].curve))
+        }
+    }
+
+    fn update_voices(control: &SynthControl<i16>, new: &VoiceSettings, old: &VoiceSettings) {
+        if new.max_voices.round() != old.max_voices.round() {
+            control.send(SynthCommand::MaxVoices(new.max_voices.round() as usize))
+        }
+        if new.stealing_idx != old.stealing_idx {
+            control.send(SynthCommand::VoiceStealing(VOICE_STEALING[new.stealing_idx.round() as usize].stealing))
+        }
+    }
+
+    fn update_expression(control: &SynthControl<i16>, new: &ExpressionSettings, old: &ExpressionSettings) {
+        if new.destination_idx != old.destination_idx {
+            control.send(SynthCommand::ExpressionDestination(
+                EXPRESSION_DESTINATIONS[new.destination_idx.round() as usize].destination,
+            ))
+        }
+        if new.curve_idx != old.curve_idx {
+            control.send(SynthCommand::ExpressionCurve(EXPRESSION_CURVES[new.curve_idx.round() as usize].curve))
         }
     }
 
-    fn update_env(&self, synth: &mut MutexGuard<Synth<i16>>, new: &EnvSettings, old: &EnvSettings) {
-        if new.attack != old.attack {
-            synth.set_env_parameter(new.id, ADSRParam::Attack(LOG_SCALE_BASE.powf(new.attack).round() as f32))
+    fn update_arp(control: &SynthControl<i16>, new: &ArpSettings, old: &ArpSettings) {
+        if new.enabled != old.enabled {
+            control.send(SynthCommand::ArpEnabled(new.enabled))
+        }
+        if new.mode_idx != old.mode_idx {
+            control.send(SynthCommand::ArpMode(ARP_MODES[new.mode_idx.round() as usize].mode))
         }
-        if new.decay != old.decay {
-            synth.set_env_parameter(new.id, ADSRParam::Decay(LOG_SCALE_BASE.powf(new.decay).round() as f32))
+        if new.synced != old.synced {
+            control.send(SynthCommand::ArpSynced(new.synced))
         }
-        if new.sustain != old.sustain {
-            synth.set_env_parameter(new.id, ADSRParam::Sustain(new.sustain as f32))
+        if new.rate != old.rate {
+            control.send(SynthCommand::ArpRate(LOG_SCALE_BASE.powf(new.rate).round() as f32))
         }
-        if new.release != old.release {
-            synth.set_env_parameter(new.id, ADSRParam::Release(LOG_SCALE_BASE.powf(new.release).round() as f32))
+        if new.division_idx != old.division_idx {
+            control.send(SynthCommand::ArpDivision(STEP_DIVISIONS[new.division_idx.round() as usize].division))
+        }
+        if new.octaves != old.octaves {
+            control.send(SynthCommand::ArpOctaves(new.octaves.round() as usize))
+        }
+        if new.gate != old.gate {
+            control.send(SynthCommand::ArpGate(new.gate as f32))
+        }
+    }
+
+    fn update_seq(control: &SynthControl<i16>, new: &SeqSettings, old: &SeqSettings) {
+        if new.division_idx != old.division_idx {
+            control.send(SynthCommand::SeqDivision(STEP_DIVISIONS[new.division_idx.round() as usize].division))
+        }
+        for (step, (new_pitch, old_pitch)) in new.pitches.iter().zip(old.pitches.iter()).enumerate() {
+            if seq_pitch(*new_pitch) != seq_pitch(*old_pitch) {
+                control.send(SynthCommand::SeqPitch(step, seq_pitch(*new_pitch)));
+            }
+        }
+        for (step, (new_gate, old_gate)) in new.gates.iter().zip(old.gates.iter()).enumerate() {
+            if new_gate != old_gate {
+                control.send(SynthCommand::SeqGate(step, *new_gate));
+            }
+        }
+        for (step, (new_accent, old_accent)) in new.accents.iter().zip(old.accents.iter()).enumerate() {
+            if new_accent != old_accent {
+                control.send(SynthCommand::SeqAccent(step, *new_accent));
+            }
+        }
+    }
+
+    fn update_filter(control: &SynthControl<i16>, new: &FilterSettings, old: &FilterSettings) {
+        if new.cutoff != old.cutoff {
+            control.send(SynthCommand::FilterCutoff(LOG_SCALE_BASE.powf(new.cutoff).round() as f32))
+        }
+        if new.resonance != old.resonance {
+            control.send(SynthCommand::FilterResonance(new.resonance as f32))
+        }
+        if new.mode_idx != old.mode_idx {
+            control.send(SynthCommand::FilterMode(FILTER_MODES[new.mode_idx as usize].mode))
+        }
+        if new.env_idx != old.env_idx {
+            control.send(SynthCommand::FilterEnv(new.env_idx.round() as usize))
+        }
+        if new.env_amount != old.env_amount {
+            control.send(SynthCommand::FilterEnvAmount(new.env_amount as f32))
         }
     }
 }
 
+// Returns true when the mouse event was over the overlay
+fn overlay_event(
+    overlay: &mut WidgetPod<SynthUIData, Box<dyn Widget<SynthUIData>>>,
+    ctx: &mut EventCtx,
+    event: &Event,
+    data: &mut SynthUIData,
+    env: &Env,
+) -> bool {
+    match event {
+        Event::MouseDown(_) | Event::MouseUp(_) | Event::MouseMove(_) | Event::Wheel(_) => {
+            overlay.event(ctx, event, data, env);
+            overlay.is_hot() || overlay.is_active()
+        }
+        _ => false,
+    }
+}
+
 impl Widget<SynthUIData> for SynthUI {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SynthUIData, env: &Env) {
+        // Changes made while handling the event are logged with its source
+        match event {
+            Event::Command(cmd) if cmd.is(SET_MIDI_VALUE) => data.audit.lock().unwrap().set_source(ChangeSource::Midi),
+            Event::MouseDown(_) | Event::MouseUp(_) | Event::MouseMove(_) | Event::Wheel(_)
+                | Event::KeyDown(_) | Event::KeyUp(_) => data.audit.lock().unwrap().set_source(ChangeSource::Ui),
+            _ => {}
+        }
+        // Overlay panels cover the UI, so mouse over them doesn't reach widgets below
+        if !self.show_help {
+            if self.show_log && overlay_event(&mut self.log, ctx, event, data, env) {
+                return
+            }
+            if !data.toasts.is_empty() && overlay_event(&mut self.toasts, ctx, event, data, env) {
+                return
+            }
+            if data.tip_idx.is_some() && overlay_event(&mut self.tips, ctx, event, data, env) {
+                return
+            }
+        }
         match event {
             Event::WindowConnected => {
                 if !ctx.is_focused() {
                     ctx.request_focus()
                 }
+                if data.first_run {
+                    data.first_run = false;
+                    load_demo_patch(data);
+                    data.tip_idx = Some(0);
+                }
+            }
+            // Text box is being edited, Enter or Escape gives keys back to notes
+            Event::KeyDown(key) if !ctx.is_focused()
+                && (key.code == KeyCode::Enter || key.code == KeyCode::Escape) => {
+                ctx.request_focus()
+            }
+            Event::KeyDown(_) | Event::KeyUp(_) if !ctx.is_focused() => {
+                self.root.event(ctx, event, data, env)
+            }
+            // Any click hides help
+            Event::MouseDown(_) if self.show_help => {
+                self.show_help = false;
+                ctx.request_paint();
+                ctx.set_handled();
+            }
+            // Clicking outside of a text box ends editing
+            Event::MouseDown(_) => {
+                if !ctx.is_focused() {
+                    ctx.request_focus()
+                }
+                self.root.event(ctx, event, data, env)
             }
             Event::KeyDown(KeyEvent {
                 code,
                 repeat,
+                mods,
                 ..
             }) => {
-                if *code == KeyCode::Escape {
-                    ctx.window().close()
+                if let Some(action) = get_action(code, mods.ctrl()) {
+                    if !repeat {
+                        self.handle_action(ctx, action, data)
+                    }
                 } else if !repeat {
                     self.handle_key_press(code, data)
                 }
@@ -151,6 +558,9 @@ impl Widget<SynthUIData> for SynthUI {
                 repeat,
                 ..
             }) => {
+                if *code == KeyCode::AltLeft || *code == KeyCode::AltRight {
+                    ctx.submit_command(END_SOLO.to(Target::Global));
+                }
                 if !repeat {
                     self.handle_key_release(code, data)
                 }
@@ -167,10 +577,17 @@ impl Widget<SynthUIData> for SynthUI {
         env: &Env,
     ) {
         match event {
-            LifeCycle::WidgetAdded => ctx.register_for_focus(),
+            LifeCycle::WidgetAdded => {
+                ctx.register_for_focus();
+                publish_patch(data);
+            }
             _ => {}
         }
-        self.root.lifecycle(ctx, event, data, env)
+        self.root.lifecycle(ctx, event, data, env);
+        self.help.lifecycle(ctx, event, data, env);
+        self.tips.lifecycle(ctx, event, data, env);
+        self.toasts.lifecycle(ctx, event, data, env);
+        self.log.lifecycle(ctx, event, data, env)
     }
 
     fn update(
@@ -181,28 +598,85 @@ impl Widget<SynthUIData> for SynthUI {
         env: &Env,
     ) {
         if !new.same(old) {
-            if !new.osc1.same(&old.osc1) {
-                let mut synth = new.synth.lock().unwrap();
-                self.update_osc(&mut synth, &new.osc1, &old.osc1);
+            if new.sample_rate_idx != old.sample_rate_idx {
+                let rate = SAMPLE_RATES[new.sample_rate_idx.round() as usize];
+                // Synth thread is gone when the output couldn't be opened
+                if new.event_sender.send(SynthUIEvent::SampleRate(rate as f32)).is_err() {
+                    report(&new.notification_sender, BaseError::ThreadError("Synth thread has stopped, sample rate isn't changed".into()));
+                }
+                // Next launch starts with the same rate
+                save_setting(&new.notification_sender, |settings| settings.sample_rate = rate);
+            }
+            if new.high_quality != old.high_quality {
+                new.control.send(SynthCommand::HighQuality(new.high_quality));
+                save_setting(&new.notification_sender, |settings| settings.high_quality = new.high_quality);
+            }
+            if new.test_tone != old.test_tone
+                || new.test_tone_idx != old.test_tone_idx
+                || new.test_tone_level.round() != old.test_tone_level.round() {
+                if new.test_tone {
+                    let freq = TEST_TONE_FREQUENCIES[new.test_tone_idx as usize];
+                    new.control.play(SynthCommand::TestTone(Some((freq, new.test_tone_level.round() as i32))));
+                } else {
+                    new.control.send(SynthCommand::TestTone(None));
+                }
+            }
+            if new.muted != old.muted {
+                new.control.send(SynthCommand::Mute(new.muted));
+            }
+            if new.binaural != old.binaural {
+                new.control.send(SynthCommand::Binaural(new.binaural));
+                save_setting(&new.notification_sender, |settings| settings.binaural = new.binaural);
+            }
+            if new.dc_blocker != old.dc_blocker {
+                new.control.send(SynthCommand::DcBlocker(new.dc_blocker));
+                save_setting(&new.notification_sender, |settings| settings.dc_blocker = new.dc_blocker);
+            }
+            if new.key_velocity.round() != old.key_velocity.round() {
+                save_setting(&new.notification_sender, |settings| settings.key_velocity = new.key_velocity.round() as u8);
+            }
+            if new.bend_range.round() != old.bend_range.round() {
+                new.control.send(SynthCommand::BendRange(new.bend_range.round() as f32));
+                save_setting(&new.notification_sender, |settings| settings.bend_range = new.bend_range.round() as u8);
             }
-            if !new.osc2.same(&old.osc2) {
-                let mut synth = new.synth.lock().unwrap();
-                self.update_osc(&mut synth, &new.osc2, &old.osc2);
+            if new.language_idx != old.language_idx {
+                save_setting(&new.notification_sender, |settings| settings.language = LANGUAGES[new.language_idx.round() as usize].code.to_owned());
             }
-            if new.volume_db != old.volume_db {
-                // Slider value is in allowed range
-                new.synth.lock().unwrap().set_volume(new.volume_db as i32).unwrap();
+            if new.theme_idx != old.theme_idx {
+                save_setting(&new.notification_sender, |settings| settings.theme = THEMES[new.theme_idx.round() as usize].code.to_owned());
             }
-            if !new.env1.same(&old.env1) {
-                let mut synth = new.synth.lock().unwrap();
-                self.update_env(&mut synth, &new.env1, &old.env1);
+            if new.midi_port != old.midi_port {
+                let mut midi = new.midi.lock().unwrap();
+                // First option means no MIDI input
+                match new.midi_port.round() as usize {
+                    0 => midi.disconnect(),
+                    port => {
+                        match midi.connect(port - 1).context("Can't connect MIDI input") {
+                            Ok(()) => notify(&new.notification_sender,
+                                trf("MIDI input {} connected", &[&midi.port_names()[port - 1]])),
+                            Err(e) => report(&new.notification_sender, e),
+                        }
+                    }
+                }
             }
-            if !new.env2.same(&old.env2) {
-                let mut synth = new.synth.lock().unwrap();
-                self.update_env(&mut synth, &new.env2, &old.env2);
+            if new.seq_running != old.seq_running {
+                // Running sequencer keeps the output stream going
+                new.control.play(SynthCommand::SeqRunning(new.seq_running));
+            }
+            new.audit.lock().unwrap().record(old, new);
+            // Commands are queued at once, so a loaded preset is mostly applied between two audio buffers
+            if Self::update_patch(new, old) {
+                publish_patch(new);
             }
         }
         self.root.update(ctx, old, new, env);
+        self.help.update(ctx, new, env);
+        self.tips.update(ctx, new, env);
+        self.toasts.update(ctx, new, env);
+        self.log.update(ctx, new, env);
+        if new.tip_idx != old.tip_idx || !new.toasts.same(&old.toasts) {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -212,26 +686,91 @@ impl Widget<SynthUIData> for SynthUI {
         data: &SynthUIData,
         env: &Env,
     ) -> Size {
-        self.root.layout(ctx, bc, data, env)
+        let size = self.root.layout(ctx, bc, data, env);
+        let help_size = self.help.layout(ctx, &bc.loosen(), data, env);
+        let help_origin = Point::new(
+            ((size.width - help_size.width) / 2.0).max(0.0),
+            ((size.height - help_size.height) / 2.0).max(0.0),
+        );
+        self.help.set_origin(ctx, data, env, help_origin);
+        let tips_size = self.tips.layout(ctx, &bc.loosen(), data, env);
+        let tips_origin = Point::new(
+            ((size.width - tips_size.width) / 2.0).max(0.0),
+            (size.height - tips_size.height - OVERLAY_MARGIN).max(0.0),
+        );
+        self.tips.set_origin(ctx, data, env, tips_origin);
+        let toasts_size = self.toasts.layout(ctx, &bc.loosen(), data, env);
+        let toasts_origin = Point::new(((size.width - toasts_size.width) / 2.0).max(0.0), OVERLAY_MARGIN);
+        self.toasts.set_origin(ctx, data, env, toasts_origin);
+        let log_size = self.log.layout(ctx, &bc.loosen(), data, env);
+        let log_origin = Point::new((size.width - log_size.width - OVERLAY_MARGIN).max(0.0), OVERLAY_MARGIN);
+        self.log.set_origin(ctx, data, env, log_origin);
+        size
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &SynthUIData, env: &Env) {
         self.root.paint(ctx, data, env);
+        if data.tip_idx.is_some() {
+            self.tips.paint(ctx, data, env);
+        }
+        if self.show_log {
+            self.log.paint(ctx, data, env);
+        }
+        if !data.toasts.is_empty() {
+            self.toasts.paint(ctx, data, env);
+        }
+        if self.show_help {
+            let rect = ctx.size().to_rect();
+            ctx.fill(rect, &HELP_SHADE_COLOR);
+            self.help.paint(ctx, data, env);
+        }
     }
 }
 
+// Part of slider range around a snap value that pulls the slider to it
+const SNAP_DISTANCE: f64 = 0.015;
+
+// Inner slider goes through 0.0-1.0, the taper of the parameter maps it to the range
 pub struct DefaultSlider {
     slider: Slider,
+    min: f64,
+    max: f64,
     parameter: DefaultParameter,
+    taper: f64,
+    snaps: &'static [f64],
 }
 
 impl DefaultSlider {
-    pub fn new(slider: Slider, parameter: DefaultParameter) -> Self {
+    pub fn new(min: f64, max: f64, parameter: DefaultParameter) -> Self {
         Self {
-            slider,
+            slider: Slider::new().with_range(0.0, 1.0),
+            min,
+            max,
+            taper: parameter.taper(),
             parameter,
+            snaps: &[],
         }
     }
+
+    fn position(&self, value: f64) -> f64 {
+        taper_position((value - self.min) / (self.max - self.min), self.taper)
+    }
+
+    fn value(&self, position: f64) -> f64 {
+        self.min + taper_value(position, self.taper) * (self.max - self.min)
+    }
+
+    // Values the slider is pulled to while dragged close to them, Shift drags past them
+    pub fn with_snaps(mut self, snaps: &'static [f64]) -> Self {
+        self.snaps = snaps;
+        self
+    }
+
+    // Bound CC moves the slider through the same range as the mouse does
+    pub fn midi_learn(self, key: impl Into<String>) -> MidiLearn<Self> {
+        let (min, max, taper) = (self.min, self.max, self.taper);
+        MidiLearn::new(self, key, min, max).with_taper(taper)
+    }
 }
 
 impl Widget<f64> for DefaultSlider {
@@ -239,19 +778,28 @@ impl Widget<f64> for DefaultSlider {
         match event {
             Event::MouseDown(e) => {
                 if e.button.is_left() && e.mods.ctrl() {
-                    match self.parameter {
-                        // Log scale parameters
-                        DefaultParameter::EnvAttack | DefaultParameter::EnvDecay | DefaultParameter::EnvRelease => {
-                            *data = slider_log(self.parameter.default_val() as f32);
-                        },
-                        _ => *data = self.parameter.default_val(),
-                    }
+                    *data = self.parameter.slider_default();
                     return
                 }
             },
             _ => {},
         }
-        self.slider.event(ctx, event, data, env)
+        let old_position = self.position(*data);
+        let mut position = old_position;
+        self.slider.event(ctx, event, &mut position, env);
+        // Value isn't touched until the slider moves, so rounding doesn't change it
+        if position != old_position {
+            *data = self.value(position);
+        }
+        match event {
+            Event::MouseDown(e) | Event::MouseMove(e) if ctx.is_active() && !e.mods.shift() => {
+                let distance = SNAP_DISTANCE * (self.max - self.min);
+                if let Some(snap) = self.snaps.iter().find(|snap| (*data - **snap).abs() <= distance) {
+                    *data = *snap;
+                }
+            }
+            _ => {}
+        }
     }
 
     fn lifecycle(
@@ -261,7 +809,8 @@ impl Widget<f64> for DefaultSlider {
         data: &f64,
         env: &Env,
     ) {
-        self.slider.lifecycle(ctx, event, data, env)
+        let position = self.position(*data);
+        self.slider.lifecycle(ctx, event, &position, env)
     }
 
     fn update(
@@ -271,7 +820,8 @@ impl Widget<f64> for DefaultSlider {
         new: &f64,
         env: &Env,
     ) {
-        self.slider.update(ctx, old, new, env)
+        let (old, new) = (self.position(*old), self.position(*new));
+        self.slider.update(ctx, &old, &new, env)
     }
 
     fn layout(
@@ -281,10 +831,725 @@ impl Widget<f64> for DefaultSlider {
         data: &f64,
         env: &Env,
     ) -> Size {
-        self.slider.layout(ctx, bc, data, env)
+        let position = self.position(*data);
+        self.slider.layout(ctx, bc, &position, env)
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &f64, env: &Env) {
-        self.slider.paint(ctx, data, env)
+        let position = self.position(*data);
+        self.slider.paint(ctx, &position, env)
+    }
+}
+
+// Dragging through this many pixels covers the whole range, Shift makes it finer
+const DRAG_RANGE_PIXELS: f64 = 200.0;
+const DRAG_FINE_FACTOR: f64 = 0.1;
+
+// Wraps a value label so the value can be changed by dragging it up or down
+pub struct DragValue<W, L> {
+    inner: W,
+    lens: L,
+    min: f64,
+    max: f64,
+    last_y: f64,
+}
+
+impl<W, L> DragValue<W, L> {
+    pub fn new(inner: W, lens: L, min: f64, max: f64) -> Self {
+        Self {
+            inner,
+            lens,
+            min,
+            max,
+            last_y: 0.0,
+        }
+    }
+}
+
+impl<T: Data, W: Widget<T>, L: Lens<T, f64>> Widget<T> for DragValue<W, L> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, _env: &Env) {
+        match event {
+            Event::MouseDown(e) if e.button.is_left() => {
+                ctx.set_active(true);
+                self.last_y = e.pos.y;
+                ctx.set_handled();
+            }
+            Event::MouseMove(e) => {
+                ctx.set_cursor(&Cursor::ResizeUpDown);
+                if ctx.is_active() {
+                    let mut delta = (self.last_y - e.pos.y) / DRAG_RANGE_PIXELS * (self.max - self.min);
+                    if e.mods.shift() {
+                        delta *= DRAG_FINE_FACTOR;
+                    }
+                    let (min, max) = (self.min, self.max);
+                    self.lens.with_mut(data, |value| {
+                        *value = (*value + delta).max(min).min(max);
+                    });
+                    self.last_y = e.pos.y;
+                }
+            }
+            Event::MouseUp(_) if ctx.is_active() => {
+                ctx.set_active(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
+        self.inner.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &T, new: &T, env: &Env) {
+        self.inner.update(ctx, old, new, env)
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, env: &Env) -> Size {
+        self.inner.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        self.inner.paint(ctx, data, env)
+    }
+}
+
+// Alt released anywhere ends the solo, hovered panel doesn't get key events
+pub const END_SOLO: Selector = Selector::new("beep-boop.end-solo");
+
+// Oscillator panel that's heard alone while hovered with Alt held
+pub struct SoloOnHover<W, L> {
+    inner: W,
+    lens: L,
+    soloed: bool,
+}
+
+impl<W, L> SoloOnHover<W, L> {
+    pub fn new(inner: W, lens: L) -> Self {
+        Self {
+            inner,
+            lens,
+            soloed: false,
+        }
+    }
+}
+
+impl<W, L: Lens<SynthUIData, OscSettings>> SoloOnHover<W, L> {
+    fn set_soloed(&mut self, soloed: bool, data: &SynthUIData) {
+        if soloed != self.soloed {
+            self.soloed = soloed;
+            let osc_idx = self.lens.with(data, |osc| osc.id);
+            data.control.send(SynthCommand::Solo(if soloed { Some(osc_idx) } else { None }));
+        }
+    }
+}
+
+impl<W: Widget<SynthUIData>, L: Lens<SynthUIData, OscSettings>> Widget<SynthUIData> for SoloOnHover<W, L> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SynthUIData, env: &Env) {
+        match event {
+            Event::MouseMove(e) => self.set_soloed(ctx.is_hot() && e.mods.alt(), data),
+            Event::Command(cmd) if cmd.is(END_SOLO) => self.set_soloed(false, data),
+            _ => {}
+        }
+        self.inner.event(ctx, event, data, env)
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &SynthUIData, env: &Env) {
+        if let LifeCycle::HotChanged(false) = event {
+            self.set_soloed(false, data);
+        }
+        self.inner.lifecycle(ctx, event, data, env)
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &SynthUIData, new: &SynthUIData, env: &Env) {
+        self.inner.update(ctx, old, new, env)
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &SynthUIData, env: &Env) -> Size {
+        self.inner.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &SynthUIData, env: &Env) {
+        self.inner.paint(ctx, data, env)
+    }
+}
+
+// Clip light is checked this often and stays on for a while, so short clips are seen too
+const CLIP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const CLIP_HOLD: Duration = Duration::from_millis(800);
+const CLIP_LIGHT_SIZE: f64 = 10.0;
+const CLIP_COLOR: druid::Color = druid::Color::rgb8(0xe5, 0x39, 0x35);
+
+// Lights up while the output is soft clipped, a hint to turn volume down
+pub struct ClipIndicator {
+    lit_until: Option<Instant>,
+    // Whether the light was painted on, it goes off on a timer tick
+    lit: bool,
+}
+
+impl ClipIndicator {
+    pub fn new() -> Self {
+        Self { lit_until: None, lit: false }
+    }
+}
+
+impl Widget<SynthUIData> for ClipIndicator {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SynthUIData, _env: &Env) {
+        if let Event::Timer(_) = event {
+            let now = Instant::now();
+            if data.control.take_clipped() {
+                self.lit_until = Some(now + CLIP_HOLD);
+            }
+            let lit = self.lit_until.map_or(false, |until| now < until);
+            if lit != self.lit {
+                self.lit = lit;
+                ctx.request_paint();
+            }
+            ctx.request_timer(CLIP_POLL_INTERVAL);
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, _data: &SynthUIData, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.request_timer(CLIP_POLL_INTERVAL);
+        }
+    }
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, _old: &SynthUIData, _new: &SynthUIData, _env: &Env) {}
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &SynthUIData, _env: &Env) -> Size {
+        bc.constrain(Size::new(CLIP_LIGHT_SIZE, CLIP_LIGHT_SIZE))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &SynthUIData, env: &Env) {
+        let light = ctx.size().to_rect().inset(-1.0);
+        if self.lit {
+            ctx.fill(light, &CLIP_COLOR);
+            // Red alone isn't seen by everyone, the light gets crossed out
+            if high_contrast() {
+                let cross_color = env.get(BORDER_COLOR);
+                ctx.stroke(Line::new((light.x0, light.y0), (light.x1, light.y1)), &cross_color, 2.0);
+                ctx.stroke(Line::new((light.x0, light.y1), (light.x1, light.y0)), &cross_color, 2.0);
+            }
+        }
+        ctx.stroke(light, &env.get(BORDER_COLOR), 1.0);
+    }
+}
+
+const BAR_GAP: f64 = 2.0;
+
+// Row of bars with levels in 0.0-1.0, click or drag over the bars draws them
+pub struct BarGraph {
+    height: f64,
+}
+
+impl BarGraph {
+    pub fn new(height: f64) -> Self {
+        Self { height }
+    }
+
+    fn set_level(&self, ctx: &EventCtx, pos: Point, data: &mut Arc<Vec<f64>>) {
+        let size = ctx.size();
+        if data.is_empty() || size.width <= 0.0 {
+            return;
+        }
+        let bar = ((pos.x / size.width * data.len() as f64).floor().max(0.0) as usize).min(data.len() - 1);
+        let level = (1.0 - pos.y / size.height).max(0.0).min(1.0);
+        if data[bar] != level {
+            Arc::make_mut(data)[bar] = level;
+        }
+    }
+}
+
+impl Widget<Arc<Vec<f64>>> for BarGraph {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Arc<Vec<f64>>, _env: &Env) {
+        match event {
+            Event::MouseDown(e) if e.button.is_left() => {
+                ctx.set_active(true);
+                self.set_level(ctx, e.pos, data);
+                ctx.set_handled();
+            }
+            Event::MouseMove(e) if ctx.is_active() => {
+                self.set_level(ctx, e.pos, data);
+            }
+            Event::MouseUp(_) if ctx.is_active() => {
+                ctx.set_active(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &Arc<Vec<f64>>, _env: &Env) {}
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &Arc<Vec<f64>>, new: &Arc<Vec<f64>>, _env: &Env) {
+        if !new.same(old) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &Arc<Vec<f64>>, _env: &Env) -> Size {
+        bc.constrain(Size::new(bc.max().width, self.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Arc<Vec<f64>>, env: &Env) {
+        let size = ctx.size();
+        let width = size.width / data.len().max(1) as f64;
+        for (bar, level) in data.iter().enumerate() {
+            let cell = Rect::from_origin_size(
+                Point::new(width * bar as f64, 0.0),
+                Size::new(width - BAR_GAP, size.height),
+            );
+            ctx.stroke(cell, &env.get(BORDER_COLOR), 1.0);
+            let bar = Rect::new(cell.x0, cell.y1 - cell.height() * level, cell.x1, cell.y1);
+            ctx.fill(bar, &env.get(druid::theme::PRIMARY_LIGHT));
+        }
+    }
+}
+
+// Semitones of white keys in an octave
+const WHITE_KEYS: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+const BLACK_KEY_WIDTH: f64 = 0.6;
+const BLACK_KEY_HEIGHT: f64 = 0.6;
+const WHITE_KEY_COLOR: druid::Color = druid::Color::rgb8(0xee, 0xee, 0xee);
+const BLACK_KEY_COLOR: druid::Color = druid::Color::rgb8(0x10, 0x10, 0x10);
+
+// Clickable piano starting at the octave of computer keyboard. Dragging
+// over the keys slides from one note to another
+pub struct Piano {
+    octaves: usize,
+    height: f64,
+    // Semitones from the lowest key
+    pressed: Option<usize>,
+}
+
+impl Piano {
+    pub fn new(octaves: usize, height: f64) -> Self {
+        Self { octaves, height, pressed: None }
+    }
+
+    // Semitone, rect and whether it's black for every key. Black keys go last,
+    // they're drawn over the white ones and are hit first
+    fn keys(&self, size: Size) -> Vec<(usize, Rect, bool)> {
+        // Octaves end with a C on top
+        let white_num = self.octaves * WHITE_KEYS.len() + 1;
+        let white_width = size.width / white_num as f64;
+        let mut keys = Vec::with_capacity(self.octaves * 12 + 1);
+        for white in 0..white_num {
+            let semitone = white / WHITE_KEYS.len() * 12 + WHITE_KEYS[white % WHITE_KEYS.len()];
+            let x = white as f64 * white_width;
+            keys.push((semitone, Rect::new(x, 0.0, x + white_width, size.height), false));
+        }
+        for white in 0..white_num - 1 {
+            let semitone = white / WHITE_KEYS.len() * 12 + WHITE_KEYS[white % WHITE_KEYS.len()];
+            // No black key between E and F or B and C
+            if semitone % 12 == 4 || semitone % 12 == 11 {
+                continue;
+            }
+            let x = (white + 1) as f64 * white_width - white_width * BLACK_KEY_WIDTH / 2.0;
+            let rect = Rect::new(x, 0.0, x + white_width * BLACK_KEY_WIDTH, size.height * BLACK_KEY_HEIGHT);
+            keys.push((semitone + 1, rect, true));
+        }
+        keys
+    }
+
+    fn key_at(&self, size: Size, pos: Point) -> Option<usize> {
+        self.keys(size)
+            .iter()
+            .rev()
+            .find(|(_, rect, _)| rect.contains(pos))
+            .map(|(semitone, _, _)| *semitone)
+    }
+
+    fn press(&mut self, key: Option<usize>, data: &SynthUIData) {
+        if key == self.pressed {
+            return;
+        }
+        if self.pressed.is_some() {
+            data.control.note_off(NoteSource::Mouse);
+        }
+        if let Some(semitone) = key {
+            let freq = NOTE_KEYS[semitone % 12].freq * 2f32.powi((semitone / 12) as i32) * data.octave_modifier;
+            data.control.note_on(freq, data.fixed_velocity(), NoteSource::Mouse);
+        }
+        self.pressed = key;
+    }
+}
+
+impl Widget<SynthUIData> for Piano {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SynthUIData, _env: &Env) {
+        match event {
+            Event::MouseDown(e) if e.button.is_left() => {
+                ctx.set_active(true);
+                self.press(self.key_at(ctx.size(), e.pos), data);
+                ctx.request_paint();
+                ctx.set_handled();
+            }
+            Event::MouseMove(e) if ctx.is_active() => {
+                let key = self.key_at(ctx.size(), e.pos);
+                if key != self.pressed {
+                    self.press(key, data);
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseUp(_) if ctx.is_active() => {
+                ctx.set_active(false);
+                self.press(None, data);
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &SynthUIData, _env: &Env) {}
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old: &SynthUIData, new: &SynthUIData, _env: &Env) {
+        if !new.held_keys.same(&old.held_keys) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &SynthUIData, _env: &Env) -> Size {
+        bc.constrain(Size::new(bc.max().width, self.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &SynthUIData, env: &Env) {
+        let highlight = env.get(druid::theme::PRIMARY_LIGHT);
+        for (semitone, rect, black) in self.keys(ctx.size()) {
+            // Computer keyboard plays from the lowest C, held keys are in semitone order
+            let held = self.pressed == Some(semitone)
+                || (semitone < data.held_keys.len() && data.held_keys[semitone]);
+            let color = match (held, black) {
+                (true, _) => &highlight,
+                (false, true) => &BLACK_KEY_COLOR,
+                (false, false) => &WHITE_KEY_COLOR,
+            };
+            ctx.fill(rect, color);
+            ctx.stroke(rect, &env.get(BORDER_COLOR), 1.0);
+            // Held keys get a dot, they don't rely on the highlight color
+            if held && high_contrast() {
+                let radius = rect.width() / 4.0;
+                let center = Point::new(rect.center().x, rect.y1 - radius * 2.0);
+                ctx.fill(Circle::new(center, radius), &BLACK_KEY_COLOR);
+            }
+        }
+    }
+}
+
+const PRESET_ROW_HEIGHT: f64 = 20.0;
+const PRESET_TEXT_PADDING: f64 = 5.0;
+
+// List of presets from presets directory, click on a name loads it
+pub struct PresetBrowser {
+    text_size: KeyOrValue<f64>,
+    // Grows with larger text of the theme
+    row_height: f64,
+    rows: Vec<WidgetPod<SynthUIData, Label<SynthUIData>>>,
+}
+
+impl PresetBrowser {
+    pub fn new(text_size: impl Into<KeyOrValue<f64>>) -> Self {
+        Self {
+            text_size: text_size.into(),
+            row_height: PRESET_ROW_HEIGHT,
+            rows: Vec::new(),
+        }
+    }
+
+    fn rebuild(&mut self, names: &[String]) {
+        let text_size = &self.text_size;
+        self.rows = names
+            .iter()
+            .map(|name| WidgetPod::new(Label::new(name.clone()).with_text_size(text_size.clone())))
+            .collect();
     }
-}
\ No newline at end of file
+}
+
+impl Widget<SynthUIData> for PresetBrowser {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SynthUIData, _env: &Env) {
+        if let Event::MouseDown(e) = event {
+            if e.button.is_left() {
+                let row = (e.pos.y / self.row_height).floor() as usize;
+                select_preset(data, row);
+                ctx.set_handled();
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &SynthUIData,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild(&data.presets);
+        }
+        for row in self.rows.iter_mut() {
+            row.lifecycle(ctx, event, data, env);
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old: &SynthUIData,
+        new: &SynthUIData,
+        env: &Env,
+    ) {
+        if !new.presets.same(&old.presets) {
+            // New rows get WidgetAdded before any update
+            self.rebuild(&new.presets);
+            ctx.children_changed();
+            return;
+        }
+        for row in self.rows.iter_mut() {
+            row.update(ctx, new, env);
+        }
+        if new.preset_idx != old.preset_idx {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &SynthUIData,
+        env: &Env,
+    ) -> Size {
+        let label_bc = bc.loosen();
+        let sizes: Vec<Size> = self.rows.iter_mut().map(|row| row.layout(ctx, &label_bc, data, env)).collect();
+        self.row_height = sizes.iter().fold(PRESET_ROW_HEIGHT, |height, size| height.max(size.height));
+        for (i, (row, size)) in self.rows.iter_mut().zip(sizes.iter()).enumerate() {
+            let y = self.row_height * i as f64 + (self.row_height - size.height) / 2.0;
+            row.set_origin(ctx, data, env, Point::new(PRESET_TEXT_PADDING, y));
+        }
+        bc.constrain(Size::new(bc.max().width, self.row_height * self.rows.len() as f64))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &SynthUIData, env: &Env) {
+        let width = ctx.size().width;
+        if let Some(selected) = data.preset_idx {
+            let origin = Point::new(0.0, self.row_height * selected as f64);
+            let row = Rect::from_origin_size(origin, Size::new(width, self.row_height));
+            paint_selected_row(ctx, row, env);
+        }
+        for row in self.rows.iter_mut() {
+            row.paint(ctx, data, env);
+        }
+    }
+}
+
+const DROPDOWN_ROW_HEIGHT: f64 = 22.0;
+const DROPDOWN_TEXT_PADDING: f64 = 5.0;
+const DROPDOWN_SELECTED_COLOR: druid::Color = druid::Color::rgba8(0x35, 0xaa, 0xee, 0x60);
+const DROPDOWN_HOVER_COLOR: druid::Color = druid::Color::rgba8(0xff, 0xff, 0xff, 0x20);
+const SELECTED_OUTLINE_WIDTH: f64 = 2.0;
+
+// Selected row is tinted, with high contrast theme it's outlined as well
+fn paint_selected_row(ctx: &mut PaintCtx, row: Rect, env: &Env) {
+    ctx.fill(row, &DROPDOWN_SELECTED_COLOR);
+    if high_contrast() {
+        ctx.stroke(row.inset(-SELECTED_OUTLINE_WIDTH / 2.0), &env.get(LABEL_COLOR_WARNING), SELECTED_OUTLINE_WIDTH);
+    }
+}
+
+// Shows selected option and expands inline into the list of all options on click.
+// Selected option is an f64 index, same as for steppers
+pub struct Dropdown<T> {
+    expanded: bool,
+    hovered: Option<usize>,
+    // Sent with option index when pointer moves onto it
+    hover_command: Option<Selector<usize>>,
+    get_index: Box<dyn Fn(&T) -> f64>,
+    put_index: Box<dyn Fn(&mut T, f64)>,
+    // Options shown, the rest of them wait for the list to grow
+    shown: Box<dyn Fn(&T) -> usize>,
+    header: WidgetPod<T, Label<T>>,
+    options: Vec<WidgetPod<T, Label<T>>>,
+    // Grows with larger text of the theme
+    row_height: f64,
+}
+
+impl Dropdown<f64> {
+    pub fn new(options: Vec<String>, text_size: impl Into<KeyOrValue<f64>>) -> Self {
+        let len = options.len();
+        Dropdown::dynamic(len, move |_, idx| options[idx].clone(), lens::Identity, text_size)
+    }
+}
+
+impl<T: Data> Dropdown<T> {
+    // Option names are taken from data, so they follow renaming
+    pub fn dynamic<L>(
+        len: usize,
+        name: impl Fn(&T, usize) -> String + 'static,
+        index_lens: L,
+        text_size: impl Into<KeyOrValue<f64>>,
+    ) -> Self
+    where
+        L: Lens<T, f64> + Clone + 'static
+    {
+        let text_size = text_size.into();
+        let name = Rc::new(name);
+        let (header_name, header_lens) = (name.clone(), index_lens.clone());
+        let header = Label::dynamic(move |data: &T, _| {
+            let idx = header_lens.get(data).round() as usize;
+            if idx < len {
+                format!("{} \u{25be}", header_name(data, idx))
+            } else {
+                "\u{25be}".into()
+            }
+        }).with_text_size(text_size.clone());
+        let options = (0..len)
+            .map(|idx| {
+                let name = name.clone();
+                WidgetPod::new(Label::dynamic(move |data: &T, _| name(data, idx)).with_text_size(text_size.clone()))
+            })
+            .collect();
+        let put_lens = index_lens.clone();
+        Self {
+            expanded: false,
+            hovered: None,
+            hover_command: None,
+            get_index: Box::new(move |data| index_lens.get(data)),
+            put_index: Box::new(move |data, idx| put_lens.put(data, idx)),
+            shown: Box::new(move |_| len),
+            header: WidgetPod::new(header),
+            options,
+            row_height: DROPDOWN_ROW_HEIGHT,
+        }
+    }
+
+    pub fn with_hover_command(mut self, selector: Selector<usize>) -> Self {
+        self.hover_command = Some(selector);
+        self
+    }
+
+    // Options past len from dynamic are never shown
+    pub fn with_shown(mut self, shown: impl Fn(&T) -> usize + 'static) -> Self {
+        let len = self.options.len();
+        self.shown = Box::new(move |data| shown(data).min(len));
+        self
+    }
+
+    // Header is the first row
+    fn option_at(&self, y: f64, data: &T) -> Option<usize> {
+        let row = (y / self.row_height).floor() as usize;
+        if row >= 1 && row <= (self.shown)(data) {
+            Some(row - 1)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Data> Widget<T> for Dropdown<T> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, _env: &Env) {
+        match event {
+            Event::MouseDown(e) if e.button.is_left() => {
+                if self.expanded {
+                    if let Some(idx) = self.option_at(e.pos.y, data) {
+                        (self.put_index)(data, idx as f64);
+                    }
+                }
+                self.expanded = !self.expanded;
+                self.hovered = None;
+                ctx.request_layout();
+                ctx.set_handled();
+            }
+            Event::MouseMove(e) if self.expanded => {
+                let hovered = if ctx.is_hot() { self.option_at(e.pos.y, data) } else { None };
+                if hovered != self.hovered {
+                    self.hovered = hovered;
+                    if let (Some(idx), Some(selector)) = (hovered, self.hover_command) {
+                        ctx.submit_command(selector.with(idx));
+                    }
+                    ctx.request_paint();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &T,
+        env: &Env,
+    ) {
+        if let LifeCycle::HotChanged(false) = event {
+            if self.hovered.take().is_some() {
+                ctx.request_paint();
+            }
+        }
+        self.header.lifecycle(ctx, event, data, env);
+        for option in self.options.iter_mut() {
+            option.lifecycle(ctx, event, data, env);
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old: &T,
+        new: &T,
+        env: &Env,
+    ) {
+        self.header.update(ctx, new, env);
+        for option in self.options.iter_mut() {
+            option.update(ctx, new, env);
+        }
+        if (self.shown)(old) != (self.shown)(new) {
+            ctx.request_layout();
+        } else if !old.same(new) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &T,
+        env: &Env,
+    ) -> Size {
+        let label_bc = bc.loosen();
+        let mut width: f64 = 0.0;
+        let header_size = self.header.layout(ctx, &label_bc, data, env);
+        width = width.max(header_size.width);
+        self.row_height = DROPDOWN_ROW_HEIGHT.max(header_size.height + DROPDOWN_TEXT_PADDING);
+        let row_height = self.row_height;
+        let header_origin = Point::new(DROPDOWN_TEXT_PADDING, (row_height - header_size.height) / 2.0);
+        self.header.set_origin(ctx, data, env, header_origin);
+        // Options are laid out even when collapsed, only painting depends on state
+        for (i, option) in self.options.iter_mut().enumerate() {
+            let size = option.layout(ctx, &label_bc, data, env);
+            width = width.max(size.width);
+            let y = row_height * (i + 1) as f64 + (row_height - size.height) / 2.0;
+            option.set_origin(ctx, data, env, Point::new(DROPDOWN_TEXT_PADDING, y));
+        }
+        let rows = if self.expanded { (self.shown)(data) + 1 } else { 1 };
+        bc.constrain(Size::new(width + DROPDOWN_TEXT_PADDING * 2.0, row_height * rows as f64))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        let size = ctx.size();
+        let header_rect = Rect::from_origin_size(Point::ORIGIN, Size::new(size.width, self.row_height));
+        ctx.stroke(header_rect.inset(-0.5), &env.get(BORDER_COLOR), 1.0);
+        self.header.paint(ctx, data, env);
+        if self.expanded {
+            ctx.stroke(size.to_rect().inset(-0.5), &env.get(BORDER_COLOR), 1.0);
+            let selected = (self.get_index)(data).round() as usize;
+            for (i, option) in self.options.iter_mut().enumerate().take((self.shown)(data)) {
+                let origin = Point::new(0.0, self.row_height * (i + 1) as f64);
+                let row = Rect::from_origin_size(origin, Size::new(size.width, self.row_height));
+                if i == selected {
+                    paint_selected_row(ctx, row, env);
+                } else if Some(i) == self.hovered {
+                    ctx.fill(row, &DROPDOWN_HOVER_COLOR);
+                }
+                option.paint(ctx, data, env);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::Result;
+
+const CHANNELS_NUM: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+// Format code of uncompressed PCM
+const PCM_FORMAT: u16 = 1;
+// Size of "fmt " chunk contents for PCM
+const FMT_CHUNK_SIZE: u32 = 16;
+// Everything in the header after RIFF chunk size, except the data itself
+const HEADER_SIZE: u32 = 36;
+// Offsets of the sizes that are known only when writing is finished
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 40;
+
+// Writes interleaved left and right samples as 16-bit stereo WAV file
+pub fn write_wav(path: &Path, sample_rate: u32, frames: &[[i16; 2]]) -> Result<()> {
+    let mut writer = WavWriter::create(path, sample_rate)?;
+    for frame in frames {
+        writer.write(frame)?;
+    }
+    writer.finish()
+}
+
+// 16-bit stereo WAV file written as samples come, sizes in the header
+// are filled in by finish
+pub struct WavWriter {
+    out: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> Result<Self> {
+        let block_align = CHANNELS_NUM * BITS_PER_SAMPLE / 8;
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&HEADER_SIZE.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&FMT_CHUNK_SIZE.to_le_bytes())?;
+        out.write_all(&PCM_FORMAT.to_le_bytes())?;
+        out.write_all(&CHANNELS_NUM.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { out, data_size: 0 })
+    }
+
+    // Interleaved left and right samples
+    pub fn write(&mut self, samples: &[i16]) -> Result<()> {
+        for sample in samples {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * BITS_PER_SAMPLE as u32 / 8;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.out.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.out.write_all(&(HEADER_SIZE + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::{BaseError, Result};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+// 2.0, the oldest version that reads stored files from directories
+const VERSION: u16 = 20;
+// Files are stored as they are, audio hardly compresses anyway
+const STORED: u16 = 0;
+// 1980-01-01 00:00 in MS-DOS format, the earliest date zip knows
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 0x21;
+const LOCAL_HEADER_SIZE: u32 = 30;
+// Offsets and sizes are 32-bit without zip64 extensions
+const MAX_SIZE: u64 = u32::MAX as u64;
+// Entry counts are 16-bit too
+const MAX_ENTRIES: usize = u16::MAX as usize;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            // Reversed polynomial of CRC-32 used by zip
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+// Uncompressed zip archive, files are added whole
+pub struct ZipWriter {
+    out: BufWriter<File>,
+    entries: Vec<Entry>,
+    offset: u64,
+}
+
+impl ZipWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            entries: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    // Fields both headers share, from the version needed to the name length
+    fn common_fields(entry: &Entry) -> Vec<u8> {
+        let mut fields = Vec::with_capacity(26);
+        fields.extend_from_slice(&VERSION.to_le_bytes());
+        // General purpose flags, bit 11 says names are UTF-8
+        fields.extend_from_slice(&(1u16 << 11).to_le_bytes());
+        fields.extend_from_slice(&STORED.to_le_bytes());
+        fields.extend_from_slice(&DOS_TIME.to_le_bytes());
+        fields.extend_from_slice(&DOS_DATE.to_le_bytes());
+        fields.extend_from_slice(&entry.crc.to_le_bytes());
+        // Compressed and uncompressed sizes are the same for stored files
+        fields.extend_from_slice(&entry.size.to_le_bytes());
+        fields.extend_from_slice(&entry.size.to_le_bytes());
+        fields.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        // No extra field
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        if self.entries.len() == MAX_ENTRIES {
+            return Err(BaseError::ConversionError(format!("{} doesn't fit into the archive, it has {} files already", name, MAX_ENTRIES)));
+        }
+        let end = self.offset + LOCAL_HEADER_SIZE as u64 + name.len() as u64 + data.len() as u64;
+        if end > MAX_SIZE {
+            return Err(BaseError::ConversionError(format!("{} doesn't fit into the archive", name)));
+        }
+        let entry = Entry {
+            name: name.to_owned(),
+            crc: crc32(data),
+            size: data.len() as u32,
+            offset: self.offset as u32,
+        };
+        self.write_all(&LOCAL_HEADER_SIGNATURE.to_le_bytes())?;
+        self.write_all(&Self::common_fields(&entry))?;
+        self.write_all(name.as_bytes())?;
+        self.write_all(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    // Writes the central directory, archive can't be read without it
+    pub fn finish(mut self) -> Result<()> {
+        let directory_offset = self.offset as u32;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in entries.iter() {
+            self.write_all(&CENTRAL_HEADER_SIGNATURE.to_le_bytes())?;
+            // Version made by, MS-DOS attributes
+            self.write_all(&VERSION.to_le_bytes())?;
+            self.write_all(&Self::common_fields(entry))?;
+            // Comment length, disk number, internal and external attributes
+            self.write_all(&[0; 10])?;
+            self.write_all(&entry.offset.to_le_bytes())?;
+            self.write_all(entry.name.as_bytes())?;
+        }
+        if self.offset > MAX_SIZE {
+            return Err(BaseError::ConversionError("File list doesn't fit into the archive".into()));
+        }
+        let directory_size = self.offset as u32 - directory_offset;
+        self.write_all(&END_SIGNATURE.to_le_bytes())?;
+        // This disk and the disk with the directory
+        self.write_all(&[0; 4])?;
+        self.write_all(&(entries.len() as u16).to_le_bytes())?;
+        self.write_all(&(entries.len() as u16).to_le_bytes())?;
+        self.write_all(&directory_size.to_le_bytes())?;
+        self.write_all(&directory_offset.to_le_bytes())?;
+        // Comment length
+        self.write_all(&0u16.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+    }
+
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn archive_ends_with_central_directory_record() {
+        let path = std::env::temp_dir().join(format!("beep-boop-zip-test-{}.zip", std::process::id()));
+        let mut zip = ZipWriter::create(&path).unwrap();
+        zip.add("a.txt", b"hello").unwrap();
+        zip.add("b/c.wav", &[]).unwrap();
+        zip.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Local headers with their names and data, then one central header per file
+        let directory_offset = (LOCAL_HEADER_SIZE as usize + 5 + 5) + (LOCAL_HEADER_SIZE as usize + 7);
+        let directory_size = (46 + 5) + (46 + 7);
+        assert_eq!(bytes.len(), directory_offset + directory_size + 22);
+        assert_eq!(u32_at(&bytes, 0), LOCAL_HEADER_SIGNATURE);
+        assert_eq!(u32_at(&bytes, 14), crc32(b"hello"));
+        assert_eq!(u32_at(&bytes, directory_offset), CENTRAL_HEADER_SIGNATURE);
+        let end = directory_offset + directory_size;
+        assert_eq!(u32_at(&bytes, end), END_SIGNATURE);
+        assert_eq!(u16_at(&bytes, end + 4), 0);
+        assert_eq!(u16_at(&bytes, end + 6), 0);
+        assert_eq!(u16_at(&bytes, end + 8), 2);
+        assert_eq!(u16_at(&bytes, end + 10), 2);
+        assert_eq!(u32_at(&bytes, end + 12), directory_size as u32);
+        assert_eq!(u32_at(&bytes, end + 16), directory_offset as u32);
+        assert_eq!(u16_at(&bytes, end + 20), 0);
+    }
+}